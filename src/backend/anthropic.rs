@@ -0,0 +1,656 @@
+//! Backend for Anthropic's native Messages API.
+//!
+//! [`AnthropicBackend`] talks to `/v1/messages` directly instead of going
+//! through Anthropic's OpenAI compatibility layer, so the system prompt is
+//! sent as the top-level `system` field and the `anthropic-version` header is
+//! always set.
+//!
+//! Endpoint: `/v1/messages`.
+//! Streaming: SSE with `data: {"type": "content_block_delta", "delta": {"text": "token"}}`.
+
+use super::rate_limit::RateLimitInfo;
+use super::sse::SseDecoder;
+use super::{Backend, LlmRequest, LlmResponse, Role, StreamLimit, Usage};
+use crate::error::Result;
+use crate::PipelineError;
+use async_trait::async_trait;
+use futures::StreamExt;
+use reqwest::Client;
+use serde_json::{json, Value};
+
+/// API version sent as `anthropic-version` unless overridden with
+/// [`AnthropicBackend::with_version`].
+pub const DEFAULT_ANTHROPIC_VERSION: &str = "2023-06-01";
+
+/// Backend for the Anthropic Messages API.
+///
+/// Tool calling isn't supported: a request with
+/// [`tools`](super::LlmRequest::tools) fails with
+/// [`PipelineError::InvalidConfig`].
+///
+/// # Example
+///
+/// ```
+/// use llm_pipeline::backend::AnthropicBackend;
+///
+/// let backend = AnthropicBackend::new().with_api_key("sk-ant-...");
+/// ```
+#[derive(Clone)]
+pub struct AnthropicBackend {
+    /// Optional API key. If set, sent as `x-api-key: {key}`.
+    pub(crate) api_key: Option<String>,
+    /// Value of the `anthropic-version` header.
+    pub(crate) version: String,
+}
+
+impl std::fmt::Debug for AnthropicBackend {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AnthropicBackend")
+            .field(
+                "api_key",
+                &self.api_key.as_ref().map(|k| {
+                    if k.len() > 6 {
+                        format!("{}***", &k[..6])
+                    } else {
+                        "***".to_string()
+                    }
+                }),
+            )
+            .field("version", &self.version)
+            .finish()
+    }
+}
+
+impl AnthropicBackend {
+    /// Create a new Anthropic backend without an API key.
+    pub fn new() -> Self {
+        Self {
+            api_key: None,
+            version: DEFAULT_ANTHROPIC_VERSION.to_string(),
+        }
+    }
+
+    /// Set the API key for authentication.
+    pub fn with_api_key(mut self, key: impl Into<String>) -> Self {
+        self.api_key = Some(key.into());
+        self
+    }
+
+    /// Set the `anthropic-version` header. Default: `"2023-06-01"`.
+    pub fn with_version(mut self, version: impl Into<String>) -> Self {
+        self.version = version.into();
+        self
+    }
+
+    /// Returns `true` if an API key has been configured.
+    pub fn has_api_key(&self) -> bool {
+        self.api_key.is_some()
+    }
+
+    /// Build the top-level `system` field, if there is a system prompt.
+    ///
+    /// System-role history messages are appended here too, since the
+    /// Messages API only accepts `user` and `assistant` turns.
+    fn build_system(request: &LlmRequest) -> Option<Value> {
+        let mut parts: Vec<&str> = if !request.system_parts.is_empty() {
+            request.system_parts.iter().map(String::as_str).collect()
+        } else {
+            request
+                .system_prompt
+                .as_deref()
+                .filter(|s| !s.is_empty())
+                .into_iter()
+                .collect()
+        };
+        parts.extend(
+            request
+                .messages
+                .iter()
+                .filter(|m| m.role == Role::System)
+                .map(|m| m.content.as_str()),
+        );
+
+        if parts.is_empty() {
+            return None;
+        }
+        if parts.len() == 1 && !request.config.cache_system {
+            return Some(json!(parts[0]));
+        }
+        let last = parts.len() - 1;
+        let blocks = parts
+            .iter()
+            .enumerate()
+            .map(|(i, part)| {
+                let mut block = json!({"type": "text", "text": part});
+                if request.config.cache_system && i == last {
+                    block["cache_control"] = json!({"type": "ephemeral"});
+                }
+                block
+            })
+            .collect();
+        Some(Value::Array(blocks))
+    }
+
+    /// Build the `messages` array of `user`/`assistant` turns.
+    fn build_messages(request: &LlmRequest) -> Vec<Value> {
+        if request.messages.is_empty() {
+            return vec![json!({"role": "user", "content": request.prompt})];
+        }
+        request
+            .messages
+            .iter()
+            .filter_map(|msg| {
+                let role = match msg.role {
+                    Role::System => return None,
+                    Role::User => "user",
+                    Role::Assistant => "assistant",
+                };
+                Some(json!({"role": role, "content": msg.content}))
+            })
+            .collect()
+    }
+
+    /// Build the request body for `/v1/messages`.
+    pub(super) fn build_body(request: &LlmRequest, stream: bool) -> Value {
+        let mut body = json!({
+            "model": request.model,
+            "messages": Self::build_messages(request),
+            "temperature": request.config.temperature,
+            "max_tokens": request.config.max_tokens,
+            "stream": stream,
+        });
+
+        if let Some(system) = Self::build_system(request) {
+            body["system"] = system;
+        }
+        if !request.config.stop.is_empty() {
+            body["stop_sequences"] = json!(request.config.stop);
+        }
+        if let Some(top_p) = request.config.top_p {
+            body["top_p"] = json!(top_p);
+        }
+        if let Some(top_k) = request.config.top_k {
+            body["top_k"] = json!(top_k);
+        }
+
+        // The Messages API has no JSON mode, `n`, `seed`, penalties, or
+        // Ollama-style options; they are skipped. Use `extra_body` for
+        // provider-specific fields.
+        request.config.apply_extra_body(&mut body);
+
+        body
+    }
+
+    /// Build the reqwest request with the version and auth headers.
+    fn build_http_request(
+        &self,
+        client: &Client,
+        url: &str,
+        body: &Value,
+    ) -> reqwest::RequestBuilder {
+        let mut req = client
+            .post(url)
+            .header("anthropic-version", self.version.as_str())
+            .json(body);
+
+        if let Some(ref key) = self.api_key {
+            req = req.header("x-api-key", key.as_str());
+        }
+
+        req
+    }
+
+    /// Concatenate the `text` content blocks of a response.
+    pub(super) fn extract_text(json_resp: &Value) -> String {
+        json_resp
+            .get("content")
+            .and_then(|c| c.as_array())
+            .map(|blocks| {
+                blocks
+                    .iter()
+                    .filter(|b| b.get("type").and_then(|t| t.as_str()).unwrap_or("text") == "text")
+                    .filter_map(|b| b.get("text").and_then(|t| t.as_str()))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Record `stop_reason`, plus its OpenAI-style `finish_reason` so
+    /// truncation and refusal handling work the same as for other backends.
+    fn record_stop_reason(stop_reason: &Value, meta: &mut serde_json::Map<String, Value>) {
+        let Some(reason) = stop_reason.as_str() else {
+            return;
+        };
+        let finish_reason = match reason {
+            "end_turn" | "stop_sequence" => "stop",
+            "max_tokens" => "length",
+            "refusal" => "content_filter",
+            other => other,
+        };
+        meta.insert("stop_reason".into(), stop_reason.clone());
+        meta.insert("finish_reason".into(), finish_reason.into());
+    }
+
+    /// Merge a `usage` object into `meta["usage"]`, key by key.
+    ///
+    /// Streams report input tokens on `message_start` and output tokens on
+    /// `message_delta`, so the two are combined.
+    fn merge_usage(usage: &Value, meta: &mut serde_json::Map<String, Value>) {
+        let Some(usage) = usage.as_object() else {
+            return;
+        };
+        let merged = meta
+            .entry("usage")
+            .or_insert_with(|| Value::Object(serde_json::Map::new()));
+        if let Some(merged) = merged.as_object_mut() {
+            for (k, v) in usage {
+                merged.insert(k.clone(), v.clone());
+            }
+        }
+    }
+
+    /// Extract metadata from an Anthropic response (or `message_start`
+    /// event's `message`).
+    pub(super) fn extract_metadata(json_resp: &Value, meta: &mut serde_json::Map<String, Value>) {
+        if let Some(v) = json_resp.get("usage") {
+            Self::merge_usage(v, meta);
+        }
+        for key in ["id", "model"] {
+            if let Some(v) = json_resp.get(key).filter(|v| !v.is_null()) {
+                meta.insert(key.into(), v.clone());
+            }
+        }
+        if let Some(v) = json_resp.get("stop_reason") {
+            Self::record_stop_reason(v, meta);
+        }
+    }
+
+    /// Apply one streamed event to `meta`, returning its text delta, if any.
+    ///
+    /// An `error` event is returned as an error: an
+    /// [`HttpError`](PipelineError::HttpError) with status 529 or 429 for
+    /// `overloaded_error` and `rate_limit_error`, so they can be retried.
+    pub(super) fn apply_event<'v>(
+        event: &'v Value,
+        meta: &mut serde_json::Map<String, Value>,
+    ) -> Result<Option<&'v str>> {
+        match event.get("type").and_then(|t| t.as_str()) {
+            Some("message_start") => {
+                if let Some(message) = event.get("message") {
+                    Self::extract_metadata(message, meta);
+                }
+            }
+            Some("content_block_delta") => {
+                return Ok(event.pointer("/delta/text").and_then(|t| t.as_str()));
+            }
+            Some("message_delta") => {
+                if let Some(v) = event.pointer("/delta/stop_reason") {
+                    Self::record_stop_reason(v, meta);
+                }
+                if let Some(v) = event.get("usage") {
+                    Self::merge_usage(v, meta);
+                }
+            }
+            Some("error") => {
+                let message = event
+                    .pointer("/error/message")
+                    .and_then(|m| m.as_str())
+                    .unwrap_or("unknown error");
+                // Overload and rate-limit errors get the status the API
+                // would have returned up front, so backoff retries them.
+                let status = match event.pointer("/error/type").and_then(|t| t.as_str()) {
+                    Some("overloaded_error") => 529,
+                    Some("rate_limit_error") => 429,
+                    _ => {
+                        return Err(PipelineError::Other(format!(
+                            "Anthropic stream error: {}",
+                            message
+                        )))
+                    }
+                };
+                return Err(PipelineError::HttpError {
+                    status,
+                    body: event.to_string(),
+                    message: Some(message.to_string()),
+                    retry_after: None,
+                });
+            }
+            _ => {}
+        }
+        Ok(None)
+    }
+}
+
+impl Default for AnthropicBackend {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait]
+impl Backend for AnthropicBackend {
+    async fn complete(
+        &self,
+        client: &Client,
+        base_url: &str,
+        request: &LlmRequest,
+    ) -> Result<LlmResponse> {
+        super::reject_tools(request, "Anthropic")?;
+        let base = base_url.trim_end_matches('/');
+        let url = format!("{}/v1/messages", base);
+        let body = Self::build_body(request, false);
+
+        let resp = self
+            .build_http_request(client, &url, &body)
+            .send()
+            .await
+            .map_err(PipelineError::Request)?;
+
+        let status = resp.status().as_u16();
+
+        if !request.accepts_status(resp.status()) {
+            let retry_after = super::retry_after(resp.headers());
+            let text = resp.text().await.unwrap_or_default();
+            return Err(PipelineError::HttpError {
+                status,
+                message: super::error_message(&text),
+                body: text,
+                retry_after,
+            });
+        }
+
+        let rate_limit = RateLimitInfo::from_headers(resp.headers());
+        let json_resp: Value = resp.json().await?;
+
+        let mut meta = serde_json::Map::new();
+        Self::extract_metadata(&json_resp, &mut meta);
+        let metadata = (!meta.is_empty()).then_some(Value::Object(meta));
+
+        let metadata = match rate_limit {
+            Some(info) => info.attach(metadata),
+            None => metadata,
+        };
+        Ok(LlmResponse {
+            text: Self::extract_text(&json_resp),
+            status,
+            usage: metadata.as_ref().and_then(Usage::from_metadata),
+            metadata,
+            candidates: Vec::new(),
+            refusal: None,
+            tool_calls: Vec::new(),
+        })
+    }
+
+    async fn complete_streaming(
+        &self,
+        client: &Client,
+        base_url: &str,
+        request: &LlmRequest,
+        on_token: &mut (dyn FnMut(String) + Send),
+    ) -> Result<LlmResponse> {
+        super::reject_tools(request, "Anthropic")?;
+        let base = base_url.trim_end_matches('/');
+        let url = format!("{}/v1/messages", base);
+        let body = Self::build_body(request, true);
+
+        let resp = self
+            .build_http_request(client, &url, &body)
+            .send()
+            .await
+            .map_err(PipelineError::Request)?;
+
+        let status = resp.status().as_u16();
+
+        if !request.accepts_status(resp.status()) {
+            let retry_after = super::retry_after(resp.headers());
+            let text = resp.text().await.unwrap_or_default();
+            return Err(PipelineError::HttpError {
+                status,
+                message: super::error_message(&text),
+                body: text,
+                retry_after,
+            });
+        }
+
+        let rate_limit = RateLimitInfo::from_headers(resp.headers());
+        let mut stream = resp.bytes_stream();
+        let mut decoder = SseDecoder::new();
+        let mut accumulated = String::new();
+        let mut limit = StreamLimit::new(request.max_stream_tokens);
+        let mut meta = serde_json::Map::new();
+
+        'stream: while let Some(chunk) = stream.next().await {
+            let chunk = chunk.map_err(PipelineError::Request)?;
+            for event in decoder.decode(&chunk) {
+                if let Some(text) = Self::apply_event(&event, &mut meta)? {
+                    if !text.is_empty() {
+                        accumulated.push_str(text);
+                        on_token(text.to_string());
+                        if limit.record() {
+                            // Dropping the stream closes the connection.
+                            break 'stream;
+                        }
+                    }
+                }
+            }
+        }
+
+        // Flush remaining SSE buffer
+        let flushed = if limit.reached() {
+            Vec::new()
+        } else {
+            decoder.flush()
+        };
+        for event in flushed {
+            if let Some(text) = Self::apply_event(&event, &mut meta)? {
+                if !text.is_empty() {
+                    accumulated.push_str(text);
+                    on_token(text.to_string());
+                }
+            }
+        }
+
+        let metadata = (!meta.is_empty()).then_some(Value::Object(meta));
+        let metadata = limit.apply(match rate_limit {
+            Some(info) => info.attach(metadata),
+            None => metadata,
+        });
+        Ok(LlmResponse {
+            text: accumulated,
+            status,
+            usage: metadata.as_ref().and_then(Usage::from_metadata),
+            metadata,
+            candidates: Vec::new(),
+            refusal: None,
+            tool_calls: Vec::new(),
+        })
+    }
+
+    fn name(&self) -> &'static str {
+        "anthropic"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::{ChatMessage, ToolSpec};
+
+    fn test_request() -> LlmRequest {
+        LlmRequest::new("claude-sonnet-4-5", "Why is the sky blue?")
+    }
+
+    #[tokio::test]
+    async fn test_tools_rejected() {
+        let mut request = test_request();
+        request.tools = vec![ToolSpec::new(
+            "get_weather",
+            "Look up the weather",
+            serde_json::json!({"type": "object"}),
+        )];
+        let err = AnthropicBackend::new()
+            .complete(&Client::new(), "http://127.0.0.1:1", &request)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, PipelineError::InvalidConfig(_)));
+    }
+
+    #[test]
+    fn test_anthropic_body_top_level_system() {
+        let mut request = test_request();
+        request.system_prompt = Some("You are a helpful assistant.".into());
+        request.config.stop = vec!["Human:".into()];
+        request.config.top_k = Some(40);
+        request.config.seed = Some(7);
+
+        let body = AnthropicBackend::build_body(&request, false);
+        assert_eq!(body["model"], "claude-sonnet-4-5");
+        assert_eq!(body["stop_sequences"], json!(["Human:"]));
+        assert_eq!(body["top_k"], 40);
+        assert!(body.get("top_p").is_none());
+        assert!(body.get("seed").is_none());
+        assert_eq!(body["max_tokens"], 2048);
+        assert_eq!(body["stream"], false);
+        assert_eq!(body["system"], "You are a helpful assistant.");
+        assert_eq!(
+            body["messages"],
+            json!([{"role": "user", "content": "Why is the sky blue?"}])
+        );
+
+        let body = AnthropicBackend::build_body(&test_request(), true);
+        assert!(body.get("system").is_none());
+        assert!(body.get("stop_sequences").is_none());
+        assert_eq!(body["stream"], true);
+    }
+
+    #[test]
+    fn test_anthropic_body_history_and_cached_system() {
+        let mut request = test_request();
+        request.system_parts = vec!["Role.".into(), "Rules.".into()];
+        request.system_prompt = Some("Role.\n\nRules.".into());
+        request.config.cache_system = true;
+        request.messages = vec![
+            ChatMessage {
+                role: Role::User,
+                content: "What is 2+2?".into(),
+            },
+            ChatMessage {
+                role: Role::Assistant,
+                content: "4".into(),
+            },
+            ChatMessage {
+                role: Role::System,
+                content: "Answer in words.".into(),
+            },
+            ChatMessage {
+                role: Role::User,
+                content: "And 3+3?".into(),
+            },
+        ];
+
+        let body = AnthropicBackend::build_body(&request, false);
+        let system = body["system"].as_array().expect("system blocks");
+        assert_eq!(system.len(), 3);
+        assert!(system[0].get("cache_control").is_none());
+        assert_eq!(system[2]["text"], "Answer in words.");
+        assert_eq!(system[2]["cache_control"], json!({"type": "ephemeral"}));
+
+        let roles: Vec<_> = body["messages"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|m| m["role"].as_str().unwrap())
+            .collect();
+        assert_eq!(roles, ["user", "assistant", "user"]);
+    }
+
+    #[test]
+    fn test_anthropic_headers() {
+        let client = Client::new();
+        let req = AnthropicBackend::new()
+            .with_api_key("sk-ant-test")
+            .build_http_request(&client, "https://api.anthropic.com/v1/messages", &json!({}))
+            .build()
+            .expect("build request");
+        assert_eq!(req.headers()["x-api-key"], "sk-ant-test");
+        assert_eq!(
+            req.headers()["anthropic-version"],
+            DEFAULT_ANTHROPIC_VERSION
+        );
+        assert!(req.headers().get("Authorization").is_none());
+
+        let backend = AnthropicBackend::new().with_api_key("sk-ant-1234567890");
+        let debug_output = format!("{:?}", backend);
+        assert!(!debug_output.contains("1234567890"));
+        assert!(debug_output.contains("sk-ant***"));
+    }
+
+    #[test]
+    fn test_anthropic_response_text_and_metadata() {
+        let resp = json!({
+            "id": "msg_01",
+            "model": "claude-sonnet-4-5",
+            "content": [{"type": "text", "text": "Rayleigh "}, {"type": "text", "text": "scattering."}],
+            "stop_reason": "max_tokens",
+            "usage": {"input_tokens": 12, "output_tokens": 3},
+        });
+        assert_eq!(
+            AnthropicBackend::extract_text(&resp),
+            "Rayleigh scattering."
+        );
+
+        let mut meta = serde_json::Map::new();
+        AnthropicBackend::extract_metadata(&resp, &mut meta);
+        assert_eq!(meta["id"], "msg_01");
+        assert_eq!(meta["stop_reason"], "max_tokens");
+        assert_eq!(meta["finish_reason"], "length");
+        assert_eq!(meta["usage"]["output_tokens"], 3);
+    }
+
+    #[test]
+    fn test_anthropic_stream_events() {
+        let mut decoder = SseDecoder::new();
+        let events = decoder.decode(
+            b"event: message_start\n\
+              data: {\"type\":\"message_start\",\"message\":{\"id\":\"msg_01\",\"usage\":{\"input_tokens\":9,\"output_tokens\":1}}}\n\n\
+              event: ping\ndata: {\"type\":\"ping\"}\n\n\
+              event: content_block_delta\n\
+              data: {\"type\":\"content_block_delta\",\"index\":0,\"delta\":{\"type\":\"text_delta\",\"text\":\"Hi\"}}\n\n\
+              event: message_delta\n\
+              data: {\"type\":\"message_delta\",\"delta\":{\"stop_reason\":\"end_turn\"},\"usage\":{\"output_tokens\":2}}\n\n",
+        );
+        let mut meta = serde_json::Map::new();
+        let texts: Vec<_> = events
+            .iter()
+            .filter_map(|e| AnthropicBackend::apply_event(e, &mut meta).unwrap())
+            .collect();
+        assert_eq!(texts, ["Hi"]);
+        assert_eq!(meta["finish_reason"], "stop");
+        assert_eq!(
+            meta["usage"],
+            json!({"input_tokens": 9, "output_tokens": 2})
+        );
+
+        let error = json!({"type": "error", "error": {"type": "overloaded_error", "message": "Overloaded"}});
+        let err = AnthropicBackend::apply_event(&error, &mut meta).unwrap_err();
+        assert!(matches!(err, PipelineError::HttpError { status: 529, .. }));
+        assert!(err.to_string().contains("Overloaded"));
+        assert!(crate::backend::is_retryable(
+            &err,
+            &crate::backend::BackoffConfig::standard()
+        ));
+
+        let error = json!({"type": "error", "error": {"type": "api_error", "message": "Internal"}});
+        let err = AnthropicBackend::apply_event(&error, &mut meta).unwrap_err();
+        assert!(matches!(err, PipelineError::Other(_)));
+    }
+
+    #[tokio::test]
+    async fn test_anthropic_connect_error_is_retryable() {
+        crate::backend::tests::assert_connect_error_retryable(
+            &AnthropicBackend::new(),
+            &test_request(),
+        )
+        .await;
+    }
+}
@@ -5,6 +5,7 @@
 //! For cloud APIs (OpenAI, Groq, Together), use [`BackoffConfig::standard()`]
 //! or tune to your rate limit tier.
 
+use super::rate_limit::RateLimitPacer;
 use std::time::Duration;
 
 /// Configuration for transport-level retry with exponential backoff and jitter.
@@ -24,6 +25,18 @@ use std::time::Duration;
 /// // Standard cloud API settings
 /// let standard = BackoffConfig::standard();
 /// assert_eq!(standard.max_retries, 3);
+///
+/// // Custom settings
+/// use llm_pipeline::backend::backoff::JitterStrategy;
+/// use std::time::Duration;
+///
+/// let custom = BackoffConfig::builder()
+///     .max_retries(4)
+///     .initial_delay(Duration::from_millis(250))
+///     .jitter(JitterStrategy::Equal)
+///     .retry_on([429, 529])
+///     .build();
+/// assert_eq!(custom.retryable_statuses, vec![429, 529]);
 /// ```
 #[derive(Debug, Clone)]
 pub struct BackoffConfig {
@@ -44,12 +57,31 @@ pub struct BackoffConfig {
     /// Jitter strategy. Default: Full.
     pub jitter: JitterStrategy,
 
-    /// HTTP status codes that trigger retry. Default: `[429, 500, 502, 503, 504]`.
+    /// HTTP status codes that trigger retry. Default: `[429, 500, 502, 503, 504, 529]`
+    /// (529 is Anthropic's "overloaded").
     pub retryable_statuses: Vec<u16>,
 
-    /// Whether to respect `Retry-After` headers from the provider.
-    /// Default: `true`.
+    /// Whether to respect `Retry-After` headers from the provider. The
+    /// requested delay is capped at `max_delay`. Default: `true`.
     pub respect_retry_after: bool,
+
+    /// On a streaming retry, continue from the tokens already received
+    /// instead of restarting. Default: `false`. See
+    /// [`resume_streams`](Self::resume_streams).
+    pub resume_streams: bool,
+
+    /// Non-2xx HTTP status codes to treat as success: the body is parsed as
+    /// a normal response instead of becoming an error. Default: empty
+    /// (standard 2xx only). See [`accept_statuses`](Self::accept_statuses).
+    pub accept_statuses: Vec<u16>,
+
+    /// Hold calls back while the provider reports a nearly spent rate-limit
+    /// budget. Default: `None`. See [`pace_rate_limits`](Self::pace_rate_limits).
+    pub rate_limit_pacer: Option<RateLimitPacer>,
+
+    /// Seed for the jitter, making delays reproducible. Default: `None`
+    /// (global random state). See [`jitter_seed`](Self::jitter_seed).
+    pub jitter_seed: Option<u64>,
 }
 
 /// Jitter strategy to prevent thundering herd on shared rate limits.
@@ -78,6 +110,15 @@ pub enum JitterStrategy {
 }
 
 impl BackoffConfig {
+    /// Create a builder starting from the [`Default`] (no retries, with
+    /// [`standard`](Self::standard) timing), so only the settings that
+    /// differ need to be named.
+    pub fn builder() -> BackoffConfigBuilder {
+        BackoffConfigBuilder {
+            config: Self::default(),
+        }
+    }
+
     /// No transport retry. For local Ollama or when you handle errors yourself.
     ///
     /// This is the default to preserve backward compatibility.
@@ -97,8 +138,12 @@ impl BackoffConfig {
             multiplier: 2.0,
             max_delay: Duration::from_secs(60),
             jitter: JitterStrategy::Full,
-            retryable_statuses: vec![429, 500, 502, 503, 504],
+            retryable_statuses: vec![429, 500, 502, 503, 504, 529],
             respect_retry_after: true,
+            resume_streams: false,
+            accept_statuses: Vec::new(),
+            rate_limit_pacer: None,
+            jitter_seed: None,
         }
     }
 
@@ -111,8 +156,12 @@ impl BackoffConfig {
             multiplier: 2.0,
             max_delay: Duration::from_secs(120),
             jitter: JitterStrategy::Full,
-            retryable_statuses: vec![429, 500, 502, 503, 504],
+            retryable_statuses: vec![429, 500, 502, 503, 504, 529],
             respect_retry_after: true,
+            resume_streams: false,
+            accept_statuses: Vec::new(),
+            rate_limit_pacer: None,
+            jitter_seed: None,
         }
     }
 
@@ -125,11 +174,70 @@ impl BackoffConfig {
             multiplier: 1.5,
             max_delay: Duration::from_secs(10),
             jitter: JitterStrategy::Full,
-            retryable_statuses: vec![429, 500, 502, 503, 504],
+            retryable_statuses: vec![429, 500, 502, 503, 504, 529],
             respect_retry_after: true,
+            resume_streams: false,
+            accept_statuses: Vec::new(),
+            rate_limit_pacer: None,
+            jitter_seed: None,
         }
     }
 
+    /// Resume interrupted streams instead of restarting them.
+    ///
+    /// When a streaming attempt fails after producing tokens, the retry
+    /// sends the partial output back as an assistant message and asks the
+    /// model to continue; the final response text is the partial plus the
+    /// continuation, and already-delivered tokens are not repeated. Only
+    /// worthwhile with a fixed seed (e.g. `options: {"seed": 42}`), since
+    /// an unseeded model may continue inconsistently.
+    pub fn resume_streams(mut self, enabled: bool) -> Self {
+        self.resume_streams = enabled;
+        self
+    }
+
+    /// Treat these HTTP status codes as success, for gateways that answer
+    /// with non-standard codes for partial or async results.
+    ///
+    /// Applied by [`with_backoff`](super::with_backoff) and
+    /// [`with_backoff_streaming`](super::with_backoff_streaming), which pass
+    /// the codes to the backend via
+    /// [`LlmRequest::accept_statuses`](super::LlmRequest::accept_statuses).
+    pub fn accept_statuses(mut self, statuses: impl IntoIterator<Item = u16>) -> Self {
+        self.accept_statuses = statuses.into_iter().collect();
+        self
+    }
+
+    /// Wait for the provider's rate-limit window to reset before sending a
+    /// call once its rate-limit headers report `headroom` or fewer requests
+    /// or tokens left, rather than sending it into a 429.
+    ///
+    /// Only backends that surface these headers (the built-in OpenAI and
+    /// Anthropic backends) are paced. The schedule is shared by clones of
+    /// this config.
+    /// A single pause is capped at [`max_delay`](Self::max_delay) and ends
+    /// early with [`PipelineError::Cancelled`](crate::PipelineError::Cancelled)
+    /// when the call's cancellation flag is raised.
+    /// Independently of this setting, a 429 without `Retry-After` is retried
+    /// after the reported reset time, capped at `max_delay`, when
+    /// [`respect_retry_after`](Self::respect_retry_after) is on.
+    pub fn pace_rate_limits(mut self, headroom: u64) -> Self {
+        self.rate_limit_pacer = Some(RateLimitPacer::new(headroom));
+        self
+    }
+
+    /// Draw jitter from a generator seeded with `seed` instead of global
+    /// random state, so [`delay_for_attempt`](Self::delay_for_attempt)
+    /// always returns the same delay for the same attempt.
+    ///
+    /// For tests and for reproducing a retry sequence while debugging;
+    /// clients sharing a seed jitter in lockstep, which defeats its purpose
+    /// against a shared rate limit.
+    pub fn jitter_seed(mut self, seed: u64) -> Self {
+        self.jitter_seed = Some(seed);
+        self
+    }
+
     /// Calculate the delay for attempt N (0-indexed).
     ///
     /// The base delay is `initial_delay * multiplier^attempt`, capped at
@@ -138,14 +246,21 @@ impl BackoffConfig {
         let base = self.initial_delay.as_secs_f64() * self.multiplier.powi(attempt as i32);
         let capped = base.min(self.max_delay.as_secs_f64());
 
+        let mut seeded = self
+            .jitter_seed
+            .map(|seed| fastrand::Rng::with_seed(seed.wrapping_add(attempt as u64)));
+        let mut random = || match seeded {
+            Some(ref mut rng) => rng.f64(),
+            None => fastrand::f64(),
+        };
         let jittered = match self.jitter {
             JitterStrategy::None => capped,
-            JitterStrategy::Full => fastrand::f64() * capped,
-            JitterStrategy::Equal => capped / 2.0 + fastrand::f64() * (capped / 2.0),
+            JitterStrategy::Full => random() * capped,
+            JitterStrategy::Equal => capped / 2.0 + random() * (capped / 2.0),
             JitterStrategy::Decorrelated => {
                 // Simplified: random in [0, capped]. Full decorrelated tracking
                 // happens in the retry loop itself.
-                fastrand::f64() * capped
+                random() * capped
             }
         };
 
@@ -159,10 +274,116 @@ impl Default for BackoffConfig {
     }
 }
 
+/// Builder for [`BackoffConfig`]. Create with [`BackoffConfig::builder`].
+#[derive(Debug, Clone)]
+pub struct BackoffConfigBuilder {
+    config: BackoffConfig,
+}
+
+impl BackoffConfigBuilder {
+    /// Maximum number of transport retries.
+    pub fn max_retries(mut self, n: u32) -> Self {
+        self.config.max_retries = n;
+        self
+    }
+
+    /// Delay before the first retry.
+    pub fn initial_delay(mut self, delay: Duration) -> Self {
+        self.config.initial_delay = delay;
+        self
+    }
+
+    /// Factor the delay grows by after each retry.
+    pub fn multiplier(mut self, multiplier: f64) -> Self {
+        self.config.multiplier = multiplier;
+        self
+    }
+
+    /// Upper bound on the delay between retries.
+    pub fn max_delay(mut self, delay: Duration) -> Self {
+        self.config.max_delay = delay;
+        self
+    }
+
+    /// Jitter strategy applied to each delay.
+    pub fn jitter(mut self, jitter: JitterStrategy) -> Self {
+        self.config.jitter = jitter;
+        self
+    }
+
+    /// HTTP status codes that trigger a retry, replacing the default
+    /// `[429, 500, 502, 503, 504, 529]`.
+    pub fn retry_on(mut self, statuses: impl IntoIterator<Item = u16>) -> Self {
+        self.config.retryable_statuses = statuses.into_iter().collect();
+        self
+    }
+
+    /// Whether to wait as long as a `Retry-After` header asks.
+    pub fn respect_retry_after(mut self, enabled: bool) -> Self {
+        self.config.respect_retry_after = enabled;
+        self
+    }
+
+    /// See [`BackoffConfig::resume_streams`].
+    pub fn resume_streams(mut self, enabled: bool) -> Self {
+        self.config.resume_streams = enabled;
+        self
+    }
+
+    /// See [`BackoffConfig::accept_statuses`].
+    pub fn accept_statuses(mut self, statuses: impl IntoIterator<Item = u16>) -> Self {
+        self.config.accept_statuses = statuses.into_iter().collect();
+        self
+    }
+
+    /// See [`BackoffConfig::pace_rate_limits`].
+    pub fn pace_rate_limits(mut self, headroom: u64) -> Self {
+        self.config = self.config.pace_rate_limits(headroom);
+        self
+    }
+
+    /// See [`BackoffConfig::jitter_seed`].
+    pub fn jitter_seed(mut self, seed: u64) -> Self {
+        self.config.jitter_seed = Some(seed);
+        self
+    }
+
+    /// Finish the builder.
+    pub fn build(self) -> BackoffConfig {
+        self.config
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_backoff_builder() {
+        let config = BackoffConfig::builder().build();
+        assert_eq!(config.max_retries, 0);
+        assert_eq!(config.retryable_statuses, vec![429, 500, 502, 503, 504, 529]);
+
+        let config = BackoffConfig::builder()
+            .max_retries(4)
+            .initial_delay(Duration::from_millis(100))
+            .multiplier(3.0)
+            .max_delay(Duration::from_secs(2))
+            .jitter(JitterStrategy::None)
+            .retry_on([429, 529])
+            .respect_retry_after(false)
+            .resume_streams(true)
+            .accept_statuses([206])
+            .build();
+        assert_eq!(config.max_retries, 4);
+        assert_eq!(config.retryable_statuses, vec![429, 529]);
+        assert!(!config.respect_retry_after);
+        assert!(config.resume_streams);
+        assert_eq!(config.accept_statuses, vec![206]);
+        assert_eq!(config.delay_for_attempt(1), Duration::from_millis(300));
+        assert_eq!(config.delay_for_attempt(5), Duration::from_secs(2));
+    }
+
     #[test]
     fn test_backoff_delay_exponential() {
         let config = BackoffConfig {
@@ -173,6 +394,10 @@ mod tests {
             jitter: JitterStrategy::None,
             retryable_statuses: vec![429],
             respect_retry_after: false,
+            resume_streams: false,
+            accept_statuses: Vec::new(),
+            rate_limit_pacer: None,
+            jitter_seed: None,
         };
 
         let d0 = config.delay_for_attempt(0);
@@ -196,6 +421,10 @@ mod tests {
             jitter: JitterStrategy::None,
             retryable_statuses: vec![429],
             respect_retry_after: false,
+            resume_streams: false,
+            accept_statuses: Vec::new(),
+            rate_limit_pacer: None,
+            jitter_seed: None,
         };
 
         // Attempt 3 would be 8s uncapped, but max_delay is 5s
@@ -207,6 +436,29 @@ mod tests {
         assert_eq!(d, Duration::from_secs(5));
     }
 
+    #[test]
+    fn test_backoff_jitter_seed_is_reproducible() {
+        let config = BackoffConfig::builder()
+            .initial_delay(Duration::from_secs(1))
+            .jitter(JitterStrategy::Full)
+            .jitter_seed(42)
+            .build();
+        let expected = Duration::from_secs_f64(fastrand::Rng::with_seed(43).f64() * 2.0);
+        assert_eq!(config.delay_for_attempt(1), expected);
+        assert_eq!(config.delay_for_attempt(1), expected);
+
+        let equal = BackoffConfig {
+            jitter: JitterStrategy::Equal,
+            ..config.clone()
+        };
+        let expected = Duration::from_secs_f64(0.5 + fastrand::Rng::with_seed(42).f64() * 0.5);
+        assert_eq!(equal.delay_for_attempt(0), expected);
+        assert_ne!(
+            config.delay_for_attempt(0),
+            config.clone().jitter_seed(7).delay_for_attempt(0)
+        );
+    }
+
     #[test]
     fn test_backoff_jitter_full_in_range() {
         let config = BackoffConfig {
@@ -217,6 +469,10 @@ mod tests {
             jitter: JitterStrategy::Full,
             retryable_statuses: vec![429],
             respect_retry_after: false,
+            resume_streams: false,
+            accept_statuses: Vec::new(),
+            rate_limit_pacer: None,
+            jitter_seed: None,
         };
 
         // Full jitter for attempt 0: random in [0, 1s]
@@ -0,0 +1,640 @@
+//! Backend for Anthropic models on AWS Bedrock.
+//!
+//! [`BedrockBackend`] signs requests with AWS Signature Version 4 and calls
+//! the Bedrock runtime's `invoke` endpoints with the Anthropic-on-Bedrock
+//! body shape (the Messages API body without `model`, plus
+//! `anthropic_version`).
+//!
+//! Endpoint: `/model/{model_id}/invoke` and
+//! `/model/{model_id}/invoke-with-response-stream`.
+//! Streaming: AWS event-stream frames whose `chunk` events carry
+//! base64-encoded Anthropic stream events.
+
+use super::anthropic::AnthropicBackend;
+use super::event_stream::{EventStreamDecoder, EventStreamMessage};
+use super::{Backend, LlmRequest, LlmResponse, StreamLimit, Usage};
+use crate::error::Result;
+use crate::PipelineError;
+use async_trait::async_trait;
+use base64::Engine;
+use futures::StreamExt;
+use reqwest::header::HeaderMap;
+use reqwest::{Client, Url};
+use serde_json::Value;
+
+/// `anthropic_version` sent in every Bedrock request body.
+pub const BEDROCK_ANTHROPIC_VERSION: &str = "bedrock-2023-05-31";
+
+/// Service name used in the SigV4 credential scope.
+const SERVICE: &str = "bedrock";
+
+/// Metadata key for the invocation latency in milliseconds, taken from the
+/// `x-amzn-bedrock-invocation-latency` header (or, when streaming, the
+/// final event's `invocationLatency`).
+const INVOCATION_LATENCY_KEY: &str = "invocation_latency_ms";
+
+/// AWS credentials used to sign Bedrock requests.
+#[derive(Clone)]
+pub struct AwsCredentials {
+    /// Access key ID.
+    pub access_key_id: String,
+    /// Secret access key.
+    pub secret_access_key: String,
+    /// Session token for temporary credentials.
+    pub session_token: Option<String>,
+}
+
+impl std::fmt::Debug for AwsCredentials {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AwsCredentials")
+            .field("access_key_id", &self.access_key_id)
+            .field("secret_access_key", &"***")
+            .field("session_token", &self.session_token.as_ref().map(|_| "***"))
+            .finish()
+    }
+}
+
+impl AwsCredentials {
+    /// Long-term credentials from an access key ID and secret.
+    pub fn new(access_key_id: impl Into<String>, secret_access_key: impl Into<String>) -> Self {
+        Self {
+            access_key_id: access_key_id.into(),
+            secret_access_key: secret_access_key.into(),
+            session_token: None,
+        }
+    }
+
+    /// Set the session token for temporary credentials.
+    pub fn with_session_token(mut self, token: impl Into<String>) -> Self {
+        self.session_token = Some(token.into());
+        self
+    }
+
+    /// Read `AWS_ACCESS_KEY_ID`, `AWS_SECRET_ACCESS_KEY`, and optionally
+    /// `AWS_SESSION_TOKEN`. `None` if either of the first two is unset.
+    pub fn from_env() -> Option<Self> {
+        let var = |key: &str| std::env::var(key).ok().filter(|v| !v.is_empty());
+        Some(Self {
+            access_key_id: var("AWS_ACCESS_KEY_ID")?,
+            secret_access_key: var("AWS_SECRET_ACCESS_KEY")?,
+            session_token: var("AWS_SESSION_TOKEN"),
+        })
+    }
+}
+
+/// Backend for Anthropic models on AWS Bedrock.
+///
+/// `LlmRequest::model` is the Bedrock model ID, e.g.
+/// `anthropic.claude-3-5-sonnet-20240620-v1:0`. Point the
+/// [`ExecCtx`](crate::ExecCtx) at [`endpoint`](Self::endpoint) (or a VPC
+/// endpoint for the same region).
+///
+/// Tool calling isn't supported: a request with
+/// [`tools`](super::LlmRequest::tools) fails with
+/// [`PipelineError::InvalidConfig`].
+///
+/// # Example
+///
+/// ```
+/// use llm_pipeline::backend::{AwsCredentials, BedrockBackend};
+/// use llm_pipeline::ExecCtx;
+/// use std::sync::Arc;
+///
+/// let backend = BedrockBackend::new("us-east-1", AwsCredentials::new("AKIA...", "secret"));
+/// let ctx = ExecCtx::builder(backend.endpoint())
+///     .backend(Arc::new(backend))
+///     .build();
+/// ```
+#[derive(Debug, Clone)]
+pub struct BedrockBackend {
+    region: String,
+    credentials: AwsCredentials,
+}
+
+impl BedrockBackend {
+    /// Create a backend for `region` signing with `credentials`.
+    pub fn new(region: impl Into<String>, credentials: AwsCredentials) -> Self {
+        Self {
+            region: region.into(),
+            credentials,
+        }
+    }
+
+    /// The region requests are signed for.
+    pub fn region(&self) -> &str {
+        &self.region
+    }
+
+    /// The public Bedrock runtime endpoint for this region.
+    pub fn endpoint(&self) -> String {
+        format!("https://bedrock-runtime.{}.amazonaws.com", self.region)
+    }
+
+    /// Build the Anthropic-on-Bedrock request body.
+    fn build_body(request: &LlmRequest) -> Value {
+        let mut body = AnthropicBackend::build_body(request, false);
+        if let Some(fields) = body.as_object_mut() {
+            // The model is in the URL, and streaming is chosen by endpoint.
+            fields.remove("model");
+            fields.remove("stream");
+            fields
+                .entry("anthropic_version")
+                .or_insert_with(|| BEDROCK_ANTHROPIC_VERSION.into());
+        }
+        body
+    }
+
+    /// The invoke URL for `model` and `action` (`invoke` or
+    /// `invoke-with-response-stream`).
+    fn invoke_url(base_url: &str, model: &str, action: &str) -> String {
+        format!(
+            "{}/model/{}/{}",
+            base_url.trim_end_matches('/'),
+            uri_encode(model),
+            action
+        )
+    }
+
+    /// Build a signed POST of `body` to `url`.
+    fn build_http_request(
+        &self,
+        client: &Client,
+        url: &str,
+        body: &Value,
+        accept: &str,
+    ) -> Result<reqwest::RequestBuilder> {
+        let bytes = serde_json::to_vec(body)?;
+        let parsed = Url::parse(url)
+            .map_err(|e| PipelineError::Other(format!("Invalid Bedrock URL {}: {}", url, e)))?;
+        let signed = self.sign(&parsed, &bytes, &amz_date(now_secs()));
+
+        let mut req = client
+            .post(parsed)
+            .header("content-type", "application/json")
+            .header("accept", accept)
+            .body(bytes);
+        for (name, value) in signed {
+            req = req.header(name, value);
+        }
+        Ok(req)
+    }
+
+    /// SigV4 headers (`x-amz-date`, `x-amz-security-token` when there is a
+    /// session token, and `authorization`) for a POST of `body` to `url`.
+    fn sign(&self, url: &Url, body: &[u8], amz_date: &str) -> Vec<(&'static str, String)> {
+        let host = match url.port() {
+            Some(port) => format!("{}:{}", url.host_str().unwrap_or_default(), port),
+            None => url.host_str().unwrap_or_default().to_string(),
+        };
+        let mut headers = vec![("host", host), ("x-amz-date", amz_date.to_string())];
+        if let Some(ref token) = self.credentials.session_token {
+            headers.push(("x-amz-security-token", token.clone()));
+        }
+
+        let signed_headers = headers
+            .iter()
+            .map(|(name, _)| *name)
+            .collect::<Vec<_>>()
+            .join(";");
+        let canonical_headers: String = headers
+            .iter()
+            .map(|(name, value)| format!("{}:{}\n", name, value.trim()))
+            .collect();
+        let canonical_request = format!(
+            "POST\n{}\n{}\n{}\n{}\n{}",
+            canonical_uri(url.path()),
+            url.query().unwrap_or_default(),
+            canonical_headers,
+            signed_headers,
+            sha256_hex(body)
+        );
+        let signature = signature(
+            &self.credentials.secret_access_key,
+            amz_date,
+            &self.region,
+            SERVICE,
+            &canonical_request,
+        );
+
+        headers.remove(0);
+        headers.push((
+            "authorization",
+            format!(
+                "AWS4-HMAC-SHA256 Credential={}/{}/{}/{}/aws4_request, SignedHeaders={}, Signature={}",
+                self.credentials.access_key_id,
+                &amz_date[..8],
+                self.region,
+                SERVICE,
+                signed_headers,
+                signature
+            ),
+        ));
+        headers
+    }
+
+    /// Apply one event-stream frame to `meta`, returning its text delta, if
+    /// any. Exception frames are returned as errors; throttling and
+    /// service-unavailable exceptions as retryable
+    /// [`HttpError`](PipelineError::HttpError)s with status 429 and 503.
+    fn apply_frame(
+        message: &EventStreamMessage,
+        meta: &mut serde_json::Map<String, Value>,
+    ) -> Result<Option<String>> {
+        if message.header(":message-type") == Some("exception") {
+            let detail = serde_json::from_slice::<Value>(&message.payload)
+                .ok()
+                .and_then(|v| {
+                    v.get("message")
+                        .and_then(|m| m.as_str())
+                        .map(str::to_string)
+                })
+                .unwrap_or_default();
+            let kind = message.header(":exception-type").unwrap_or("exception");
+            // Throttling and unavailability get the status the API would
+            // have returned up front, so backoff retries them.
+            let status = match kind {
+                "throttlingException" => 429,
+                "serviceUnavailableException" => 503,
+                _ => return Err(PipelineError::Other(format!("Bedrock {}: {}", kind, detail))),
+            };
+            return Err(PipelineError::HttpError {
+                status,
+                body: String::from_utf8_lossy(&message.payload).into_owned(),
+                message: Some(format!("{}: {}", kind, detail)),
+                retry_after: None,
+            });
+        }
+        if message.header(":event-type") != Some("chunk") {
+            return Ok(None);
+        }
+
+        let chunk: Value = serde_json::from_slice(&message.payload)?;
+        let Some(encoded) = chunk.get("bytes").and_then(|b| b.as_str()) else {
+            return Ok(None);
+        };
+        let decoded = base64::engine::general_purpose::STANDARD
+            .decode(encoded)
+            .map_err(|e| PipelineError::Other(format!("Invalid Bedrock chunk: {}", e)))?;
+        let event: Value = serde_json::from_slice(&decoded)?;
+        if let Some(latency) = event.pointer("/amazon-bedrock-invocationMetrics/invocationLatency")
+        {
+            meta.insert(INVOCATION_LATENCY_KEY.into(), latency.clone());
+        }
+        Ok(AnthropicBackend::apply_event(&event, meta)?.map(str::to_string))
+    }
+
+    /// Record the `x-amzn-bedrock-invocation-latency` header, if present.
+    fn record_latency(headers: &HeaderMap, meta: &mut serde_json::Map<String, Value>) {
+        if let Some(ms) = headers
+            .get("x-amzn-bedrock-invocation-latency")
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.trim().parse::<u64>().ok())
+        {
+            meta.insert(INVOCATION_LATENCY_KEY.into(), ms.into());
+        }
+    }
+
+    /// Turn a non-success response into [`PipelineError::HttpError`].
+    async fn http_error(resp: reqwest::Response) -> PipelineError {
+        let status = resp.status().as_u16();
+        let retry_after = super::retry_after(resp.headers());
+        let text = resp.text().await.unwrap_or_default();
+        PipelineError::HttpError {
+            status,
+            message: super::error_message(&text),
+            body: text,
+            retry_after,
+        }
+    }
+}
+
+#[async_trait]
+impl Backend for BedrockBackend {
+    async fn complete(
+        &self,
+        client: &Client,
+        base_url: &str,
+        request: &LlmRequest,
+    ) -> Result<LlmResponse> {
+        super::reject_tools(request, "Bedrock")?;
+        let url = Self::invoke_url(base_url, &request.model, "invoke");
+        let body = Self::build_body(request);
+
+        let resp = self
+            .build_http_request(client, &url, &body, "application/json")?
+            .send()
+            .await
+            .map_err(PipelineError::Request)?;
+
+        let status = resp.status().as_u16();
+        if !request.accepts_status(resp.status()) {
+            return Err(Self::http_error(resp).await);
+        }
+
+        let mut meta = serde_json::Map::new();
+        Self::record_latency(resp.headers(), &mut meta);
+        let json_resp: Value = resp.json().await?;
+        AnthropicBackend::extract_metadata(&json_resp, &mut meta);
+
+        let metadata = (!meta.is_empty()).then_some(Value::Object(meta));
+        Ok(LlmResponse {
+            text: AnthropicBackend::extract_text(&json_resp),
+            status,
+            usage: metadata.as_ref().and_then(Usage::from_metadata),
+            metadata,
+            candidates: Vec::new(),
+            refusal: None,
+            tool_calls: Vec::new(),
+        })
+    }
+
+    async fn complete_streaming(
+        &self,
+        client: &Client,
+        base_url: &str,
+        request: &LlmRequest,
+        on_token: &mut (dyn FnMut(String) + Send),
+    ) -> Result<LlmResponse> {
+        super::reject_tools(request, "Bedrock")?;
+        let url = Self::invoke_url(base_url, &request.model, "invoke-with-response-stream");
+        let body = Self::build_body(request);
+
+        let resp = self
+            .build_http_request(client, &url, &body, "application/vnd.amazon.eventstream")?
+            .send()
+            .await
+            .map_err(PipelineError::Request)?;
+
+        let status = resp.status().as_u16();
+        if !request.accepts_status(resp.status()) {
+            return Err(Self::http_error(resp).await);
+        }
+
+        let mut meta = serde_json::Map::new();
+        Self::record_latency(resp.headers(), &mut meta);
+        let mut stream = resp.bytes_stream();
+        let mut decoder = EventStreamDecoder::new();
+        let mut accumulated = String::new();
+        let mut limit = StreamLimit::new(request.max_stream_tokens);
+
+        'stream: while let Some(chunk) = stream.next().await {
+            let chunk = chunk.map_err(PipelineError::Request)?;
+            for message in decoder.decode(&chunk)? {
+                if let Some(text) = Self::apply_frame(&message, &mut meta)? {
+                    if !text.is_empty() {
+                        accumulated.push_str(&text);
+                        on_token(text);
+                        if limit.record() {
+                            // Dropping the stream closes the connection.
+                            break 'stream;
+                        }
+                    }
+                }
+            }
+        }
+
+        let metadata = limit.apply((!meta.is_empty()).then_some(Value::Object(meta)));
+        Ok(LlmResponse {
+            text: accumulated,
+            status,
+            usage: metadata.as_ref().and_then(Usage::from_metadata),
+            metadata,
+            candidates: Vec::new(),
+            refusal: None,
+            tool_calls: Vec::new(),
+        })
+    }
+
+    fn name(&self) -> &'static str {
+        "bedrock"
+    }
+}
+
+/// Percent-encode everything but RFC 3986 unreserved characters.
+fn uri_encode(s: &str) -> String {
+    s.bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+/// The SigV4 canonical URI: every path segment encoded again, so an
+/// already-encoded `%3A` in a model ID is signed as `%253A`.
+fn canonical_uri(path: &str) -> String {
+    path.split('/')
+        .map(uri_encode)
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn sha256_hex(bytes: &[u8]) -> String {
+    hex(ring::digest::digest(&ring::digest::SHA256, bytes).as_ref())
+}
+
+fn hmac_sha256(key: &[u8], msg: &str) -> Vec<u8> {
+    let key = ring::hmac::Key::new(ring::hmac::HMAC_SHA256, key);
+    ring::hmac::sign(&key, msg.as_bytes()).as_ref().to_vec()
+}
+
+/// The SigV4 signature of `canonical_request` (hex-encoded).
+fn signature(
+    secret: &str,
+    amz_date: &str,
+    region: &str,
+    service: &str,
+    canonical_request: &str,
+) -> String {
+    let date = &amz_date[..8];
+    let scope = format!("{}/{}/{}/aws4_request", date, region, service);
+    let string_to_sign = format!(
+        "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+        amz_date,
+        scope,
+        sha256_hex(canonical_request.as_bytes())
+    );
+    let key = [date, region, service, "aws4_request"]
+        .iter()
+        .fold(format!("AWS4{}", secret).into_bytes(), |key, part| {
+            hmac_sha256(&key, part)
+        });
+    hex(&hmac_sha256(&key, &string_to_sign))
+}
+
+fn now_secs() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// Format Unix seconds as a SigV4 timestamp, `YYYYMMDD'T'HHMMSS'Z'`.
+fn amz_date(secs: u64) -> String {
+    // Civil-from-days conversion (proleptic Gregorian, UTC).
+    let days = (secs / 86_400) as i64 + 719_468;
+    let era = days.div_euclid(146_097);
+    let doe = days - era * 146_097;
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    let rem = secs % 86_400;
+    format!(
+        "{:04}{:02}{:02}T{:02}{:02}{:02}Z",
+        year,
+        month,
+        day,
+        rem / 3_600,
+        rem % 3_600 / 60,
+        rem % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::event_stream::encode_frame;
+    use serde_json::json;
+
+    fn test_backend() -> BedrockBackend {
+        BedrockBackend::new(
+            "us-east-1",
+            AwsCredentials::new("AKIDEXAMPLE", "wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY"),
+        )
+    }
+
+    #[test]
+    fn test_sigv4_signature_matches_aws_example() {
+        // The IAM ListUsers example from the AWS SigV4 documentation.
+        let canonical_request = "GET\n/\nAction=ListUsers&Version=2010-05-08\n\
+            content-type:application/x-www-form-urlencoded; charset=utf-8\n\
+            host:iam.amazonaws.com\nx-amz-date:20150830T123600Z\n\n\
+            content-type;host;x-amz-date\n\
+            e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855";
+        assert_eq!(
+            signature(
+                "wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY",
+                "20150830T123600Z",
+                "us-east-1",
+                "iam",
+                canonical_request
+            ),
+            "5d672d79c15b13162d9279b0855cfba6789a8edb4c82c400e06b5924a6f2b5d7"
+        );
+        assert_eq!(amz_date(1_440_938_160), "20150830T123600Z");
+        assert_eq!(amz_date(1_709_251_199), "20240229T235959Z");
+    }
+
+    #[test]
+    fn test_bedrock_signed_headers() {
+        let backend = BedrockBackend::new(
+            "us-west-2",
+            AwsCredentials::new("AKIDEXAMPLE", "s3cr3t-value").with_session_token("token"),
+        );
+        let url = BedrockBackend::invoke_url(
+            &backend.endpoint(),
+            "anthropic.claude-3-haiku-20240307-v1:0",
+            "invoke",
+        );
+        assert_eq!(
+            url,
+            "https://bedrock-runtime.us-west-2.amazonaws.com/model/anthropic.claude-3-haiku-20240307-v1%3A0/invoke"
+        );
+        assert_eq!(
+            canonical_uri(Url::parse(&url).unwrap().path()),
+            "/model/anthropic.claude-3-haiku-20240307-v1%253A0/invoke"
+        );
+
+        let headers = backend.sign(&Url::parse(&url).unwrap(), b"{}", "20240301T000000Z");
+        let names: Vec<_> = headers.iter().map(|(name, _)| *name).collect();
+        assert_eq!(
+            names,
+            ["x-amz-date", "x-amz-security-token", "authorization"]
+        );
+        let auth = &headers[2].1;
+        assert!(auth.starts_with(
+            "AWS4-HMAC-SHA256 Credential=AKIDEXAMPLE/20240301/us-west-2/bedrock/aws4_request, \
+             SignedHeaders=host;x-amz-date;x-amz-security-token, Signature="
+        ));
+
+        let debug_output = format!("{:?}", backend);
+        assert!(!debug_output.contains("s3cr3t"));
+        assert!(!debug_output.contains("\"token\""));
+    }
+
+    #[test]
+    fn test_bedrock_body_shape() {
+        let mut request = LlmRequest::new("anthropic.claude-3-haiku-20240307-v1:0", "Hi");
+        request.system_prompt = Some("Be brief.".into());
+        request.stream = true;
+        let body = BedrockBackend::build_body(&request);
+        assert_eq!(body["anthropic_version"], BEDROCK_ANTHROPIC_VERSION);
+        assert_eq!(body["system"], "Be brief.");
+        assert_eq!(body["messages"], json!([{"role": "user", "content": "Hi"}]));
+        assert!(body.get("model").is_none());
+        assert!(body.get("stream").is_none());
+        assert_eq!(test_backend().name(), "bedrock");
+    }
+
+    #[test]
+    fn test_bedrock_stream_frames() {
+        let chunk = |event: Value| {
+            let encoded = base64::engine::general_purpose::STANDARD.encode(event.to_string());
+            encode_frame(
+                &[(":event-type", "chunk"), (":message-type", "event")],
+                json!({"bytes": encoded}).to_string().as_bytes(),
+            )
+        };
+        let mut bytes = chunk(json!({
+            "type": "content_block_delta",
+            "index": 0,
+            "delta": {"type": "text_delta", "text": "Hello"},
+        }));
+        bytes.extend(chunk(json!({
+            "type": "message_stop",
+            "amazon-bedrock-invocationMetrics": {"invocationLatency": 321},
+        })));
+
+        let mut meta = serde_json::Map::new();
+        let texts: Vec<_> = EventStreamDecoder::new()
+            .decode(&bytes)
+            .unwrap()
+            .iter()
+            .filter_map(|m| BedrockBackend::apply_frame(m, &mut meta).unwrap())
+            .collect();
+        assert_eq!(texts, ["Hello"]);
+        assert_eq!(meta[INVOCATION_LATENCY_KEY], 321);
+
+        let exception = encode_frame(
+            &[
+                (":message-type", "exception"),
+                (":exception-type", "throttlingException"),
+            ],
+            b"{\"message\":\"Too many requests\"}",
+        );
+        let message = &EventStreamDecoder::new().decode(&exception).unwrap()[0];
+        let err = BedrockBackend::apply_frame(message, &mut meta).unwrap_err();
+        assert!(err.to_string().contains("throttlingException"));
+        assert!(err.to_string().contains("Too many requests"));
+        assert!(matches!(err, PipelineError::HttpError { status: 429, .. }));
+        assert!(crate::backend::is_retryable(
+            &err,
+            &crate::backend::BackoffConfig::standard()
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_bedrock_connect_error_is_retryable() {
+        let request = LlmRequest::new("anthropic.claude-3-haiku", "hi");
+        crate::backend::tests::assert_connect_error_retryable(&test_backend(), &request).await;
+    }
+}
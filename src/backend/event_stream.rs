@@ -0,0 +1,222 @@
+//! Decoder for the AWS event-stream binary framing.
+//!
+//! Bedrock's `invoke-with-response-stream` endpoint answers with
+//! `application/vnd.amazon.eventstream` frames rather than SSE. Each frame
+//! is laid out as:
+//!
+//! ```text
+//! total_len: u32 | headers_len: u32 | prelude_crc: u32 | headers | payload | message_crc: u32
+//! ```
+//!
+//! Lengths are big-endian and both CRCs are CRC-32 (IEEE). Frames can be
+//! split across TCP chunks; [`EventStreamDecoder`] buffers until a whole
+//! frame has arrived.
+
+use crate::error::Result;
+use crate::PipelineError;
+
+/// Size of the fixed prelude (`total_len`, `headers_len`, `prelude_crc`).
+const PRELUDE_LEN: usize = 12;
+/// Size of the trailing message CRC.
+const CRC_LEN: usize = 4;
+
+/// One decoded event-stream frame.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EventStreamMessage {
+    /// String-valued headers such as `:event-type` and `:message-type`.
+    /// Headers of other types are skipped.
+    pub headers: Vec<(String, String)>,
+    /// The raw frame payload.
+    pub payload: Vec<u8>,
+}
+
+impl EventStreamMessage {
+    /// The value of the string header `name`, if present.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(k, _)| k == name)
+            .map(|(_, v)| v.as_str())
+    }
+}
+
+/// Incremental decoder for AWS event-stream frames.
+///
+/// # Example
+///
+/// ```
+/// use llm_pipeline::backend::event_stream::EventStreamDecoder;
+///
+/// let mut decoder = EventStreamDecoder::new();
+/// assert!(decoder.decode(&[0, 0, 0]).unwrap().is_empty());
+/// ```
+#[derive(Debug, Default)]
+pub struct EventStreamDecoder {
+    buffer: Vec<u8>,
+}
+
+impl EventStreamDecoder {
+    /// Create a new empty decoder.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Feed raw bytes into the decoder and return every complete frame.
+    ///
+    /// Returns an error if a frame is malformed or fails its CRC check;
+    /// the stream cannot be resynchronized after that.
+    pub fn decode(&mut self, chunk: &[u8]) -> Result<Vec<EventStreamMessage>> {
+        self.buffer.extend_from_slice(chunk);
+
+        let mut messages = Vec::new();
+        while self.buffer.len() >= PRELUDE_LEN {
+            let total_len = read_u32(&self.buffer[0..4]) as usize;
+            let headers_len = read_u32(&self.buffer[4..8]) as usize;
+            if read_u32(&self.buffer[8..12]) != crc32(&self.buffer[..8]) {
+                return Err(malformed("prelude CRC mismatch"));
+            }
+            if total_len < PRELUDE_LEN + headers_len + CRC_LEN {
+                return Err(malformed("frame shorter than its headers"));
+            }
+            if self.buffer.len() < total_len {
+                break;
+            }
+
+            let frame: Vec<u8> = self.buffer.drain(..total_len).collect();
+            let body_end = total_len - CRC_LEN;
+            if read_u32(&frame[body_end..]) != crc32(&frame[..body_end]) {
+                return Err(malformed("message CRC mismatch"));
+            }
+            let headers_end = PRELUDE_LEN + headers_len;
+            messages.push(EventStreamMessage {
+                headers: parse_headers(&frame[PRELUDE_LEN..headers_end])?,
+                payload: frame[headers_end..body_end].to_vec(),
+            });
+        }
+        Ok(messages)
+    }
+}
+
+fn malformed(reason: &str) -> PipelineError {
+    PipelineError::Other(format!("Malformed event-stream frame: {}", reason))
+}
+
+fn read_u32(bytes: &[u8]) -> u32 {
+    u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+}
+
+/// Split the first `n` bytes off `bytes`.
+fn take<'a>(bytes: &mut &'a [u8], n: usize) -> Result<&'a [u8]> {
+    if bytes.len() < n {
+        return Err(malformed("truncated header"));
+    }
+    let (head, rest) = bytes.split_at(n);
+    *bytes = rest;
+    Ok(head)
+}
+
+/// Parse the header block, keeping string-valued headers only.
+fn parse_headers(mut bytes: &[u8]) -> Result<Vec<(String, String)>> {
+    let mut headers = Vec::new();
+    while !bytes.is_empty() {
+        let name_len = take(&mut bytes, 1)?[0] as usize;
+        let name = String::from_utf8_lossy(take(&mut bytes, name_len)?).into_owned();
+        let value_len = match take(&mut bytes, 1)?[0] {
+            // bool true / bool false carry no value.
+            0 | 1 => 0,
+            2 => 1,
+            3 => 2,
+            4 => 4,
+            5 | 8 => 8,
+            9 => 16,
+            // Byte arrays and strings are prefixed with a u16 length.
+            kind @ (6 | 7) => {
+                let len = take(&mut bytes, 2)?;
+                let len = u16::from_be_bytes([len[0], len[1]]) as usize;
+                let value = take(&mut bytes, len)?;
+                if kind == 7 {
+                    headers.push((name, String::from_utf8_lossy(value).into_owned()));
+                }
+                continue;
+            }
+            other => return Err(malformed(&format!("unknown header type {}", other))),
+        };
+        take(&mut bytes, value_len)?;
+    }
+    Ok(headers)
+}
+
+/// CRC-32 (IEEE 802.3), as used by the event-stream framing.
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in bytes {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+/// Encode a frame with string headers. Used by tests.
+#[cfg(test)]
+pub(crate) fn encode_frame(headers: &[(&str, &str)], payload: &[u8]) -> Vec<u8> {
+    let mut header_bytes = Vec::new();
+    for (name, value) in headers {
+        header_bytes.push(name.len() as u8);
+        header_bytes.extend_from_slice(name.as_bytes());
+        header_bytes.push(7);
+        header_bytes.extend_from_slice(&(value.len() as u16).to_be_bytes());
+        header_bytes.extend_from_slice(value.as_bytes());
+    }
+    let total_len = PRELUDE_LEN + header_bytes.len() + payload.len() + CRC_LEN;
+    let mut frame = Vec::with_capacity(total_len);
+    frame.extend_from_slice(&(total_len as u32).to_be_bytes());
+    frame.extend_from_slice(&(header_bytes.len() as u32).to_be_bytes());
+    frame.extend_from_slice(&crc32(&frame).to_be_bytes());
+    frame.extend_from_slice(&header_bytes);
+    frame.extend_from_slice(payload);
+    frame.extend_from_slice(&crc32(&frame).to_be_bytes());
+    frame
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_crc32_check_value() {
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+    }
+
+    #[test]
+    fn test_decode_split_frames() {
+        let mut bytes = encode_frame(
+            &[(":event-type", "chunk"), (":message-type", "event")],
+            b"{\"bytes\":\"aGk=\"}",
+        );
+        bytes.extend(encode_frame(&[(":event-type", "chunk")], b"second"));
+
+        let mut decoder = EventStreamDecoder::new();
+        let (head, tail) = bytes.split_at(20);
+        assert!(decoder.decode(head).unwrap().is_empty());
+        let messages = decoder.decode(tail).unwrap();
+        assert_eq!(messages.len(), 2);
+        assert_eq!(messages[0].header(":event-type"), Some("chunk"));
+        assert_eq!(messages[0].header(":message-type"), Some("event"));
+        assert_eq!(messages[0].payload, b"{\"bytes\":\"aGk=\"}");
+        assert_eq!(messages[1].payload, b"second");
+    }
+
+    #[test]
+    fn test_decode_rejects_corrupt_frame() {
+        let mut bytes = encode_frame(&[(":event-type", "chunk")], b"payload");
+        let last = bytes.len() - 5;
+        bytes[last] ^= 0xFF;
+        assert!(EventStreamDecoder::new().decode(&bytes).is_err());
+    }
+}
@@ -0,0 +1,600 @@
+//! Backend for the Google Gemini API.
+//!
+//! [`GeminiBackend`] calls Gemini's native `generateContent` API, so no
+//! OpenAI-compatible proxy is needed.
+//!
+//! Endpoint: `/v1beta/models/{model}:generateContent` and
+//! `:streamGenerateContent?alt=sse`.
+//! Streaming: SSE where each `data:` line is a partial response,
+//! `{"candidates": [{"content": {"parts": [{"text": "token"}]}}]}`.
+
+use super::rate_limit::RateLimitInfo;
+use super::sse::SseDecoder;
+use super::{Backend, LlmRequest, LlmResponse, Role, StreamLimit, Usage};
+use crate::error::Result;
+use crate::PipelineError;
+use async_trait::async_trait;
+use futures::StreamExt;
+use reqwest::Client;
+use serde_json::{json, Value};
+
+/// Backend for the Google Gemini API.
+///
+/// Point the [`ExecCtx`](crate::ExecCtx) at
+/// `https://generativelanguage.googleapis.com`.
+///
+/// Tool calling isn't supported: a request with
+/// [`tools`](super::LlmRequest::tools) fails with
+/// [`PipelineError::InvalidConfig`].
+///
+/// # Example
+///
+/// ```
+/// use llm_pipeline::backend::GeminiBackend;
+///
+/// let backend = GeminiBackend::new().with_api_key("AIza...");
+/// ```
+#[derive(Clone)]
+pub struct GeminiBackend {
+    /// Optional API key. If set, sent as `x-goog-api-key: {key}`.
+    pub(crate) api_key: Option<String>,
+}
+
+impl std::fmt::Debug for GeminiBackend {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("GeminiBackend")
+            .field(
+                "api_key",
+                &self.api_key.as_ref().map(|k| {
+                    if k.len() > 6 {
+                        format!("{}***", &k[..6])
+                    } else {
+                        "***".to_string()
+                    }
+                }),
+            )
+            .finish()
+    }
+}
+
+impl GeminiBackend {
+    /// Create a new Gemini backend without an API key.
+    pub fn new() -> Self {
+        Self { api_key: None }
+    }
+
+    /// Set the API key for authentication.
+    pub fn with_api_key(mut self, key: impl Into<String>) -> Self {
+        self.api_key = Some(key.into());
+        self
+    }
+
+    /// Returns `true` if an API key has been configured.
+    pub fn has_api_key(&self) -> bool {
+        self.api_key.is_some()
+    }
+
+    /// The endpoint URL for `model` and `method` (`generateContent` or
+    /// `streamGenerateContent`). Accepts model names with or without the
+    /// `models/` prefix.
+    fn endpoint(base_url: &str, model: &str, method: &str) -> String {
+        let model = model.strip_prefix("models/").unwrap_or(model);
+        format!(
+            "{}/v1beta/models/{}:{}",
+            base_url.trim_end_matches('/'),
+            model,
+            method
+        )
+    }
+
+    /// Build `systemInstruction` from the system prompt and any
+    /// system-role history messages.
+    fn build_system_instruction(request: &LlmRequest) -> Option<Value> {
+        let mut parts: Vec<&str> = if !request.system_parts.is_empty() {
+            request.system_parts.iter().map(String::as_str).collect()
+        } else {
+            request
+                .system_prompt
+                .as_deref()
+                .filter(|s| !s.is_empty())
+                .into_iter()
+                .collect()
+        };
+        parts.extend(
+            request
+                .messages
+                .iter()
+                .filter(|m| m.role == Role::System)
+                .map(|m| m.content.as_str()),
+        );
+
+        if parts.is_empty() {
+            return None;
+        }
+        let parts: Vec<Value> = parts.iter().map(|p| json!({"text": p})).collect();
+        Some(json!({"parts": parts}))
+    }
+
+    /// Build the `contents` array of `user`/`model` turns.
+    fn build_contents(request: &LlmRequest) -> Vec<Value> {
+        if request.messages.is_empty() {
+            return vec![json!({"role": "user", "parts": [{"text": request.prompt}]})];
+        }
+        request
+            .messages
+            .iter()
+            .filter_map(|msg| {
+                let role = match msg.role {
+                    Role::System => return None,
+                    Role::User => "user",
+                    Role::Assistant => "model",
+                };
+                Some(json!({"role": role, "parts": [{"text": msg.content}]}))
+            })
+            .collect()
+    }
+
+    /// Build the request body for `generateContent`.
+    fn build_body(request: &LlmRequest, stream: bool) -> Value {
+        let mut generation_config = json!({
+            "temperature": request.config.temperature,
+            "maxOutputTokens": request.config.max_tokens,
+        });
+        if request.config.json_mode {
+            generation_config["responseMimeType"] = json!("application/json");
+        }
+        // The streaming loop reads `candidates[0]` only.
+        if request.config.n > 1 && !stream {
+            generation_config["candidateCount"] = json!(request.config.n);
+        }
+        if !request.config.stop.is_empty() {
+            generation_config["stopSequences"] = json!(request.config.stop);
+        }
+        if let Some(top_p) = request.config.top_p {
+            generation_config["topP"] = json!(top_p);
+        }
+        if let Some(top_k) = request.config.top_k {
+            generation_config["topK"] = json!(top_k);
+        }
+        if let Some(seed) = request.config.seed {
+            generation_config["seed"] = json!(seed);
+        }
+        if let Some(penalty) = request.config.presence_penalty {
+            generation_config["presencePenalty"] = json!(penalty);
+        }
+        if let Some(penalty) = request.config.frequency_penalty {
+            generation_config["frequencyPenalty"] = json!(penalty);
+        }
+
+        let mut body = json!({
+            "contents": Self::build_contents(request),
+            "generationConfig": generation_config,
+        });
+        if let Some(system) = Self::build_system_instruction(request) {
+            body["systemInstruction"] = system;
+        }
+
+        // `thinking`, `cache_system`, and Ollama-style options are skipped;
+        // use `extra_body` for fields such as `safetySettings`.
+        request.config.apply_extra_body(&mut body);
+
+        body
+    }
+
+    /// Build the reqwest request with the API key header.
+    fn build_http_request(
+        &self,
+        client: &Client,
+        url: &str,
+        body: &Value,
+    ) -> reqwest::RequestBuilder {
+        let mut req = client.post(url).json(body);
+
+        if let Some(ref key) = self.api_key {
+            req = req.header("x-goog-api-key", key.as_str());
+        }
+
+        req
+    }
+
+    /// Concatenate the text parts of a candidate, skipping thought
+    /// summaries.
+    fn candidate_text(candidate: &Value) -> String {
+        candidate
+            .pointer("/content/parts")
+            .and_then(|p| p.as_array())
+            .map(|parts| {
+                parts
+                    .iter()
+                    .filter(|p| !p.get("thought").and_then(|t| t.as_bool()).unwrap_or(false))
+                    .filter_map(|p| p.get("text").and_then(|t| t.as_str()))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// The text of every candidate, in order.
+    fn extract_candidates(json_resp: &Value) -> Vec<String> {
+        json_resp
+            .get("candidates")
+            .and_then(|c| c.as_array())
+            .map(|candidates| candidates.iter().map(Self::candidate_text).collect())
+            .unwrap_or_default()
+    }
+
+    /// Copy usage, model version, and finish reason from a response or
+    /// streamed chunk into `meta`.
+    ///
+    /// `usageMetadata` is kept as `usage_metadata` and also normalized into
+    /// an OpenAI-style `usage` object, and `finishReason` is kept as
+    /// `gemini_finish_reason` and mapped onto `finish_reason`, so token
+    /// accounting, truncation, and refusal handling work as for other
+    /// backends.
+    fn extract_metadata(json_resp: &Value, meta: &mut serde_json::Map<String, Value>) {
+        if let Some(usage) = json_resp.get("usageMetadata") {
+            let mut normalized = serde_json::Map::new();
+            for (from, to) in [
+                ("promptTokenCount", "prompt_tokens"),
+                ("candidatesTokenCount", "completion_tokens"),
+                ("totalTokenCount", "total_tokens"),
+            ] {
+                if let Some(v) = usage.get(from) {
+                    normalized.insert(to.into(), v.clone());
+                }
+            }
+            meta.insert("usage".into(), Value::Object(normalized));
+            meta.insert("usage_metadata".into(), usage.clone());
+        }
+        if let Some(v) = json_resp.get("modelVersion") {
+            meta.insert("model".into(), v.clone());
+        }
+        if let Some(v) = json_resp.get("responseId") {
+            meta.insert("id".into(), v.clone());
+        }
+        let finish = json_resp
+            .pointer("/candidates/0/finishReason")
+            .and_then(|v| v.as_str())
+            .map(|reason| (reason, reason))
+            .or_else(|| {
+                // A blocked prompt has no candidates, only `promptFeedback`.
+                json_resp
+                    .pointer("/promptFeedback/blockReason")
+                    .and_then(|v| v.as_str())
+                    .map(|reason| (reason, "SAFETY"))
+            });
+        if let Some((reason, kind)) = finish {
+            let finish_reason = match kind {
+                "STOP" => "stop",
+                "MAX_TOKENS" => "length",
+                "SAFETY" | "RECITATION" | "BLOCKLIST" | "PROHIBITED_CONTENT" | "SPII" => {
+                    "content_filter"
+                }
+                _ => reason,
+            };
+            meta.insert("gemini_finish_reason".into(), reason.into());
+            meta.insert("finish_reason".into(), finish_reason.into());
+        }
+    }
+}
+
+impl Default for GeminiBackend {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait]
+impl Backend for GeminiBackend {
+    async fn complete(
+        &self,
+        client: &Client,
+        base_url: &str,
+        request: &LlmRequest,
+    ) -> Result<LlmResponse> {
+        super::reject_tools(request, "Gemini")?;
+        let url = Self::endpoint(base_url, &request.model, "generateContent");
+        let body = Self::build_body(request, false);
+
+        let resp = self
+            .build_http_request(client, &url, &body)
+            .send()
+            .await
+            .map_err(PipelineError::Request)?;
+
+        let status = resp.status().as_u16();
+
+        if !request.accepts_status(resp.status()) {
+            let retry_after = super::retry_after(resp.headers());
+            let text = resp.text().await.unwrap_or_default();
+            return Err(PipelineError::HttpError {
+                status,
+                message: super::error_message(&text),
+                body: text,
+                retry_after,
+            });
+        }
+
+        let rate_limit = RateLimitInfo::from_headers(resp.headers());
+        let json_resp: Value = resp.json().await?;
+
+        let mut candidates = Self::extract_candidates(&json_resp);
+        let text = candidates.first().cloned().unwrap_or_default();
+        if candidates.len() < 2 {
+            candidates.clear();
+        }
+
+        let mut meta = serde_json::Map::new();
+        Self::extract_metadata(&json_resp, &mut meta);
+        let metadata = (!meta.is_empty()).then_some(Value::Object(meta));
+
+        let metadata = match rate_limit {
+            Some(info) => info.attach(metadata),
+            None => metadata,
+        };
+        Ok(LlmResponse {
+            text,
+            status,
+            usage: metadata.as_ref().and_then(Usage::from_metadata),
+            metadata,
+            candidates,
+            refusal: None,
+            tool_calls: Vec::new(),
+        })
+    }
+
+    async fn complete_streaming(
+        &self,
+        client: &Client,
+        base_url: &str,
+        request: &LlmRequest,
+        on_token: &mut (dyn FnMut(String) + Send),
+    ) -> Result<LlmResponse> {
+        super::reject_tools(request, "Gemini")?;
+        let url = Self::endpoint(base_url, &request.model, "streamGenerateContent");
+        let body = Self::build_body(request, true);
+
+        let resp = self
+            .build_http_request(client, &url, &body)
+            .query(&[("alt", "sse")])
+            .send()
+            .await
+            .map_err(PipelineError::Request)?;
+
+        let status = resp.status().as_u16();
+
+        if !request.accepts_status(resp.status()) {
+            let retry_after = super::retry_after(resp.headers());
+            let text = resp.text().await.unwrap_or_default();
+            return Err(PipelineError::HttpError {
+                status,
+                message: super::error_message(&text),
+                body: text,
+                retry_after,
+            });
+        }
+
+        let rate_limit = RateLimitInfo::from_headers(resp.headers());
+        let mut stream = resp.bytes_stream();
+        let mut decoder = SseDecoder::new();
+        let mut accumulated = String::new();
+        let mut limit = StreamLimit::new(request.max_stream_tokens);
+        let mut meta = serde_json::Map::new();
+
+        'stream: while let Some(chunk) = stream.next().await {
+            let chunk = chunk.map_err(PipelineError::Request)?;
+            for json_val in decoder.decode(&chunk) {
+                Self::extract_metadata(&json_val, &mut meta);
+                let content = json_val
+                    .pointer("/candidates/0")
+                    .map(Self::candidate_text)
+                    .unwrap_or_default();
+                if !content.is_empty() {
+                    accumulated.push_str(&content);
+                    on_token(content);
+                    if limit.record() {
+                        // Dropping the stream closes the connection.
+                        break 'stream;
+                    }
+                }
+            }
+        }
+
+        // Flush remaining SSE buffer
+        let flushed = if limit.reached() {
+            Vec::new()
+        } else {
+            decoder.flush()
+        };
+        for json_val in flushed {
+            Self::extract_metadata(&json_val, &mut meta);
+            let content = json_val
+                .pointer("/candidates/0")
+                .map(Self::candidate_text)
+                .unwrap_or_default();
+            if !content.is_empty() {
+                accumulated.push_str(&content);
+                on_token(content);
+            }
+        }
+
+        let metadata = (!meta.is_empty()).then_some(Value::Object(meta));
+        let metadata = limit.apply(match rate_limit {
+            Some(info) => info.attach(metadata),
+            None => metadata,
+        });
+        Ok(LlmResponse {
+            text: accumulated,
+            status,
+            usage: metadata.as_ref().and_then(Usage::from_metadata),
+            metadata,
+            candidates: Vec::new(),
+            refusal: None,
+            tool_calls: Vec::new(),
+        })
+    }
+
+    fn name(&self) -> &'static str {
+        "gemini"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::ChatMessage;
+
+    fn test_request() -> LlmRequest {
+        LlmRequest::new("gemini-2.0-flash", "Why is the sky blue?")
+    }
+
+    #[test]
+    fn test_gemini_body() {
+        let mut request = test_request();
+        request.system_prompt = Some("Be brief.".into());
+        request.config.json_mode = true;
+        request.config.stop = vec!["END".into()];
+        request.config.top_p = Some(0.5);
+        request.config.seed = Some(7);
+        request.config.frequency_penalty = Some(0.5);
+
+        let body = GeminiBackend::build_body(&request, false);
+        assert_eq!(
+            body["contents"],
+            json!([{"role": "user", "parts": [{"text": "Why is the sky blue?"}]}])
+        );
+        assert_eq!(
+            body["systemInstruction"],
+            json!({"parts": [{"text": "Be brief."}]})
+        );
+        let config = &body["generationConfig"];
+        assert_eq!(config["maxOutputTokens"], 2048);
+        assert_eq!(config["responseMimeType"], "application/json");
+        assert!(config.get("candidateCount").is_none());
+        assert_eq!(config["stopSequences"], json!(["END"]));
+        assert_eq!(config["topP"], 0.5);
+        assert_eq!(config["seed"], 7);
+        assert!(config.get("topK").is_none());
+        assert_eq!(config["frequencyPenalty"], 0.5);
+        assert!(config.get("presencePenalty").is_none());
+
+        let body = GeminiBackend::build_body(&test_request(), false);
+        assert!(body.get("systemInstruction").is_none());
+        assert!(body["generationConfig"].get("responseMimeType").is_none());
+        assert!(body["generationConfig"].get("stopSequences").is_none());
+    }
+
+    #[test]
+    fn test_gemini_body_history_roles() {
+        let mut request = test_request();
+        request.messages = vec![
+            ChatMessage {
+                role: Role::User,
+                content: "What is 2+2?".into(),
+            },
+            ChatMessage {
+                role: Role::Assistant,
+                content: "4".into(),
+            },
+            ChatMessage {
+                role: Role::User,
+                content: "And 3+3?".into(),
+            },
+        ];
+        let body = GeminiBackend::build_body(&request, true);
+        let roles: Vec<_> = body["contents"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|c| c["role"].as_str().unwrap())
+            .collect();
+        assert_eq!(roles, ["user", "model", "user"]);
+    }
+
+    #[test]
+    fn test_gemini_endpoint_and_key() {
+        assert_eq!(
+            GeminiBackend::endpoint(
+                "https://generativelanguage.googleapis.com/",
+                "models/gemini-2.0-flash",
+                "generateContent"
+            ),
+            "https://generativelanguage.googleapis.com/v1beta/models/gemini-2.0-flash:generateContent"
+        );
+
+        let client = Client::new();
+        let req = GeminiBackend::new()
+            .with_api_key("AIza-test")
+            .build_http_request(
+                &client,
+                "https://example.com/v1beta/models/m:generateContent",
+                &json!({}),
+            )
+            .build()
+            .expect("build request");
+        assert_eq!(req.headers()["x-goog-api-key"], "AIza-test");
+        assert_eq!(req.url().query(), None);
+
+        let debug_output = format!("{:?}", GeminiBackend::new().with_api_key("AIza-1234567890"));
+        assert!(!debug_output.contains("1234567890"));
+    }
+
+    #[test]
+    fn test_gemini_response_text_and_metadata() {
+        let resp = json!({
+            "candidates": [{
+                "content": {"role": "model", "parts": [
+                    {"text": "planning", "thought": true},
+                    {"text": "Rayleigh scattering."},
+                ]},
+                "finishReason": "MAX_TOKENS",
+            }],
+            "usageMetadata": {"promptTokenCount": 7, "candidatesTokenCount": 3, "totalTokenCount": 10},
+            "modelVersion": "gemini-2.0-flash-001",
+        });
+        assert_eq!(
+            GeminiBackend::extract_candidates(&resp),
+            vec!["Rayleigh scattering."]
+        );
+
+        let mut meta = serde_json::Map::new();
+        GeminiBackend::extract_metadata(&resp, &mut meta);
+        assert_eq!(meta["finish_reason"], "length");
+        assert_eq!(meta["gemini_finish_reason"], "MAX_TOKENS");
+        assert_eq!(meta["usage"]["completion_tokens"], 3);
+        assert_eq!(meta["model"], "gemini-2.0-flash-001");
+
+        let blocked = json!({"promptFeedback": {"blockReason": "OTHER"}});
+        let mut meta = serde_json::Map::new();
+        GeminiBackend::extract_metadata(&blocked, &mut meta);
+        assert_eq!(meta["finish_reason"], "content_filter");
+    }
+
+    #[tokio::test]
+    async fn test_gemini_connect_error_is_retryable() {
+        let backend = GeminiBackend::new().with_api_key("secret-key");
+        let err =
+            crate::backend::tests::assert_connect_error_retryable(&backend, &test_request()).await;
+        assert!(!err.to_string().contains("secret-key"));
+    }
+
+    #[tokio::test]
+    async fn test_gemini_stream_error_omits_api_key() {
+        let addr = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let err = GeminiBackend::new()
+            .with_api_key("secret-key")
+            .complete_streaming(
+                &Client::new(),
+                &format!("http://{}", addr),
+                &test_request(),
+                &mut |_| {},
+            )
+            .await
+            .unwrap_err();
+        let message = err.to_string();
+        assert!(message.contains("streamGenerateContent"), "{}", message);
+        assert!(!message.contains("secret-key"), "{}", message);
+    }
+}
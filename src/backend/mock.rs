@@ -12,21 +12,167 @@
 //! ```
 
 use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
 use async_trait::async_trait;
 use reqwest::Client;
 
-use super::{Backend, LlmRequest, LlmResponse};
+use super::{Backend, LlmRequest, LlmResponse, ToolCall, Usage};
 use crate::error::Result;
+use crate::PipelineError;
+
+/// One scripted reply of a [`MockBackend`].
+///
+/// A reply is streamed as its chunks, in order, and answers non-streaming
+/// calls with their concatenation.
+///
+/// # Example
+///
+/// ```
+/// use llm_pipeline::backend::{MockBackend, MockReply};
+/// use std::time::Duration;
+///
+/// let mock = MockBackend::scripted(vec![
+///     // Streams "Hel", then fails with a 503.
+///     MockReply::chunks(["Hel"]).then_fail(503),
+///     MockReply::chunks(["Hel", "lo"]).with_chunk_delay(Duration::from_millis(5)),
+/// ]);
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct MockReply {
+    chunks: Vec<String>,
+    refusal: Option<String>,
+    tool_calls: Vec<ToolCall>,
+    metadata: Option<serde_json::Value>,
+    delay: Duration,
+    chunk_delay: Duration,
+    fail_status: Option<u16>,
+}
+
+impl MockReply {
+    /// A reply with `text`, streamed as a single token.
+    pub fn text(text: impl Into<String>) -> Self {
+        Self {
+            chunks: vec![text.into()],
+            ..Default::default()
+        }
+    }
+
+    /// A reply streamed as `chunks`, one token each.
+    pub fn chunks<I, S>(chunks: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        Self {
+            chunks: chunks.into_iter().map(Into::into).collect(),
+            ..Default::default()
+        }
+    }
+
+    /// An empty reply carrying a [`refusal`](LlmResponse::refusal).
+    pub fn refusal(reason: impl Into<String>) -> Self {
+        Self {
+            refusal: Some(reason.into()),
+            ..Default::default()
+        }
+    }
+
+    /// Request `calls`, returned in [`tool_calls`](LlmResponse::tool_calls).
+    pub fn with_tool_calls(mut self, calls: Vec<ToolCall>) -> Self {
+        self.tool_calls = calls;
+        self
+    }
+
+    /// Attach provider metadata; [`usage`](LlmResponse::usage) is read from it.
+    pub fn with_metadata(mut self, metadata: serde_json::Value) -> Self {
+        self.metadata = Some(metadata);
+        self
+    }
+
+    /// Wait `delay` before answering.
+    pub fn with_delay(mut self, delay: Duration) -> Self {
+        self.delay = delay;
+        self
+    }
+
+    /// Wait `delay` before each streamed chunk.
+    pub fn with_chunk_delay(mut self, delay: Duration) -> Self {
+        self.chunk_delay = delay;
+        self
+    }
+
+    /// Fail with [`PipelineError::HttpError`] with `status` instead of
+    /// answering. Streamed chunks are still delivered first, as when a
+    /// connection drops mid-stream.
+    pub fn then_fail(mut self, status: u16) -> Self {
+        self.fail_status = Some(status);
+        self
+    }
+
+    fn text_concat(&self) -> String {
+        self.chunks.concat()
+    }
+
+    fn into_response(self) -> Result<LlmResponse> {
+        if let Some(status) = self.fail_status {
+            return Err(PipelineError::HttpError {
+                status,
+                body: format!("mock failure {}", status),
+                message: None,
+                retry_after: None,
+            });
+        }
+        Ok(LlmResponse {
+            text: self.text_concat(),
+            status: 200,
+            usage: self.metadata.as_ref().and_then(Usage::from_metadata),
+            metadata: self.metadata,
+            candidates: Vec::new(),
+            refusal: self.refusal,
+            tool_calls: self.tool_calls,
+        })
+    }
+}
+
+/// Computes a [`MockReply`] from the request; see [`MockBackend::from_fn`].
+type Responder = dyn Fn(&LlmRequest) -> Result<MockReply> + Send + Sync;
+
+/// Where a [`MockBackend`]'s replies come from.
+enum Replies {
+    Scripted(Vec<MockReply>),
+    Computed(Arc<Responder>),
+}
 
 /// A test backend that returns canned responses in order.
 ///
 /// Cycles back to the beginning when all responses have been consumed.
-/// For streaming, emits the entire response as a single token.
-#[derive(Debug)]
+/// For streaming, emits each response as its [`MockReply`] chunks (a
+/// single token for [`new`](Self::new) and [`fixed`](Self::fixed)). A
+/// non-streaming request with [`LlmConfig::n`](crate::LlmConfig::n) above 1
+/// consumes `n` responses and returns them as
+/// [`candidates`](super::LlmResponse::candidates). Every request received is
+/// kept and available from [`requests`](Self::requests).
+///
+/// [`embed`](Backend::embed) returns deterministic bag-of-words vectors, so
+/// texts sharing most of their words are close and identical texts have a
+/// cosine similarity of 1.
 pub struct MockBackend {
-    responses: Vec<String>,
+    replies: Replies,
     index: AtomicUsize,
+    requests: Mutex<Vec<LlmRequest>>,
+}
+
+impl std::fmt::Debug for MockBackend {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut s = f.debug_struct("MockBackend");
+        match &self.replies {
+            Replies::Scripted(replies) => s.field("replies", replies),
+            Replies::Computed(_) => s.field("replies", &"<fn>"),
+        };
+        s.field("index", &self.index).finish()
+    }
 }
 
 impl MockBackend {
@@ -34,11 +180,7 @@ impl MockBackend {
     ///
     /// Responses are returned in order. When exhausted, cycles from the beginning.
     pub fn new(responses: Vec<String>) -> Self {
-        assert!(!responses.is_empty(), "MockBackend requires at least one response");
-        Self {
-            responses,
-            index: AtomicUsize::new(0),
-        }
+        Self::scripted(responses.into_iter().map(MockReply::text).collect())
     }
 
     /// Create a mock that always returns the same response.
@@ -46,9 +188,48 @@ impl MockBackend {
         Self::new(vec![response.into()])
     }
 
-    fn next_response(&self) -> String {
-        let idx = self.index.fetch_add(1, Ordering::Relaxed) % self.responses.len();
-        self.responses[idx].clone()
+    /// Create a mock that plays `replies` in order, cycling when exhausted.
+    pub fn scripted(replies: Vec<MockReply>) -> Self {
+        assert!(
+            !replies.is_empty(),
+            "MockBackend requires at least one response"
+        );
+        Self::with_replies(Replies::Scripted(replies))
+    }
+
+    /// Create a mock that computes each reply from the request it receives.
+    /// An `Err` is returned from the call as-is.
+    pub fn from_fn(
+        responder: impl Fn(&LlmRequest) -> Result<MockReply> + Send + Sync + 'static,
+    ) -> Self {
+        Self::with_replies(Replies::Computed(Arc::new(responder)))
+    }
+
+    fn with_replies(replies: Replies) -> Self {
+        Self {
+            replies,
+            index: AtomicUsize::new(0),
+            requests: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// The requests received so far, in order.
+    pub fn requests(&self) -> Vec<LlmRequest> {
+        self.requests.lock().unwrap().clone()
+    }
+
+    fn next_reply(&self, request: &LlmRequest) -> Result<MockReply> {
+        match &self.replies {
+            Replies::Scripted(replies) => {
+                let idx = self.index.fetch_add(1, Ordering::Relaxed) % replies.len();
+                Ok(replies[idx].clone())
+            }
+            Replies::Computed(responder) => responder(request),
+        }
+    }
+
+    fn record(&self, request: &LlmRequest) {
+        self.requests.lock().unwrap().push(request.clone());
     }
 }
 
@@ -58,30 +239,48 @@ impl Backend for MockBackend {
         &self,
         _client: &Client,
         _base_url: &str,
-        _request: &LlmRequest,
+        request: &LlmRequest,
     ) -> Result<LlmResponse> {
-        let text = self.next_response();
-        Ok(LlmResponse {
-            text,
-            status: 200,
-            metadata: Default::default(),
-        })
+        self.record(request);
+        let reply = self.next_reply(request)?;
+        pause(reply.delay).await;
+        let mut candidates = Vec::new();
+        if request.config.n > 1 {
+            candidates.push(reply.text_concat());
+            for _ in 1..request.config.n {
+                candidates.push(self.next_reply(request)?.text_concat());
+            }
+        }
+        let mut response = reply.into_response()?;
+        response.candidates = candidates;
+        Ok(response)
     }
 
     async fn complete_streaming(
         &self,
         _client: &Client,
         _base_url: &str,
-        _request: &LlmRequest,
+        request: &LlmRequest,
         on_token: &mut (dyn FnMut(String) + Send),
     ) -> Result<LlmResponse> {
-        let text = self.next_response();
-        on_token(text.clone());
-        Ok(LlmResponse {
-            text,
-            status: 200,
-            metadata: Default::default(),
-        })
+        self.record(request);
+        let reply = self.next_reply(request)?;
+        pause(reply.delay).await;
+        for chunk in &reply.chunks {
+            pause(reply.chunk_delay).await;
+            on_token(chunk.clone());
+        }
+        reply.into_response()
+    }
+
+    async fn embed(
+        &self,
+        _client: &Client,
+        _base_url: &str,
+        _model: &str,
+        texts: &[String],
+    ) -> Result<Vec<Vec<f32>>> {
+        Ok(texts.iter().map(|t| bag_of_words(t)).collect())
     }
 
     fn name(&self) -> &'static str {
@@ -89,6 +288,27 @@ impl Backend for MockBackend {
     }
 }
 
+/// Sleep for `delay`, skipping the timer entirely when it is zero so
+/// runtimes without the time driver work.
+async fn pause(delay: Duration) {
+    if !delay.is_zero() {
+        tokio::time::sleep(delay).await;
+    }
+}
+
+/// Hash each lowercased word into one of 64 buckets.
+fn bag_of_words(text: &str) -> Vec<f32> {
+    use std::hash::{DefaultHasher, Hash, Hasher};
+
+    let mut v = vec![0.0; 64];
+    for word in text.split(|c: char| !c.is_alphanumeric()).filter(|w| !w.is_empty()) {
+        let mut h = DefaultHasher::new();
+        word.to_lowercase().hash(&mut h);
+        v[(h.finish() % 64) as usize] += 1.0;
+    }
+    v
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -97,14 +317,7 @@ mod tests {
     async fn test_mock_fixed_response() {
         let mock = MockBackend::fixed("Hello!");
         let client = Client::new();
-        let request = LlmRequest {
-            model: "test".to_string(),
-            system_prompt: None,
-            prompt: "test".to_string(),
-            messages: vec![],
-            config: Default::default(),
-            stream: false,
-        };
+        let request = LlmRequest::new("test", "test");
         let resp = mock.complete(&client, "http://unused", &request).await.unwrap();
         assert_eq!(resp.text, "Hello!");
         assert_eq!(resp.status, 200);
@@ -114,14 +327,7 @@ mod tests {
     async fn test_mock_cycles_responses() {
         let mock = MockBackend::new(vec!["first".into(), "second".into()]);
         let client = Client::new();
-        let request = LlmRequest {
-            model: "test".to_string(),
-            system_prompt: None,
-            prompt: "test".to_string(),
-            messages: vec![],
-            config: Default::default(),
-            stream: false,
-        };
+        let request = LlmRequest::new("test", "test");
         let r1 = mock.complete(&client, "http://unused", &request).await.unwrap();
         let r2 = mock.complete(&client, "http://unused", &request).await.unwrap();
         let r3 = mock.complete(&client, "http://unused", &request).await.unwrap();
@@ -134,14 +340,8 @@ mod tests {
     async fn test_mock_streaming() {
         let mock = MockBackend::fixed("streamed");
         let client = Client::new();
-        let request = LlmRequest {
-            model: "test".to_string(),
-            system_prompt: None,
-            prompt: "test".to_string(),
-            messages: vec![],
-            config: Default::default(),
-            stream: true,
-        };
+        let mut request = LlmRequest::new("test", "test");
+        request.stream = true;
         let mut tokens = Vec::new();
         let resp = mock.complete_streaming(
             &client,
@@ -152,4 +352,60 @@ mod tests {
         assert_eq!(resp.text, "streamed");
         assert_eq!(tokens, vec!["streamed"]);
     }
+
+    #[tokio::test]
+    async fn test_mock_n_candidates() {
+        let mock = MockBackend::new(vec!["a".into(), "b".into(), "c".into()]);
+        let client = Client::new();
+        let mut request = LlmRequest::new("test", "test");
+        request.config = crate::LlmConfig::default().with_n(3);
+        let resp = mock.complete(&client, "http://unused", &request).await.unwrap();
+        assert_eq!(resp.text, "a");
+        assert_eq!(resp.candidates, vec!["a", "b", "c"]);
+    }
+
+    #[tokio::test]
+    async fn test_mock_scripted_replies() {
+        let mock = MockBackend::scripted(vec![
+            MockReply::chunks(["Hel", "lo"]).then_fail(503),
+            MockReply::refusal("No.").with_metadata(serde_json::json!({"eval_count": 3})),
+        ]);
+        let client = Client::new();
+        let mut request = LlmRequest::new("test", "test");
+        request.stream = true;
+        let mut tokens = Vec::new();
+        let err = mock
+            .complete_streaming(&client, "http://unused", &request, &mut |t| tokens.push(t))
+            .await
+            .unwrap_err();
+        assert!(matches!(err, PipelineError::HttpError { status: 503, .. }));
+        assert_eq!(tokens, vec!["Hel", "lo"]);
+
+        let resp = mock
+            .complete(&client, "http://unused", &request)
+            .await
+            .unwrap();
+        assert_eq!(resp.refusal.as_deref(), Some("No."));
+        assert_eq!(resp.usage.unwrap().completion_tokens, Some(3));
+        assert_eq!(mock.requests().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_mock_from_fn() {
+        let mock = MockBackend::from_fn(|request| Ok(MockReply::text(request.model.clone())));
+        let mut request = LlmRequest::new("a", "test");
+        let client = Client::new();
+        let r1 = mock
+            .complete(&client, "http://unused", &request)
+            .await
+            .unwrap();
+        request.model = "b".to_string();
+        let r2 = mock
+            .complete(&client, "http://unused", &request)
+            .await
+            .unwrap();
+        assert_eq!((r1.text.as_str(), r2.text.as_str()), ("a", "b"));
+        let models: Vec<String> = mock.requests().into_iter().map(|r| r.model).collect();
+        assert_eq!(models, vec!["a", "b"]);
+    }
 }
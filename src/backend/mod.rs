@@ -2,7 +2,8 @@
 //!
 //! The [`Backend`] trait abstracts over LLM providers, translating between
 //! normalized [`LlmRequest`]/[`LlmResponse`] types and provider-specific
-//! HTTP APIs. Built-in implementations: [`OllamaBackend`], [`OpenAiBackend`].
+//! HTTP APIs. Built-in implementations: [`OllamaBackend`], [`OpenAiBackend`],
+//! [`AnthropicBackend`], [`GeminiBackend`], [`BedrockBackend`].
 //!
 //! ## Architecture
 //!
@@ -16,25 +17,45 @@
 //!                   NDJSON streaming
 //! ```
 
+#[cfg(feature = "anthropic")]
+pub mod anthropic;
 pub mod backoff;
+#[cfg(feature = "bedrock")]
+pub mod bedrock;
+#[cfg(feature = "bedrock")]
+pub mod event_stream;
+#[cfg(feature = "gemini")]
+pub mod gemini;
 pub mod mock;
 pub mod ollama;
 #[cfg(feature = "openai")]
 pub mod openai;
-#[cfg(feature = "openai")]
+pub mod rate_limit;
+pub mod replay;
+#[cfg(any(feature = "openai", feature = "anthropic", feature = "gemini"))]
 pub mod sse;
 
-pub use backoff::BackoffConfig;
-pub use mock::MockBackend;
+#[cfg(feature = "anthropic")]
+pub use anthropic::AnthropicBackend;
+pub use backoff::{BackoffConfig, BackoffConfigBuilder};
+#[cfg(feature = "bedrock")]
+pub use bedrock::{AwsCredentials, BedrockBackend};
+#[cfg(feature = "gemini")]
+pub use gemini::GeminiBackend;
+pub use mock::{MockBackend, MockReply};
 pub use ollama::OllamaBackend;
 #[cfg(feature = "openai")]
 pub use openai::OpenAiBackend;
+pub use rate_limit::{RateLimitInfo, RateLimitPacer};
+pub use replay::{RecordedCall, RecordedToken, RecordingBackend, ReplayBackend};
 
 use crate::client::LlmConfig;
 use crate::error::Result;
+use crate::streaming::StreamDedup;
 use crate::PipelineError;
 use async_trait::async_trait;
 use reqwest::Client;
+use std::borrow::Cow;
 use std::sync::Arc;
 
 /// Type alias for the callback invoked before each transport retry.
@@ -46,7 +67,10 @@ pub type RetryCallback<'a> = Option<&'a mut (dyn FnMut(u32, std::time::Duration,
 ///
 /// [`LlmCall`](crate::llm_call::LlmCall) builds this from its config.
 /// The [`Backend`] translates it into the provider-specific HTTP request.
-#[derive(Debug, Clone)]
+/// Fields may be added in minor releases; outside this crate, start from
+/// [`LlmRequest::new`] and assign the fields you need.
+#[derive(Debug, Clone, Default)]
+#[non_exhaustive]
 pub struct LlmRequest {
     /// Model identifier (e.g. `"llama3.2:3b"`, `"gpt-4o"`).
     pub model: String,
@@ -55,6 +79,13 @@ pub struct LlmRequest {
     /// If `None`, this is a generate-style call (prompt only).
     pub system_prompt: Option<String>,
 
+    /// The system prompt's fragments, when it was built from parts with
+    /// [`LlmCall::with_system_parts`](crate::LlmCall::with_system_parts).
+    /// `system_prompt` still holds the joined text: backends with structured
+    /// system content send each fragment as its own block, the rest ignore
+    /// this. Empty otherwise.
+    pub system_parts: Vec<String>,
+
     /// The user prompt text.
     pub prompt: String,
 
@@ -67,6 +98,56 @@ pub struct LlmRequest {
 
     /// Whether to use the streaming endpoint.
     pub stream: bool,
+
+    /// Client-side cap on streamed tokens. When reached, the backend stops
+    /// reading, drops the connection, and reports `finish_reason =
+    /// "client_limit"` in the response metadata. Ignored for non-streaming
+    /// calls. Set from [`ExecCtx`](crate::ExecCtx)'s `max_stream_tokens`.
+    pub max_stream_tokens: Option<usize>,
+
+    /// When a stream is resumed, skip the already-received text if the
+    /// server re-sends it, as some proxies do after a mid-stream reconnect.
+    /// Applied by [`with_backoff_streaming`]. Set from
+    /// [`ExecCtxBuilder::dedup_stream`](crate::ExecCtxBuilder::dedup_stream).
+    pub dedup_stream: bool,
+
+    /// Non-2xx HTTP status codes the backend should treat as success.
+    /// Filled in from [`BackoffConfig::accept_statuses`] by
+    /// [`with_backoff`] and [`with_backoff_streaming`].
+    pub accept_statuses: Vec<u16>,
+
+    /// Deadline for each attempt, independent of the HTTP client's timeout.
+    /// An attempt that runs longer fails with [`PipelineError::Timeout`],
+    /// which [`with_backoff`] and [`with_backoff_streaming`] retry. Set from
+    /// [`LlmCall::with_timeout`](crate::LlmCall::with_timeout).
+    pub timeout: Option<std::time::Duration>,
+
+    /// Functions the model may call instead of (or before) answering.
+    /// Calls it makes come back in [`LlmResponse::tool_calls`]. Sent by
+    /// [`OpenAiBackend`] and [`OllamaBackend`] (which then uses
+    /// `/api/chat`). The Anthropic, Gemini, and Bedrock backends don't
+    /// support tools yet and fail with
+    /// [`PipelineError::InvalidConfig`] when this is non-empty. Empty by
+    /// default.
+    pub tools: Vec<ToolSpec>,
+}
+
+impl LlmRequest {
+    /// A generate-style, non-streaming request for `prompt` to `model`, with
+    /// the default [`LlmConfig`] and every other field empty.
+    pub fn new(model: impl Into<String>, prompt: impl Into<String>) -> Self {
+        Self {
+            model: model.into(),
+            prompt: prompt.into(),
+            ..Default::default()
+        }
+    }
+
+    /// Whether a response with `status` should be parsed as a success:
+    /// any 2xx, or one of [`accept_statuses`](Self::accept_statuses).
+    pub fn accepts_status(&self, status: reqwest::StatusCode) -> bool {
+        status.is_success() || self.accept_statuses.contains(&status.as_u16())
+    }
 }
 
 /// A single message in a chat conversation.
@@ -78,6 +159,114 @@ pub struct ChatMessage {
     pub content: String,
 }
 
+/// A function offered to the model via [`LlmRequest::tools`].
+///
+/// # Example
+///
+/// ```
+/// use llm_pipeline::backend::ToolSpec;
+/// use serde_json::json;
+///
+/// let weather = ToolSpec::new(
+///     "get_weather",
+///     "Current weather for a city",
+///     json!({
+///         "type": "object",
+///         "properties": {"city": {"type": "string"}},
+///         "required": ["city"],
+///     }),
+/// );
+/// assert_eq!(weather.name, "get_weather");
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct ToolSpec {
+    /// The name the model calls the function by.
+    pub name: String,
+    /// What the function does, so the model knows when to call it.
+    pub description: String,
+    /// JSON Schema of the arguments object.
+    pub parameters: serde_json::Value,
+}
+
+impl ToolSpec {
+    /// Create a tool from its name, description, and JSON Schema parameters.
+    pub fn new(
+        name: impl Into<String>,
+        description: impl Into<String>,
+        parameters: serde_json::Value,
+    ) -> Self {
+        Self {
+            name: name.into(),
+            description: description.into(),
+            parameters,
+        }
+    }
+
+    /// The `{"type": "function", "function": {...}}` form used by OpenAI
+    /// and Ollama.
+    pub(crate) fn to_function_json(&self) -> serde_json::Value {
+        serde_json::json!({
+            "type": "function",
+            "function": {
+                "name": self.name,
+                "description": self.description,
+                "parameters": self.parameters,
+            },
+        })
+    }
+}
+
+/// A function call requested by the model, from
+/// [`LlmResponse::tool_calls`].
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct ToolCall {
+    /// Provider-assigned call ID (OpenAI), used to match results to calls.
+    /// `None` for providers without one (Ollama).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    /// The [`ToolSpec::name`] of the function to call.
+    pub name: String,
+    /// The arguments object. If the model produced arguments that are not
+    /// valid JSON, they are kept as a `Value::String`.
+    pub arguments: serde_json::Value,
+}
+
+impl ToolCall {
+    /// Read one entry of an OpenAI- or Ollama-style `tool_calls` array:
+    /// `{"id": ..., "function": {"name": ..., "arguments": ...}}`, where
+    /// `arguments` is a JSON-encoded string (OpenAI) or an object (Ollama).
+    pub(crate) fn from_function_json(call: &serde_json::Value) -> Option<Self> {
+        let function = call.get("function")?;
+        let arguments = match function.get("arguments") {
+            Some(serde_json::Value::String(raw)) => Self::parse_arguments(raw),
+            Some(other) => other.clone(),
+            None => serde_json::Value::Null,
+        };
+        Some(Self {
+            id: call.get("id").and_then(|v| v.as_str()).map(str::to_string),
+            name: function.get("name")?.as_str()?.to_string(),
+            arguments,
+        })
+    }
+
+    /// Every call in `message.tool_calls`, skipping malformed entries.
+    pub(crate) fn all_in_message(message: Option<&serde_json::Value>) -> Vec<Self> {
+        message
+            .and_then(|m| m.get("tool_calls"))
+            .and_then(|v| v.as_array())
+            .map(|calls| calls.iter().filter_map(Self::from_function_json).collect())
+            .unwrap_or_default()
+    }
+
+    /// Parse JSON-encoded arguments, keeping the raw string if invalid.
+    pub(crate) fn parse_arguments(raw: &str) -> serde_json::Value {
+        if raw.trim().is_empty() {
+            return serde_json::json!({});
+        }
+        serde_json::from_str(raw).unwrap_or_else(|_| serde_json::Value::String(raw.to_string()))
+    }
+}
+
 /// The role of a chat message author.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Role {
@@ -90,7 +279,11 @@ pub enum Role {
 }
 
 /// A normalized LLM response.
-#[derive(Debug)]
+///
+/// Fields may be added in minor releases; outside this crate, start from
+/// [`LlmResponse::new`] and assign the fields you need.
+#[derive(Debug, Default)]
+#[non_exhaustive]
 pub struct LlmResponse {
     /// The generated text content.
     pub text: String,
@@ -101,6 +294,183 @@ pub struct LlmResponse {
     /// Provider-specific metadata (token counts, timing, model info).
     /// Stored as raw JSON — each provider returns different fields.
     pub metadata: Option<serde_json::Value>,
+
+    /// Every completion returned when more than one was requested via
+    /// [`LlmConfig::n`](crate::LlmConfig::n), in provider order. `text` is
+    /// the first. Empty for single-completion responses.
+    pub candidates: Vec<String>,
+
+    /// The model's explanation when it declined to answer, as reported by
+    /// providers with structured refusals (OpenAI's `message.refusal`).
+    /// `text` is usually empty then. `None` otherwise.
+    pub refusal: Option<String>,
+
+    /// Token usage reported by the provider, normalized across backends.
+    /// `None` if the provider reported none. The raw fields stay in
+    /// `metadata`.
+    pub usage: Option<Usage>,
+
+    /// Functions the model asked to call, in order, when the request
+    /// offered [`tools`](LlmRequest::tools). `text` is often empty then.
+    /// Empty otherwise.
+    pub tool_calls: Vec<ToolCall>,
+}
+
+/// Token counts for one or more LLM calls.
+///
+/// Built-in backends fill [`LlmResponse::usage`] from their provider's
+/// fields; [`Usage::from_metadata`] does the same for any metadata shape
+/// they produce. Usages add up with `+`, fieldwise, a field staying `None`
+/// only if it is `None` on both sides.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Usage {
+    /// Tokens in the prompt.
+    pub prompt_tokens: Option<u64>,
+    /// Tokens generated.
+    pub completion_tokens: Option<u64>,
+    /// Prompt plus completion tokens.
+    pub total_tokens: Option<u64>,
+}
+
+impl Usage {
+    /// Read usage from provider metadata: Ollama's
+    /// `prompt_eval_count`/`eval_count`, or a `usage` object with OpenAI's
+    /// `prompt_tokens`/`completion_tokens` or Anthropic's
+    /// `input_tokens`/`output_tokens`. `None` if no count is present.
+    ///
+    /// `total_tokens` is taken from the provider when reported, otherwise
+    /// summed from the other two.
+    pub fn from_metadata(metadata: &serde_json::Value) -> Option<Usage> {
+        let count = |v: Option<&serde_json::Value>| v.and_then(|v| v.as_u64());
+        let (prompt_tokens, completion_tokens, total_tokens) = match metadata.get("usage") {
+            Some(usage) => {
+                let either = |a: &str, b: &str| count(usage.get(a).or_else(|| usage.get(b)));
+                (
+                    either("prompt_tokens", "input_tokens"),
+                    either("completion_tokens", "output_tokens"),
+                    count(usage.get("total_tokens")),
+                )
+            }
+            None => (
+                count(metadata.get("prompt_eval_count")),
+                count(metadata.get("eval_count")),
+                None,
+            ),
+        };
+        Self::new(prompt_tokens, completion_tokens, total_tokens)
+    }
+
+    /// Usage from the given counts, filling in `total_tokens` when both
+    /// other counts are known. `None` if every count is `None`.
+    pub(crate) fn new(
+        prompt_tokens: Option<u64>,
+        completion_tokens: Option<u64>,
+        total_tokens: Option<u64>,
+    ) -> Option<Usage> {
+        let total_tokens = total_tokens.or(match (prompt_tokens, completion_tokens) {
+            (Some(p), Some(c)) => Some(p + c),
+            _ => None,
+        });
+        (prompt_tokens.is_some() || completion_tokens.is_some() || total_tokens.is_some())
+            .then_some(Usage {
+                prompt_tokens,
+                completion_tokens,
+                total_tokens,
+            })
+    }
+}
+
+impl std::ops::Add for Usage {
+    type Output = Usage;
+
+    fn add(self, other: Usage) -> Usage {
+        let sum = |a: Option<u64>, b: Option<u64>| match (a, b) {
+            (None, None) => None,
+            _ => Some(a.unwrap_or(0) + b.unwrap_or(0)),
+        };
+        Usage {
+            prompt_tokens: sum(self.prompt_tokens, other.prompt_tokens),
+            completion_tokens: sum(self.completion_tokens, other.completion_tokens),
+            total_tokens: sum(self.total_tokens, other.total_tokens),
+        }
+    }
+}
+
+impl std::ops::AddAssign for Usage {
+    fn add_assign(&mut self, other: Usage) {
+        *self = *self + other;
+    }
+}
+
+impl std::iter::Sum for Usage {
+    fn sum<I: Iterator<Item = Usage>>(iter: I) -> Usage {
+        iter.fold(Usage::default(), |a, b| a + b)
+    }
+}
+
+impl LlmResponse {
+    /// A response with `text` and HTTP `status`, and no metadata,
+    /// candidates, refusal, usage, or tool calls.
+    pub fn new(text: impl Into<String>, status: u16) -> Self {
+        Self {
+            text: text.into(),
+            status,
+            ..Default::default()
+        }
+    }
+
+    /// Generation speed in tokens per second, from Ollama's `eval_count`
+    /// and `eval_duration`. `None` if either is missing or the duration is
+    /// zero.
+    pub fn tokens_per_second(&self) -> Option<f64> {
+        tokens_per_second(self.metadata.as_ref()?)
+    }
+
+    /// Time spent evaluating the prompt, in milliseconds (Ollama's
+    /// `prompt_eval_duration`).
+    pub fn prompt_eval_duration_ms(&self) -> Option<f64> {
+        duration_ms(self.metadata.as_ref()?, "prompt_eval_duration")
+    }
+
+    /// Time spent generating the response, in milliseconds (Ollama's
+    /// `eval_duration`).
+    pub fn eval_duration_ms(&self) -> Option<f64> {
+        duration_ms(self.metadata.as_ref()?, "eval_duration")
+    }
+
+    /// Wall time of the whole request, in milliseconds (Ollama's
+    /// `total_duration`).
+    pub fn total_duration_ms(&self) -> Option<f64> {
+        duration_ms(self.metadata.as_ref()?, "total_duration")
+    }
+
+    /// The provider's remaining rate-limit budget, if the backend recorded
+    /// one (the `rate_limit` metadata entry).
+    pub fn rate_limit(&self) -> Option<RateLimitInfo> {
+        RateLimitInfo::from_metadata(self.metadata.as_ref()?)
+    }
+
+    /// [`usage`](Self::usage), or for backends that leave it unset, usage
+    /// read from `metadata`.
+    pub(crate) fn reported_usage(&self) -> Option<Usage> {
+        self.usage
+            .or_else(|| Usage::from_metadata(self.metadata.as_ref()?))
+    }
+}
+
+/// Read a nanosecond duration field from provider metadata as milliseconds.
+pub(crate) fn duration_ms(metadata: &serde_json::Value, key: &str) -> Option<f64> {
+    metadata
+        .get(key)
+        .and_then(|v| v.as_u64())
+        .map(|ns| ns as f64 / 1_000_000.0)
+}
+
+/// `eval_count / eval_duration`, converted to tokens per second.
+pub(crate) fn tokens_per_second(metadata: &serde_json::Value) -> Option<f64> {
+    let count = metadata.get("eval_count")?.as_u64()?;
+    let ns = metadata.get("eval_duration")?.as_u64()?;
+    (ns > 0).then(|| count as f64 / (ns as f64 / 1_000_000_000.0))
 }
 
 /// Abstraction over LLM providers.
@@ -109,7 +479,8 @@ pub struct LlmResponse {
 /// and the provider's HTTP API. The trait handles two modes: non-streaming
 /// completion and streaming completion with token callbacks.
 ///
-/// Built-in implementations: [`OllamaBackend`], [`OpenAiBackend`].
+/// Built-in implementations: [`OllamaBackend`], [`OpenAiBackend`], [`AnthropicBackend`],
+/// [`GeminiBackend`], [`BedrockBackend`].
 ///
 /// # Object Safety
 ///
@@ -136,10 +507,177 @@ pub trait Backend: Send + Sync {
         on_token: &mut (dyn FnMut(String) + Send),
     ) -> Result<LlmResponse>;
 
+    /// Execute a streaming LLM call with a separate callback for `<think>`
+    /// block tokens.
+    ///
+    /// When `request.config.think_stream` is
+    /// [`ThinkStreamMode::Separate`](crate::streaming::ThinkStreamMode::Separate),
+    /// backends that support it send think-block tokens to `on_thinking`
+    /// instead of `on_token`. The default implementation ignores
+    /// `on_thinking` and delegates to [`complete_streaming`](Self::complete_streaming).
+    async fn complete_streaming_with_thinking(
+        &self,
+        client: &Client,
+        base_url: &str,
+        request: &LlmRequest,
+        on_token: &mut (dyn FnMut(String) + Send),
+        _on_thinking: &mut (dyn FnMut(String) + Send),
+    ) -> Result<LlmResponse> {
+        self.complete_streaming(client, base_url, request, on_token)
+            .await
+    }
+
+    /// Execute a streaming LLM call, also reporting the provider's final
+    /// metadata (token counts, timings) to `on_metadata`.
+    ///
+    /// `on_thinking` behaves as in
+    /// [`complete_streaming_with_thinking`](Self::complete_streaming_with_thinking).
+    /// Backends that can should call `on_metadata` as soon as the metadata
+    /// arrives on the stream. The default implementation calls it with the
+    /// returned [`LlmResponse::metadata`], if any, once the stream is done.
+    async fn complete_streaming_with_metadata(
+        &self,
+        client: &Client,
+        base_url: &str,
+        request: &LlmRequest,
+        on_token: &mut (dyn FnMut(String) + Send),
+        on_thinking: Option<&mut (dyn FnMut(String) + Send)>,
+        on_metadata: &mut (dyn FnMut(serde_json::Value) + Send),
+    ) -> Result<LlmResponse> {
+        let response = match on_thinking {
+            Some(on_thinking) => {
+                self.complete_streaming_with_thinking(
+                    client,
+                    base_url,
+                    request,
+                    on_token,
+                    on_thinking,
+                )
+                .await?
+            }
+            None => {
+                self.complete_streaming(client, base_url, request, on_token)
+                    .await?
+            }
+        };
+        if let Some(ref metadata) = response.metadata {
+            on_metadata(metadata.clone());
+        }
+        Ok(response)
+    }
+
+    /// Embed `texts` with `model`, returning one vector per input in order.
+    ///
+    /// The default implementation returns an error; backends whose provider
+    /// has an embeddings endpoint override it.
+    async fn embed(
+        &self,
+        _client: &Client,
+        _base_url: &str,
+        _model: &str,
+        _texts: &[String],
+    ) -> Result<Vec<Vec<f32>>> {
+        Err(PipelineError::Other(format!(
+            "The {} backend does not support embeddings",
+            self.name()
+        )))
+    }
+
     /// Human-readable name for logging and diagnostics.
     fn name(&self) -> &'static str;
 }
 
+/// Counts streamed tokens against [`LlmRequest::max_stream_tokens`].
+///
+/// Shared by the streaming loops of the built-in backends. Each non-empty
+/// token passed to `on_token` counts as one.
+#[derive(Debug)]
+pub(crate) struct StreamLimit {
+    max: Option<usize>,
+    seen: usize,
+}
+
+impl StreamLimit {
+    pub(crate) fn new(max: Option<usize>) -> Self {
+        Self { max, seen: 0 }
+    }
+
+    /// Record one token. Returns `true` once the limit has been reached.
+    pub(crate) fn record(&mut self) -> bool {
+        self.seen += 1;
+        self.reached()
+    }
+
+    pub(crate) fn reached(&self) -> bool {
+        self.max.is_some_and(|max| self.seen >= max)
+    }
+
+    /// Tag `metadata` with `finish_reason = "client_limit"` if the limit hit.
+    pub(crate) fn apply(&self, metadata: Option<serde_json::Value>) -> Option<serde_json::Value> {
+        if !self.reached() {
+            return metadata;
+        }
+        let mut meta = match metadata {
+            Some(serde_json::Value::Object(map)) => map,
+            _ => serde_json::Map::new(),
+        };
+        meta.insert("finish_reason".into(), "client_limit".into());
+        Some(serde_json::Value::Object(meta))
+    }
+}
+
+/// Pull the human-readable message out of a JSON error body.
+///
+/// Understands the common provider shapes: `{"error": {"message": "..."}}`
+/// (OpenAI-compatible), `{"error": "..."}` (Ollama), and a top-level
+/// `message` or `detail`. Returns `None` for plain-text bodies.
+pub(crate) fn error_message(body: &str) -> Option<String> {
+    let json: serde_json::Value = serde_json::from_str(body.trim()).ok()?;
+    let message = match json.get("error") {
+        Some(serde_json::Value::String(message)) => Some(message.as_str()),
+        Some(error) => error.get("message").and_then(|m| m.as_str()),
+        None => None,
+    }
+    .or_else(|| json.get("message").and_then(|m| m.as_str()))
+    .or_else(|| json.get("detail").and_then(|m| m.as_str()))?;
+    Some(message.to_string())
+}
+
+/// Fail with [`PipelineError::InvalidConfig`] if `request` has tools, for
+/// backends that can't send them.
+#[cfg(any(feature = "anthropic", feature = "gemini"))]
+pub(crate) fn reject_tools(request: &LlmRequest, backend: &str) -> Result<()> {
+    if request.tools.is_empty() {
+        Ok(())
+    } else {
+        Err(PipelineError::InvalidConfig(format!(
+            "the {} backend does not support tool calling",
+            backend
+        )))
+    }
+}
+
+/// The `Retry-After` header of a response, as whole seconds.
+pub(crate) fn retry_after(headers: &reqwest::header::HeaderMap) -> Option<std::time::Duration> {
+    headers
+        .get("retry-after")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.trim().parse::<u64>().ok())
+        .map(std::time::Duration::from_secs)
+}
+
+/// Read embedding vectors out of a JSON array of number arrays.
+pub(crate) fn parse_embeddings<'v>(
+    rows: impl Iterator<Item = &'v serde_json::Value>,
+) -> Result<Vec<Vec<f32>>> {
+    rows.map(|row| {
+        row.as_array()
+            .map(|xs| xs.iter().filter_map(|x| x.as_f64()).map(|x| x as f32).collect())
+            .ok_or_else(|| PipelineError::Other("Malformed embedding in response".to_string()))
+    })
+    .collect()
+}
+
 /// Check whether a [`PipelineError`] is retryable based on the backoff config.
 ///
 /// Retryable conditions:
@@ -148,7 +686,7 @@ pub trait Backend: Send + Sync {
 pub fn is_retryable(error: &PipelineError, config: &BackoffConfig) -> bool {
     match error {
         PipelineError::HttpError { status, .. } => config.retryable_statuses.contains(status),
-        PipelineError::Request(_) => true,
+        PipelineError::Request(_) | PipelineError::Timeout(_) => true,
         _ => false,
     }
 }
@@ -180,6 +718,8 @@ pub async fn with_backoff(
     cancel: Option<&std::sync::atomic::AtomicBool>,
     mut on_retry: RetryCallback<'_>,
 ) -> Result<LlmResponse> {
+    let request = with_accepted_statuses(request, config);
+    let request: &LlmRequest = &request;
     let mut last_error: Option<PipelineError> = None;
 
     for attempt in 0..=config.max_retries {
@@ -192,19 +732,7 @@ pub async fn with_backoff(
 
         // Wait for backoff delay (not on first attempt)
         if attempt > 0 {
-            let delay = if let Some(PipelineError::HttpError {
-                retry_after: Some(ra),
-                ..
-            }) = &last_error
-            {
-                if config.respect_retry_after {
-                    *ra
-                } else {
-                    config.delay_for_attempt(attempt - 1)
-                }
-            } else {
-                config.delay_for_attempt(attempt - 1)
-            };
+            let delay = retry_delay(config, attempt, last_error.as_ref());
 
             let reason = last_error
                 .as_ref()
@@ -215,18 +743,24 @@ pub async fn with_backoff(
                 cb(attempt, delay, &reason);
             }
 
-            tokio::time::sleep(delay).await;
+            if !sleep_unless_cancelled(delay, cancel).await {
+                return Err(PipelineError::Cancelled);
+            }
+        }
 
-            // Check cancellation after sleep
-            if let Some(flag) = cancel {
-                if flag.load(std::sync::atomic::Ordering::Relaxed) {
-                    return Err(PipelineError::Cancelled);
-                }
+        if let Some(ref pacer) = config.rate_limit_pacer {
+            if !pacer.wait(config.max_delay, cancel).await {
+                return Err(PipelineError::Cancelled);
             }
         }
 
-        match backend.complete(client, base_url, request).await {
-            Ok(response) => return Ok(response),
+        let result =
+            within_timeout(request.timeout, backend.complete(client, base_url, request)).await;
+        match result {
+            Ok(response) => {
+                observe_rate_limit(config, &response);
+                return Ok(response);
+            }
             Err(e) => {
                 if attempt < config.max_retries && is_retryable(&e, config) {
                     last_error = Some(e);
@@ -243,6 +777,88 @@ pub async fn with_backoff(
     )))
 }
 
+/// Delay before retry `attempt` (1-based) after `last_error`: the delay the
+/// provider asked for when [`BackoffConfig::respect_retry_after`] is on,
+/// capped at [`BackoffConfig::max_delay`], otherwise the exponential backoff.
+fn retry_delay(
+    config: &BackoffConfig,
+    attempt: u32,
+    last_error: Option<&PipelineError>,
+) -> std::time::Duration {
+    match last_error {
+        Some(PipelineError::HttpError {
+            retry_after: Some(ra),
+            ..
+        }) if config.respect_retry_after => (*ra).min(config.max_delay),
+        _ => config.delay_for_attempt(attempt - 1),
+    }
+}
+
+/// How often a cancellable sleep checks the cancellation flag.
+const CANCEL_POLL: std::time::Duration = std::time::Duration::from_millis(50);
+
+/// Sleep for `delay`. Returns `false` as soon as `cancel` is raised, without
+/// finishing the sleep.
+pub(crate) async fn sleep_unless_cancelled(
+    delay: std::time::Duration,
+    cancel: Option<&std::sync::atomic::AtomicBool>,
+) -> bool {
+    use std::sync::atomic::Ordering;
+
+    let sleep = tokio::time::sleep(delay);
+    let Some(flag) = cancel else {
+        sleep.await;
+        return true;
+    };
+    let cancelled = async {
+        while !flag.load(Ordering::Relaxed) {
+            tokio::time::sleep(CANCEL_POLL).await;
+        }
+    };
+    tokio::select! {
+        _ = sleep => !flag.load(Ordering::Relaxed),
+        _ = cancelled => false,
+    }
+}
+
+/// Run `call`, failing with [`PipelineError::Timeout`] if it takes longer
+/// than `timeout`.
+pub(crate) async fn within_timeout(
+    timeout: Option<std::time::Duration>,
+    call: impl std::future::Future<Output = Result<LlmResponse>>,
+) -> Result<LlmResponse> {
+    match timeout {
+        Some(limit) => tokio::time::timeout(limit, call)
+            .await
+            .unwrap_or(Err(PipelineError::Timeout(limit))),
+        None => call.await,
+    }
+}
+
+/// Feed the rate limit `response` reports to the config's pacer, if any.
+fn observe_rate_limit(config: &BackoffConfig, response: &LlmResponse) {
+    if let (Some(pacer), Some(info)) = (&config.rate_limit_pacer, response.rate_limit()) {
+        pacer.observe(&info);
+    }
+}
+
+/// `request` with `config.accept_statuses` added to its own.
+fn with_accepted_statuses<'r>(
+    request: &'r LlmRequest,
+    config: &BackoffConfig,
+) -> Cow<'r, LlmRequest> {
+    if config
+        .accept_statuses
+        .iter()
+        .all(|s| request.accept_statuses.contains(s))
+    {
+        return Cow::Borrowed(request);
+    }
+    let mut request = request.clone();
+    request.accept_statuses.extend(&config.accept_statuses);
+    Cow::Owned(request)
+}
+
 /// Options for [`with_backoff_streaming`] — bundles the optional/callback parameters.
 pub struct BackoffStreamOpts<'a> {
     /// Optional cancellation flag.
@@ -251,13 +867,48 @@ pub struct BackoffStreamOpts<'a> {
     pub on_retry: RetryCallback<'a>,
     /// Token callback — receives each token as it arrives.
     pub on_token: &'a mut (dyn FnMut(String) + Send),
+    /// Optional callback for `<think>` block tokens, used when the request's
+    /// `think_stream` mode is `Separate`.
+    pub on_thinking: Option<&'a mut (dyn FnMut(String) + Send)>,
+    /// Optional callback for the provider's end-of-stream metadata. See
+    /// [`Backend::complete_streaming_with_metadata`].
+    pub on_metadata: Option<&'a mut (dyn FnMut(serde_json::Value) + Send)>,
+}
+
+/// Instruction sent after the partial output when resuming a stream.
+const RESUME_PROMPT: &str =
+    "Your previous response was cut off. Continue exactly where it stopped, without repeating anything.";
+
+/// Build the follow-up request that continues from `partial`.
+fn resume_request(request: &LlmRequest, partial: &str, tokens_seen: usize) -> LlmRequest {
+    let mut resumed = request.clone();
+    if resumed.messages.is_empty() {
+        resumed.messages.push(ChatMessage {
+            role: Role::User,
+            content: request.prompt.clone(),
+        });
+    }
+    resumed.messages.push(ChatMessage {
+        role: Role::Assistant,
+        content: partial.to_string(),
+    });
+    resumed.messages.push(ChatMessage {
+        role: Role::User,
+        content: RESUME_PROMPT.to_string(),
+    });
+    resumed.max_stream_tokens = request
+        .max_stream_tokens
+        .map(|max| max.saturating_sub(tokens_seen).max(1));
+    resumed
 }
 
 /// Execute a streaming backend call with transport-level retry.
 ///
-/// Same as [`with_backoff`] but for streaming calls. Note: each retry
+/// Same as [`with_backoff`] but for streaming calls. By default each retry
 /// restarts the stream from scratch — partial tokens from failed attempts
-/// are discarded.
+/// are discarded. With [`BackoffConfig::resume_streams`] the retry instead
+/// continues from the partial output, and the response metadata records
+/// the number of resumes as `stream_resumes`.
 pub async fn with_backoff_streaming(
     backend: &Arc<dyn Backend>,
     client: &Client,
@@ -270,8 +921,16 @@ pub async fn with_backoff_streaming(
         cancel,
         mut on_retry,
         on_token,
+        mut on_thinking,
+        mut on_metadata,
     } = opts;
+    let request = with_accepted_statuses(request, config);
+    let request: &LlmRequest = &request;
     let mut last_error: Option<PipelineError> = None;
+    // Output delivered by failed attempts, when resuming.
+    let mut partial = String::new();
+    let mut partial_tokens = 0usize;
+    let mut resumes = 0u32;
 
     for attempt in 0..=config.max_retries {
         if let Some(flag) = cancel {
@@ -281,19 +940,7 @@ pub async fn with_backoff_streaming(
         }
 
         if attempt > 0 {
-            let delay = if let Some(PipelineError::HttpError {
-                retry_after: Some(ra),
-                ..
-            }) = &last_error
-            {
-                if config.respect_retry_after {
-                    *ra
-                } else {
-                    config.delay_for_attempt(attempt - 1)
-                }
-            } else {
-                config.delay_for_attempt(attempt - 1)
-            };
+            let delay = retry_delay(config, attempt, last_error.as_ref());
 
             let reason = last_error
                 .as_ref()
@@ -304,22 +951,107 @@ pub async fn with_backoff_streaming(
                 cb(attempt, delay, &reason);
             }
 
-            tokio::time::sleep(delay).await;
+            if !sleep_unless_cancelled(delay, cancel).await {
+                return Err(PipelineError::Cancelled);
+            }
+        }
 
-            if let Some(flag) = cancel {
-                if flag.load(std::sync::atomic::Ordering::Relaxed) {
-                    return Err(PipelineError::Cancelled);
-                }
+        if let Some(ref pacer) = config.rate_limit_pacer {
+            if !pacer.wait(config.max_delay, cancel).await {
+                return Err(PipelineError::Cancelled);
             }
         }
 
-        match backend
-            .complete_streaming(client, base_url, request, on_token)
-            .await
-        {
-            Ok(response) => return Ok(response),
+        let resumed;
+        let attempt_request = if config.resume_streams && !partial.is_empty() {
+            resumes += 1;
+            resumed = resume_request(request, &partial, partial_tokens);
+            &resumed
+        } else {
+            request
+        };
+
+        let mut attempt_text = String::new();
+        let mut attempt_tokens = 0usize;
+        let mut dedup = (request.dedup_stream && !partial.is_empty())
+            .then(|| StreamDedup::resuming(partial.clone()));
+        let mut tee = |mut token: String| {
+            if let Some(ref mut dedup) = dedup {
+                let replayed = token.len() - dedup.push(&token).len();
+                if replayed > 0 && replayed == token.len() {
+                    return;
+                }
+                token.drain(..replayed);
+            }
+            attempt_text.push_str(&token);
+            attempt_tokens += 1;
+            on_token(token);
+        };
+
+        let call = async {
+            match (on_metadata.as_deref_mut(), on_thinking.as_deref_mut()) {
+                (Some(on_metadata), on_thinking) => {
+                    backend
+                        .complete_streaming_with_metadata(
+                            client,
+                            base_url,
+                            attempt_request,
+                            &mut tee,
+                            on_thinking.map(|f| f as &mut (dyn FnMut(String) + Send)),
+                            on_metadata,
+                        )
+                        .await
+                }
+                (None, Some(on_thinking)) => {
+                    backend
+                        .complete_streaming_with_thinking(
+                            client,
+                            base_url,
+                            attempt_request,
+                            &mut tee,
+                            on_thinking,
+                        )
+                        .await
+                }
+                (None, None) => {
+                    backend
+                        .complete_streaming(client, base_url, attempt_request, &mut tee)
+                        .await
+                }
+            }
+        };
+        let result = within_timeout(attempt_request.timeout, call).await;
+
+        match result {
+            Ok(mut response) => {
+                let duplicates = dedup.map_or(0, |dedup| dedup.dropped());
+                if duplicates > 0 {
+                    response.text = attempt_text;
+                    let mut meta = match response.metadata.take() {
+                        Some(serde_json::Value::Object(map)) => map,
+                        _ => serde_json::Map::new(),
+                    };
+                    meta.insert("stream_duplicates".into(), duplicates.into());
+                    response.metadata = Some(serde_json::Value::Object(meta));
+                }
+                if resumes > 0 {
+                    response.text = format!("{}{}", partial, response.text);
+                    let mut meta = match response.metadata.take() {
+                        Some(serde_json::Value::Object(map)) => map,
+                        _ => serde_json::Map::new(),
+                    };
+                    meta.insert("stream_resumes".into(), resumes.into());
+                    response.metadata = Some(serde_json::Value::Object(meta));
+                }
+                observe_rate_limit(config, &response);
+                return Ok(response);
+            }
             Err(e) => {
                 if attempt < config.max_retries && is_retryable(&e, config) {
+                    if config.resume_streams {
+                        partial.push_str(&attempt_text);
+                        partial_tokens += attempt_tokens;
+                    }
                     last_error = Some(e);
                     continue;
                 }
@@ -336,14 +1068,94 @@ pub async fn with_backoff_streaming(
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::sync::atomic::Ordering;
     use std::time::Duration;
 
+    /// Send `request` through `backend` to a port nothing listens on and
+    /// assert the failure is a transport error the standard backoff
+    /// retries. Returns the error for backend-specific checks.
+    #[cfg(any(feature = "anthropic", feature = "gemini"))]
+    pub(super) async fn assert_connect_error_retryable(
+        backend: &dyn Backend,
+        request: &LlmRequest,
+    ) -> PipelineError {
+        let addr = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let err = backend
+            .complete(&Client::new(), &format!("http://{}", addr), request)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, PipelineError::Request(_)), "{:?}", err);
+        assert!(is_retryable(&err, &BackoffConfig::standard()));
+        err
+    }
+
+    #[test]
+    fn test_response_timing_accessors() {
+        let response = LlmResponse {
+            metadata: Some(serde_json::json!({
+                "eval_count": 50,
+                "eval_duration": 2_000_000_000u64,
+                "prompt_eval_duration": 150_000_000u64,
+                "total_duration": 2_500_000_000u64,
+            })),
+            ..Default::default()
+        };
+        assert_eq!(response.tokens_per_second(), Some(25.0));
+        assert_eq!(response.prompt_eval_duration_ms(), Some(150.0));
+        assert_eq!(response.eval_duration_ms(), Some(2000.0));
+        assert_eq!(response.total_duration_ms(), Some(2500.0));
+
+        let zero = LlmResponse {
+            metadata: Some(serde_json::json!({"eval_count": 5, "eval_duration": 0})),
+            ..Default::default()
+        };
+        assert_eq!(zero.tokens_per_second(), None);
+        assert_eq!(LlmResponse::default().tokens_per_second(), None);
+    }
+
+    #[test]
+    fn test_usage_from_metadata_and_sum() {
+        use serde_json::json;
+
+        let ollama = Usage::from_metadata(&json!({"prompt_eval_count": 9, "eval_count": 2}));
+        let openai = Usage::from_metadata(&json!({
+            "usage": {"prompt_tokens": 5, "completion_tokens": 7, "total_tokens": 13}
+        }));
+        let anthropic = Usage::from_metadata(&json!({
+            "usage": {"input_tokens": 4, "output_tokens": 1}
+        }));
+        assert_eq!(ollama.unwrap().total_tokens, Some(11));
+        assert_eq!(openai.unwrap().total_tokens, Some(13));
+        assert_eq!(anthropic.unwrap().prompt_tokens, Some(4));
+        assert_eq!(Usage::from_metadata(&json!({"model": "x"})), None);
+
+        let partial = Usage {
+            completion_tokens: Some(3),
+            ..Default::default()
+        };
+        let sum: Usage = [ollama.unwrap(), openai.unwrap(), partial]
+            .into_iter()
+            .sum();
+        assert_eq!(
+            sum,
+            Usage {
+                prompt_tokens: Some(14),
+                completion_tokens: Some(12),
+                total_tokens: Some(24),
+            }
+        );
+    }
+
     #[test]
     fn test_is_retryable_429() {
         let config = BackoffConfig::standard();
         let err = PipelineError::HttpError {
             status: 429,
             body: "rate limited".into(),
+            message: None,
             retry_after: None,
         };
         assert!(is_retryable(&err, &config));
@@ -355,6 +1167,7 @@ mod tests {
         let err = PipelineError::HttpError {
             status: 503,
             body: "service unavailable".into(),
+            message: None,
             retry_after: None,
         };
         assert!(is_retryable(&err, &config));
@@ -366,6 +1179,7 @@ mod tests {
         let err = PipelineError::HttpError {
             status: 400,
             body: "bad request".into(),
+            message: None,
             retry_after: None,
         };
         assert!(!is_retryable(&err, &config));
@@ -385,6 +1199,32 @@ mod tests {
         assert!(!is_retryable(&err, &config));
     }
 
+    #[test]
+    fn test_error_message() {
+        assert_eq!(
+            error_message(r#"{"error": {"message": "Invalid API key", "type": "auth"}}"#),
+            Some("Invalid API key".into())
+        );
+        assert_eq!(
+            error_message(r#"{"error": "model 'x' not found"}"#),
+            Some("model 'x' not found".into())
+        );
+        assert_eq!(
+            error_message(r#"{"detail": "Not Found"}"#),
+            Some("Not Found".into())
+        );
+        assert_eq!(error_message("502 Bad Gateway"), None);
+        assert_eq!(error_message(r#"{"error": {"code": 1}}"#), None);
+
+        let err = PipelineError::HttpError {
+            status: 401,
+            body: r#"{"error": {"message": "Invalid API key"}}"#.into(),
+            message: Some("Invalid API key".into()),
+            retry_after: None,
+        };
+        assert_eq!(err.to_string(), "HTTP 401: Invalid API key");
+    }
+
     #[test]
     fn test_backoff_none_no_retry() {
         let config = BackoffConfig::none();
@@ -399,14 +1239,7 @@ mod tests {
         let cancel = AtomicBool::new(true);
         let backend: Arc<dyn Backend> = Arc::new(OllamaBackend);
         let client = Client::new();
-        let request = LlmRequest {
-            model: "test".into(),
-            system_prompt: None,
-            prompt: "test".into(),
-            messages: Vec::new(),
-            config: LlmConfig::default(),
-            stream: false,
-        };
+        let request = LlmRequest::new("test", "test");
 
         let result = with_backoff(
             &backend,
@@ -428,6 +1261,7 @@ mod tests {
         let err = PipelineError::HttpError {
             status: 429,
             body: "rate limited".into(),
+            message: None,
             retry_after: Some(Duration::from_secs(30)),
         };
 
@@ -435,4 +1269,226 @@ mod tests {
             assert_eq!(retry_after, Some(Duration::from_secs(30)));
         }
     }
+
+    #[test]
+    fn test_retry_delay_caps_retry_after() {
+        let config = BackoffConfig::builder()
+            .max_retries(1)
+            .max_delay(Duration::from_secs(10))
+            .jitter(super::backoff::JitterStrategy::None)
+            .build();
+        let rate_limited = |secs| PipelineError::HttpError {
+            status: 429,
+            body: String::new(),
+            message: None,
+            retry_after: Some(Duration::from_secs(secs)),
+        };
+        assert_eq!(
+            retry_delay(&config, 1, Some(&rate_limited(3))),
+            Duration::from_secs(3)
+        );
+        assert_eq!(
+            retry_delay(&config, 1, Some(&rate_limited(3600))),
+            Duration::from_secs(10)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_sleep_unless_cancelled() {
+        use std::sync::atomic::AtomicBool;
+
+        let cancel = Arc::new(AtomicBool::new(false));
+        let flag = cancel.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            flag.store(true, Ordering::Relaxed);
+        });
+        let start = std::time::Instant::now();
+        assert!(!sleep_unless_cancelled(Duration::from_secs(60), Some(&cancel)).await);
+        assert!(start.elapsed() < Duration::from_secs(1));
+        assert!(sleep_unless_cancelled(Duration::from_millis(1), None).await);
+    }
+
+    #[test]
+    fn test_stream_limit_unset_never_reached() {
+        let mut limit = StreamLimit::new(None);
+        for _ in 0..1000 {
+            assert!(!limit.record());
+        }
+        assert!(limit.apply(None).is_none());
+    }
+
+    #[test]
+    fn test_stream_limit_marks_client_limit() {
+        let mut limit = StreamLimit::new(Some(2));
+        assert!(!limit.record());
+        assert!(limit.record());
+        let meta = limit
+            .apply(Some(serde_json::json!({"model": "m"})))
+            .unwrap();
+        assert_eq!(meta["finish_reason"], "client_limit");
+        assert_eq!(meta["model"], "m");
+    }
+
+    /// Streams "Hello, " then fails with a 503 on the first call; later
+    /// calls stream "world" (resumed) or "Hello, world" (restarted).
+    fn drops_once() -> MockBackend {
+        let calls = std::sync::atomic::AtomicUsize::new(0);
+        MockBackend::from_fn(move |request| {
+            Ok(if calls.fetch_add(1, Ordering::Relaxed) == 0 {
+                MockReply::text("Hello, ").then_fail(503)
+            } else if request.messages.is_empty() {
+                MockReply::text("Hello, world")
+            } else {
+                MockReply::text("world")
+            })
+        })
+    }
+
+    async fn stream_with(config: BackoffConfig) -> (LlmResponse, Vec<String>, Vec<LlmRequest>) {
+        let backend = Arc::new(drops_once());
+        let dyn_backend: Arc<dyn Backend> = backend.clone();
+        let mut request = LlmRequest::new("test", "greet");
+        request.stream = true;
+        let mut tokens = Vec::new();
+        let mut on_token = |t: String| tokens.push(t);
+        let response = with_backoff_streaming(
+            &dyn_backend,
+            &Client::new(),
+            "http://test",
+            &request,
+            &BackoffConfig {
+                initial_delay: Duration::ZERO,
+                ..config
+            },
+            BackoffStreamOpts {
+                cancel: None,
+                on_retry: None,
+                on_token: &mut on_token,
+                on_thinking: None,
+                on_metadata: None,
+            },
+        )
+        .await
+        .unwrap();
+        (response, tokens, backend.requests())
+    }
+
+    #[tokio::test]
+    async fn test_streaming_retry_restarts_by_default() {
+        let (response, tokens, _) = stream_with(BackoffConfig::standard()).await;
+        assert_eq!(response.text, "Hello, world");
+        assert_eq!(tokens, vec!["Hello, ", "Hello, world"]);
+    }
+
+    #[tokio::test]
+    async fn test_streaming_retry_resumes() {
+        let (response, tokens, calls) =
+            stream_with(BackoffConfig::standard().resume_streams(true)).await;
+        assert_eq!(response.text, "Hello, world");
+        assert_eq!(tokens, vec!["Hello, ", "world"]);
+        assert_eq!(response.metadata.unwrap()["stream_resumes"], 1);
+
+        let resumed = &calls[1].messages;
+        assert_eq!(resumed.len(), 3);
+        assert_eq!(resumed[0].content, "greet");
+        assert_eq!(resumed[1].role, Role::Assistant);
+        assert_eq!(resumed[1].content, "Hello, ");
+    }
+
+    #[tokio::test]
+    async fn test_streaming_dedup() {
+        let run = |replies: Vec<MockReply>, dedup_stream: bool| async move {
+            let backend: Arc<dyn Backend> = Arc::new(MockBackend::scripted(replies));
+            let mut request = LlmRequest::new("test", "greet");
+            request.stream = true;
+            request.dedup_stream = dedup_stream;
+            let mut tokens = Vec::new();
+            let mut on_token = |t: String| tokens.push(t);
+            let response = with_backoff_streaming(
+                &backend,
+                &Client::new(),
+                "http://test",
+                &request,
+                &BackoffConfig {
+                    initial_delay: Duration::ZERO,
+                    ..BackoffConfig::standard().resume_streams(true)
+                },
+                BackoffStreamOpts {
+                    cancel: None,
+                    on_retry: None,
+                    on_token: &mut on_token,
+                    on_thinking: None,
+                    on_metadata: None,
+                },
+            )
+            .await
+            .unwrap();
+            (response, tokens)
+        };
+
+        // The resumed stream replays the received text before continuing,
+        // as a flaky proxy might after reconnecting.
+        let replays = || {
+            vec![
+                MockReply::chunks(["Hel", "lo, "]).then_fail(503),
+                MockReply::chunks(["Hel", "lo, wor", "ld"]),
+            ]
+        };
+        let (response, _) = run(replays(), false).await;
+        assert_eq!(response.text, "Hello, Hello, world");
+
+        let (response, tokens) = run(replays(), true).await;
+        assert_eq!(response.text, "Hello, world");
+        assert_eq!(tokens, vec!["Hel", "lo, ", "wor", "ld"]);
+        assert_eq!(response.metadata.unwrap()["stream_duplicates"], 2);
+
+        // Repeated tokens are kept, with or without a resume.
+        let repeats = ["ha", "ha", "\n", "\n", "  ", "  ", "10", "0"];
+        let (response, tokens) = run(vec![MockReply::chunks(repeats)], true).await;
+        assert_eq!(response.text, repeats.concat());
+        assert_eq!(tokens, repeats);
+
+        let (response, _) = run(
+            vec![
+                MockReply::chunks(["1", "0"]).then_fail(503),
+                MockReply::chunks(["0", "0"]),
+            ],
+            true,
+        )
+        .await;
+        assert_eq!(response.text, "1000");
+    }
+
+    #[tokio::test]
+    async fn test_backoff_observes_rate_limit() {
+        // Answers every call, reporting an exhausted request budget.
+        let info = RateLimitInfo {
+            remaining_requests: Some(0),
+            reset_requests: Some(Duration::from_secs(30)),
+            ..Default::default()
+        };
+        let backend: Arc<dyn Backend> =
+            Arc::new(MockBackend::scripted(vec![
+                MockReply::text("ok").with_metadata(info.attach(None).unwrap())
+            ]));
+        let request = LlmRequest::new("test", "test");
+        let config = BackoffConfig::none().pace_rate_limits(1);
+        let response = with_backoff(
+            &backend,
+            &Client::new(),
+            "http://test",
+            &request,
+            &config.clone(),
+            None,
+            None,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(response.rate_limit().unwrap().remaining_requests, Some(0));
+        // The clone shares the schedule: the next call would wait ~30s.
+        let pending = config.rate_limit_pacer.unwrap().pending_delay().unwrap();
+        assert!(pending > Duration::from_secs(29));
+    }
 }
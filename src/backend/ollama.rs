@@ -6,9 +6,11 @@
 //!
 //! This is the default backend and preserves all existing behavior.
 
-use super::{Backend, LlmRequest, LlmResponse, Role};
+use super::{
+    parse_embeddings, Backend, LlmRequest, LlmResponse, Role, StreamLimit, ToolCall, Usage,
+};
 use crate::error::Result;
-use crate::streaming::StreamingDecoder;
+use crate::streaming::{StreamingDecoder, ThinkChunk, ThinkFilter, ThinkStreamMode};
 use crate::PipelineError;
 use async_trait::async_trait;
 use futures::StreamExt;
@@ -17,7 +19,7 @@ use serde_json::{json, Value};
 
 /// Backend for Ollama's native API.
 ///
-/// Endpoints: `/api/generate` (prompt-only), `/api/chat` (with system prompt or messages).
+/// Endpoints: `/api/generate` (prompt-only), `/api/chat` (with system prompt, messages, or tools).
 /// Streaming: NDJSON with `{"response": "token"}` per line.
 ///
 /// This is the default backend. Existing code using `ExecCtx::builder("url").build()`
@@ -26,11 +28,14 @@ use serde_json::{json, Value};
 /// # Endpoint selection
 ///
 /// Uses `/api/chat` when ANY of:
-/// - `system_prompt` is set (non-empty)
+/// - `system_prompt` is set (non-empty), unless `config.prefer_generate`
 /// - `messages` are present (retry with history)
+/// - `tools` are present
 ///
-/// Uses `/api/generate` when:
+/// Uses `/api/generate` when there are no tools and:
 /// - No system prompt AND no message history (prompt-only mode)
+/// - `config.prefer_generate` is set and there is no message history; the
+///   system prompt is prepended to the prompt
 #[derive(Debug, Clone)]
 pub struct OllamaBackend;
 
@@ -44,6 +49,24 @@ impl OllamaBackend {
         if request.config.thinking {
             opts["extended_thinking"] = json!(true);
         }
+        if !request.config.stop.is_empty() {
+            opts["stop"] = json!(request.config.stop);
+        }
+        if let Some(top_p) = request.config.top_p {
+            opts["top_p"] = json!(top_p);
+        }
+        if let Some(top_k) = request.config.top_k {
+            opts["top_k"] = json!(top_k);
+        }
+        if let Some(seed) = request.config.seed {
+            opts["seed"] = json!(seed);
+        }
+        if let Some(penalty) = request.config.presence_penalty {
+            opts["presence_penalty"] = json!(penalty);
+        }
+        if let Some(penalty) = request.config.frequency_penalty {
+            opts["frequency_penalty"] = json!(penalty);
+        }
         if let Some(ref custom) = request.config.options {
             if let (Some(base), Some(extra)) = (opts.as_object_mut(), custom.as_object()) {
                 for (k, v) in extra {
@@ -56,24 +79,32 @@ impl OllamaBackend {
 
     /// Whether this request should use `/api/chat` (vs `/api/generate`).
     fn use_chat(request: &LlmRequest) -> bool {
-        request
+        let has_system = request
             .system_prompt
             .as_ref()
-            .is_some_and(|s| !s.is_empty())
+            .is_some_and(|s| !s.is_empty());
+        (has_system && !request.config.prefer_generate)
             || !request.messages.is_empty()
+            || !request.tools.is_empty()
     }
 
-    /// Build the JSON body for `/api/generate`.
+    /// Build the JSON body for `/api/generate`. A non-empty system prompt
+    /// (only present here with `prefer_generate`) is prepended to the prompt.
     fn build_generate_body(request: &LlmRequest, stream: bool) -> Value {
+        let prompt = match request.system_prompt.as_deref() {
+            Some(sys) if !sys.is_empty() => format!("{}\n\n{}", sys, request.prompt),
+            _ => request.prompt.clone(),
+        };
         let mut body = json!({
             "model": request.model,
-            "prompt": request.prompt,
+            "prompt": prompt,
             "stream": stream,
             "options": Self::build_options(request),
         });
         if request.config.json_mode {
             body["format"] = json!("json");
         }
+        request.config.apply_extra_body(&mut body);
         body
     }
 
@@ -110,38 +141,37 @@ impl OllamaBackend {
             "stream": stream,
             "options": Self::build_options(request),
         });
+        if !request.tools.is_empty() {
+            let tools: Vec<Value> = request.tools.iter().map(|t| t.to_function_json()).collect();
+            body["tools"] = json!(tools);
+        }
         if request.config.json_mode {
             body["format"] = json!("json");
         }
+        request.config.apply_extra_body(&mut body);
         body
     }
 
-    /// Parse a Retry-After header value as seconds.
-    fn parse_retry_after(value: &str) -> Option<std::time::Duration> {
-        // Try integer seconds first
-        if let Ok(secs) = value.trim().parse::<u64>() {
-            return Some(std::time::Duration::from_secs(secs));
-        }
-        None
-    }
-
-    /// Send a non-streaming request and parse the response.
-    async fn send_request(client: &Client, url: &str, body: &Value) -> Result<(Value, u16)> {
+    /// Send a non-streaming request and parse the response if `accepts` its
+    /// status, e.g. [`LlmRequest::accepts_status`].
+    async fn send_request(
+        client: &Client,
+        url: &str,
+        body: &Value,
+        accepts: impl Fn(reqwest::StatusCode) -> bool,
+    ) -> Result<(Value, u16)> {
         let resp = client.post(url).json(body).send().await.map_err(|e| {
             PipelineError::Other(format!("Failed to connect to LLM at {}: {}", url, e))
         })?;
 
         let status = resp.status().as_u16();
 
-        if !resp.status().is_success() {
-            let retry_after = resp
-                .headers()
-                .get("retry-after")
-                .and_then(|v| v.to_str().ok())
-                .and_then(Self::parse_retry_after);
+        if !accepts(resp.status()) {
+            let retry_after = super::retry_after(resp.headers());
             let text = resp.text().await.unwrap_or_default();
             return Err(PipelineError::HttpError {
                 status,
+                message: super::error_message(&text),
                 body: text,
                 retry_after,
             });
@@ -151,9 +181,12 @@ impl OllamaBackend {
         Ok((json_resp, status))
     }
 
-    /// Extract metadata fields from an Ollama response.
-    fn extract_metadata(json_resp: &Value) -> Option<Value> {
+    /// Extract metadata fields from an Ollama response, recording which
+    /// endpoint produced it as `endpoint_mode` (`"chat"` or `"generate"`).
+    fn extract_metadata(json_resp: &Value, use_chat: bool) -> Value {
         let mut meta = serde_json::Map::new();
+        let mode = if use_chat { "chat" } else { "generate" };
+        meta.insert("endpoint_mode".into(), mode.into());
         if let Some(v) = json_resp.get("total_duration") {
             meta.insert("total_duration".into(), v.clone());
         }
@@ -166,71 +199,31 @@ impl OllamaBackend {
         if let Some(v) = json_resp.get("prompt_eval_count") {
             meta.insert("prompt_eval_count".into(), v.clone());
         }
+        if let Some(v) = json_resp.get("prompt_eval_duration") {
+            meta.insert("prompt_eval_duration".into(), v.clone());
+        }
         if let Some(v) = json_resp.get("model") {
             meta.insert("model".into(), v.clone());
         }
-        if meta.is_empty() {
-            None
-        } else {
-            Some(Value::Object(meta))
-        }
-    }
-}
-
-#[async_trait]
-impl Backend for OllamaBackend {
-    async fn complete(
-        &self,
-        client: &Client,
-        base_url: &str,
-        request: &LlmRequest,
-    ) -> Result<LlmResponse> {
-        let base = base_url.trim_end_matches('/');
-
-        if Self::use_chat(request) {
-            // Chat endpoint
-            let body = Self::build_chat_body(request, false);
-            let url = format!("{}/api/chat", base);
-            let (json_resp, status) = Self::send_request(client, &url, &body).await?;
-
-            let text = json_resp
-                .get("message")
-                .and_then(|m| m.get("content"))
-                .and_then(|v| v.as_str())
-                .unwrap_or("")
-                .to_string();
-
-            Ok(LlmResponse {
-                text,
-                status,
-                metadata: Self::extract_metadata(&json_resp),
-            })
-        } else {
-            // Generate endpoint
-            let body = Self::build_generate_body(request, false);
-            let url = format!("{}/api/generate", base);
-            let (json_resp, status) = Self::send_request(client, &url, &body).await?;
-
-            let text = json_resp
-                .get("response")
-                .and_then(|v| v.as_str())
-                .unwrap_or("")
-                .to_string();
-
-            Ok(LlmResponse {
-                text,
-                status,
-                metadata: Self::extract_metadata(&json_resp),
-            })
+        // Normalized to the OpenAI key so callers see one name for both.
+        if let Some(v) = json_resp.get("done_reason") {
+            meta.insert("finish_reason".into(), v.clone());
         }
+        Value::Object(meta)
     }
 
-    async fn complete_streaming(
+    /// Shared streaming loop. Think-block tokens are routed according to
+    /// `request.config.think_stream`; `on_thinking` receives them in
+    /// `Separate` mode. `on_metadata` receives the `done` line's metadata as
+    /// soon as it is decoded.
+    async fn stream_completion<'a>(
         &self,
         client: &Client,
         base_url: &str,
         request: &LlmRequest,
-        on_token: &mut (dyn FnMut(String) + Send),
+        on_token: &'a mut (dyn FnMut(String) + Send),
+        on_thinking: Option<&'a mut (dyn FnMut(String) + Send)>,
+        mut on_metadata: Option<&'a mut (dyn FnMut(Value) + Send)>,
     ) -> Result<LlmResponse> {
         let base = base_url.trim_end_matches('/');
         let use_chat = Self::use_chat(request);
@@ -253,15 +246,12 @@ impl Backend for OllamaBackend {
 
         let status = resp.status().as_u16();
 
-        if !resp.status().is_success() {
-            let retry_after = resp
-                .headers()
-                .get("retry-after")
-                .and_then(|v| v.to_str().ok())
-                .and_then(Self::parse_retry_after);
+        if !request.accepts_status(resp.status()) {
+            let retry_after = super::retry_after(resp.headers());
             let text = resp.text().await.unwrap_or_default();
             return Err(PipelineError::HttpError {
                 status,
+                message: super::error_message(&text),
                 body: text,
                 retry_after,
             });
@@ -271,10 +261,20 @@ impl Backend for OllamaBackend {
         let mut decoder = StreamingDecoder::new();
         let mut accumulated = String::new();
         let mut last_metadata = None;
+        let mut tool_calls = Vec::new();
+        let mut limit = StreamLimit::new(request.max_stream_tokens);
+        let mut router = TokenRouter {
+            mode: request.config.think_stream,
+            filter: ThinkFilter::new(),
+            on_token,
+            on_thinking,
+        };
 
-        while let Some(chunk) = stream.next().await {
+        'stream: while let Some(chunk) = stream.next().await {
             let chunk = chunk.map_err(PipelineError::Request)?;
-            for json_val in decoder.decode(&chunk) {
+            let values = decoder.decode(&chunk);
+            decoder.check_dropped()?;
+            for json_val in values {
                 let token_str = if use_chat {
                     json_val
                         .get("message")
@@ -283,20 +283,29 @@ impl Backend for OllamaBackend {
                 } else {
                     json_val.get("response").and_then(|r| r.as_str())
                 };
+                // Ollama sends each tool call whole, never split across chunks.
+                tool_calls.extend(ToolCall::all_in_message(json_val.get("message")));
                 if let Some(t) = token_str {
                     if !t.is_empty() {
                         accumulated.push_str(t);
-                        on_token(t.to_string());
+                        router.token(t);
+                        if limit.record() {
+                            // Dropping the stream closes the connection.
+                            break 'stream;
+                        }
                     }
                 }
                 if json_val.get("done").and_then(|v| v.as_bool()) == Some(true) {
-                    last_metadata = Self::extract_metadata(&json_val);
+                    last_metadata = Some(Self::extract_metadata(&json_val, use_chat));
+                    if let (Some(cb), Some(meta)) = (on_metadata.as_mut(), &last_metadata) {
+                        cb(meta.clone());
+                    }
                 }
             }
         }
 
         // Flush remaining buffer
-        if let Some(json_val) = decoder.flush() {
+        if let Some(json_val) = decoder.flush().filter(|_| !limit.reached()) {
             let token_str = if use_chat {
                 json_val
                     .get("message")
@@ -305,23 +314,196 @@ impl Backend for OllamaBackend {
             } else {
                 json_val.get("response").and_then(|r| r.as_str())
             };
+            tool_calls.extend(ToolCall::all_in_message(json_val.get("message")));
             if let Some(t) = token_str {
                 if !t.is_empty() {
                     accumulated.push_str(t);
-                    on_token(t.to_string());
+                    router.token(t);
                 }
             }
             if json_val.get("done").and_then(|v| v.as_bool()) == Some(true) {
-                last_metadata = Self::extract_metadata(&json_val);
+                last_metadata = Some(Self::extract_metadata(&json_val, use_chat));
+                if let (Some(cb), Some(meta)) = (on_metadata.as_mut(), &last_metadata) {
+                    cb(meta.clone());
+                }
             }
         }
 
+        router.finish();
+
+        let metadata = limit.apply(last_metadata);
         Ok(LlmResponse {
             text: accumulated,
             status,
-            metadata: last_metadata,
+            usage: metadata.as_ref().and_then(Usage::from_metadata),
+            metadata,
+            candidates: Vec::new(),
+            refusal: None,
+            tool_calls,
         })
     }
+}
+
+/// Delivers streamed tokens according to the request's [`ThinkStreamMode`].
+struct TokenRouter<'a> {
+    mode: ThinkStreamMode,
+    filter: ThinkFilter,
+    on_token: &'a mut (dyn FnMut(String) + Send),
+    on_thinking: Option<&'a mut (dyn FnMut(String) + Send)>,
+}
+
+impl TokenRouter<'_> {
+    fn token(&mut self, token: &str) {
+        if self.mode == ThinkStreamMode::Inline {
+            (self.on_token)(token.to_string());
+            return;
+        }
+        for chunk in self.filter.push(token) {
+            self.deliver(chunk);
+        }
+    }
+
+    /// Release any partial tag held back by the filter.
+    fn finish(&mut self) {
+        if let Some(chunk) = self.filter.flush() {
+            self.deliver(chunk);
+        }
+    }
+
+    fn deliver(&mut self, chunk: ThinkChunk) {
+        match chunk {
+            ThinkChunk::Answer(text) => (self.on_token)(text),
+            ThinkChunk::Thinking(text) => {
+                if self.mode == ThinkStreamMode::Separate {
+                    if let Some(on_thinking) = self.on_thinking.as_deref_mut() {
+                        on_thinking(text);
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl Backend for OllamaBackend {
+    async fn complete(
+        &self,
+        client: &Client,
+        base_url: &str,
+        request: &LlmRequest,
+    ) -> Result<LlmResponse> {
+        let base = base_url.trim_end_matches('/');
+
+        if Self::use_chat(request) {
+            // Chat endpoint
+            let body = Self::build_chat_body(request, false);
+            let url = format!("{}/api/chat", base);
+            let (json_resp, status) =
+                Self::send_request(client, &url, &body, |s| request.accepts_status(s)).await?;
+
+            let text = json_resp
+                .get("message")
+                .and_then(|m| m.get("content"))
+                .and_then(|v| v.as_str())
+                .unwrap_or("")
+                .to_string();
+
+            let metadata = Some(Self::extract_metadata(&json_resp, true));
+            Ok(LlmResponse {
+                text,
+                status,
+                usage: metadata.as_ref().and_then(Usage::from_metadata),
+                metadata,
+                candidates: Vec::new(),
+                refusal: None,
+                tool_calls: ToolCall::all_in_message(json_resp.get("message")),
+            })
+        } else {
+            // Generate endpoint
+            let body = Self::build_generate_body(request, false);
+            let url = format!("{}/api/generate", base);
+            let (json_resp, status) =
+                Self::send_request(client, &url, &body, |s| request.accepts_status(s)).await?;
+
+            let text = json_resp
+                .get("response")
+                .and_then(|v| v.as_str())
+                .unwrap_or("")
+                .to_string();
+
+            let metadata = Some(Self::extract_metadata(&json_resp, false));
+            Ok(LlmResponse {
+                text,
+                status,
+                usage: metadata.as_ref().and_then(Usage::from_metadata),
+                metadata,
+                candidates: Vec::new(),
+                refusal: None,
+                tool_calls: Vec::new(),
+            })
+        }
+    }
+
+    async fn complete_streaming(
+        &self,
+        client: &Client,
+        base_url: &str,
+        request: &LlmRequest,
+        on_token: &mut (dyn FnMut(String) + Send),
+    ) -> Result<LlmResponse> {
+        self.stream_completion(client, base_url, request, on_token, None, None)
+            .await
+    }
+
+    async fn complete_streaming_with_thinking(
+        &self,
+        client: &Client,
+        base_url: &str,
+        request: &LlmRequest,
+        on_token: &mut (dyn FnMut(String) + Send),
+        on_thinking: &mut (dyn FnMut(String) + Send),
+    ) -> Result<LlmResponse> {
+        self.stream_completion(client, base_url, request, on_token, Some(on_thinking), None)
+            .await
+    }
+
+    async fn complete_streaming_with_metadata(
+        &self,
+        client: &Client,
+        base_url: &str,
+        request: &LlmRequest,
+        on_token: &mut (dyn FnMut(String) + Send),
+        on_thinking: Option<&mut (dyn FnMut(String) + Send)>,
+        on_metadata: &mut (dyn FnMut(Value) + Send),
+    ) -> Result<LlmResponse> {
+        self.stream_completion(
+            client,
+            base_url,
+            request,
+            on_token,
+            on_thinking.map(|f| f as &mut (dyn FnMut(String) + Send)),
+            Some(on_metadata),
+        )
+        .await
+    }
+
+    /// Uses `/api/embed`.
+    async fn embed(
+        &self,
+        client: &Client,
+        base_url: &str,
+        model: &str,
+        texts: &[String],
+    ) -> Result<Vec<Vec<f32>>> {
+        let url = format!("{}/api/embed", base_url.trim_end_matches('/'));
+        let body = json!({ "model": model, "input": texts });
+        let (json_resp, _) = Self::send_request(client, &url, &body, |s| s.is_success()).await?;
+        let rows = json_resp
+            .get("embeddings")
+            .and_then(|e| e.as_array())
+            .ok_or_else(|| PipelineError::Other("Ollama response has no embeddings".into()))?;
+        parse_embeddings(rows.iter())
+    }
 
     fn name(&self) -> &'static str {
         "ollama"
@@ -331,18 +513,11 @@ impl Backend for OllamaBackend {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::backend::{ChatMessage, Role};
-    use crate::client::LlmConfig;
+    use crate::backend::{ChatMessage, Role, ToolSpec};
+    use crate::test_support::serve;
 
     fn test_request() -> LlmRequest {
-        LlmRequest {
-            model: "llama3.2".into(),
-            system_prompt: None,
-            prompt: "Why is the sky blue?".into(),
-            messages: Vec::new(),
-            config: LlmConfig::default(),
-            stream: false,
-        }
+        LlmRequest::new("llama3.2", "Why is the sky blue?")
     }
 
     #[test]
@@ -413,6 +588,27 @@ mod tests {
         assert!(OllamaBackend::use_chat(&request));
     }
 
+    #[test]
+    fn test_ollama_backend_prefer_generate() {
+        let mut request = test_request();
+        request.system_prompt = Some("You are terse.".into());
+        request.config.prefer_generate = true;
+        assert!(!OllamaBackend::use_chat(&request));
+
+        let body = OllamaBackend::build_generate_body(&request, false);
+        assert_eq!(
+            body["prompt"],
+            format!("You are terse.\n\n{}", request.prompt)
+        );
+
+        // Retry history still needs the chat endpoint.
+        request.messages.push(ChatMessage {
+            role: Role::User,
+            content: "hello".into(),
+        });
+        assert!(OllamaBackend::use_chat(&request));
+    }
+
     #[test]
     fn test_ollama_backend_thinking_mode() {
         let mut request = test_request();
@@ -434,6 +630,77 @@ mod tests {
         assert_eq!(body["options"]["temperature"], 0.7);
     }
 
+    #[test]
+    fn test_ollama_backend_stop() {
+        let mut request = test_request();
+        let body = OllamaBackend::build_generate_body(&request, false);
+        assert!(body["options"].get("stop").is_none());
+
+        request.config = request.config.with_stop(vec!["\n\n".into(), "END".into()]);
+        let body = OllamaBackend::build_generate_body(&request, false);
+        assert_eq!(body["options"]["stop"], json!(["\n\n", "END"]));
+    }
+
+    #[test]
+    fn test_ollama_backend_sampling_params() {
+        let mut request = test_request();
+        let body = OllamaBackend::build_generate_body(&request, false);
+        for key in ["top_p", "top_k", "seed"] {
+            assert!(body["options"].get(key).is_none(), "{}", key);
+        }
+
+        request.config = request.config.with_top_p(0.9).with_top_k(40).with_seed(7);
+        let body = OllamaBackend::build_generate_body(&request, false);
+        assert_eq!(body["options"]["top_p"], 0.9);
+        assert_eq!(body["options"]["top_k"], 40);
+        assert_eq!(body["options"]["seed"], 7);
+    }
+
+    #[test]
+    fn test_ollama_backend_penalties() {
+        let mut request = test_request();
+        let body = OllamaBackend::build_generate_body(&request, false);
+        assert!(body["options"].get("presence_penalty").is_none());
+        assert!(body["options"].get("frequency_penalty").is_none());
+
+        request.config = request
+            .config
+            .with_presence_penalty(0.5)
+            .with_frequency_penalty(1.25);
+        let body = OllamaBackend::build_generate_body(&request, false);
+        assert_eq!(body["options"]["presence_penalty"], 0.5);
+        assert_eq!(body["options"]["frequency_penalty"], 1.25);
+    }
+
+    #[test]
+    fn test_ollama_backend_tools_use_chat() {
+        let mut request = test_request();
+        assert!(OllamaBackend::build_chat_body(&request, false)
+            .get("tools")
+            .is_none());
+
+        request.tools = vec![ToolSpec::new(
+            "get_weather",
+            "Current weather for a city",
+            json!({"type": "object"}),
+        )];
+        assert!(OllamaBackend::use_chat(&request));
+        let body = OllamaBackend::build_chat_body(&request, false);
+        assert_eq!(body["tools"][0]["function"]["name"], "get_weather");
+    }
+
+    #[test]
+    fn test_ollama_backend_extra_body() {
+        let mut request = test_request();
+        request.config = request
+            .config
+            .with_extra_body(json!({"keep_alive": "10m", "raw": true}));
+        let body = OllamaBackend::build_generate_body(&request, false);
+        assert_eq!(body["keep_alive"], "10m");
+        assert_eq!(body["raw"], true);
+        assert_eq!(body["prompt"], request.prompt.as_str());
+    }
+
     #[test]
     fn test_ollama_backend_chat_with_history() {
         let mut request = test_request();
@@ -471,4 +738,279 @@ mod tests {
         let body = OllamaBackend::build_generate_body(&request, true);
         assert_eq!(body["stream"], true);
     }
+
+    fn route(mode: ThinkStreamMode, tokens: &[&str]) -> (String, String) {
+        let mut answer = String::new();
+        let mut thinking = String::new();
+        let mut on_token = |t: String| answer.push_str(&t);
+        let mut on_thinking = |t: String| thinking.push_str(&t);
+        let mut router = TokenRouter {
+            mode,
+            filter: ThinkFilter::new(),
+            on_token: &mut on_token,
+            on_thinking: Some(&mut on_thinking),
+        };
+        for t in tokens {
+            router.token(t);
+        }
+        router.finish();
+        (answer, thinking)
+    }
+
+    #[test]
+    fn test_token_router_modes() {
+        let tokens = ["<thi", "nk>plan", "ning</thi", "nk>", "Answer"];
+
+        let (answer, thinking) = route(ThinkStreamMode::Inline, &tokens);
+        assert_eq!(answer, "<think>planning</think>Answer");
+        assert!(thinking.is_empty());
+
+        let (answer, thinking) = route(ThinkStreamMode::Separate, &tokens);
+        assert_eq!(answer, "Answer");
+        assert_eq!(thinking, "planning");
+
+        let (answer, thinking) = route(ThinkStreamMode::Suppress, &tokens);
+        assert_eq!(answer, "Answer");
+        assert!(thinking.is_empty());
+    }
+
+    /// Serve `connections` requests on a local port, each answered with
+    /// `status` and a generate-style JSON body.
+    async fn serve_status(status: u16, connections: usize) -> String {
+        serve(
+            status,
+            r#"{"response": "partial result", "done": true}"#,
+            connections,
+        )
+        .await
+    }
+
+    #[tokio::test]
+    async fn test_accept_statuses_parses_custom_status() {
+        use crate::backend::{with_backoff, BackoffConfig};
+        use std::sync::Arc;
+
+        let base_url = serve_status(420, 2).await;
+        let backend: Arc<dyn Backend> = Arc::new(OllamaBackend);
+        let client = Client::new();
+        let request = test_request();
+
+        let err = with_backoff(
+            &backend,
+            &client,
+            &base_url,
+            &request,
+            &BackoffConfig::none(),
+            None,
+            None,
+        )
+        .await
+        .unwrap_err();
+        assert!(matches!(err, PipelineError::HttpError { status: 420, .. }));
+
+        let response = with_backoff(
+            &backend,
+            &client,
+            &base_url,
+            &request,
+            &BackoffConfig::none().accept_statuses([420]),
+            None,
+            None,
+        )
+        .await
+        .unwrap();
+        assert_eq!(response.text, "partial result");
+        assert_eq!(response.status, 420);
+    }
+
+    #[tokio::test]
+    async fn test_http_error_message_from_json_body() {
+        let base_url = serve(404, r#"{"error": "model 'nope' not found"}"#, 1).await;
+        let err = OllamaBackend
+            .complete(&Client::new(), &base_url, &test_request())
+            .await
+            .unwrap_err();
+        match err {
+            PipelineError::HttpError {
+                status,
+                body,
+                message,
+                ..
+            } => {
+                assert_eq!(status, 404);
+                assert!(body.starts_with('{'));
+                assert_eq!(message.as_deref(), Some("model 'nope' not found"));
+            }
+            other => panic!("expected HttpError, got {other:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_stream_metadata_event() {
+        use crate::events::{Event, FnEventHandler};
+        use crate::{ExecCtx, LlmCall, Payload};
+        use std::sync::{Arc, Mutex};
+
+        let base_url = serve(
+            200,
+            "{\"response\": \"Hel\", \"done\": false}\n\
+             {\"response\": \"lo\", \"done\": false}\n\
+             {\"response\": \"\", \"done\": true, \"done_reason\": \"stop\", \"eval_count\": 2, \"prompt_eval_count\": 9}\n",
+            1,
+        )
+        .await;
+
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let sink = events.clone();
+        let ctx = ExecCtx::builder(base_url)
+            .event_handler(Arc::new(FnEventHandler(move |event: Event| match event {
+                Event::Token { chunk, .. } => sink.lock().unwrap().push(chunk),
+                Event::StreamMetadata { name, metadata } => sink
+                    .lock()
+                    .unwrap()
+                    .push(format!("{}:{}", name, metadata["eval_count"])),
+                _ => {}
+            })))
+            .build();
+        let call = LlmCall::new("greet", "{input}")
+            .with_streaming(true)
+            .expecting_text();
+
+        let out = call.invoke(&ctx, serde_json::json!("hi")).await.unwrap();
+        assert_eq!(out.value, "Hello");
+        assert_eq!(*events.lock().unwrap(), vec!["Hel", "lo", "greet:2"]);
+        let diag = out.diagnostics.unwrap();
+        assert_eq!(diag.finish_reason.as_deref(), Some("stop"));
+        assert_eq!(diag.completion_tokens, Some(2));
+    }
+
+    #[tokio::test]
+    async fn test_stream_usage() {
+        use crate::{ExecCtx, LlmCall, Payload};
+
+        let base_url = serve(
+            200,
+            "{\"response\": \"Hi\", \"done\": false}\n\
+             {\"response\": \"\", \"done\": true, \"eval_count\": 2, \"prompt_eval_count\": 9}\n",
+            1,
+        )
+        .await;
+        let ctx = ExecCtx::builder(base_url).build();
+        let call = LlmCall::new("greet", "{input}")
+            .with_streaming(true)
+            .expecting_text();
+
+        let out = call.invoke(&ctx, serde_json::json!("hi")).await.unwrap();
+        assert_eq!(
+            out.diagnostics.unwrap().usage(),
+            Some(crate::backend::Usage {
+                prompt_tokens: Some(9),
+                completion_tokens: Some(2),
+                total_tokens: Some(11),
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_timing_metadata_on_output() {
+        use crate::{ExecCtx, LlmCall, Payload};
+
+        let base_url = serve(
+            200,
+            r#"{"response": "Hi", "done": true, "eval_count": 4, "eval_duration": 200000000,
+                "prompt_eval_duration": 30000000, "total_duration": 250000000}"#,
+            1,
+        )
+        .await;
+        let ctx = ExecCtx::builder(base_url).build();
+        let call = LlmCall::new("greet", "{input}").expecting_text();
+
+        let out = call.invoke(&ctx, serde_json::json!("hi")).await.unwrap();
+        assert_eq!(out.provider_metadata.as_ref().unwrap()["eval_count"], 4);
+        assert_eq!(out.tokens_per_second(), Some(20.0));
+        assert_eq!(out.prompt_eval_duration_ms(), Some(30.0));
+        assert_eq!(out.eval_duration_ms(), Some(200.0));
+        assert_eq!(out.total_duration_ms(), Some(250.0));
+    }
+
+    #[tokio::test]
+    async fn test_endpoint_mode_generate() {
+        use crate::{ExecCtx, LlmCall, Payload};
+
+        let base_url = serve(
+            200,
+            "{\"response\": \"Hi\", \"done\": false}\n{\"response\": \"\", \"done\": true}\n",
+            1,
+        )
+        .await;
+        let ctx = ExecCtx::builder(base_url).build();
+        let call = LlmCall::new("greet", "{input}")
+            .with_streaming(true)
+            .expecting_text();
+
+        let out = call.invoke(&ctx, serde_json::json!("hi")).await.unwrap();
+        assert_eq!(out.value, "Hi");
+        let diag = out.diagnostics.unwrap();
+        assert_eq!(diag.endpoint_mode.as_deref(), Some("generate"));
+    }
+
+    #[tokio::test]
+    async fn test_endpoint_mode_chat() {
+        use crate::{ExecCtx, LlmCall, Payload};
+
+        let base_url = serve(
+            200,
+            r#"{"message": {"role": "assistant", "content": "Hi"}, "done": true}"#,
+            1,
+        )
+        .await;
+        let ctx = ExecCtx::builder(base_url).build();
+        let call = LlmCall::new("greet", "{input}")
+            .with_system("Be friendly.")
+            .expecting_text();
+
+        let out = call.invoke(&ctx, serde_json::json!("hi")).await.unwrap();
+        assert_eq!(out.value, "Hi");
+        let diag = out.diagnostics.unwrap();
+        assert_eq!(diag.endpoint_mode.as_deref(), Some("chat"));
+        assert_eq!(out.provider_metadata.unwrap()["endpoint_mode"], "chat");
+    }
+
+    #[tokio::test]
+    async fn test_tool_calls_surface_in_output() {
+        use crate::{ExecCtx, LlmCall, Payload, RetryConfig};
+
+        let base_url = serve(
+            200,
+            r#"{"message": {"role": "assistant", "content": "", "tool_calls": [
+                {"function": {"name": "get_weather", "arguments": {"city": "Paris"}}}
+            ]}, "done": true}"#,
+            1,
+        )
+        .await;
+        let ctx = ExecCtx::builder(base_url).build();
+        let call = LlmCall::new("weather", "{input}")
+            .with_tools(vec![ToolSpec::new(
+                "get_weather",
+                "Current weather for a city",
+                json!({"type": "object"}),
+            )])
+            .expecting_json()
+            .with_retry(RetryConfig::new(2));
+
+        // Only one connection is served: an empty answer with tool calls
+        // must not trigger a retry.
+        let out = call.invoke(&ctx, json!("Weather in Paris?")).await.unwrap();
+        assert_eq!(
+            out.tool_calls,
+            vec![ToolCall {
+                id: None,
+                name: "get_weather".into(),
+                arguments: json!({"city": "Paris"}),
+            }]
+        );
+        let diag = out.diagnostics.unwrap();
+        assert_eq!(diag.endpoint_mode.as_deref(), Some("chat"));
+        assert_eq!(diag.retry_attempts, 0);
+    }
 }
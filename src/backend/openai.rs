@@ -7,15 +7,23 @@
 //! Endpoint: `/v1/chat/completions` (always chat mode).
 //! Streaming: SSE with `data: {"choices": [{"delta": {"content": "token"}}]}`.
 
+use super::rate_limit::RateLimitInfo;
 use super::sse::SseDecoder;
-use super::{Backend, LlmRequest, LlmResponse, Role};
+use super::{
+    parse_embeddings, Backend, LlmRequest, LlmResponse, Role, StreamLimit, ToolCall, Usage,
+};
 use crate::error::Result;
 use crate::PipelineError;
 use async_trait::async_trait;
 use futures::StreamExt;
+use reqwest::header::HeaderMap;
 use reqwest::Client;
 use serde_json::{json, Value};
 
+/// Most tool calls a streamed response may assemble; a higher `index` in a
+/// `delta.tool_calls` fragment is rejected.
+const MAX_TOOL_CALLS: usize = 128;
+
 /// Backend for any OpenAI-compatible API.
 ///
 /// Covers: OpenAI, Anthropic (compat), vLLM, llama.cpp, LM Studio,
@@ -35,6 +43,8 @@ pub struct OpenAiBackend {
     pub(crate) api_key: Option<String>,
     /// Optional organization ID. If set, sent as `OpenAI-Organization: {org}`.
     pub(crate) organization: Option<String>,
+    /// Ask for token usage on streamed responses.
+    pub(crate) stream_usage: bool,
 }
 
 impl std::fmt::Debug for OpenAiBackend {
@@ -48,6 +58,7 @@ impl std::fmt::Debug for OpenAiBackend {
                 }
             }))
             .field("organization", &self.organization)
+            .field("stream_usage", &self.stream_usage)
             .finish()
     }
 }
@@ -58,6 +69,7 @@ impl OpenAiBackend {
         Self {
             api_key: None,
             organization: None,
+            stream_usage: false,
         }
     }
 
@@ -73,6 +85,18 @@ impl OpenAiBackend {
         self
     }
 
+    /// Ask the server to report token usage on streamed responses by
+    /// sending `stream_options: {"include_usage": true}`.
+    ///
+    /// Without it, OpenAI reports no usage for a streamed call, so chain
+    /// token budgets and cost estimates see nothing. Off by default because
+    /// some OpenAI-compatible servers reject the unknown field; usage a
+    /// server sends on its own is recorded either way.
+    pub fn with_stream_usage(mut self, enabled: bool) -> Self {
+        self.stream_usage = enabled;
+        self
+    }
+
     /// Returns `true` if an API key has been configured.
     pub fn has_api_key(&self) -> bool {
         self.api_key.is_some()
@@ -87,9 +111,36 @@ impl OpenAiBackend {
     fn build_messages(request: &LlmRequest) -> Vec<Value> {
         let mut messages = Vec::new();
 
-        // System prompt
+        // System prompt. Content blocks are only used to carry
+        // `cache_control`; many OpenAI-compatible servers (llama.cpp, LM
+        // Studio, older vLLM) accept nothing but a string here, so system
+        // parts are otherwise sent joined.
         if let Some(ref sys) = request.system_prompt {
-            if !sys.is_empty() {
+            if request.config.cache_system && !request.system_parts.is_empty() {
+                let last = request.system_parts.len() - 1;
+                let blocks: Vec<Value> = request
+                    .system_parts
+                    .iter()
+                    .enumerate()
+                    .map(|(i, part)| {
+                        let mut block = json!({"type": "text", "text": part});
+                        if i == last {
+                            block["cache_control"] = json!({"type": "ephemeral"});
+                        }
+                        block
+                    })
+                    .collect();
+                messages.push(json!({"role": "system", "content": blocks}));
+            } else if request.config.cache_system && !sys.is_empty() {
+                messages.push(json!({
+                    "role": "system",
+                    "content": [{
+                        "type": "text",
+                        "text": sys,
+                        "cache_control": {"type": "ephemeral"},
+                    }],
+                }));
+            } else if !sys.is_empty() {
                 messages.push(json!({"role": "system", "content": sys}));
             }
         }
@@ -126,18 +177,59 @@ impl OpenAiBackend {
             body["response_format"] = json!({"type": "json_object"});
         }
 
-        // Note: `thinking` / `extended_thinking` are skipped silently for OpenAI.
-        // Custom options are also skipped — they're Ollama-specific.
+        // Multiple completions only make sense for non-streaming calls;
+        // the streaming loop reads `choices[0]` only.
+        if request.config.n > 1 && !stream {
+            body["n"] = json!(request.config.n);
+        }
+
+        if !request.config.stop.is_empty() {
+            body["stop"] = json!(request.config.stop);
+        }
+        if let Some(top_p) = request.config.top_p {
+            body["top_p"] = json!(top_p);
+        }
+        if let Some(seed) = request.config.seed {
+            body["seed"] = json!(seed);
+        }
+        if let Some(penalty) = request.config.presence_penalty {
+            body["presence_penalty"] = json!(penalty);
+        }
+        if let Some(penalty) = request.config.frequency_penalty {
+            body["frequency_penalty"] = json!(penalty);
+        }
+        if !request.tools.is_empty() {
+            let tools: Vec<Value> = request.tools.iter().map(|t| t.to_function_json()).collect();
+            body["tools"] = json!(tools);
+        }
+
+        // Note: `thinking` / `extended_thinking` and `top_k` are skipped
+        // silently for OpenAI.
+        // Custom options are also skipped — they're Ollama-specific; use
+        // `extra_body` for provider-specific fields instead.
+        request.config.apply_extra_body(&mut body);
 
         body
     }
 
-    /// Parse a `Retry-After` header value as seconds.
-    fn parse_retry_after(value: &str) -> Option<std::time::Duration> {
-        if let Ok(secs) = value.trim().parse::<u64>() {
-            return Some(std::time::Duration::from_secs(secs));
+    /// Build the streaming request body, asking for usage if
+    /// [`with_stream_usage`](Self::with_stream_usage) is set. A
+    /// `stream_options` from `extra_body` wins.
+    fn build_stream_body(&self, request: &LlmRequest) -> Value {
+        let mut body = Self::build_body(request, true);
+        if self.stream_usage && body.get("stream_options").is_none() {
+            body["stream_options"] = json!({"include_usage": true});
         }
-        None
+        body
+    }
+
+    /// How long the provider asks to wait before retrying: the `Retry-After`
+    /// header, or for a 429 without one, the reported rate-limit reset.
+    fn retry_after(headers: &HeaderMap, status: u16) -> Option<std::time::Duration> {
+        super::retry_after(headers).or_else(|| match status {
+            429 => RateLimitInfo::from_headers(headers)?.retry_delay(),
+            _ => None,
+        })
     }
 
     /// Build the reqwest request with appropriate headers.
@@ -159,18 +251,144 @@ impl OpenAiBackend {
         req
     }
 
+    /// Extract the `message.content` of every choice, in order.
+    fn extract_choices(json_resp: &Value) -> Vec<String> {
+        json_resp
+            .get("choices")
+            .and_then(|c| c.as_array())
+            .map(|choices| {
+                choices
+                    .iter()
+                    .map(|c| {
+                        c.get("message")
+                            .and_then(|m| m.get("content"))
+                            .and_then(|v| v.as_str())
+                            .unwrap_or("")
+                            .to_string()
+                    })
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// The first choice's `message.refusal`, set when the model declined
+    /// to answer.
+    fn extract_refusal(json_resp: &Value) -> Option<String> {
+        json_resp
+            .pointer("/choices/0/message/refusal")
+            .and_then(|v| v.as_str())
+            .filter(|r| !r.is_empty())
+            .map(str::to_string)
+    }
+
+    /// Append a streamed chunk's `delta.refusal` to `refusal`.
+    fn accumulate_refusal(json_val: &Value, refusal: &mut Option<String>) {
+        if let Some(part) = json_val
+            .pointer("/choices/0/delta/refusal")
+            .and_then(|v| v.as_str())
+        {
+            refusal.get_or_insert_with(String::new).push_str(part);
+        }
+    }
+
+    /// Merge a streamed chunk's `delta.tool_calls` fragments into `partial`.
+    ///
+    /// Each fragment carries an `index`; the first one for a call has its
+    /// `id` and function name, later ones append to the `arguments` string.
+    /// Fails on an index of [`MAX_TOOL_CALLS`] or more, so a malformed
+    /// chunk can't make the buffer grow to a size the server picked.
+    fn accumulate_tool_calls(json_val: &Value, partial: &mut Vec<Value>) -> Result<()> {
+        let Some(fragments) = json_val
+            .pointer("/choices/0/delta/tool_calls")
+            .and_then(|v| v.as_array())
+        else {
+            return Ok(());
+        };
+        for fragment in fragments {
+            let index = fragment.get("index").and_then(|v| v.as_u64()).unwrap_or(0);
+            let index = match usize::try_from(index) {
+                Ok(index) if index < MAX_TOOL_CALLS => index,
+                _ => {
+                    return Err(PipelineError::Other(format!(
+                        "OpenAI stream sent tool call index {}; at most {} calls are supported",
+                        index, MAX_TOOL_CALLS
+                    )))
+                }
+            };
+            if partial.len() <= index {
+                let empty = || json!({"id": null, "function": {"name": "", "arguments": ""}});
+                partial.resize_with(index + 1, empty);
+            }
+            let call = &mut partial[index];
+            if let Some(id) = fragment.get("id").filter(|v| v.is_string()) {
+                call["id"] = id.clone();
+            }
+            for field in ["name", "arguments"] {
+                let Some(part) = fragment
+                    .get("function")
+                    .and_then(|f| f.get(field))
+                    .and_then(|v| v.as_str())
+                else {
+                    continue;
+                };
+                let mut joined = call["function"][field].as_str().unwrap_or("").to_string();
+                joined.push_str(part);
+                call["function"][field] = Value::String(joined);
+            }
+        }
+        Ok(())
+    }
+
+    /// Copy the served `model` and its `system_fingerprint` into `meta`.
+    ///
+    /// Both appear on full responses and on every streamed chunk; the
+    /// fingerprint changes when the provider changes the serving backend,
+    /// which can shift outputs even with a fixed seed.
+    fn record_served_model(json_resp: &Value, meta: &mut serde_json::Map<String, Value>) {
+        for key in ["model", "system_fingerprint"] {
+            if let Some(v) = json_resp.get(key).filter(|v| !v.is_null()) {
+                meta.insert(key.into(), v.clone());
+            }
+        }
+    }
+
+    /// Copy the served model, `usage`, and `choices[0].finish_reason` from a
+    /// streamed chunk into `meta`.
+    ///
+    /// `finish_reason` arrives on the last content chunk and `usage` on a
+    /// final chunk with no choices; both are `null` on the others.
+    fn record_stream_metadata(json_val: &Value, meta: &mut serde_json::Map<String, Value>) {
+        Self::record_served_model(json_val, meta);
+        let fields = [
+            ("usage", json_val.get("usage")),
+            ("finish_reason", json_val.pointer("/choices/0/finish_reason")),
+        ];
+        for (key, value) in fields {
+            if let Some(v) = value.filter(|v| !v.is_null()) {
+                meta.insert(key.into(), v.clone());
+            }
+        }
+    }
+
     /// Extract metadata from an OpenAI response.
     fn extract_metadata(json_resp: &Value) -> Option<Value> {
         let mut meta = serde_json::Map::new();
         if let Some(v) = json_resp.get("usage") {
             meta.insert("usage".into(), v.clone());
         }
-        if let Some(v) = json_resp.get("model") {
-            meta.insert("model".into(), v.clone());
-        }
+        Self::record_served_model(json_resp, &mut meta);
         if let Some(v) = json_resp.get("id") {
             meta.insert("id".into(), v.clone());
         }
+        if let Some(v) = json_resp.pointer("/choices/0/finish_reason") {
+            if !v.is_null() {
+                meta.insert("finish_reason".into(), v.clone());
+            }
+        }
+        // Azure OpenAI reports per-category content filter verdicts.
+        if let Some(v) = json_resp.pointer("/choices/0/content_filter_results") {
+            meta.insert("content_filter_results".into(), v.clone());
+        }
         if meta.is_empty() {
             None
         } else {
@@ -207,35 +425,38 @@ impl Backend for OpenAiBackend {
 
         let status = resp.status().as_u16();
 
-        if !resp.status().is_success() {
-            let retry_after = resp
-                .headers()
-                .get("retry-after")
-                .and_then(|v| v.to_str().ok())
-                .and_then(Self::parse_retry_after);
+        if !request.accepts_status(resp.status()) {
+            let retry_after = Self::retry_after(resp.headers(), status);
             let text = resp.text().await.unwrap_or_default();
             return Err(PipelineError::HttpError {
                 status,
+                message: super::error_message(&text),
                 body: text,
                 retry_after,
             });
         }
 
+        let rate_limit = RateLimitInfo::from_headers(resp.headers());
         let json_resp: Value = resp.json().await?;
 
-        let text = json_resp
-            .get("choices")
-            .and_then(|c| c.get(0))
-            .and_then(|c| c.get("message"))
-            .and_then(|m| m.get("content"))
-            .and_then(|v| v.as_str())
-            .unwrap_or("")
-            .to_string();
+        let mut candidates = Self::extract_choices(&json_resp);
+        let text = candidates.first().cloned().unwrap_or_default();
+        if candidates.len() < 2 {
+            candidates.clear();
+        }
 
+        let metadata = match rate_limit {
+            Some(info) => info.attach(Self::extract_metadata(&json_resp)),
+            None => Self::extract_metadata(&json_resp),
+        };
         Ok(LlmResponse {
             text,
             status,
-            metadata: Self::extract_metadata(&json_resp),
+            usage: metadata.as_ref().and_then(Usage::from_metadata),
+            metadata,
+            candidates,
+            refusal: Self::extract_refusal(&json_resp),
+            tool_calls: ToolCall::all_in_message(json_resp.pointer("/choices/0/message")),
         })
     }
 
@@ -248,7 +469,7 @@ impl Backend for OpenAiBackend {
     ) -> Result<LlmResponse> {
         let base = base_url.trim_end_matches('/');
         let url = format!("{}/v1/chat/completions", base);
-        let body = Self::build_body(request, true);
+        let body = self.build_stream_body(request);
 
         let resp = self
             .build_http_request(client, &url, &body)
@@ -260,27 +481,32 @@ impl Backend for OpenAiBackend {
 
         let status = resp.status().as_u16();
 
-        if !resp.status().is_success() {
-            let retry_after = resp
-                .headers()
-                .get("retry-after")
-                .and_then(|v| v.to_str().ok())
-                .and_then(Self::parse_retry_after);
+        if !request.accepts_status(resp.status()) {
+            let retry_after = Self::retry_after(resp.headers(), status);
             let text = resp.text().await.unwrap_or_default();
             return Err(PipelineError::HttpError {
                 status,
+                message: super::error_message(&text),
                 body: text,
                 retry_after,
             });
         }
 
+        let rate_limit = RateLimitInfo::from_headers(resp.headers());
         let mut stream = resp.bytes_stream();
         let mut decoder = SseDecoder::new();
         let mut accumulated = String::new();
+        let mut limit = StreamLimit::new(request.max_stream_tokens);
+        let mut meta = serde_json::Map::new();
+        let mut refusal = None;
+        let mut partial_calls = Vec::new();
 
-        while let Some(chunk) = stream.next().await {
+        'stream: while let Some(chunk) = stream.next().await {
             let chunk = chunk.map_err(PipelineError::Request)?;
             for json_val in decoder.decode(&chunk) {
+                Self::record_stream_metadata(&json_val, &mut meta);
+                Self::accumulate_refusal(&json_val, &mut refusal);
+                Self::accumulate_tool_calls(&json_val, &mut partial_calls)?;
                 if let Some(content) = json_val
                     .get("choices")
                     .and_then(|c| c.get(0))
@@ -291,13 +517,25 @@ impl Backend for OpenAiBackend {
                     if !content.is_empty() {
                         accumulated.push_str(content);
                         on_token(content.to_string());
+                        if limit.record() {
+                            // Dropping the stream closes the connection.
+                            break 'stream;
+                        }
                     }
                 }
             }
         }
 
         // Flush remaining SSE buffer
-        for json_val in decoder.flush() {
+        let flushed = if limit.reached() {
+            Vec::new()
+        } else {
+            decoder.flush()
+        };
+        for json_val in flushed {
+            Self::record_stream_metadata(&json_val, &mut meta);
+            Self::accumulate_refusal(&json_val, &mut refusal);
+            Self::accumulate_tool_calls(&json_val, &mut partial_calls)?;
             if let Some(content) = json_val
                 .get("choices")
                 .and_then(|c| c.get(0))
@@ -312,13 +550,64 @@ impl Backend for OpenAiBackend {
             }
         }
 
+        let metadata = (!meta.is_empty()).then_some(Value::Object(meta));
+        let metadata = limit.apply(match rate_limit {
+            Some(info) => info.attach(metadata),
+            None => metadata,
+        });
         Ok(LlmResponse {
             text: accumulated,
             status,
-            metadata: None,
+            usage: metadata.as_ref().and_then(Usage::from_metadata),
+            metadata,
+            candidates: Vec::new(),
+            refusal,
+            tool_calls: partial_calls
+                .iter()
+                .filter_map(ToolCall::from_function_json)
+                .collect(),
         })
     }
 
+    /// Uses `/v1/embeddings`.
+    async fn embed(
+        &self,
+        client: &Client,
+        base_url: &str,
+        model: &str,
+        texts: &[String],
+    ) -> Result<Vec<Vec<f32>>> {
+        let url = format!("{}/v1/embeddings", base_url.trim_end_matches('/'));
+        let body = json!({ "model": model, "input": texts });
+
+        let resp = self
+            .build_http_request(client, &url, &body)
+            .send()
+            .await
+            .map_err(|e| {
+                PipelineError::Other(format!("Failed to connect to LLM at {}: {}", url, e))
+            })?;
+
+        let status = resp.status().as_u16();
+        if !resp.status().is_success() {
+            let retry_after = Self::retry_after(resp.headers(), status);
+            let text = resp.text().await.unwrap_or_default();
+            return Err(PipelineError::HttpError {
+                status,
+                message: super::error_message(&text),
+                body: text,
+                retry_after,
+            });
+        }
+
+        let json_resp: Value = resp.json().await?;
+        let data = json_resp
+            .get("data")
+            .and_then(|d| d.as_array())
+            .ok_or_else(|| PipelineError::Other("OpenAI response has no data".into()))?;
+        parse_embeddings(data.iter().map(|d| &d["embedding"]))
+    }
+
     fn name(&self) -> &'static str {
         "openai"
     }
@@ -327,18 +616,11 @@ impl Backend for OpenAiBackend {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::backend::{ChatMessage, Role};
+    use crate::backend::{ChatMessage, Role, ToolSpec};
     use crate::client::LlmConfig;
 
     fn test_request() -> LlmRequest {
-        LlmRequest {
-            model: "gpt-4o".into(),
-            system_prompt: None,
-            prompt: "Why is the sky blue?".into(),
-            messages: Vec::new(),
-            config: LlmConfig::default(),
-            stream: false,
-        }
+        LlmRequest::new("gpt-4o", "Why is the sky blue?")
     }
 
     #[test]
@@ -364,6 +646,39 @@ mod tests {
         assert!(body.get("response_format").is_none());
     }
 
+    #[test]
+    fn test_openai_backend_cached_system() {
+        let mut request = test_request();
+        request.system_prompt = Some("Long shared instructions.".into());
+        request.config.cache_system = true;
+
+        let body = OpenAiBackend::build_body(&request, false);
+        let system = &body["messages"][0];
+        assert_eq!(system["role"], "system");
+        assert_eq!(system["content"][0]["text"], "Long shared instructions.");
+        assert_eq!(
+            system["content"][0]["cache_control"],
+            json!({"type": "ephemeral"})
+        );
+        assert_eq!(body["messages"][1]["content"], "Why is the sky blue?");
+    }
+
+    #[test]
+    fn test_openai_backend_system_parts() {
+        let mut request = test_request();
+        request.system_prompt = Some("Role.\n\nRules.".into());
+        request.system_parts = vec!["Role.".into(), "Rules.".into()];
+
+        let body = OpenAiBackend::build_body(&request, false);
+        assert_eq!(body["messages"][0]["content"], "Role.\n\nRules.");
+
+        request.config.cache_system = true;
+        let body = OpenAiBackend::build_body(&request, false);
+        let content = &body["messages"][0]["content"];
+        assert_eq!(content[0], json!({"type": "text", "text": "Role."}));
+        assert_eq!(content[1]["cache_control"], json!({"type": "ephemeral"}));
+    }
+
     #[test]
     fn test_openai_backend_json_mode() {
         let mut request = test_request();
@@ -406,6 +721,65 @@ mod tests {
         assert!(body.get("top_p").is_none());
     }
 
+    #[test]
+    fn test_openai_backend_stop() {
+        let mut request = test_request();
+        let body = OpenAiBackend::build_body(&request, false);
+        assert!(body.get("stop").is_none());
+
+        request.config = request.config.with_stop(vec!["END".into()]);
+        let body = OpenAiBackend::build_body(&request, false);
+        assert_eq!(body["stop"], json!(["END"]));
+    }
+
+    #[test]
+    fn test_openai_backend_sampling_params() {
+        let mut request = test_request();
+        let body = OpenAiBackend::build_body(&request, false);
+        for key in ["top_p", "top_k", "seed"] {
+            assert!(body.get(key).is_none(), "{}", key);
+        }
+
+        request.config = request.config.with_top_p(0.5).with_top_k(40).with_seed(7);
+        let body = OpenAiBackend::build_body(&request, false);
+        assert_eq!(body["top_p"], 0.5);
+        assert_eq!(body["seed"], 7);
+        assert!(body.get("top_k").is_none());
+    }
+
+    #[test]
+    fn test_openai_backend_penalties() {
+        let mut request = test_request();
+        let body = OpenAiBackend::build_body(&request, false);
+        assert!(body.get("presence_penalty").is_none());
+        assert!(body.get("frequency_penalty").is_none());
+
+        request.config = request.config.with_presence_penalty(-0.5);
+        let body = OpenAiBackend::build_body(&request, false);
+        assert_eq!(body["presence_penalty"], -0.5);
+        assert!(body.get("frequency_penalty").is_none());
+
+        request.config = request.config.with_frequency_penalty(1.5);
+        let body = OpenAiBackend::build_body(&request, false);
+        assert_eq!(body["frequency_penalty"], 1.5);
+    }
+
+    #[test]
+    fn test_openai_backend_extra_body() {
+        let mut request = test_request();
+        request.config = request
+            .config
+            .with_extra_body(json!({"top_k": 40, "min_p": 0.05, "temperature": 0.1}));
+        let body = OpenAiBackend::build_body(&request, false);
+        assert_eq!(body["top_k"], 40);
+        assert_eq!(body["min_p"], 0.05);
+        assert_eq!(body["temperature"], 0.1);
+
+        request.config.extra_body = Some(json!("not an object"));
+        let body = OpenAiBackend::build_body(&request, false);
+        assert_eq!(body["temperature"], 0.7);
+    }
+
     #[test]
     fn test_openai_backend_auth_header() {
         let backend = OpenAiBackend::new()
@@ -451,6 +825,46 @@ mod tests {
         assert_eq!(body["stream"], true);
     }
 
+    #[test]
+    fn test_openai_stream_usage_option() {
+        let request = test_request();
+        let body = OpenAiBackend::new().build_stream_body(&request);
+        assert!(body.get("stream_options").is_none());
+
+        let backend = OpenAiBackend::new().with_stream_usage(true);
+        let body = backend.build_stream_body(&request);
+        assert_eq!(body["stream_options"], json!({"include_usage": true}));
+    }
+
+    #[tokio::test]
+    async fn test_openai_streaming_usage_and_finish_reason() {
+        let base_url = crate::test_support::serve(
+            200,
+            "data: {\"model\": \"gpt-4o\", \"choices\": [{\"delta\": {\"content\": \"Hi\"}, \"finish_reason\": null}], \"usage\": null}\n\n\
+             data: {\"model\": \"gpt-4o\", \"choices\": [{\"delta\": {}, \"finish_reason\": \"length\"}], \"usage\": null}\n\n\
+             data: {\"model\": \"gpt-4o\", \"choices\": [], \"usage\": {\"prompt_tokens\": 9, \"completion_tokens\": 1, \"total_tokens\": 10}}\n\n\
+             data: [DONE]\n\n",
+            1,
+        )
+        .await;
+        let backend = OpenAiBackend::new().with_stream_usage(true);
+        let response = backend
+            .complete_streaming(&Client::new(), &base_url, &test_request(), &mut |_| {})
+            .await
+            .unwrap();
+
+        assert_eq!(response.text, "Hi");
+        assert_eq!(
+            response.usage,
+            Some(Usage {
+                prompt_tokens: Some(9),
+                completion_tokens: Some(1),
+                total_tokens: Some(10),
+            })
+        );
+        assert_eq!(response.metadata.unwrap()["finish_reason"], "length");
+    }
+
     #[test]
     fn test_openai_backend_with_history() {
         let mut request = test_request();
@@ -496,6 +910,35 @@ mod tests {
         assert!(debug_output.contains("None"), "No-key case should show None");
     }
 
+    #[test]
+    fn test_extract_metadata_finish_reason() {
+        let resp = json!({
+            "id": "chatcmpl-1",
+            "choices": [{"message": {"content": "{"}, "finish_reason": "length"}]
+        });
+        let meta = OpenAiBackend::extract_metadata(&resp).unwrap();
+        assert_eq!(meta["finish_reason"], "length");
+    }
+
+    #[test]
+    fn test_extract_metadata_system_fingerprint() {
+        let resp = json!({
+            "model": "gpt-4o-2024-08-06",
+            "system_fingerprint": "fp_44709d6fcb",
+            "choices": [{"message": {"content": "hi"}}]
+        });
+        let meta = OpenAiBackend::extract_metadata(&resp).unwrap();
+        assert_eq!(meta["model"], "gpt-4o-2024-08-06");
+        assert_eq!(meta["system_fingerprint"], "fp_44709d6fcb");
+
+        let meta = OpenAiBackend::extract_metadata(&json!({
+            "model": "local",
+            "system_fingerprint": null
+        }))
+        .unwrap();
+        assert!(meta.get("system_fingerprint").is_none());
+    }
+
     #[test]
     fn test_has_api_key() {
         let without = OpenAiBackend::new();
@@ -511,4 +954,125 @@ mod tests {
         let with = OpenAiBackend::new().with_organization("org-abc");
         assert!(with.has_organization());
     }
+
+    #[test]
+    fn test_openai_n_only_when_non_streaming() {
+        let mut request = test_request();
+        let body = OpenAiBackend::build_body(&request, false);
+        assert!(body.get("n").is_none());
+
+        request.config = LlmConfig::default().with_n(3);
+        let body = OpenAiBackend::build_body(&request, false);
+        assert_eq!(body["n"], 3);
+        let body = OpenAiBackend::build_body(&request, true);
+        assert!(body.get("n").is_none());
+    }
+
+    #[test]
+    fn test_openai_tools() {
+        let mut request = test_request();
+        let body = OpenAiBackend::build_body(&request, false);
+        assert!(body.get("tools").is_none());
+
+        request.tools = vec![ToolSpec::new(
+            "get_weather",
+            "Current weather for a city",
+            json!({"type": "object", "properties": {"city": {"type": "string"}}}),
+        )];
+        let body = OpenAiBackend::build_body(&request, false);
+        assert_eq!(body["tools"][0]["type"], "function");
+        assert_eq!(body["tools"][0]["function"]["name"], "get_weather");
+        assert_eq!(
+            body["tools"][0]["function"]["parameters"]["properties"]["city"]["type"],
+            "string"
+        );
+
+        let resp = json!({"choices": [{"message": {"content": null, "tool_calls": [{
+            "id": "call_1",
+            "type": "function",
+            "function": {"name": "get_weather", "arguments": "{\"city\": \"Paris\"}"},
+        }]}}]});
+        let calls = ToolCall::all_in_message(resp.pointer("/choices/0/message"));
+        assert_eq!(
+            calls,
+            vec![ToolCall {
+                id: Some("call_1".into()),
+                name: "get_weather".into(),
+                arguments: json!({"city": "Paris"}),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_openai_accumulate_tool_calls() {
+        let chunks = [
+            json!({"choices": [{"delta": {"tool_calls": [{"index": 0, "id": "call_1",
+                "function": {"name": "get_weather", "arguments": ""}}]}}]}),
+            json!({"choices": [{"delta": {"tool_calls": [{"index": 0,
+                "function": {"arguments": "{\"city\": "}}]}}]}),
+            json!({"choices": [{"delta": {"tool_calls": [{"index": 1, "id": "call_2",
+                "function": {"name": "get_time"}}]}}]}),
+            json!({"choices": [{"delta": {"tool_calls": [{"index": 0,
+                "function": {"arguments": "\"Paris\"}"}}]}}]}),
+            json!({"choices": [{"delta": {"content": "ignored"}}]}),
+        ];
+        let mut partial = Vec::new();
+        for chunk in &chunks {
+            OpenAiBackend::accumulate_tool_calls(chunk, &mut partial).unwrap();
+        }
+        let calls: Vec<ToolCall> = partial
+            .iter()
+            .filter_map(ToolCall::from_function_json)
+            .collect();
+        assert_eq!(calls.len(), 2);
+        assert_eq!(calls[0].id.as_deref(), Some("call_1"));
+        assert_eq!(calls[0].arguments, json!({"city": "Paris"}));
+        assert_eq!(calls[1].name, "get_time");
+        assert_eq!(calls[1].arguments, json!({}));
+
+        for index in [json!(MAX_TOOL_CALLS), json!(1_000_000_000_000u64), json!(u64::MAX)] {
+            let chunk = json!({"choices": [{"delta": {"tool_calls": [{"index": index}]}}]});
+            let mut partial = Vec::new();
+            assert!(OpenAiBackend::accumulate_tool_calls(&chunk, &mut partial).is_err());
+            assert!(partial.is_empty());
+        }
+    }
+
+    #[test]
+    fn test_openai_extract_choices() {
+        let resp = json!({"choices": [
+            {"message": {"content": "A"}},
+            {"message": {"content": "B"}},
+        ]});
+        assert_eq!(OpenAiBackend::extract_choices(&resp), vec!["A", "B"]);
+        assert!(OpenAiBackend::extract_choices(&json!({})).is_empty());
+    }
+
+    #[test]
+    fn test_openai_extract_refusal() {
+        let resp = json!({"choices": [{
+            "message": {"content": null, "refusal": "I can't help with that."},
+            "finish_reason": "stop",
+            "content_filter_results": {"violence": {"filtered": false}},
+        }]});
+        assert_eq!(
+            OpenAiBackend::extract_refusal(&resp).as_deref(),
+            Some("I can't help with that.")
+        );
+        let meta = OpenAiBackend::extract_metadata(&resp).unwrap();
+        assert_eq!(
+            meta["content_filter_results"]["violence"]["filtered"],
+            false
+        );
+        let answered = json!({"choices": [{"message": {"content": "hi", "refusal": null}}]});
+        assert_eq!(OpenAiBackend::extract_refusal(&answered), None);
+
+        let mut refusal = None;
+        for part in ["I can't", " help."] {
+            let chunk = json!({"choices": [{"delta": {"refusal": part}}]});
+            OpenAiBackend::accumulate_refusal(&chunk, &mut refusal);
+        }
+        OpenAiBackend::accumulate_refusal(&json!({"choices": [{"delta": {}}]}), &mut refusal);
+        assert_eq!(refusal.as_deref(), Some("I can't help."));
+    }
 }
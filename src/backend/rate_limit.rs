@@ -0,0 +1,390 @@
+//! Provider-reported rate limits.
+//!
+//! Cloud providers report how much of the current rate-limit window is left
+//! in `x-ratelimit-*` (OpenAI) or `anthropic-ratelimit-*` response headers. [`RateLimitInfo`] reads them, and a
+//! [`RateLimitPacer`] (enabled with
+//! [`BackoffConfig::pace_rate_limits`](super::BackoffConfig::pace_rate_limits))
+//! holds the next call back until the window resets when the remaining
+//! budget runs low, instead of letting it fail with a 429.
+
+use reqwest::header::HeaderMap;
+use serde_json::{json, Value};
+use std::sync::atomic::AtomicBool;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+
+/// Remaining rate-limit budget reported by a provider.
+///
+/// Read from the OpenAI-style headers `x-ratelimit-remaining-requests`,
+/// `x-ratelimit-remaining-tokens`, `x-ratelimit-reset-requests`, and
+/// `x-ratelimit-reset-tokens`, or Anthropic's
+/// `anthropic-ratelimit-{requests,tokens}-{remaining,reset}`, whose resets
+/// are RFC 3339 timestamps. The built-in OpenAI and Anthropic backends store
+/// it in the response metadata under `rate_limit`; see
+/// [`LlmResponse::rate_limit`](super::LlmResponse::rate_limit).
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RateLimitInfo {
+    /// Requests left in the current window.
+    pub remaining_requests: Option<u64>,
+    /// Tokens left in the current window.
+    pub remaining_tokens: Option<u64>,
+    /// Time until the request budget resets.
+    pub reset_requests: Option<Duration>,
+    /// Time until the token budget resets.
+    pub reset_tokens: Option<Duration>,
+}
+
+impl RateLimitInfo {
+    /// Read the rate-limit headers. `None` if the response carries none.
+    pub fn from_headers(headers: &HeaderMap) -> Option<Self> {
+        Self::from_lookup(|name| headers.get(name).and_then(|v| v.to_str().ok()))
+    }
+
+    fn from_lookup<'h>(get: impl Fn(&str) -> Option<&'h str>) -> Option<Self> {
+        let first = |names: [&str; 2]| names.into_iter().find_map(&get);
+        let count = |names| first(names).and_then(|v| v.trim().parse().ok());
+        let reset = |names| first(names).and_then(parse_reset);
+        let info = Self {
+            remaining_requests: count([
+                "x-ratelimit-remaining-requests",
+                "anthropic-ratelimit-requests-remaining",
+            ]),
+            remaining_tokens: count([
+                "x-ratelimit-remaining-tokens",
+                "anthropic-ratelimit-tokens-remaining",
+            ]),
+            reset_requests: reset([
+                "x-ratelimit-reset-requests",
+                "anthropic-ratelimit-requests-reset",
+            ]),
+            reset_tokens: reset([
+                "x-ratelimit-reset-tokens",
+                "anthropic-ratelimit-tokens-reset",
+            ]),
+        };
+        (info != Self::default()).then_some(info)
+    }
+
+    /// Read the `rate_limit` entry of response metadata, as written by
+    /// [`to_json`](Self::to_json).
+    pub fn from_metadata(metadata: &Value) -> Option<Self> {
+        let entry = metadata.get("rate_limit")?;
+        let ms = |key: &str| {
+            entry
+                .get(key)
+                .and_then(Value::as_u64)
+                .map(Duration::from_millis)
+        };
+        Some(Self {
+            remaining_requests: entry.get("remaining_requests").and_then(Value::as_u64),
+            remaining_tokens: entry.get("remaining_tokens").and_then(Value::as_u64),
+            reset_requests: ms("reset_requests_ms"),
+            reset_tokens: ms("reset_tokens_ms"),
+        })
+    }
+
+    /// JSON form stored in response metadata, with resets in milliseconds.
+    pub fn to_json(&self) -> Value {
+        let ms = |d: Option<Duration>| d.map(|d| d.as_millis() as u64);
+        json!({
+            "remaining_requests": self.remaining_requests,
+            "remaining_tokens": self.remaining_tokens,
+            "reset_requests_ms": ms(self.reset_requests),
+            "reset_tokens_ms": ms(self.reset_tokens),
+        })
+    }
+
+    /// Add this info to `metadata` as its `rate_limit` entry, which is where
+    /// a [`RateLimitPacer`] looks for it. For custom backends that read the
+    /// headers themselves.
+    pub fn attach(&self, metadata: Option<Value>) -> Option<Value> {
+        let mut meta = match metadata {
+            Some(Value::Object(map)) => map,
+            _ => serde_json::Map::new(),
+        };
+        meta.insert("rate_limit".into(), self.to_json());
+        Some(Value::Object(meta))
+    }
+
+    /// How long to wait before the budgets that are down to `headroom` or
+    /// less reset. `None` if neither is that low.
+    pub fn delay_at(&self, headroom: u64) -> Option<Duration> {
+        let low = |remaining: Option<u64>, reset: Option<Duration>| {
+            remaining.filter(|&n| n <= headroom).and(reset)
+        };
+        low(self.remaining_requests, self.reset_requests)
+            .max(low(self.remaining_tokens, self.reset_tokens))
+    }
+
+    /// Delay before retrying a rate-limited (429) request: until the
+    /// exhausted budget resets, or the later of the two resets if neither
+    /// reports zero remaining (a request can need more tokens than are left).
+    pub fn retry_delay(&self) -> Option<Duration> {
+        self.delay_at(0)
+            .or(self.reset_requests.max(self.reset_tokens))
+    }
+}
+
+/// Parse a reset time: plain seconds (`"20"`, `"0.5"`), a Go-style
+/// duration (`"1s"`, `"6m0s"`, `"120ms"`), or an RFC 3339 timestamp
+/// (`"2024-05-01T12:00:30Z"`), which gives the time left until then.
+fn parse_reset(value: &str) -> Option<Duration> {
+    let value = value.trim();
+    if let Some(at) = parse_timestamp(value) {
+        return Some(at.duration_since(SystemTime::now()).unwrap_or_default());
+    }
+    if let Ok(secs) = value.parse::<f64>() {
+        return Duration::try_from_secs_f64(secs).ok();
+    }
+    let mut total = 0.0;
+    let mut rest = value;
+    while !rest.is_empty() {
+        let split = rest
+            .find(|c: char| !c.is_ascii_digit() && c != '.')
+            .filter(|&i| i > 0)?;
+        let (number, tail) = rest.split_at(split);
+        let number: f64 = number.parse().ok()?;
+        let unit_len = tail
+            .find(|c: char| c.is_ascii_digit())
+            .unwrap_or(tail.len());
+        let (unit, tail) = tail.split_at(unit_len);
+        total += number
+            * match unit {
+                "h" => 3600.0,
+                "m" => 60.0,
+                "s" => 1.0,
+                "ms" => 0.001,
+                _ => return None,
+            };
+        rest = tail;
+    }
+    Duration::try_from_secs_f64(total).ok()
+}
+
+/// Parse an RFC 3339 timestamp such as `"2024-05-01T12:00:30Z"` or
+/// `"2024-05-01T14:00:30.5+02:00"`.
+fn parse_timestamp(value: &str) -> Option<SystemTime> {
+    let (date, time) = value.split_once(['T', 't'])?;
+    let mut date = date.splitn(3, '-').map(|part| part.parse::<i64>().ok());
+    let (year, month, day) = (date.next()??, date.next()??, date.next()??);
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
+    }
+
+    let (clock, offset) = match time.find(['Z', 'z', '+', '-']) {
+        Some(i) => time.split_at(i),
+        None => return None,
+    };
+    let offset_secs = match offset {
+        "Z" | "z" => 0,
+        _ => {
+            let (hours, minutes) = offset[1..].split_once(':')?;
+            let secs = hours.parse::<i64>().ok()? * 3600 + minutes.parse::<i64>().ok()? * 60;
+            if offset.starts_with('-') {
+                -secs
+            } else {
+                secs
+            }
+        }
+    };
+    let mut clock = clock.splitn(3, ':');
+    let hours: i64 = clock.next()?.parse().ok()?;
+    let minutes: i64 = clock.next()?.parse().ok()?;
+    let seconds: f64 = clock.next()?.parse().ok()?;
+
+    // Days since the Unix epoch in the proleptic Gregorian calendar.
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;
+    let doy = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let days = era * 146_097 + yoe * 365 + yoe / 4 - yoe / 100 + doy - 719_468;
+
+    let whole = days * 86_400 + hours * 3600 + minutes * 60 - offset_secs;
+    let secs = whole as f64 + seconds;
+    let since_epoch = Duration::try_from_secs_f64(secs).ok()?;
+    SystemTime::UNIX_EPOCH.checked_add(since_epoch)
+}
+
+/// Holds calls back while a provider's rate-limit budget is nearly spent.
+///
+/// After each successful response that reports a [`RateLimitInfo`], a
+/// budget with `headroom` or fewer units left schedules a pause until it
+/// resets; the next call made through
+/// [`with_backoff`](super::with_backoff) or
+/// [`with_backoff_streaming`](super::with_backoff_streaming) waits it out.
+/// Clones share the same schedule, so every call using the same
+/// [`BackoffConfig`](super::BackoffConfig) (e.g. through a shared
+/// [`ExecCtx`](crate::ExecCtx)) is paced together.
+#[derive(Debug, Clone)]
+pub struct RateLimitPacer {
+    headroom: u64,
+    resume_at: Arc<Mutex<Option<Instant>>>,
+}
+
+impl RateLimitPacer {
+    /// Pause once a budget has `headroom` or fewer requests or tokens left.
+    pub fn new(headroom: u64) -> Self {
+        Self {
+            headroom,
+            resume_at: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    /// Record the budget reported by a response.
+    pub fn observe(&self, info: &RateLimitInfo) {
+        if let Some(delay) = info.delay_at(self.headroom) {
+            let until = Instant::now() + delay;
+            let mut resume_at = self.resume_at.lock().unwrap();
+            *resume_at = (*resume_at).max(Some(until));
+        }
+    }
+
+    /// How long the next call would be held back.
+    pub fn pending_delay(&self) -> Option<Duration> {
+        self.resume_at
+            .lock()
+            .unwrap()
+            .map(|until| until.saturating_duration_since(Instant::now()))
+            .filter(|d| !d.is_zero())
+    }
+
+    /// Sleep until the scheduled pause, if any, is over, for at most `max`.
+    ///
+    /// Returns `false` without finishing the pause if `cancel` is raised
+    /// while waiting.
+    pub(crate) async fn wait(&self, max: Duration, cancel: Option<&AtomicBool>) -> bool {
+        let Some(delay) = self.pending_delay() else {
+            return true;
+        };
+        super::sleep_unless_cancelled(delay.min(max), cancel).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::Ordering;
+
+    fn info(pairs: &[(&str, &str)]) -> Option<RateLimitInfo> {
+        RateLimitInfo::from_lookup(|name| pairs.iter().find(|(k, _)| *k == name).map(|(_, v)| *v))
+    }
+
+    #[test]
+    fn test_parse_reset_formats() {
+        assert_eq!(parse_reset("20"), Some(Duration::from_secs(20)));
+        assert_eq!(parse_reset("0.5"), Some(Duration::from_millis(500)));
+        assert_eq!(parse_reset("1s"), Some(Duration::from_secs(1)));
+        assert_eq!(parse_reset("6m0s"), Some(Duration::from_secs(360)));
+        assert_eq!(parse_reset("120ms"), Some(Duration::from_millis(120)));
+        assert_eq!(
+            parse_reset("1h2m3.5s"),
+            Some(Duration::from_millis(3_723_500))
+        );
+        assert_eq!(parse_reset("soon"), None);
+        assert_eq!(parse_reset("5d"), None);
+    }
+
+    #[test]
+    fn test_rate_limit_info_from_headers() {
+        assert_eq!(info(&[]), None);
+        let parsed = info(&[
+            ("x-ratelimit-remaining-requests", "0"),
+            ("x-ratelimit-remaining-tokens", "4000"),
+            ("x-ratelimit-reset-requests", "2s"),
+            ("x-ratelimit-reset-tokens", "500ms"),
+        ])
+        .unwrap();
+        assert_eq!(parsed.remaining_requests, Some(0));
+        assert_eq!(parsed.delay_at(0), Some(Duration::from_secs(2)));
+        assert_eq!(parsed.delay_at(5000), Some(Duration::from_secs(2)));
+        assert_eq!(parsed.retry_delay(), Some(Duration::from_secs(2)));
+
+        let roundtrip = RateLimitInfo::from_metadata(&json!({"rate_limit": parsed.to_json()}));
+        assert_eq!(roundtrip, Some(parsed));
+
+        // No budget at zero: retry once the later window resets.
+        let partial = info(&[
+            ("x-ratelimit-remaining-tokens", "10"),
+            ("x-ratelimit-reset-requests", "1s"),
+            ("x-ratelimit-reset-tokens", "3s"),
+        ])
+        .unwrap();
+        assert_eq!(partial.delay_at(0), None);
+        assert_eq!(partial.retry_delay(), Some(Duration::from_secs(3)));
+    }
+
+    #[test]
+    fn test_parse_timestamp() {
+        let at = |secs: u64| Some(SystemTime::UNIX_EPOCH + Duration::from_secs(secs));
+        assert_eq!(parse_timestamp("1970-01-01T00:00:00Z"), at(0));
+        assert_eq!(parse_timestamp("2024-05-01T12:00:30Z"), at(1_714_564_830));
+        assert_eq!(
+            parse_timestamp("2024-05-01T14:00:30+02:00"),
+            at(1_714_564_830)
+        );
+        assert_eq!(
+            parse_timestamp("2024-05-01T12:00:30.5Z"),
+            Some(SystemTime::UNIX_EPOCH + Duration::from_millis(1_714_564_830_500))
+        );
+        assert_eq!(parse_timestamp("2024-05-01"), None);
+        assert_eq!(parse_timestamp("2024-13-01T00:00:00Z"), None);
+    }
+
+    #[test]
+    fn test_rate_limit_info_from_anthropic_headers() {
+        let parsed = info(&[
+            ("anthropic-ratelimit-requests-remaining", "0"),
+            ("anthropic-ratelimit-tokens-remaining", "12000"),
+            ("anthropic-ratelimit-requests-reset", "1970-01-01T00:00:00Z"),
+        ])
+        .unwrap();
+        assert_eq!(parsed.remaining_requests, Some(0));
+        assert_eq!(parsed.remaining_tokens, Some(12000));
+        // A reset time already in the past means no wait.
+        assert_eq!(parsed.reset_requests, Some(Duration::ZERO));
+        assert_eq!(parsed.reset_tokens, None);
+    }
+
+    #[test]
+    fn test_pacer_schedules_pause_below_headroom() {
+        let pacer = RateLimitPacer::new(2);
+        pacer.observe(&RateLimitInfo {
+            remaining_requests: Some(3),
+            reset_requests: Some(Duration::from_secs(10)),
+            ..Default::default()
+        });
+        assert_eq!(pacer.pending_delay(), None);
+
+        pacer.clone().observe(&RateLimitInfo {
+            remaining_requests: Some(1),
+            reset_requests: Some(Duration::from_secs(10)),
+            ..Default::default()
+        });
+        let delay = pacer.pending_delay().unwrap();
+        assert!(delay > Duration::from_secs(9) && delay <= Duration::from_secs(10));
+    }
+
+    #[tokio::test]
+    async fn test_pacer_wait_is_capped_and_cancellable() {
+        let pacer = RateLimitPacer::new(0);
+        pacer.observe(&RateLimitInfo {
+            remaining_requests: Some(0),
+            reset_requests: Some(Duration::from_secs(3600)),
+            ..Default::default()
+        });
+
+        let start = Instant::now();
+        assert!(pacer.wait(Duration::from_millis(20), None).await);
+        assert!(start.elapsed() < Duration::from_secs(1));
+
+        let cancel = Arc::new(AtomicBool::new(false));
+        let flag = cancel.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            flag.store(true, Ordering::Relaxed);
+        });
+        let start = Instant::now();
+        assert!(!pacer.wait(Duration::from_secs(60), Some(&cancel)).await);
+        assert!(start.elapsed() < Duration::from_secs(1));
+    }
+}
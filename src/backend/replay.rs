@@ -0,0 +1,504 @@
+//! Record live responses and replay them later, with stream timing.
+//!
+//! [`RecordingBackend`] wraps another backend and keeps a [`RecordedCall`]
+//! for every completion, including each streamed token and the time since
+//! the previous one. [`ReplayBackend`] plays the recordings back in order,
+//! re-emitting streamed tokens with their original pacing (optionally sped
+//! up), so streaming UIs can be tested deterministically against realistic
+//! timing without a live model.
+//!
+//! Recordings are plain serde types; persist them with `serde_json`.
+//!
+//! # Example
+//!
+//! ```
+//! use llm_pipeline::backend::{MockBackend, RecordingBackend, ReplayBackend};
+//! use llm_pipeline::payload::Payload;
+//! use llm_pipeline::{ExecCtx, LlmCall};
+//! use serde_json::json;
+//! use std::sync::Arc;
+//!
+//! # tokio_test::block_on(async {
+//! let recorder = Arc::new(RecordingBackend::new(Arc::new(MockBackend::fixed("hi"))));
+//! let ctx = ExecCtx::builder("http://localhost:11434")
+//!     .backend(recorder.clone())
+//!     .build();
+//! let call = LlmCall::new("greet", "Greet {input}").with_streaming(true);
+//! call.invoke(&ctx, json!("Ada")).await.unwrap();
+//!
+//! // Serve the same stream again, twice as fast.
+//! let replay = ReplayBackend::new(recorder.recordings()).with_speed(2.0);
+//! # });
+//! ```
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use super::{Backend, LlmRequest, LlmResponse, ToolCall, Usage};
+use crate::error::Result;
+
+/// One streamed token and how long after the previous token (or the start
+/// of the call, for the first) it arrived.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RecordedToken {
+    /// Token text.
+    pub text: String,
+    /// Milliseconds since the previous token.
+    pub delay_ms: u64,
+    /// Whether the token came from a `<think>` block reported separately
+    /// (see [`ThinkStreamMode::Separate`](crate::streaming::ThinkStreamMode::Separate)).
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub thinking: bool,
+}
+
+/// A completion captured by [`RecordingBackend`].
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct RecordedCall {
+    /// Model the request asked for.
+    pub model: String,
+    /// The request's prompt.
+    pub prompt: String,
+    /// The full response text.
+    pub text: String,
+    /// HTTP status of the response.
+    pub status: u16,
+    /// Provider metadata of the response.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<Value>,
+    /// The response's refusal, if the model declined to answer.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub refusal: Option<String>,
+    /// Tool calls the model requested.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tool_calls: Vec<ToolCall>,
+    /// Streamed tokens in arrival order. Empty for non-streaming calls.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tokens: Vec<RecordedToken>,
+}
+
+impl RecordedCall {
+    fn new(request: &LlmRequest, response: &LlmResponse, tokens: Vec<RecordedToken>) -> Self {
+        Self {
+            model: request.model.clone(),
+            prompt: request.prompt.clone(),
+            text: response.text.clone(),
+            status: response.status,
+            metadata: response.metadata.clone(),
+            refusal: response.refusal.clone(),
+            tool_calls: response.tool_calls.clone(),
+            tokens,
+        }
+    }
+
+    fn response(&self) -> LlmResponse {
+        LlmResponse {
+            text: self.text.clone(),
+            status: self.status,
+            metadata: self.metadata.clone(),
+            candidates: Vec::new(),
+            refusal: self.refusal.clone(),
+            usage: self.metadata.as_ref().and_then(Usage::from_metadata),
+            tool_calls: self.tool_calls.clone(),
+        }
+    }
+}
+
+/// Stamps tokens with the time since the previous one.
+struct TokenClock {
+    last: Instant,
+    tokens: Vec<RecordedToken>,
+}
+
+impl TokenClock {
+    fn new() -> Self {
+        Self {
+            last: Instant::now(),
+            tokens: Vec::new(),
+        }
+    }
+
+    fn record(&mut self, text: &str, thinking: bool) {
+        let now = Instant::now();
+        self.tokens.push(RecordedToken {
+            text: text.to_string(),
+            delay_ms: now.duration_since(self.last).as_millis() as u64,
+            thinking,
+        });
+        self.last = now;
+    }
+}
+
+/// Wraps a backend and records every completion it serves.
+///
+/// Streaming calls also record each token with its timing. Failed calls are
+/// not recorded. Read the recordings with
+/// [`recordings`](Self::recordings).
+pub struct RecordingBackend {
+    inner: Arc<dyn Backend>,
+    calls: Mutex<Vec<RecordedCall>>,
+}
+
+impl RecordingBackend {
+    /// Record the calls served by `inner`.
+    pub fn new(inner: Arc<dyn Backend>) -> Self {
+        Self {
+            inner,
+            calls: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// The calls recorded so far, in completion order.
+    pub fn recordings(&self) -> Vec<RecordedCall> {
+        self.calls.lock().unwrap().clone()
+    }
+
+    fn push(&self, call: RecordedCall) {
+        self.calls.lock().unwrap().push(call);
+    }
+}
+
+#[async_trait]
+impl Backend for RecordingBackend {
+    async fn complete(
+        &self,
+        client: &Client,
+        base_url: &str,
+        request: &LlmRequest,
+    ) -> Result<LlmResponse> {
+        let response = self.inner.complete(client, base_url, request).await?;
+        self.push(RecordedCall::new(request, &response, Vec::new()));
+        Ok(response)
+    }
+
+    async fn complete_streaming(
+        &self,
+        client: &Client,
+        base_url: &str,
+        request: &LlmRequest,
+        on_token: &mut (dyn FnMut(String) + Send),
+    ) -> Result<LlmResponse> {
+        let mut clock = TokenClock::new();
+        let mut record = |token: String| {
+            clock.record(&token, false);
+            on_token(token);
+        };
+        let response = self
+            .inner
+            .complete_streaming(client, base_url, request, &mut record)
+            .await?;
+        let tokens = clock.tokens;
+        self.push(RecordedCall::new(request, &response, tokens));
+        Ok(response)
+    }
+
+    async fn complete_streaming_with_thinking(
+        &self,
+        client: &Client,
+        base_url: &str,
+        request: &LlmRequest,
+        on_token: &mut (dyn FnMut(String) + Send),
+        on_thinking: &mut (dyn FnMut(String) + Send),
+    ) -> Result<LlmResponse> {
+        // Both callbacks stamp the same clock.
+        let clock = Mutex::new(TokenClock::new());
+        let mut record = |token: String| {
+            clock.lock().unwrap().record(&token, false);
+            on_token(token);
+        };
+        let mut record_thinking = |token: String| {
+            clock.lock().unwrap().record(&token, true);
+            on_thinking(token);
+        };
+        let response = self
+            .inner
+            .complete_streaming_with_thinking(
+                client,
+                base_url,
+                request,
+                &mut record,
+                &mut record_thinking,
+            )
+            .await?;
+        let tokens = clock.into_inner().unwrap().tokens;
+        self.push(RecordedCall::new(request, &response, tokens));
+        Ok(response)
+    }
+
+    async fn complete_streaming_with_metadata(
+        &self,
+        client: &Client,
+        base_url: &str,
+        request: &LlmRequest,
+        on_token: &mut (dyn FnMut(String) + Send),
+        on_thinking: Option<&mut (dyn FnMut(String) + Send)>,
+        on_metadata: &mut (dyn FnMut(serde_json::Value) + Send),
+    ) -> Result<LlmResponse> {
+        // Forwarded as-is so the inner backend can still report metadata
+        // mid-stream.
+        let clock = Mutex::new(TokenClock::new());
+        let mut record = |token: String| {
+            clock.lock().unwrap().record(&token, false);
+            on_token(token);
+        };
+        let mut record_thinking = on_thinking.map(|on_thinking| {
+            let clock = &clock;
+            move |token: String| {
+                clock.lock().unwrap().record(&token, true);
+                on_thinking(token);
+            }
+        });
+        let response = self
+            .inner
+            .complete_streaming_with_metadata(
+                client,
+                base_url,
+                request,
+                &mut record,
+                record_thinking
+                    .as_mut()
+                    .map(|f| f as &mut (dyn FnMut(String) + Send)),
+                on_metadata,
+            )
+            .await?;
+        let tokens = clock.into_inner().unwrap().tokens;
+        self.push(RecordedCall::new(request, &response, tokens));
+        Ok(response)
+    }
+
+    async fn embed(
+        &self,
+        client: &Client,
+        base_url: &str,
+        model: &str,
+        texts: &[String],
+    ) -> Result<Vec<Vec<f32>>> {
+        self.inner.embed(client, base_url, model, texts).await
+    }
+
+    fn name(&self) -> &'static str {
+        self.inner.name()
+    }
+}
+
+/// Serves recorded calls in order, replaying stream timing.
+///
+/// Like [`MockBackend`](super::MockBackend), cycles back to the first
+/// recording when all have been served. Streaming calls re-emit the
+/// recorded tokens, sleeping for each token's recorded delay divided by
+/// [`with_speed`](Self::with_speed); a recording made without streaming is
+/// emitted as a single token.
+#[derive(Debug)]
+pub struct ReplayBackend {
+    calls: Vec<RecordedCall>,
+    index: AtomicUsize,
+    speed: f64,
+}
+
+impl ReplayBackend {
+    /// Replay `calls` in order at their original pace.
+    pub fn new(calls: Vec<RecordedCall>) -> Self {
+        assert!(
+            !calls.is_empty(),
+            "ReplayBackend requires at least one recording"
+        );
+        Self {
+            calls,
+            index: AtomicUsize::new(0),
+            speed: 1.0,
+        }
+    }
+
+    /// Replay `factor` times faster than recorded (`2.0` halves every
+    /// delay). `f64::INFINITY` drops the delays entirely. Non-positive
+    /// factors are ignored. Default: `1.0`.
+    pub fn with_speed(mut self, factor: f64) -> Self {
+        if factor > 0.0 {
+            self.speed = factor;
+        }
+        self
+    }
+
+    fn next_call(&self) -> &RecordedCall {
+        let idx = self.index.fetch_add(1, Ordering::Relaxed) % self.calls.len();
+        &self.calls[idx]
+    }
+
+    async fn pause(&self, delay_ms: u64) {
+        let delay = Duration::from_millis(delay_ms).div_f64(self.speed);
+        if !delay.is_zero() {
+            tokio::time::sleep(delay).await;
+        }
+    }
+
+    async fn replay(
+        &self,
+        on_token: &mut (dyn FnMut(String) + Send),
+        mut on_thinking: Option<&mut (dyn FnMut(String) + Send)>,
+    ) -> LlmResponse {
+        let call = self.next_call();
+        if call.tokens.is_empty() {
+            on_token(call.text.clone());
+        }
+        for token in &call.tokens {
+            self.pause(token.delay_ms).await;
+            match on_thinking.as_mut() {
+                Some(on_thinking) if token.thinking => on_thinking(token.text.clone()),
+                _ => on_token(token.text.clone()),
+            }
+        }
+        call.response()
+    }
+}
+
+#[async_trait]
+impl Backend for ReplayBackend {
+    async fn complete(
+        &self,
+        _client: &Client,
+        _base_url: &str,
+        _request: &LlmRequest,
+    ) -> Result<LlmResponse> {
+        Ok(self.next_call().response())
+    }
+
+    async fn complete_streaming(
+        &self,
+        _client: &Client,
+        _base_url: &str,
+        _request: &LlmRequest,
+        on_token: &mut (dyn FnMut(String) + Send),
+    ) -> Result<LlmResponse> {
+        Ok(self.replay(on_token, None).await)
+    }
+
+    async fn complete_streaming_with_thinking(
+        &self,
+        _client: &Client,
+        _base_url: &str,
+        _request: &LlmRequest,
+        on_token: &mut (dyn FnMut(String) + Send),
+        on_thinking: &mut (dyn FnMut(String) + Send),
+    ) -> Result<LlmResponse> {
+        Ok(self.replay(on_token, Some(on_thinking)).await)
+    }
+
+    fn name(&self) -> &'static str {
+        "replay"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::{MockBackend, MockReply};
+
+    /// Streams the words of `text` with a pause before each.
+    fn slow_words(text: &str) -> MockBackend {
+        MockBackend::scripted(vec![MockReply::chunks(text.split_inclusive(' '))
+            .with_chunk_delay(Duration::from_millis(20))])
+    }
+
+    fn request() -> LlmRequest {
+        let mut request = LlmRequest::new("test", "say it");
+        request.stream = true;
+        request
+    }
+
+    #[tokio::test]
+    async fn test_record_and_replay_stream_timing() {
+        let client = Client::new();
+        let recorder = RecordingBackend::new(Arc::new(slow_words("one two three")));
+        let mut seen = Vec::new();
+        recorder
+            .complete_streaming(&client, "", &request(), &mut |t| seen.push(t))
+            .await
+            .unwrap();
+        recorder.complete(&client, "", &request()).await.unwrap();
+
+        let calls = recorder.recordings();
+        assert_eq!(calls.len(), 2);
+        assert_eq!(calls[0].prompt, "say it");
+        assert_eq!(calls[0].tokens.len(), 3);
+        assert!(calls[0].tokens.iter().all(|t| t.delay_ms >= 15));
+        assert!(calls[1].tokens.is_empty());
+
+        let json = serde_json::to_string(&calls).unwrap();
+        let calls: Vec<RecordedCall> = serde_json::from_str(&json).unwrap();
+
+        let replay = ReplayBackend::new(calls).with_speed(2.0);
+        let mut replayed = Vec::new();
+        let start = Instant::now();
+        let response = replay
+            .complete_streaming(&client, "", &request(), &mut |t| replayed.push(t))
+            .await
+            .unwrap();
+        assert!(start.elapsed() >= Duration::from_millis(20));
+        assert_eq!(replayed, seen);
+        assert_eq!(response.text, "one two three");
+
+        // A non-streaming recording replays as a single token.
+        let mut single = Vec::new();
+        replay
+            .complete_streaming(&client, "", &request(), &mut |t| single.push(t))
+            .await
+            .unwrap();
+        assert_eq!(single, vec!["one two three"]);
+    }
+
+    #[tokio::test]
+    async fn test_record_stream_with_metadata() {
+        let inner = MockBackend::scripted(vec![
+            MockReply::chunks(["a", "b"]).with_metadata(serde_json::json!({"eval_count": 2}))
+        ]);
+        let recorder = RecordingBackend::new(Arc::new(inner));
+        let mut seen = Vec::new();
+        let mut metadata = Vec::new();
+        recorder
+            .complete_streaming_with_metadata(
+                &Client::new(),
+                "",
+                &request(),
+                &mut |t| seen.push(t),
+                None,
+                &mut |m| metadata.push(m),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(seen, vec!["a", "b"]);
+        assert_eq!(metadata, vec![serde_json::json!({"eval_count": 2})]);
+        let calls = recorder.recordings();
+        assert_eq!(calls.len(), 1);
+        assert_eq!(calls[0].tokens.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_record_and_replay_tool_calls() {
+        let call = ToolCall {
+            id: Some("call_1".to_string()),
+            name: "get_weather".to_string(),
+            arguments: serde_json::json!({"city": "Paris"}),
+        };
+        let inner =
+            MockBackend::scripted(vec![MockReply::text("").with_tool_calls(vec![call.clone()])]);
+        let recorder = RecordingBackend::new(Arc::new(inner));
+        let client = Client::new();
+        recorder.complete(&client, "", &request()).await.unwrap();
+
+        let json = serde_json::to_string(&recorder.recordings()).unwrap();
+        let calls: Vec<RecordedCall> = serde_json::from_str(&json).unwrap();
+        assert_eq!(calls[0].tool_calls, vec![call.clone()]);
+
+        let response = ReplayBackend::new(calls)
+            .complete(&client, "", &request())
+            .await
+            .unwrap();
+        assert_eq!(response.tool_calls, vec![call]);
+    }
+}
@@ -1,8 +1,10 @@
 //! SSE (Server-Sent Events) stream decoder for OpenAI-compatible APIs.
 //!
 //! Handles the `data: ` prefix, `[DONE]` termination, line buffering across
-//! TCP chunk boundaries, and empty keep-alive lines.
+//! TCP chunk boundaries (including split multi-byte UTF-8 characters), and
+//! empty keep-alive lines.
 
+use crate::streaming::{decode_utf8_into, flush_utf8_into};
 use serde_json::Value;
 
 /// SSE stream decoder for OpenAI-compatible APIs.
@@ -23,6 +25,7 @@ use serde_json::Value;
 #[derive(Debug)]
 pub struct SseDecoder {
     buffer: String,
+    pending: Vec<u8>,
 }
 
 impl SseDecoder {
@@ -30,6 +33,7 @@ impl SseDecoder {
     pub fn new() -> Self {
         Self {
             buffer: String::new(),
+            pending: Vec::new(),
         }
     }
 
@@ -39,8 +43,7 @@ impl SseDecoder {
     /// keep-alive lines. Returns parsed JSON for each complete `data:` line
     /// (excluding the `[DONE]` terminator).
     pub fn decode(&mut self, chunk: &[u8]) -> Vec<Value> {
-        let text = String::from_utf8_lossy(chunk);
-        self.buffer.push_str(&text);
+        decode_utf8_into(&mut self.pending, chunk, &mut self.buffer);
 
         let mut values = Vec::new();
 
@@ -80,6 +83,7 @@ impl SseDecoder {
 
     /// Flush any remaining buffer content.
     pub fn flush(&mut self) -> Vec<Value> {
+        flush_utf8_into(&mut self.pending, &mut self.buffer);
         let remaining = self.buffer.trim().to_string();
         self.buffer.clear();
 
@@ -171,4 +175,14 @@ mod tests {
         let values = decoder.decode(chunk);
         assert_eq!(values.len(), 3);
     }
+
+    #[test]
+    fn test_sse_multibyte_split_across_chunks() {
+        let mut decoder = SseDecoder::new();
+        let data = "data: {\"choices\":[{\"delta\":{\"content\":\"é😀\"}}]}\n\n".as_bytes();
+        let split = data.iter().position(|&b| b == 0xF0).unwrap() + 1;
+        assert!(decoder.decode(&data[..split]).is_empty());
+        let values = decoder.decode(&data[split..]);
+        assert_eq!(values[0]["choices"][0]["delta"]["content"], "é😀");
+    }
 }
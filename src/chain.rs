@@ -2,15 +2,138 @@
 //!
 //! [`Chain`] composes multiple payloads into a sequential pipeline,
 //! passing each payload's output `value` as the next payload's input.
-//! For branching, loops, or parallel execution, use a graph runtime.
+//! Steps added with [`Chain::push_until`] can end the chain early, and
+//! [`Chain::with_token_budget`] and [`Chain::with_deadline`] cap what the
+//! chain may spend in tokens and time, and [`Chain::with_pipe_mode`] can
+//! forward the raw response text as well.
+//! Every step's
+//! output, including those inside nested chains, is kept in a
+//! [`ChainResult`] tree. For branching, loops, or parallel execution, use a
+//! graph runtime.
 
 use crate::{
+    backend::Usage,
     error::Result,
+    events::{emit, Event},
     exec_ctx::ExecCtx,
+    output_strategy::OutputStrategy,
     payload::{BoxFut, Payload, PayloadOutput},
     PipelineError,
 };
 use serde_json::Value;
+use std::time::{Duration, Instant};
+
+/// Predicate deciding whether a [`Chain`] should stop after a step.
+type StopFn = Box<dyn Fn(&PayloadOutput) -> bool + Send + Sync>;
+
+/// What a [`Chain`] passes from one step to the next.
+///
+/// Set with [`Chain::with_pipe_mode`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PipeMode {
+    /// The parsed [`value`](PayloadOutput::value). The default.
+    #[default]
+    ValueOnly,
+    /// The unparsed [`raw_response`](PayloadOutput::raw_response), as a
+    /// JSON string.
+    RawText,
+    /// Both, as `{"value": ..., "raw": "..."}`.
+    Both,
+}
+
+impl PipeMode {
+    /// The next step's input for `output`.
+    fn input_from(self, output: &PayloadOutput) -> Value {
+        match self {
+            PipeMode::ValueOnly => output.value.clone(),
+            PipeMode::RawText => Value::String(output.raw_response.clone()),
+            PipeMode::Both => serde_json::json!({
+                "value": output.value,
+                "raw": output.raw_response,
+            }),
+        }
+    }
+}
+
+/// Step-by-step outputs of a [`Chain`] run.
+///
+/// Returned by [`Chain::execute_tree`] and attached to the output of
+/// [`Chain::execute`] (and therefore of a chain used as a [`Payload`]) as
+/// [`PayloadOutput::chain`]. A step that is itself a chain carries its own
+/// `ChainResult`, so nested pipelines form a tree. In an attached tree the
+/// last step's output is the output it is attached to, so that step keeps
+/// only its model, diagnostics, and sub-chain.
+#[derive(Debug, Clone)]
+pub struct ChainResult {
+    /// Name of the chain.
+    pub name: String,
+    /// One entry per executed step, in order.
+    pub steps: Vec<ChainStep>,
+}
+
+/// One executed step of a [`ChainResult`].
+#[derive(Debug, Clone)]
+pub struct ChainStep {
+    /// The step payload's name.
+    pub name: String,
+    /// The step payload's kind (e.g. `"llm-call"`, `"chain"`).
+    pub kind: &'static str,
+    /// What the step returned.
+    pub output: PayloadOutput,
+}
+
+impl ChainStep {
+    /// The step's own step tree, if it is a nested chain.
+    pub fn sub_chain(&self) -> Option<&ChainResult> {
+        self.output.chain.as_deref()
+    }
+}
+
+impl ChainResult {
+    /// The final step's output. In a tree attached to a
+    /// [`Chain::execute`] output that is a stub; use the output itself.
+    pub fn output(&self) -> Option<&PayloadOutput> {
+        self.steps.last().map(|step| &step.output)
+    }
+
+    /// Every leaf step (one that isn't itself a chain), depth-first, paired
+    /// with its `/`-separated path from this chain, e.g.
+    /// `"outer/inner/summarize"`.
+    pub fn flatten(&self) -> Vec<(String, &PayloadOutput)> {
+        let mut leaves = Vec::new();
+        self.collect_leaves(&self.name, &mut leaves);
+        leaves
+    }
+
+    /// Token usage summed over every leaf step (see [`flatten`](Self::flatten)),
+    /// from each step's [`ParseDiagnostics::usage`](crate::ParseDiagnostics::usage).
+    /// `None` if no step reported usage.
+    pub fn total_usage(&self) -> Option<Usage> {
+        self.flatten()
+            .into_iter()
+            .filter_map(|(_, output)| output.diagnostics.as_ref()?.usage())
+            .reduce(|a, b| a + b)
+    }
+
+    /// Estimated USD cost summed over every leaf step (see
+    /// [`flatten`](Self::flatten)). `None` if no step has an estimate.
+    pub fn total_cost_usd(&self) -> Option<f64> {
+        self.flatten()
+            .into_iter()
+            .filter_map(|(_, output)| output.estimated_cost_usd())
+            .reduce(|a, b| a + b)
+    }
+
+    fn collect_leaves<'a>(&'a self, prefix: &str, leaves: &mut Vec<(String, &'a PayloadOutput)>) {
+        for step in &self.steps {
+            let path = format!("{}/{}", prefix, step.name);
+            match step.sub_chain() {
+                Some(sub) => sub.collect_leaves(&path, leaves),
+                None => leaves.push((path, &step.output)),
+            }
+        }
+    }
+}
 
 /// A sequential chain of payloads.
 ///
@@ -36,6 +159,12 @@ use serde_json::Value;
 pub struct Chain {
     name: String,
     payloads: Vec<Box<dyn Payload>>,
+    stops: Vec<Option<StopFn>>,
+    default_output_strategy: Option<OutputStrategy>,
+    token_budget: Option<u64>,
+    deadline: Option<Duration>,
+    prev_thinking: bool,
+    pipe_mode: PipeMode,
 }
 
 impl Chain {
@@ -44,18 +173,146 @@ impl Chain {
         Self {
             name: name.into(),
             payloads: Vec::new(),
+            stops: Vec::new(),
+            default_output_strategy: None,
+            token_budget: None,
+            deadline: None,
+            prev_thinking: false,
+            pipe_mode: PipeMode::ValueOnly,
         }
     }
 
     /// Add a payload to the end of the chain (builder style).
     pub fn push(mut self, payload: Box<dyn Payload>) -> Self {
-        self.payloads.push(payload);
+        self.add(payload);
         self
     }
 
     /// Add a payload to the end of the chain (mutation style).
     pub fn add(&mut self, payload: Box<dyn Payload>) {
         self.payloads.push(payload);
+        self.stops.push(None);
+    }
+
+    /// Add a payload that can end the chain early (builder style).
+    ///
+    /// After `payload` runs, `stop` is called with its output; if it returns
+    /// `true`, the remaining steps are skipped, that output becomes the
+    /// chain's result, and [`Event::ChainShortCircuit`] is emitted.
+    ///
+    /// ```ignore
+    /// let chain = Chain::new("triage")
+    ///     .push_until(Box::new(classifier), |out| out.value == "irrelevant")
+    ///     .push(Box::new(summarizer));
+    /// ```
+    pub fn push_until<F>(mut self, payload: Box<dyn Payload>, stop: F) -> Self
+    where
+        F: Fn(&PayloadOutput) -> bool + Send + Sync + 'static,
+    {
+        self.payloads.push(payload);
+        self.stops.push(Some(Box::new(stop)));
+        self
+    }
+
+    /// Set the output strategy for every [`LlmCall`](crate::LlmCall) in this
+    /// chain (including nested chains) that doesn't set its own.
+    ///
+    /// Overrides the context's
+    /// [`default_output_strategy`](crate::ExecCtxBuilder::default_output_strategy).
+    pub fn with_default_output_strategy(mut self, strategy: OutputStrategy) -> Self {
+        self.default_output_strategy = Some(strategy);
+        self
+    }
+
+    /// Abort with [`PipelineError::TokenBudgetExceeded`] once the chain's
+    /// steps have generated more than `max_total_completion_tokens`.
+    ///
+    /// Checked after each step against the context's
+    /// [`completion_tokens_used`](ExecCtx::completion_tokens_used), so only
+    /// usage the backend reports is counted, and other work sharing the
+    /// context while the chain runs counts too. A step is never cut off
+    /// mid-call; the chain stops before the next one.
+    pub fn with_token_budget(mut self, max_total_completion_tokens: u64) -> Self {
+        self.token_budget = Some(max_total_completion_tokens);
+        self
+    }
+
+    /// Abort with [`PipelineError::DeadlineExceeded`] once `deadline` has
+    /// passed since the chain started, for an end-to-end latency budget.
+    /// The error carries the outputs of the steps that did finish.
+    ///
+    /// Checked before each step, so a step that is already running is
+    /// never cut off; bound individual calls with
+    /// [`LlmCall::with_timeout`](crate::LlmCall::with_timeout) or the
+    /// context's request timeouts.
+    pub fn with_deadline(mut self, deadline: Duration) -> Self {
+        self.deadline = Some(deadline);
+        self
+    }
+
+    /// Expose each step's [`thinking`](PayloadOutput::thinking) to the next
+    /// step as the `{prev_thinking}` template var.
+    ///
+    /// Lets a later step reflect on the reasoning behind an earlier answer,
+    /// not just the answer itself. The var is empty for the first step and
+    /// after a step that produced no thinking. Default: off.
+    ///
+    /// ```ignore
+    /// let chain = Chain::new("reflect")
+    ///     .push(Box::new(LlmCall::new("solve", "Solve: {input}")))
+    ///     .push(Box::new(LlmCall::new(
+    ///         "review",
+    ///         "Your reasoning was:\n{prev_thinking}\n\nYour answer was: {input}\nCheck it.",
+    ///     )))
+    ///     .with_prev_thinking(true);
+    /// ```
+    pub fn with_prev_thinking(mut self, enabled: bool) -> Self {
+        self.prev_thinking = enabled;
+        self
+    }
+
+    /// Choose what each step receives from the one before it. Default:
+    /// [`PipeMode::ValueOnly`].
+    ///
+    /// [`PipeMode::Both`] lets a step see the prose the model wrote around
+    /// its JSON, at the cost of a larger input. The first step always gets
+    /// the chain's input unchanged.
+    pub fn with_pipe_mode(mut self, mode: PipeMode) -> Self {
+        self.pipe_mode = mode;
+        self
+    }
+
+    /// Validate the chain, finishing the builder flow.
+    ///
+    /// Fails with [`PipelineError::InvalidConfig`] if the chain is empty or
+    /// two of its steps share a name, so configuration mistakes surface
+    /// here instead of at [`execute`](Self::execute) time. Nested chains
+    /// are validated when they are built, not by their parent.
+    ///
+    /// ```ignore
+    /// let chain = Chain::new("my-chain")
+    ///     .push(Box::new(LlmCall::new("step1", "Analyze: {input}")))
+    ///     .push(Box::new(LlmCall::new("step2", "Refine: {input}")))
+    ///     .build()?;
+    /// ```
+    pub fn build(self) -> Result<Self> {
+        if self.payloads.is_empty() {
+            return Err(PipelineError::InvalidConfig(format!(
+                "Chain '{}' has no payloads",
+                self.name
+            )));
+        }
+        let mut seen = std::collections::HashSet::new();
+        for payload in &self.payloads {
+            if !seen.insert(payload.name()) {
+                return Err(PipelineError::InvalidConfig(format!(
+                    "Chain '{}' has more than one step named '{}'",
+                    self.name,
+                    payload.name()
+                )));
+            }
+        }
+        Ok(self)
     }
 
     /// Number of payloads in the chain.
@@ -71,34 +328,123 @@ impl Chain {
     /// Execute all payloads sequentially, returning every intermediate output.
     ///
     /// The first payload receives `input`. Each subsequent payload receives
-    /// the previous output's `value`.
+    /// the previous output's `value` (or its raw text, per
+    /// [`with_pipe_mode`](Self::with_pipe_mode)). If a [`push_until`](Self::push_until)
+    /// step stops the chain, the returned `Vec` ends with that step's output.
+    ///
+    /// With a [`cost_table`](crate::ExecCtxBuilder::cost_table) set, the
+    /// run's estimated cost is the sum over the outputs:
+    ///
+    /// ```ignore
+    /// let outputs = chain.execute_all(&ctx, input).await?;
+    /// let cost: f64 = outputs.iter().filter_map(|o| o.estimated_cost_usd()).sum();
+    /// ```
     pub async fn execute_all(&self, ctx: &ExecCtx, input: Value) -> Result<Vec<PayloadOutput>> {
+        let tree = self.execute_tree(ctx, input).await?;
+        Ok(tree.steps.into_iter().map(|step| step.output).collect())
+    }
+
+    /// Execute all payloads sequentially, returning the step tree.
+    ///
+    /// Like [`execute_all`](Self::execute_all), but each step is labelled
+    /// with its payload's name and kind, and steps that are nested chains
+    /// expose their own steps via [`ChainStep::sub_chain`].
+    pub async fn execute_tree(&self, ctx: &ExecCtx, input: Value) -> Result<ChainResult> {
         if self.payloads.is_empty() {
             return Err(PipelineError::InvalidConfig(
                 "Chain has no payloads".to_string(),
             ));
         }
 
-        let mut outputs = Vec::with_capacity(self.payloads.len());
+        let scoped;
+        let ctx = match &self.default_output_strategy {
+            Some(strategy) => {
+                let mut child = ctx.clone();
+                child.default_output_strategy = Some(strategy.clone());
+                scoped = child;
+                &scoped
+            }
+            None => ctx,
+        };
+
+        let mut steps: Vec<ChainStep> = Vec::with_capacity(self.payloads.len());
         let mut current = input;
+        let tokens_at_start = ctx.completion_tokens_used();
+        let started = Instant::now();
+        let mut thinking: Option<String> = None;
 
-        for payload in &self.payloads {
+        for (step, (payload, stop)) in self.payloads.iter().zip(&self.stops).enumerate() {
             ctx.check_cancelled()?;
-            let output = payload.invoke(ctx, current).await?;
-            current = output.value.clone();
-            outputs.push(output);
+            if let Some(deadline) = self.deadline {
+                let elapsed = started.elapsed();
+                if elapsed > deadline {
+                    return Err(PipelineError::DeadlineExceeded {
+                        elapsed,
+                        deadline,
+                        completed: steps.into_iter().map(|step| step.output).collect(),
+                    });
+                }
+            }
+            let output = if self.prev_thinking {
+                let step_ctx =
+                    ctx.with_extra_vars([("prev_thinking", thinking.take().unwrap_or_default())]);
+                payload.invoke(&step_ctx, current).await?
+            } else {
+                payload.invoke(ctx, current).await?
+            };
+            if let Some(budget) = self.token_budget {
+                let used = ctx.completion_tokens_used() - tokens_at_start;
+                if used > budget {
+                    return Err(PipelineError::TokenBudgetExceeded { used, budget });
+                }
+            }
+            let stopped = stop.as_ref().is_some_and(|stop| stop(&output));
+            current = self.pipe_mode.input_from(&output);
+            thinking = output.thinking.clone();
+            steps.push(ChainStep {
+                name: payload.name().to_string(),
+                kind: payload.kind(),
+                output,
+            });
+
+            if stopped && step + 1 < self.payloads.len() {
+                emit(
+                    &ctx.event_handler,
+                    Event::ChainShortCircuit {
+                        name: self.name.clone(),
+                        at_step: step,
+                    },
+                );
+                break;
+            }
         }
 
-        Ok(outputs)
+        Ok(ChainResult {
+            name: self.name.clone(),
+            steps,
+        })
     }
 
-    /// Execute all payloads and return only the final output.
+    /// Execute all payloads and return only the final output, with the
+    /// full step tree attached as [`PayloadOutput::chain`].
+    ///
+    /// The final output is moved out of the tree rather than copied: the
+    /// tree's last step keeps only its model, diagnostics, and (for a nested
+    /// chain) its own step tree, so [`ChainResult::flatten`] and the totals
+    /// still count it.
     pub async fn execute(&self, ctx: &ExecCtx, input: Value) -> Result<PayloadOutput> {
-        let outputs = self.execute_all(ctx, input).await?;
-        outputs
-            .into_iter()
-            .last()
-            .ok_or_else(|| PipelineError::Other("Chain produced no outputs".to_string()))
+        let mut tree = self.execute_tree(ctx, input).await?;
+        let last = tree
+            .steps
+            .last_mut()
+            .ok_or_else(|| PipelineError::Other("Chain produced no outputs".to_string()))?;
+        let mut stub = PayloadOutput::from_value(Value::Null);
+        stub.model = last.output.model.clone();
+        stub.diagnostics = last.output.diagnostics.clone();
+        stub.chain = last.output.chain.take();
+        let mut output = std::mem::replace(&mut last.output, stub);
+        output.chain = Some(Box::new(tree));
+        Ok(output)
     }
 }
 
@@ -188,6 +534,32 @@ mod tests {
         assert_eq!(outputs[1].value["from"], "b");
     }
 
+    #[tokio::test]
+    async fn test_chain_pipe_mode() {
+        use crate::payload::from_fn;
+
+        let run = |mode: PipeMode| async move {
+            let chain = Chain::new("test")
+                .push(from_fn("answer", "test", |_, _| {
+                    Box::pin(async {
+                        let mut output = PayloadOutput::from_value(json!({"n": 1}));
+                        output.raw_response = r#"Sure! {"n": 1}"#.to_string();
+                        Ok(output)
+                    })
+                }))
+                .push(Box::new(EchoPayload { tag: "b".into() }))
+                .with_pipe_mode(mode);
+            chain.execute(&test_ctx(), json!("x")).await.unwrap().value["input"].clone()
+        };
+
+        assert_eq!(run(PipeMode::ValueOnly).await, json!({"n": 1}));
+        assert_eq!(run(PipeMode::RawText).await, json!(r#"Sure! {"n": 1}"#));
+        assert_eq!(
+            run(PipeMode::Both).await,
+            json!({"value": {"n": 1}, "raw": r#"Sure! {"n": 1}"#})
+        );
+    }
+
     #[tokio::test]
     async fn test_chain_empty_fails() {
         let chain = Chain::new("empty");
@@ -195,6 +567,30 @@ mod tests {
         assert!(result.is_err());
     }
 
+    #[test]
+    fn test_chain_build_validates() {
+        assert!(matches!(
+            Chain::new("empty").build(),
+            Err(PipelineError::InvalidConfig(_))
+        ));
+
+        let duplicate = Chain::new("dup")
+            .push(Box::new(EchoPayload { tag: "a".into() }))
+            .push(Box::new(EchoPayload { tag: "a".into() }))
+            .build();
+        match duplicate {
+            Err(PipelineError::InvalidConfig(msg)) => assert!(msg.contains("'a'")),
+            _ => panic!("expected InvalidConfig"),
+        }
+
+        let chain = Chain::new("ok")
+            .push(Box::new(EchoPayload { tag: "a".into() }))
+            .push(Box::new(EchoPayload { tag: "b".into() }))
+            .build()
+            .unwrap();
+        assert_eq!(chain.len(), 2);
+    }
+
     #[tokio::test]
     async fn test_chain_cancellation() {
         let cancel = Arc::new(AtomicBool::new(true));
@@ -219,4 +615,309 @@ mod tests {
         let out = outer.execute(&test_ctx(), json!("input")).await.unwrap();
         assert_eq!(out.value["from"], "inner-step");
     }
+
+    #[tokio::test]
+    async fn test_chain_nested_step_tree() {
+        let inner = Chain::new("inner")
+            .push(Box::new(EchoPayload { tag: "i1".into() }))
+            .push(Box::new(EchoPayload { tag: "i2".into() }));
+        let outer = Chain::new("outer")
+            .push(Box::new(EchoPayload { tag: "o1".into() }))
+            .push(Box::new(inner))
+            .push(Box::new(EchoPayload { tag: "o2".into() }));
+
+        let out = outer.execute(&test_ctx(), json!("x")).await.unwrap();
+        let tree = out.chain.as_deref().unwrap();
+        assert_eq!(tree.name, "outer");
+        assert_eq!(tree.steps[1].kind, "chain");
+
+        let sub = tree.steps[1].sub_chain().unwrap();
+        assert_eq!(sub.steps.len(), 2);
+        assert_eq!(sub.steps[0].output.value["input"]["from"], "o1");
+
+        let paths: Vec<String> = tree.flatten().into_iter().map(|(p, _)| p).collect();
+        assert_eq!(
+            paths,
+            ["outer/o1", "outer/inner/i1", "outer/inner/i2", "outer/o2"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_chain_output_not_copied_into_tree() {
+        let inner = Chain::new("inner")
+            .push(Box::new(EchoPayload { tag: "i1".into() }))
+            .push(Box::new(EchoPayload { tag: "i2".into() }));
+        let outer = Chain::new("outer")
+            .push(Box::new(EchoPayload { tag: "o1".into() }))
+            .push(Box::new(inner));
+
+        let out = outer.execute(&test_ctx(), json!("x")).await.unwrap();
+        assert_eq!(out.value["from"], "i2");
+        let tree = out.chain.as_deref().unwrap();
+        assert_eq!(tree.name, "outer");
+
+        let last = &tree.steps[1];
+        assert_eq!(last.output.value, Value::Null);
+        let sub = last.sub_chain().unwrap();
+        assert_eq!(sub.name, "inner");
+        assert_eq!(sub.steps[1].output.value, Value::Null);
+        assert!(sub.steps[1].sub_chain().is_none());
+
+        let paths: Vec<String> = tree.flatten().into_iter().map(|(p, _)| p).collect();
+        assert_eq!(paths, ["outer/o1", "outer/inner/i1", "outer/inner/i2"]);
+    }
+
+    #[tokio::test]
+    async fn test_chain_default_output_strategy() {
+        use crate::{LlmCall, MockBackend, OutputStrategy};
+
+        let ctx = ExecCtx::builder("http://test")
+            .backend(Arc::new(MockBackend::fixed("- a\n- b")))
+            .default_output_strategy(OutputStrategy::Text)
+            .build();
+        let chain = Chain::new("test")
+            .with_default_output_strategy(OutputStrategy::StringList)
+            .push(Box::new(LlmCall::new("inherits", "{input}")))
+            .push(Box::new(LlmCall::new("explicit", "{input}").expecting_text()));
+
+        let outputs = chain.execute_all(&ctx, json!("x")).await.unwrap();
+        let strategy = |o: &PayloadOutput| o.diagnostics.as_ref().unwrap().strategy;
+        assert_eq!(strategy(&outputs[0]), Some("string_list"));
+        assert_eq!(outputs[0].value, json!(["a", "b"]));
+        assert_eq!(strategy(&outputs[1]), Some("text"));
+
+        // Without a chain default, the context default applies.
+        let chain = Chain::new("test").push(Box::new(LlmCall::new("inherits", "{input}")));
+        let out = chain.execute(&ctx, json!("x")).await.unwrap();
+        assert_eq!(strategy(&out), Some("text"));
+    }
+
+    #[tokio::test]
+    async fn test_chain_push_until_short_circuits() {
+        use crate::events::FnEventHandler;
+        use std::sync::Mutex;
+
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let sink = events.clone();
+        let ctx = ExecCtx::builder("http://test")
+            .event_handler(Arc::new(FnEventHandler(move |e: Event| {
+                if let Event::ChainShortCircuit { at_step, .. } = e {
+                    sink.lock().unwrap().push(at_step);
+                }
+            })))
+            .build();
+
+        let chain = Chain::new("test")
+            .push(Box::new(EchoPayload { tag: "a".into() }))
+            .push_until(Box::new(EchoPayload { tag: "b".into() }), |out| {
+                out.value["from"] == "b"
+            })
+            .push(Box::new(EchoPayload { tag: "c".into() }));
+
+        let outputs = chain.execute_all(&ctx, json!("x")).await.unwrap();
+        assert_eq!(outputs.len(), 2);
+        assert_eq!(outputs[1].value["from"], "b");
+        assert_eq!(*events.lock().unwrap(), vec![1]);
+    }
+
+    #[tokio::test]
+    async fn test_chain_push_until_continues() {
+        let chain = Chain::new("test")
+            .push_until(Box::new(EchoPayload { tag: "a".into() }), |_| false)
+            .push(Box::new(EchoPayload { tag: "b".into() }));
+
+        let out = chain.execute(&test_ctx(), json!("x")).await.unwrap();
+        assert_eq!(out.value["from"], "b");
+    }
+
+    /// Answers every call with `"ok"` and reports 10 completion tokens.
+    fn ten_tokens() -> Arc<crate::backend::MockBackend> {
+        Arc::new(crate::backend::MockBackend::scripted(vec![
+            crate::backend::MockReply::text("ok").with_metadata(json!({"eval_count": 10})),
+        ]))
+    }
+
+    /// Returns the `{prev_thinking}` var it was invoked with.
+    struct PrevThinking;
+
+    impl Payload for PrevThinking {
+        fn kind(&self) -> &'static str {
+            "prev-thinking"
+        }
+        fn name(&self) -> &str {
+            "prev-thinking"
+        }
+        fn invoke<'a>(
+            &'a self,
+            ctx: &'a ExecCtx,
+            _input: Value,
+        ) -> BoxFut<'a, Result<PayloadOutput>> {
+            let seen = ctx.vars.get("prev_thinking").cloned();
+            Box::pin(async move { Ok(PayloadOutput::from_value(json!(seen))) })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_chain_prev_thinking() {
+        use crate::{LlmCall, MockBackend};
+
+        let ctx = ExecCtx::builder("http://test")
+            .backend(Arc::new(MockBackend::fixed(
+                "<think>carry the one</think>42",
+            )))
+            .build();
+        let chain = || {
+            Chain::new("reflect")
+                .push(Box::new(LlmCall::new("solve", "{input}")))
+                .push(Box::new(PrevThinking))
+        };
+
+        let out = chain()
+            .with_prev_thinking(true)
+            .execute(&ctx, json!("6 * 7"))
+            .await
+            .unwrap();
+        assert_eq!(out.value, json!("carry the one"));
+
+        let out = chain().execute(&ctx, json!("6 * 7")).await.unwrap();
+        assert_eq!(out.value, Value::Null);
+    }
+
+    #[tokio::test]
+    async fn test_chain_token_budget() {
+        use crate::LlmCall;
+
+        let ctx = ExecCtx::builder("http://test")
+            .backend(ten_tokens())
+            .build();
+        let chain = || {
+            Chain::new("budgeted")
+                .push(Box::new(LlmCall::new("a", "{input}")))
+                .push(Box::new(LlmCall::new("b", "{input}")))
+                .push(Box::new(LlmCall::new("c", "{input}")))
+        };
+
+        let outputs = chain()
+            .with_token_budget(30)
+            .execute_all(&ctx, json!("x"))
+            .await
+            .unwrap();
+        assert_eq!(outputs.len(), 3);
+        assert_eq!(
+            outputs[0].diagnostics.as_ref().unwrap().completion_tokens,
+            Some(10)
+        );
+        assert_eq!(ctx.completion_tokens_used(), 30);
+
+        // The budget only counts this run, not the 30 tokens spent above.
+        let err = chain()
+            .with_token_budget(15)
+            .execute(&ctx, json!("x"))
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            PipelineError::TokenBudgetExceeded {
+                used: 20,
+                budget: 15
+            }
+        ));
+        assert_eq!(ctx.completion_tokens_used(), 50);
+    }
+
+    #[tokio::test]
+    async fn test_chain_total_usage() {
+        use crate::LlmCall;
+
+        let ctx = ExecCtx::builder("http://test")
+            .backend(ten_tokens())
+            .build();
+        let inner = Chain::new("inner")
+            .push(Box::new(LlmCall::new("a", "{input}")))
+            .push(Box::new(LlmCall::new("b", "{input}")));
+        let outer = Chain::new("outer")
+            .push(Box::new(inner))
+            .push(Box::new(EchoPayload { tag: "echo".into() }))
+            .push(Box::new(LlmCall::new("c", "{input}")));
+
+        let out = outer.execute(&ctx, json!("x")).await.unwrap();
+        let usage = out.chain.as_deref().unwrap().total_usage().unwrap();
+        assert_eq!(usage.completion_tokens, Some(30));
+        assert_eq!(usage.prompt_tokens, None);
+
+        let tree = Chain::new("none")
+            .push(Box::new(EchoPayload { tag: "e".into() }))
+            .execute(&ctx, json!("x"))
+            .await
+            .unwrap();
+        assert_eq!(tree.chain.as_deref().unwrap().total_usage(), None);
+    }
+
+    #[tokio::test]
+    async fn test_chain_estimated_cost() {
+        use crate::cost::{CostTable, ModelPrice};
+        use crate::LlmCall;
+
+        let ctx = ExecCtx::builder("http://test")
+            .backend(ten_tokens())
+            .cost_table(CostTable::new().with_model("priced", ModelPrice::new(0.5, 2.0)))
+            .build();
+        let chain = Chain::new("costs")
+            .push(Box::new(LlmCall::new("a", "{input}").with_model("priced")))
+            .push(Box::new(LlmCall::new("b", "{input}").with_model("free")))
+            .push(Box::new(LlmCall::new("c", "{input}").with_model("priced")));
+
+        let outputs = chain.execute_all(&ctx, json!("x")).await.unwrap();
+        assert_eq!(outputs[0].estimated_cost_usd(), Some(0.02));
+        assert_eq!(outputs[1].estimated_cost_usd(), None);
+        let sum: f64 = outputs.iter().filter_map(|o| o.estimated_cost_usd()).sum();
+        assert_eq!(sum, 0.04);
+
+        let tree = chain.execute(&ctx, json!("x")).await.unwrap();
+        assert_eq!(tree.chain.as_deref().unwrap().total_cost_usd(), Some(0.04));
+    }
+
+    #[tokio::test]
+    async fn test_chain_deadline() {
+        use crate::payload::from_fn;
+
+        let chain = |deadline: Duration| {
+            let slow = || {
+                from_fn("slow", "test", |_, input| {
+                    Box::pin(async move {
+                        tokio::time::sleep(Duration::from_millis(30)).await;
+                        Ok(PayloadOutput::from_value(input))
+                    })
+                })
+            };
+            Chain::new("timed")
+                .push(slow())
+                .push(slow())
+                .with_deadline(deadline)
+        };
+
+        let err = chain(Duration::from_millis(10))
+            .execute(&test_ctx(), json!("x"))
+            .await
+            .unwrap_err();
+        match err {
+            PipelineError::DeadlineExceeded {
+                elapsed,
+                deadline,
+                completed,
+            } => {
+                assert!(elapsed >= Duration::from_millis(30));
+                assert_eq!(deadline, Duration::from_millis(10));
+                assert_eq!(completed.len(), 1);
+                assert_eq!(completed[0].value, "x");
+            }
+            other => panic!("expected DeadlineExceeded, got {:?}", other),
+        }
+
+        let out = chain(Duration::from_secs(5))
+            .execute(&test_ctx(), json!("x"))
+            .await
+            .unwrap();
+        assert_eq!(out.value, "x");
+    }
 }
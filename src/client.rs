@@ -1,5 +1,9 @@
 use crate::{
-    error::Result, parsing, streaming::StreamingDecoder, types::StageOutput, PipelineError,
+    error::Result,
+    parsing,
+    streaming::{StreamingDecoder, ThinkStreamMode},
+    types::StageOutput,
+    PipelineError,
 };
 use futures::StreamExt;
 use reqwest::Client;
@@ -22,6 +26,63 @@ pub struct LlmConfig {
 
     /// Custom options merged into the Ollama options object.
     pub options: Option<Value>,
+
+    /// How `<think>` block tokens are delivered while streaming.
+    /// Default: [`ThinkStreamMode::Inline`].
+    pub think_stream: ThinkStreamMode,
+
+    /// Number of completions to request (OpenAI `n`). Values above 1 fill
+    /// [`LlmResponse::candidates`](crate::backend::LlmResponse::candidates)
+    /// on non-streaming calls. Ignored by backends without multi-completion
+    /// support (Ollama). Default: 1.
+    pub n: u32,
+
+    /// Keep Ollama on `/api/generate` when a system prompt is set, prepending
+    /// the system prompt to the prompt instead of switching to `/api/chat`.
+    /// Retries with message history still use `/api/chat`. Ignored by other
+    /// backends. Default: `false`.
+    pub prefer_generate: bool,
+
+    /// Extra fields merged into the top level of the request body by every
+    /// backend, for provider-specific parameters without first-class
+    /// support (`min_p`, `repetition_penalty`, ...). Passed through
+    /// untouched; keys the backend already sets are overwritten. Must be a
+    /// JSON object; anything else is ignored.
+    pub extra_body: Option<Value>,
+
+    /// Mark the system prompt as cacheable (`cache_control: {"type":
+    /// "ephemeral"}`) for providers with prompt caching, so a large shared
+    /// system prompt is billed at the cached rate on repeat calls. Ignored
+    /// by backends without support (Ollama). Default: `false`.
+    pub cache_system: bool,
+
+    /// Strings that end generation when the model produces one. Sent as
+    /// Ollama's `options.stop`, OpenAI's `stop`, Anthropic's
+    /// `stop_sequences`, and Gemini's `stopSequences`; omitted when empty.
+    /// Default: empty.
+    pub stop: Vec<String>,
+
+    /// Nucleus sampling: sample only from the smallest token set whose
+    /// probability mass reaches `top_p`. Omitted when `None`. Default: `None`.
+    pub top_p: Option<f64>,
+
+    /// Sample only from the `top_k` most likely tokens. Not supported by
+    /// OpenAI, which omits it. Omitted when `None`. Default: `None`.
+    pub top_k: Option<u32>,
+
+    /// Sampling seed, for reproducible output where the provider supports
+    /// it (not Anthropic). Omitted when `None`. Default: `None`.
+    pub seed: Option<u64>,
+
+    /// Penalize tokens that already appear at all, nudging the model
+    /// toward new topics. Not supported by Anthropic. Omitted when `None`.
+    /// Default: `None`.
+    pub presence_penalty: Option<f64>,
+
+    /// Penalize tokens in proportion to how often they already appear,
+    /// reducing verbatim repetition. Not supported by Anthropic. Omitted
+    /// when `None`. Default: `None`.
+    pub frequency_penalty: Option<f64>,
 }
 
 impl Default for LlmConfig {
@@ -32,6 +93,17 @@ impl Default for LlmConfig {
             thinking: false,
             json_mode: false,
             options: None,
+            think_stream: ThinkStreamMode::default(),
+            n: 1,
+            prefer_generate: false,
+            extra_body: None,
+            cache_system: false,
+            stop: Vec::new(),
+            top_p: None,
+            top_k: None,
+            seed: None,
+            presence_penalty: None,
+            frequency_penalty: None,
         }
     }
 }
@@ -56,6 +128,269 @@ impl LlmConfig {
         self.json_mode = enabled;
         self
     }
+
+    pub fn with_think_stream(mut self, mode: ThinkStreamMode) -> Self {
+        self.think_stream = mode;
+        self
+    }
+
+    pub fn with_n(mut self, n: u32) -> Self {
+        self.n = n.max(1);
+        self
+    }
+
+    pub fn with_prefer_generate(mut self, enabled: bool) -> Self {
+        self.prefer_generate = enabled;
+        self
+    }
+
+    pub fn with_extra_body(mut self, fields: Value) -> Self {
+        self.extra_body = Some(fields);
+        self
+    }
+
+    pub fn with_cache_system(mut self, enabled: bool) -> Self {
+        self.cache_system = enabled;
+        self
+    }
+
+    pub fn with_stop(mut self, stop: Vec<String>) -> Self {
+        self.stop = stop;
+        self
+    }
+
+    pub fn with_top_p(mut self, top_p: f64) -> Self {
+        self.top_p = Some(top_p);
+        self
+    }
+
+    pub fn with_top_k(mut self, top_k: u32) -> Self {
+        self.top_k = Some(top_k);
+        self
+    }
+
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
+    pub fn with_presence_penalty(mut self, penalty: f64) -> Self {
+        self.presence_penalty = Some(penalty);
+        self
+    }
+
+    pub fn with_frequency_penalty(mut self, penalty: f64) -> Self {
+        self.frequency_penalty = Some(penalty);
+        self
+    }
+
+    /// Overwrite every field that `overlay` sets, leaving the rest as is.
+    pub fn apply_override(&mut self, overlay: &LlmConfigOverride) {
+        if let Some(temperature) = overlay.temperature {
+            self.temperature = temperature;
+        }
+        if let Some(max_tokens) = overlay.max_tokens {
+            self.max_tokens = max_tokens;
+        }
+        if let Some(thinking) = overlay.thinking {
+            self.thinking = thinking;
+        }
+        if let Some(json_mode) = overlay.json_mode {
+            self.json_mode = json_mode;
+        }
+        if let Some(ref options) = overlay.options {
+            self.options = Some(options.clone());
+        }
+        if let Some(think_stream) = overlay.think_stream {
+            self.think_stream = think_stream;
+        }
+        if let Some(n) = overlay.n {
+            self.n = n.max(1);
+        }
+        if let Some(prefer_generate) = overlay.prefer_generate {
+            self.prefer_generate = prefer_generate;
+        }
+        if let Some(ref extra_body) = overlay.extra_body {
+            self.extra_body = Some(extra_body.clone());
+        }
+        if let Some(cache_system) = overlay.cache_system {
+            self.cache_system = cache_system;
+        }
+        if let Some(ref stop) = overlay.stop {
+            self.stop = stop.clone();
+        }
+        if let Some(top_p) = overlay.top_p {
+            self.top_p = Some(top_p);
+        }
+        if let Some(top_k) = overlay.top_k {
+            self.top_k = Some(top_k);
+        }
+        if let Some(seed) = overlay.seed {
+            self.seed = Some(seed);
+        }
+        if let Some(penalty) = overlay.presence_penalty {
+            self.presence_penalty = Some(penalty);
+        }
+        if let Some(penalty) = overlay.frequency_penalty {
+            self.frequency_penalty = Some(penalty);
+        }
+    }
+
+    /// `base` with every field set in `overlay` replaced. See
+    /// [`apply_override`](Self::apply_override).
+    pub fn merge(base: &LlmConfig, overlay: &LlmConfigOverride) -> LlmConfig {
+        let mut merged = base.clone();
+        merged.apply_override(overlay);
+        merged
+    }
+
+    /// Merge [`extra_body`](Self::extra_body) into the top level of `body`.
+    pub(crate) fn apply_extra_body(&self, body: &mut Value) {
+        if let (Some(body), Some(extra)) = (
+            body.as_object_mut(),
+            self.extra_body.as_ref().and_then(Value::as_object),
+        ) {
+            for (k, v) in extra {
+                body.insert(k.clone(), v.clone());
+            }
+        }
+    }
+}
+
+/// A partial [`LlmConfig`]: every field is optional, and only the ones
+/// that are `Some` take effect when applied with
+/// [`LlmConfig::apply_override`] or [`LlmConfig::merge`].
+///
+/// This is how a layer (a call, a context default) changes some settings
+/// without resetting the others to their defaults.
+///
+/// # Example
+///
+/// ```
+/// use llm_pipeline::{LlmConfig, LlmConfigOverride};
+///
+/// let base = LlmConfig::default().with_max_tokens(512);
+/// let overlay = LlmConfigOverride::default().with_temperature(0.0);
+/// let merged = LlmConfig::merge(&base, &overlay);
+/// assert_eq!(merged.temperature, 0.0);
+/// assert_eq!(merged.max_tokens, 512);
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct LlmConfigOverride {
+    /// See [`LlmConfig::temperature`].
+    pub temperature: Option<f64>,
+    /// See [`LlmConfig::max_tokens`].
+    pub max_tokens: Option<u32>,
+    /// See [`LlmConfig::thinking`].
+    pub thinking: Option<bool>,
+    /// See [`LlmConfig::json_mode`].
+    pub json_mode: Option<bool>,
+    /// See [`LlmConfig::options`]. Replaces the whole object.
+    pub options: Option<Value>,
+    /// See [`LlmConfig::think_stream`].
+    pub think_stream: Option<ThinkStreamMode>,
+    /// See [`LlmConfig::n`].
+    pub n: Option<u32>,
+    /// See [`LlmConfig::prefer_generate`].
+    pub prefer_generate: Option<bool>,
+    /// See [`LlmConfig::extra_body`]. Replaces the whole object.
+    pub extra_body: Option<Value>,
+    /// See [`LlmConfig::cache_system`].
+    pub cache_system: Option<bool>,
+    /// See [`LlmConfig::stop`]. Replaces the whole list.
+    pub stop: Option<Vec<String>>,
+    /// See [`LlmConfig::top_p`].
+    pub top_p: Option<f64>,
+    /// See [`LlmConfig::top_k`].
+    pub top_k: Option<u32>,
+    /// See [`LlmConfig::seed`].
+    pub seed: Option<u64>,
+    /// See [`LlmConfig::presence_penalty`].
+    pub presence_penalty: Option<f64>,
+    /// See [`LlmConfig::frequency_penalty`].
+    pub frequency_penalty: Option<f64>,
+}
+
+impl LlmConfigOverride {
+    pub fn with_temperature(mut self, temp: f64) -> Self {
+        self.temperature = Some(temp);
+        self
+    }
+
+    pub fn with_max_tokens(mut self, tokens: u32) -> Self {
+        self.max_tokens = Some(tokens);
+        self
+    }
+
+    pub fn with_thinking(mut self, enabled: bool) -> Self {
+        self.thinking = Some(enabled);
+        self
+    }
+
+    pub fn with_json_mode(mut self, enabled: bool) -> Self {
+        self.json_mode = Some(enabled);
+        self
+    }
+
+    pub fn with_options(mut self, options: Value) -> Self {
+        self.options = Some(options);
+        self
+    }
+
+    pub fn with_think_stream(mut self, mode: ThinkStreamMode) -> Self {
+        self.think_stream = Some(mode);
+        self
+    }
+
+    pub fn with_n(mut self, n: u32) -> Self {
+        self.n = Some(n);
+        self
+    }
+
+    pub fn with_prefer_generate(mut self, enabled: bool) -> Self {
+        self.prefer_generate = Some(enabled);
+        self
+    }
+
+    pub fn with_extra_body(mut self, fields: Value) -> Self {
+        self.extra_body = Some(fields);
+        self
+    }
+
+    pub fn with_cache_system(mut self, enabled: bool) -> Self {
+        self.cache_system = Some(enabled);
+        self
+    }
+
+    pub fn with_stop(mut self, stop: Vec<String>) -> Self {
+        self.stop = Some(stop);
+        self
+    }
+
+    pub fn with_top_p(mut self, top_p: f64) -> Self {
+        self.top_p = Some(top_p);
+        self
+    }
+
+    pub fn with_top_k(mut self, top_k: u32) -> Self {
+        self.top_k = Some(top_k);
+        self
+    }
+
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
+    pub fn with_presence_penalty(mut self, penalty: f64) -> Self {
+        self.presence_penalty = Some(penalty);
+        self
+    }
+
+    pub fn with_frequency_penalty(mut self, penalty: f64) -> Self {
+        self.frequency_penalty = Some(penalty);
+        self
+    }
 }
 
 /// Call LLM with `/api/generate` and parse the response into `T`.
@@ -123,9 +458,11 @@ where
     let output: T = parsing::parse_as(&cleaned_response)?;
 
     Ok(StageOutput {
+        stage: String::new(),
         output,
         thinking,
         raw_response,
+        prompt: prompt.to_string(),
     })
 }
 
@@ -202,9 +539,11 @@ where
     let output: T = parsing::parse_as(&cleaned_response)?;
 
     Ok(StageOutput {
+        stage: String::new(),
         output,
         thinking,
         raw_response,
+        prompt: user_prompt.to_string(),
     })
 }
 
@@ -273,7 +612,9 @@ where
 
     while let Some(chunk) = stream.next().await {
         let chunk = chunk.map_err(PipelineError::Request)?;
-        for json_val in decoder.decode(&chunk) {
+        let values = decoder.decode(&chunk);
+        decoder.check_dropped()?;
+        for json_val in values {
             if let Some(response) = json_val.get("response").and_then(|v| v.as_str()) {
                 accumulated.push_str(response);
                 on_chunk(response);
@@ -293,9 +634,11 @@ where
     let output: T = parsing::parse_as(&cleaned)?;
 
     Ok(StageOutput {
+        stage: String::new(),
         output,
         thinking,
         raw_response: accumulated,
+        prompt: prompt.to_string(),
     })
 }
 
@@ -338,4 +681,31 @@ mod tests {
         assert!(config.thinking);
         assert!(config.json_mode);
     }
+
+    #[test]
+    fn test_llm_config_apply_override() {
+        let base = LlmConfig::default()
+            .with_temperature(0.3)
+            .with_json_mode(true)
+            .with_extra_body(json!({"top_k": 40}));
+
+        // An empty override changes nothing.
+        let same = LlmConfig::merge(&base, &LlmConfigOverride::default());
+        assert_eq!(same.temperature, 0.3);
+        assert!(same.json_mode);
+
+        let overlay = LlmConfigOverride::default()
+            .with_temperature(0.0)
+            .with_json_mode(false)
+            .with_n(0)
+            .with_think_stream(ThinkStreamMode::Suppress);
+        let mut config = base.clone();
+        config.apply_override(&overlay);
+        assert_eq!(config.temperature, 0.0);
+        assert!(!config.json_mode);
+        assert_eq!(config.n, 1);
+        assert_eq!(config.think_stream, ThinkStreamMode::Suppress);
+        assert_eq!(config.max_tokens, 2048);
+        assert_eq!(config.extra_body, Some(json!({"top_k": 40})));
+    }
 }
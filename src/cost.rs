@@ -0,0 +1,148 @@
+//! Rough spend estimates from token usage.
+//!
+//! A [`CostTable`] maps model names to per-1K-token prices. Set one with
+//! [`ExecCtxBuilder::cost_table`](crate::ExecCtxBuilder::cost_table) and
+//! every [`LlmCall`](crate::LlmCall) records
+//! [`ParseDiagnostics::estimated_cost_usd`](crate::ParseDiagnostics::estimated_cost_usd)
+//! from the usage its backend reports.
+
+use crate::backend::Usage;
+use crate::error::Result;
+use crate::PipelineError;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::Path;
+
+/// Price of one model, in USD per 1,000 tokens.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ModelPrice {
+    /// USD per 1,000 prompt tokens.
+    pub input_per_1k: f64,
+    /// USD per 1,000 completion tokens.
+    pub output_per_1k: f64,
+}
+
+impl ModelPrice {
+    /// A price from per-1K input and output rates.
+    pub fn new(input_per_1k: f64, output_per_1k: f64) -> Self {
+        Self {
+            input_per_1k,
+            output_per_1k,
+        }
+    }
+}
+
+/// Per-model token prices for cost estimates.
+///
+/// Model names match exactly. The JSON form is an object keyed by model
+/// name:
+///
+/// ```
+/// use llm_pipeline::backend::Usage;
+/// use llm_pipeline::cost::CostTable;
+///
+/// let table = CostTable::from_json(
+///     r#"{"gpt-4o-mini": {"input_per_1k": 0.00015, "output_per_1k": 0.0006}}"#,
+/// )
+/// .unwrap();
+/// let usage = Usage {
+///     prompt_tokens: Some(2000),
+///     completion_tokens: Some(1000),
+///     total_tokens: Some(3000),
+/// };
+/// let cost = table.estimate("gpt-4o-mini", &usage).unwrap();
+/// assert!((cost - 0.0009).abs() < 1e-12);
+/// assert!(table.estimate("llama3.2:3b", &usage).is_none());
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct CostTable {
+    prices: HashMap<String, ModelPrice>,
+}
+
+impl CostTable {
+    /// An empty table.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add or replace the price of `model`.
+    pub fn with_model(mut self, model: impl Into<String>, price: ModelPrice) -> Self {
+        self.prices.insert(model.into(), price);
+        self
+    }
+
+    /// Parse a table from JSON (see the type docs for the shape).
+    pub fn from_json(json: &str) -> Result<Self> {
+        Ok(serde_json::from_str(json)?)
+    }
+
+    /// Read a table from a JSON file.
+    pub fn from_json_file(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let json = std::fs::read_to_string(path).map_err(|e| {
+            PipelineError::InvalidConfig(format!(
+                "Failed to read cost table {}: {}",
+                path.display(),
+                e
+            ))
+        })?;
+        Self::from_json(&json)
+    }
+
+    /// The price of `model`, if listed.
+    pub fn price(&self, model: &str) -> Option<ModelPrice> {
+        self.prices.get(model).copied()
+    }
+
+    /// Estimated USD cost of `usage` on `model`, or `None` if the model is
+    /// not listed or `usage` has no prompt or completion count. A missing
+    /// count on one side is taken as zero.
+    pub fn estimate(&self, model: &str, usage: &Usage) -> Option<f64> {
+        let price = self.price(model)?;
+        if usage.prompt_tokens.is_none() && usage.completion_tokens.is_none() {
+            return None;
+        }
+        let tokens = |n: Option<u64>| n.unwrap_or(0) as f64 / 1000.0;
+        Some(
+            tokens(usage.prompt_tokens) * price.input_per_1k
+                + tokens(usage.completion_tokens) * price.output_per_1k,
+        )
+    }
+}
+
+impl From<HashMap<String, ModelPrice>> for CostTable {
+    fn from(prices: HashMap<String, ModelPrice>) -> Self {
+        Self { prices }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cost_table_sources_and_estimate() {
+        let table = CostTable::from(HashMap::from([(
+            "big".to_string(),
+            ModelPrice::new(0.01, 0.03),
+        )]));
+        let usage = Usage {
+            prompt_tokens: Some(500),
+            completion_tokens: None,
+            total_tokens: None,
+        };
+        assert_eq!(table.estimate("big", &usage), Some(0.005));
+        assert_eq!(table.estimate("big", &Usage::default()), None);
+        assert_eq!(table.estimate("small", &usage), None);
+
+        let path = std::env::temp_dir().join(format!("cost-table-{}.json", std::process::id()));
+        std::fs::write(&path, serde_json::to_string(&table).unwrap()).unwrap();
+        assert_eq!(CostTable::from_json_file(&path).unwrap(), table);
+        std::fs::remove_file(&path).unwrap();
+        assert!(matches!(
+            CostTable::from_json_file(&path),
+            Err(PipelineError::InvalidConfig(_))
+        ));
+    }
+}
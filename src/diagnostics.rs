@@ -4,6 +4,9 @@
 //! strategy was used, whether parsing succeeded, how many retries were
 //! attempted, and whether repair or auto-completion was involved.
 
+use crate::backend::Usage;
+use crate::retry::RetryReason;
+
 /// Records what happened during output parsing.
 ///
 /// Attached to every [`PayloadOutput`](crate::payload::PayloadOutput) produced
@@ -22,7 +25,10 @@
 #[derive(Debug, Clone, Default)]
 pub struct ParseDiagnostics {
     /// Which parse strategy ultimately produced the Value.
-    /// e.g. `"lossy"`, `"json"`, `"string_list"`, `"xml_tag"`, `"custom"`.
+    /// e.g. `"lossy"`, `"json"`, `"string_list"`, `"xml_tag"`, `"custom"`,
+    /// `"tool_calls"` for a response with only tool calls and no text, or
+    /// `"passthrough"` for a value no LLM call produced (see
+    /// [`PayloadOutput::from_value`](crate::payload::PayloadOutput::from_value)).
     pub strategy: Option<&'static str>,
 
     /// If parsing failed, the error message. `None` means success.
@@ -32,6 +38,10 @@ pub struct ParseDiagnostics {
     /// or retry was not configured).
     pub retry_attempts: u32,
 
+    /// Why each semantic retry was triggered, in order. One entry per retry
+    /// attempt that got a response.
+    pub retry_reasons: Vec<RetryReason>,
+
     /// Number of transport retries (429, 5xx) before the request succeeded.
     /// 0 = first attempt succeeded.
     pub transport_retries: u32,
@@ -42,8 +52,68 @@ pub struct ParseDiagnostics {
     /// Whether JSON repair was applied (trailing commas, single quotes, etc.).
     pub repaired: bool,
 
-    /// Whether auto-completion was used (streaming partial parse).
+    /// Whether the value was recovered from truncated JSON by closing
+    /// unterminated strings and brackets. See
+    /// [`PayloadOutput::was_auto_completed`](crate::payload::PayloadOutput::was_auto_completed).
     pub auto_completed: bool,
+
+    /// Why generation stopped, when known. `"client_limit"` means the
+    /// stream was cut by [`ExecCtxBuilder::max_stream_tokens`](crate::exec_ctx::ExecCtxBuilder::max_stream_tokens).
+    pub finish_reason: Option<String>,
+
+    /// Which Ollama endpoint served the request: `"chat"` or `"generate"`.
+    /// Useful when a prompt behaves differently than expected, since a
+    /// system prompt (without
+    /// [`prefer_generate`](crate::LlmConfig::prefer_generate)) or retry
+    /// history switches the call to `/api/chat`. `None` for other backends.
+    pub endpoint_mode: Option<String>,
+
+    /// HTTP status of the response that produced the value, e.g. to spot
+    /// one of [`BackoffConfig::accept_statuses`](crate::backend::BackoffConfig::accept_statuses)
+    /// that was let through. `None` for cache hits and backends that report
+    /// no status.
+    pub http_status: Option<u16>,
+
+    /// For the `code_block` strategy: whether the code came from the
+    /// bare-fence fallback rather than a block tagged with the requested
+    /// language.
+    pub code_block_fallback: bool,
+
+    /// Whether this output was served from the context's semantic cache
+    /// instead of a fresh model call (`semantic-cache` feature).
+    pub cache_hit: bool,
+
+    /// Prompt tokens reported by the backend, summed over the initial call
+    /// and any retries. `None` if the backend reported no usage.
+    pub prompt_tokens: Option<u64>,
+
+    /// Completion tokens reported by the backend, summed over the initial
+    /// call and any retries. `None` if the backend reported no usage.
+    pub completion_tokens: Option<u64>,
+
+    /// Whether the call's `{input}` was clipped by
+    /// [`LlmCall::with_input_truncation`](crate::LlmCall::with_input_truncation)
+    /// before the prompt was sent.
+    pub input_truncated: bool,
+
+    /// The model's refusal, when it declined to answer (see
+    /// [`LlmResponse::refusal`](crate::backend::LlmResponse::refusal)) or
+    /// the provider's content filter blocked the response. A refusal also
+    /// sets `parse_error`, so the output is not [`ok`](Self::ok), and
+    /// triggers semantic retries as [`RetryReason::Refusal`].
+    pub refusal: Option<String>,
+
+    /// Whether the response ended inside an unclosed `<think>` block with
+    /// no answer, as reasoning models do when `max_tokens` runs out
+    /// mid-thought. Also sets `parse_error` and triggers semantic retries
+    /// as [`RetryReason::TruncatedThinking`].
+    pub truncated_in_thinking: bool,
+
+    /// Estimated USD cost of the call, from [`usage`](Self::usage) and the
+    /// context's [`cost_table`](crate::ExecCtxBuilder::cost_table). `None`
+    /// without a table, for models it does not list, or when the backend
+    /// reported no usage.
+    pub estimated_cost_usd: Option<f64>,
 }
 
 impl ParseDiagnostics {
@@ -51,6 +121,27 @@ impl ParseDiagnostics {
     pub fn ok(&self) -> bool {
         self.parse_error.is_none()
     }
+
+    /// Whether the model declined to answer.
+    pub fn refused(&self) -> bool {
+        self.refusal.is_some()
+    }
+
+    /// Record a refusal, replacing any parse error the empty response
+    /// caused with one naming the refusal.
+    pub(crate) fn mark_refusal(&mut self, refusal: Option<String>) {
+        if let Some(refusal) = refusal {
+            self.parse_error = Some(format!("model refused: {}", refusal));
+            self.refusal = Some(refusal);
+        }
+    }
+
+    /// [`prompt_tokens`](Self::prompt_tokens) and
+    /// [`completion_tokens`](Self::completion_tokens) as a [`Usage`], or
+    /// `None` if the backend reported neither.
+    pub fn usage(&self) -> Option<Usage> {
+        Usage::new(self.prompt_tokens, self.completion_tokens, None)
+    }
 }
 
 #[cfg(test)]
@@ -1,3 +1,4 @@
+use crate::payload::PayloadOutput;
 use std::time::Duration;
 use thiserror::Error;
 
@@ -29,16 +30,51 @@ pub enum PipelineError {
     /// Returned by [`Backend`](crate::backend::Backend) implementations when
     /// the provider returns a non-success status code. The `retry_after` field
     /// is populated from the `Retry-After` response header when present.
-    #[error("HTTP {status}: {body}")]
+    /// Displays `message` when the body carried one, the raw body otherwise.
+    #[error("HTTP {status}: {}", message.as_deref().unwrap_or(body))]
     HttpError {
         /// HTTP status code (e.g. 429, 500, 503).
         status: u16,
         /// Response body text.
         body: String,
+        /// Human-readable message extracted from a JSON error body, e.g.
+        /// `{"error": {"message": "..."}}`. `None` for plain-text bodies.
+        message: Option<String>,
         /// Parsed `Retry-After` header value, if present.
         retry_after: Option<Duration>,
     },
 
+    /// A [`Chain`](crate::Chain) generated more completion tokens than its
+    /// [`token budget`](crate::Chain::with_token_budget) allows.
+    #[error("Token budget exceeded: {used} completion tokens used, budget is {budget}")]
+    TokenBudgetExceeded {
+        /// Completion tokens generated so far.
+        used: u64,
+        /// The configured budget.
+        budget: u64,
+    },
+
+    /// A [`Chain`](crate::Chain) ran past its
+    /// [`deadline`](crate::Chain::with_deadline) before starting a step.
+    #[error(
+        "Deadline of {deadline:?} exceeded after {elapsed:?} ({} steps completed)",
+        completed.len()
+    )]
+    DeadlineExceeded {
+        /// Time since the chain started.
+        elapsed: Duration,
+        /// The configured deadline.
+        deadline: Duration,
+        /// Outputs of the steps that finished before the deadline was
+        /// noticed, in order.
+        completed: Vec<PayloadOutput>,
+    },
+
+    /// A request took longer than its per-call timeout
+    /// ([`LlmCall::with_timeout`](crate::LlmCall::with_timeout)).
+    #[error("Request timed out after {0:?}")]
+    Timeout(Duration),
+
     /// Catch-all for other errors.
     #[error("{0}")]
     Other(String),
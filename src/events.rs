@@ -5,6 +5,8 @@
 //! Users can implement [`EventHandler`] to receive these events for
 //! logging, progress tracking, or streaming UIs.
 
+use crate::retry::RetryReason;
+use std::panic::AssertUnwindSafe;
 use std::sync::Arc;
 
 /// Events emitted during payload execution.
@@ -24,6 +26,37 @@ pub enum Event {
         /// The token text.
         chunk: String,
     },
+    /// The JSON parsed so far changed while streaming. Emitted by
+    /// [`LlmCall`](crate::LlmCall)s using the `Json` or `JsonPointer`
+    /// strategy, after the token that changed it, with unclosed strings and
+    /// brackets auto-completed. `value` is the whole partial document; the
+    /// final parsed output still arrives in the payload's result.
+    PartialValue {
+        /// Instance name of the payload.
+        name: String,
+        /// The partial value.
+        value: serde_json::Value,
+    },
+    /// A `<think>` block token was received during streaming. Only emitted
+    /// when the call's `think_stream` mode is
+    /// [`ThinkStreamMode::Separate`](crate::streaming::ThinkStreamMode::Separate).
+    ThinkingToken {
+        /// Instance name of the payload producing this token.
+        name: String,
+        /// The token text.
+        chunk: String,
+    },
+    /// The provider's end-of-stream metadata arrived on a streaming call
+    /// (Ollama: eval counts and durations from the final `done` line).
+    /// Emitted before the response is parsed, so usage can be accounted for
+    /// without waiting for the payload to finish.
+    StreamMetadata {
+        /// Instance name of the payload.
+        name: String,
+        /// Provider metadata, as in
+        /// [`LlmResponse::metadata`](crate::backend::LlmResponse::metadata).
+        metadata: serde_json::Value,
+    },
     /// A payload has finished executing.
     PayloadEnd {
         /// Instance name of the payload.
@@ -39,6 +72,8 @@ pub enum Event {
         attempt: u32,
         /// Why the retry was triggered (parse error or validator message).
         reason: String,
+        /// The category of `reason`, for aggregating retries.
+        category: RetryReason,
     },
     /// A semantic retry sequence has completed.
     RetryEnd {
@@ -58,6 +93,38 @@ pub enum Event {
         /// Whether the JSON appears complete (all brackets closed).
         complete: bool,
     },
+    /// Final parse outcome of an [`LlmCall`](crate::LlmCall), after any
+    /// semantic retries. Mirrors the call's
+    /// [`ParseDiagnostics`](crate::diagnostics::ParseDiagnostics).
+    ParseResult {
+        /// Instance name of the payload.
+        name: String,
+        /// Strategy that produced the value (e.g. `"json"`, `"lossy"`).
+        strategy: &'static str,
+        /// Whether parsing succeeded. `false` means the value fell back to
+        /// the raw text.
+        ok: bool,
+        /// Whether JSON repair or auto-completion was needed.
+        repaired: bool,
+        /// Number of semantic retries before this result.
+        retry_attempts: u32,
+    },
+    /// A [`Chain`](crate::Chain) stopped early because a
+    /// [`push_until`](crate::Chain::push_until) predicate returned `true`.
+    ChainShortCircuit {
+        /// Instance name of the chain.
+        name: String,
+        /// Zero-based index of the step whose output stopped the chain.
+        at_step: usize,
+    },
+    /// [`ExecCtxBuilder::build`](crate::ExecCtxBuilder::build) found a
+    /// setting that looks wrong, such as a backend paired with another
+    /// provider's URL. Advisory only; see
+    /// [`ExecCtx::config_warnings`](crate::ExecCtx::config_warnings).
+    ConfigWarning {
+        /// What looks wrong and how to fix it.
+        message: String,
+    },
     /// A transport-level retry due to HTTP error.
     TransportRetry {
         /// Instance name or operation description.
@@ -91,20 +158,34 @@ pub enum Event {
 ///             Event::Token { chunk, .. } => print!("{}", chunk),
 ///             Event::PayloadStart { name, .. } => println!("[start] {}", name),
 ///             Event::PayloadEnd { name, ok, .. } => println!("[end] {} ok={}", name, ok),
-///             _ => {} // RetryStart, RetryEnd, PartialParse, TransportRetry
+///             _ => {} // retries, partial parses, and other lifecycle events
 ///         }
 ///     }
 /// }
 /// ```
+///
+/// Handlers should not panic. If one does, the panic is caught, reported on
+/// stderr, and the event is dropped; execution continues.
 pub trait EventHandler: Send + Sync {
     /// Called when a payload emits an event.
     fn on_event(&self, event: Event);
 }
 
 /// Emit an event if a handler is present. No-op otherwise.
+///
+/// A panicking handler is contained here: the panic is reported on stderr
+/// and the payload carries on, so a buggy observer can't abort the workflow.
 pub(crate) fn emit(handler: &Option<Arc<dyn EventHandler>>, event: Event) {
     if let Some(ref h) = handler {
-        h.on_event(event);
+        let result = std::panic::catch_unwind(AssertUnwindSafe(|| h.on_event(event)));
+        if let Err(panic) = result {
+            let message = panic
+                .downcast_ref::<&str>()
+                .copied()
+                .or_else(|| panic.downcast_ref::<String>().map(String::as_str))
+                .unwrap_or("<non-string panic payload>");
+            eprintln!("llm-pipeline: event handler panicked (ignored): {}", message);
+        }
     }
 }
 
@@ -129,3 +210,27 @@ impl<F: Fn(Event) + Send + Sync> EventHandler for FnEventHandler<F> {
         (self.0)(event);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ExecCtx, LlmCall, MockBackend, Payload};
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn test_panicking_handler_does_not_abort_call() {
+        let ctx = ExecCtx::builder("http://test")
+            .backend(Arc::new(MockBackend::fixed("ok")))
+            .event_handler(Arc::new(FnEventHandler(|event: Event| {
+                if let Event::PayloadStart { .. } = event {
+                    panic!("observer bug");
+                }
+            })))
+            .build();
+        let out = LlmCall::new("test", "{input}")
+            .invoke(&ctx, json!("x"))
+            .await
+            .unwrap();
+        assert_eq!(out.value, json!("ok"));
+    }
+}
@@ -4,18 +4,32 @@
 //! cancellation handle, and optional event handler. It is designed to be
 //! constructed once and shared across all payloads in a chain or graph.
 
-use crate::backend::{Backend, BackoffConfig, OllamaBackend};
+use crate::backend::{Backend, BackoffConfig, LlmRequest, OllamaBackend};
+#[cfg(feature = "anthropic")]
+use crate::backend::AnthropicBackend;
+#[cfg(feature = "gemini")]
+use crate::backend::GeminiBackend;
 #[cfg(feature = "openai")]
 use crate::backend::OpenAiBackend;
-use crate::events::EventHandler;
+use crate::client::LlmConfig;
+use crate::cost::CostTable;
+use crate::events::{emit, Event, EventHandler};
+use crate::output_strategy::OutputStrategy;
+#[cfg(feature = "semantic-cache")]
+use crate::semantic_cache::SemanticCache;
 use reqwest::Client;
 use std::collections::HashMap;
 use std::sync::{
-    atomic::{AtomicBool, Ordering},
+    atomic::{AtomicBool, AtomicU64, Ordering},
     Arc,
 };
 use std::time::Duration;
 
+/// A template variable recomputed on every render.
+///
+/// See [`ExecCtxBuilder::dynamic_var`].
+pub type DynamicVar = Arc<dyn Fn() -> String + Send + Sync>;
+
 /// Shared execution context for payload invocations.
 ///
 /// Carries everything a payload needs from the runtime environment
@@ -31,6 +45,7 @@ use std::time::Duration;
 ///     .var("audience", "researchers")
 ///     .build();
 /// ```
+#[derive(Clone)]
 pub struct ExecCtx {
     /// HTTP client (cheap to clone -- uses `Arc` internally).
     pub client: Client,
@@ -42,10 +57,50 @@ pub struct ExecCtx {
     pub backoff: BackoffConfig,
     /// Template variables substituted into prompt `{key}` placeholders.
     pub vars: HashMap<String, String>,
+    /// Template variables computed by a closure at render time.
+    pub dynamic_vars: HashMap<String, DynamicVar>,
     /// Optional cancellation flag; payloads should check before starting.
     pub cancellation: Option<Arc<AtomicBool>>,
     /// Optional event handler for streaming tokens and lifecycle events.
     pub event_handler: Option<Arc<dyn EventHandler>>,
+    /// Deadline for each non-streaming backend attempt made by an
+    /// [`LlmCall`](crate::LlmCall) without its own
+    /// [`with_timeout`](crate::LlmCall::with_timeout). Default: 60 seconds,
+    /// or `None` when the context was built with a custom client.
+    pub request_timeout: Option<Duration>,
+    /// Like `request_timeout`, for streaming attempts. Default: 10 minutes,
+    /// or `None` when the context was built with a custom client.
+    pub stream_timeout: Option<Duration>,
+    /// Hard client-side cap on streamed tokens, independent of the
+    /// backend's `max_tokens`. Default: `None` (unlimited).
+    pub max_stream_tokens: Option<usize>,
+    /// Skip text a resumed stream re-sends. Default: `false`. See
+    /// [`ExecCtxBuilder::dedup_stream`].
+    pub dedup_stream: bool,
+    /// Output strategy for [`LlmCall`](crate::LlmCall)s that don't set their
+    /// own. Default: `None` (such calls use `Lossy`).
+    pub default_output_strategy: Option<OutputStrategy>,
+    /// Model for [`LlmCall`](crate::LlmCall)s that don't set their own.
+    /// Default: `None` (such calls use `"llama3.2:3b"`).
+    pub default_model: Option<String>,
+    /// Normalize smart quotes, non-breaking spaces, and zero-width
+    /// characters in responses before parsing. Default: `false`.
+    pub normalize_unicode: bool,
+    /// Rewrite top-level keys of parsed JSON objects in snake_case.
+    /// Default: `false`. See [`ExecCtxBuilder::normalize_keys`].
+    pub normalize_keys: bool,
+    /// Similarity cache consulted by [`LlmCall`](crate::LlmCall) before
+    /// calling the backend. Default: `None`.
+    #[cfg(feature = "semantic-cache")]
+    pub semantic_cache: Option<Arc<SemanticCache>>,
+    /// Model prices used to fill in
+    /// [`ParseDiagnostics::estimated_cost_usd`](crate::ParseDiagnostics::estimated_cost_usd).
+    /// Default: `None`.
+    pub cost_table: Option<Arc<CostTable>>,
+    /// Running total of completion tokens reported by backends for calls
+    /// made with this context. Shared by clones, so child contexts count
+    /// toward the same total.
+    pub completion_tokens: Arc<AtomicU64>,
 }
 
 impl ExecCtx {
@@ -57,9 +112,136 @@ impl ExecCtx {
             backend: None,
             backoff: None,
             vars: HashMap::new(),
+            dynamic_vars: HashMap::new(),
             cancellation: None,
             event_handler: None,
             timeout: None,
+            connect_timeout: None,
+            request_timeout: None,
+            stream_timeout: None,
+            max_stream_tokens: None,
+            dedup_stream: false,
+            default_output_strategy: None,
+            default_model: None,
+            normalize_unicode: false,
+            normalize_keys: false,
+            #[cfg(feature = "semantic-cache")]
+            semantic_cache: None,
+            cost_table: None,
+        }
+    }
+
+    /// Build a context from environment variables.
+    ///
+    /// | Variable       | Meaning | Default |
+    /// |----------------|---------|---------|
+    /// | `LLM_BACKEND`  | `ollama` or `openai` (needs the `openai` feature) | `ollama` |
+    /// | `LLM_BASE_URL` | Provider endpoint | `http://localhost:11434` (Ollama), `https://api.openai.com` (OpenAI) |
+    /// | `LLM_MODEL`    | [`default_model`](ExecCtxBuilder::default_model) | unset |
+    /// | `LLM_API_KEY`  | Bearer token for the OpenAI backend (ignored by Ollama) | unset |
+    ///
+    /// Empty values count as unset. Returns
+    /// [`PipelineError::InvalidConfig`](crate::PipelineError::InvalidConfig)
+    /// for an unknown backend or a base URL that isn't `http(s)://`.
+    ///
+    /// Use [`builder_from_env`](Self::builder_from_env) to customize further.
+    pub fn from_env() -> crate::error::Result<ExecCtx> {
+        Ok(Self::builder_from_env()?.build())
+    }
+
+    /// Like [`from_env`](Self::from_env), but returns the builder.
+    pub fn builder_from_env() -> crate::error::Result<ExecCtxBuilder> {
+        Self::builder_from_lookup(|key| std::env::var(key).ok())
+    }
+
+    fn builder_from_lookup(
+        get: impl Fn(&str) -> Option<String>,
+    ) -> crate::error::Result<ExecCtxBuilder> {
+        let get = |key: &str| get(key).map(|v| v.trim().to_string()).filter(|v| !v.is_empty());
+        let invalid = |msg: String| crate::PipelineError::InvalidConfig(msg);
+
+        let backend = get("LLM_BACKEND").unwrap_or_else(|| "ollama".to_string());
+        let default_url = match backend.to_ascii_lowercase().as_str() {
+            "ollama" => "http://localhost:11434",
+            "openai" if cfg!(feature = "openai") => "https://api.openai.com",
+            "openai" => {
+                return Err(invalid(
+                    "LLM_BACKEND=openai requires the `openai` feature".to_string(),
+                ))
+            }
+            other => {
+                return Err(invalid(format!(
+                    "LLM_BACKEND must be 'ollama' or 'openai', got '{}'",
+                    other
+                )))
+            }
+        };
+
+        let base_url = get("LLM_BASE_URL").unwrap_or_else(|| default_url.to_string());
+        if !base_url.starts_with("http://") && !base_url.starts_with("https://") {
+            return Err(invalid(format!(
+                "LLM_BASE_URL must start with http:// or https://, got '{}'",
+                base_url
+            )));
+        }
+
+        #[allow(unused_mut)]
+        let mut builder = ExecCtx::builder(base_url);
+        #[cfg(feature = "openai")]
+        if backend.eq_ignore_ascii_case("openai") {
+            builder = match get("LLM_API_KEY") {
+                Some(key) => builder.openai_with_key(key),
+                None => builder.openai(),
+            };
+        }
+        builder.default_model = get("LLM_MODEL");
+        Ok(builder)
+    }
+
+    /// Heuristic checks for settings that are probably wrong, such as the
+    /// OpenAI backend pointed at Ollama's default port (`:11434`) or the
+    /// Ollama backend pointed at `api.openai.com`. Empty if nothing looks off.
+    ///
+    /// [`ExecCtxBuilder::build`] emits each warning as
+    /// [`Event::ConfigWarning`]; use [`validate`](Self::validate) to treat
+    /// them as errors instead.
+    pub fn config_warnings(&self) -> Vec<String> {
+        let authority = self
+            .base_url
+            .split_once("://")
+            .map_or(self.base_url.as_str(), |(_, rest)| rest)
+            .split('/')
+            .next()
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+        let host = authority.split(':').next().unwrap_or_default();
+
+        let mut warnings = Vec::new();
+        match self.backend.name() {
+            "openai" if authority.ends_with(":11434") => warnings.push(format!(
+                "OpenAI backend is pointed at {}, Ollama's default port; use OllamaBackend \
+                 unless you mean to call Ollama's OpenAI-compatible API",
+                self.base_url
+            )),
+            "ollama" if host == "api.openai.com" => warnings.push(format!(
+                "Ollama backend is pointed at {}; use OpenAiBackend (the `openai` feature) \
+                 for the OpenAI API",
+                self.base_url
+            )),
+            _ => {}
+        }
+        warnings
+    }
+
+    /// Strict form of [`config_warnings`](Self::config_warnings): returns
+    /// [`PipelineError::InvalidConfig`](crate::PipelineError::InvalidConfig)
+    /// listing every warning, or `Ok(())` if there are none.
+    pub fn validate(&self) -> crate::error::Result<()> {
+        let warnings = self.config_warnings();
+        if warnings.is_empty() {
+            Ok(())
+        } else {
+            Err(crate::PipelineError::InvalidConfig(warnings.join("; ")))
         }
     }
 
@@ -78,22 +260,109 @@ impl ExecCtx {
         Ok(())
     }
 
+    /// Completion tokens generated so far by calls made with this context
+    /// (or any context cloned from it).
+    pub fn completion_tokens_used(&self) -> u64 {
+        self.completion_tokens.load(Ordering::Relaxed)
+    }
+
+    /// The deadline an attempt gets when its [`LlmCall`](crate::LlmCall)
+    /// sets none: [`stream_timeout`](Self::stream_timeout) for streaming
+    /// requests, [`request_timeout`](Self::request_timeout) otherwise.
+    pub(crate) fn call_timeout(&self, stream: bool) -> Option<Duration> {
+        if stream {
+            self.stream_timeout
+        } else {
+            self.request_timeout
+        }
+    }
+
+    /// Add `tokens` to the shared completion-token counter.
+    pub(crate) fn record_completion_tokens(&self, tokens: u64) {
+        self.completion_tokens.fetch_add(tokens, Ordering::Relaxed);
+    }
+
+    /// Load `model` into memory ahead of time by sending it a trivial
+    /// one-token request, so the first real call doesn't pay the cold-start
+    /// cost.
+    ///
+    /// Best-effort: the request is sent once, without transport retries, and
+    /// the reply is discarded. It is abandoned with
+    /// [`PipelineError::Timeout`](crate::PipelineError::Timeout) after
+    /// [`request_timeout`](Self::request_timeout). Callers that don't care
+    /// whether the warmup worked can ignore the result. On Ollama the model
+    /// stays loaded for the server's `keep_alive` period.
+    pub async fn warmup(&self, model: &str) -> crate::error::Result<()> {
+        self.check_cancelled()?;
+        let mut request = LlmRequest::new(model, "hi");
+        request.config = LlmConfig::default()
+            .with_temperature(0.0)
+            .with_max_tokens(1);
+        request.timeout = self.call_timeout(false);
+        let call = self.backend.complete(&self.client, &self.base_url, &request);
+        crate::backend::within_timeout(request.timeout, call).await.map(|_| ())
+    }
+
     /// Get a reference to the cancellation AtomicBool, if set.
     pub fn cancel_flag(&self) -> Option<&AtomicBool> {
         self.cancellation.as_deref()
     }
+
+    /// Snapshot of all template variables: static `vars` plus every
+    /// dynamic var evaluated now. A dynamic var shadows a static var with
+    /// the same key.
+    pub fn resolved_vars(&self) -> HashMap<String, String> {
+        let mut vars = self.vars.clone();
+        for (key, f) in &self.dynamic_vars {
+            vars.insert(key.clone(), f());
+        }
+        vars
+    }
+
+    /// Derive a child context that shares everything with `self` but has
+    /// additional (or overridden) template variables.
+    ///
+    /// Used by composite payloads to inject per-step vars such as `{acc}`.
+    /// An extra var overrides both a static and a dynamic var with the same
+    /// key.
+    pub fn with_extra_vars<K, V>(&self, extra: impl IntoIterator<Item = (K, V)>) -> ExecCtx
+    where
+        K: Into<String>,
+        V: Into<String>,
+    {
+        let mut child = self.clone();
+        for (key, value) in extra {
+            let key = key.into();
+            child.dynamic_vars.remove(&key);
+            child.vars.insert(key, value.into());
+        }
+        child
+    }
 }
 
 impl std::fmt::Debug for ExecCtx {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        f.debug_struct("ExecCtx")
-            .field("base_url", &self.base_url)
+        let mut d = f.debug_struct("ExecCtx");
+        d.field("base_url", &self.base_url)
             .field("backend", &self.backend.name())
             .field("backoff", &self.backoff)
             .field("vars_count", &self.vars.len())
+            .field("dynamic_vars_count", &self.dynamic_vars.len())
             .field("has_cancellation", &self.cancellation.is_some())
             .field("has_event_handler", &self.event_handler.is_some())
-            .finish()
+            .field("request_timeout", &self.request_timeout)
+            .field("stream_timeout", &self.stream_timeout)
+            .field("max_stream_tokens", &self.max_stream_tokens)
+            .field("dedup_stream", &self.dedup_stream)
+            .field("default_output_strategy", &self.default_output_strategy)
+            .field("default_model", &self.default_model)
+            .field("normalize_unicode", &self.normalize_unicode)
+            .field("normalize_keys", &self.normalize_keys)
+            .field("has_cost_table", &self.cost_table.is_some())
+            .field("completion_tokens", &self.completion_tokens_used());
+        #[cfg(feature = "semantic-cache")]
+        d.field("semantic_cache", &self.semantic_cache);
+        d.finish()
     }
 }
 
@@ -104,9 +373,22 @@ pub struct ExecCtxBuilder {
     backend: Option<Arc<dyn Backend>>,
     backoff: Option<BackoffConfig>,
     vars: HashMap<String, String>,
+    dynamic_vars: HashMap<String, DynamicVar>,
     cancellation: Option<Arc<AtomicBool>>,
     event_handler: Option<Arc<dyn EventHandler>>,
     timeout: Option<Duration>,
+    connect_timeout: Option<Duration>,
+    request_timeout: Option<Duration>,
+    stream_timeout: Option<Duration>,
+    max_stream_tokens: Option<usize>,
+    dedup_stream: bool,
+    default_output_strategy: Option<OutputStrategy>,
+    default_model: Option<String>,
+    normalize_unicode: bool,
+    normalize_keys: bool,
+    #[cfg(feature = "semantic-cache")]
+    semantic_cache: Option<Arc<SemanticCache>>,
+    cost_table: Option<Arc<CostTable>>,
 }
 
 impl ExecCtxBuilder {
@@ -142,6 +424,27 @@ impl ExecCtxBuilder {
         self
     }
 
+    /// Use the native Anthropic Messages API backend with API key authentication.
+    ///
+    /// Sets the backend to [`AnthropicBackend`] with the given API key sent as
+    /// `x-api-key: {key}`. Point the builder at `https://api.anthropic.com`.
+    #[cfg(feature = "anthropic")]
+    pub fn anthropic_with_key(mut self, api_key: impl Into<String>) -> Self {
+        self.backend = Some(Arc::new(AnthropicBackend::new().with_api_key(api_key)));
+        self
+    }
+
+    /// Use the Google Gemini backend with API key authentication.
+    ///
+    /// Sets the backend to [`GeminiBackend`] with the given API key sent in
+    /// the `x-goog-api-key` header. Point the builder at
+    /// `https://generativelanguage.googleapis.com`.
+    #[cfg(feature = "gemini")]
+    pub fn gemini_with_key(mut self, api_key: impl Into<String>) -> Self {
+        self.backend = Some(Arc::new(GeminiBackend::new().with_api_key(api_key)));
+        self
+    }
+
     /// Set the transport retry configuration. Default: [`BackoffConfig::none()`].
     pub fn backoff(mut self, config: BackoffConfig) -> Self {
         self.backoff = Some(config);
@@ -160,6 +463,31 @@ impl ExecCtxBuilder {
         self
     }
 
+    /// Insert a variable whose value is recomputed each time a prompt is
+    /// rendered, e.g. `{timestamp}` or a step counter.
+    ///
+    /// Evaluation order: an [`LlmCall`](crate::LlmCall) evaluates every
+    /// dynamic var once per invocation, before substitution, and uses that
+    /// snapshot for both the prompt and the system prompt (semantic retries
+    /// reuse it too). `{input}` is substituted first, then all variables;
+    /// a dynamic var shadows a static [`var`](Self::var) with the same key,
+    /// and is itself overridden by a var passed to
+    /// [`ExecCtx::with_extra_vars`].
+    ///
+    /// ```
+    /// use llm_pipeline::ExecCtx;
+    /// use std::sync::Arc;
+    ///
+    /// let ctx = ExecCtx::builder("http://localhost:11434")
+    ///     .dynamic_var("year", Arc::new(|| "2025".to_string()))
+    ///     .build();
+    /// assert_eq!(ctx.resolved_vars()["year"], "2025");
+    /// ```
+    pub fn dynamic_var(mut self, key: impl Into<String>, f: DynamicVar) -> Self {
+        self.dynamic_vars.insert(key.into(), f);
+        self
+    }
+
     /// Set the cancellation flag.
     pub fn cancellation(mut self, cancel: Option<Arc<AtomicBool>>) -> Self {
         self.cancellation = cancel;
@@ -172,34 +500,195 @@ impl ExecCtxBuilder {
         self
     }
 
-    /// Set the request timeout. Default: 60 seconds.
+    /// Set a total timeout on the built HTTP client, covering every request
+    /// including streams. Default: none.
     ///
-    /// If no custom `Client` is provided, the built client will use this timeout.
-    /// If a custom `Client` is provided via `.client()`, this setting is ignored
-    /// (the custom client's own timeout applies).
+    /// Prefer [`request_timeout`](Self::request_timeout) and
+    /// [`stream_timeout`](Self::stream_timeout), which let long streams
+    /// outlive short completions. If this is set and `request_timeout` isn't,
+    /// it is also the deadline for non-streaming calls. A custom `Client`
+    /// provided via `.client()` is not modified, but that deadline still
+    /// applies to it.
     pub fn timeout(mut self, timeout: Duration) -> Self {
         self.timeout = Some(timeout);
         self
     }
 
+    /// Set how long the built HTTP client waits to establish a connection.
+    /// Default: 10 seconds. Ignored if a custom `Client` is provided.
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connect_timeout = Some(timeout);
+        self
+    }
+
+    /// Set the deadline for each non-streaming backend attempt. Default: 60
+    /// seconds, or none with a custom `Client`, whose own timeout then
+    /// governs.
+    ///
+    /// Enforced around the call rather than by the HTTP client, so it also
+    /// applies with a custom `Client`. A timed-out attempt fails with
+    /// [`PipelineError::Timeout`](crate::PipelineError::Timeout) and is
+    /// retried by the transport [`backoff`](Self::backoff).
+    /// [`LlmCall::with_timeout`](crate::LlmCall::with_timeout) overrides it.
+    pub fn request_timeout(mut self, timeout: Duration) -> Self {
+        self.request_timeout = Some(timeout);
+        self
+    }
+
+    /// Set the deadline for each streaming backend attempt, measured to the
+    /// end of the stream. Default: 10 minutes, long enough for most
+    /// generations while still failing a stalled stream, or none with a
+    /// custom `Client`. Otherwise like
+    /// [`request_timeout`](Self::request_timeout).
+    pub fn stream_timeout(mut self, timeout: Duration) -> Self {
+        self.stream_timeout = Some(timeout);
+        self
+    }
+
+    /// Stop streaming after `n` tokens, regardless of what the server does.
+    ///
+    /// Enforced client-side in the streaming loops of the built-in backends:
+    /// once `n` tokens have arrived the connection is dropped and the output's
+    /// [`finish_reason`](crate::diagnostics::ParseDiagnostics::finish_reason)
+    /// is `"client_limit"`. Protects against local models that ignore
+    /// `num_predict`/`max_tokens`. Has no effect on non-streaming calls.
+    pub fn max_stream_tokens(mut self, n: usize) -> Self {
+        self.max_stream_tokens = Some(n);
+        self
+    }
+
+    /// Skip text the server re-sends when a stream is resumed, e.g. after
+    /// a mid-stream reconnect through a flaky proxy. Default: `false`.
+    ///
+    /// Only applies to streams resumed with
+    /// [`BackoffConfig::resume_streams`](crate::BackoffConfig::resume_streams):
+    /// chunks that replay the partial output received before the failure
+    /// are left out, and the response metadata counts them as
+    /// `stream_duplicates`. Output after the replayed prefix is never
+    /// touched. Opt-in because a server that continues instead of replaying
+    /// could, rarely, start with the same text it was resumed from. See
+    /// [`StreamDedup`](crate::streaming::StreamDedup).
+    pub fn dedup_stream(mut self, enabled: bool) -> Self {
+        self.dedup_stream = enabled;
+        self
+    }
+
+    /// Set the output strategy inherited by every [`LlmCall`](crate::LlmCall)
+    /// that doesn't set its own.
+    ///
+    /// Precedence: explicit `LlmCall` strategy > [`Chain`](crate::Chain)
+    /// default > this default > `Lossy`.
+    pub fn default_output_strategy(mut self, strategy: OutputStrategy) -> Self {
+        self.default_output_strategy = Some(strategy);
+        self
+    }
+
+    /// Set the model used by every [`LlmCall`](crate::LlmCall) that doesn't
+    /// set its own via [`with_model`](crate::LlmCall::with_model).
+    pub fn default_model(mut self, model: impl Into<String>) -> Self {
+        self.default_model = Some(model.into());
+        self
+    }
+
+    /// Run [`normalize_unicode`](crate::output_parser::normalize_unicode)
+    /// on every response before its output strategy parses it: smart
+    /// quotes become ASCII quotes, non-breaking spaces become plain spaces,
+    /// and zero-width characters are dropped. `raw_response` keeps the
+    /// original text. Off by default since it can alter intentional content.
+    pub fn normalize_unicode(mut self, enabled: bool) -> Self {
+        self.normalize_unicode = enabled;
+        self
+    }
+
+    /// Run [`normalize_keys`](crate::output_parser::normalize_keys) on the
+    /// values parsed by the `Json`, `JsonPointer`, and `Lossy` strategies, so
+    /// `"Title"`, `"TITLE"`, and `"title"` all reach
+    /// [`parse_as`](crate::PayloadOutput::parse_as) as `title`. For
+    /// `JsonPointer`, keys are normalized before the pointer is resolved.
+    /// Off by default since it changes keys callers may rely on exactly.
+    pub fn normalize_keys(mut self, enabled: bool) -> Self {
+        self.normalize_keys = enabled;
+        self
+    }
+
+    /// Serve [`LlmCall`](crate::LlmCall) outputs from a similarity cache.
+    ///
+    /// Before each call, the rendered prompt is embedded with `embed_model`
+    /// via [`Backend::embed`]; if a previous prompt of the same call (same
+    /// payload name and model) has cosine similarity above `threshold`, its
+    /// output is returned with
+    /// [`cache_hit`](crate::diagnostics::ParseDiagnostics::cache_hit) set.
+    /// Only successfully parsed outputs are stored. Embedding failures are
+    /// treated as cache misses. Requires the `semantic-cache` feature.
+    #[cfg(feature = "semantic-cache")]
+    pub fn semantic_cache(mut self, threshold: f32, embed_model: impl Into<String>) -> Self {
+        self.semantic_cache = Some(Arc::new(SemanticCache::new(threshold, embed_model)));
+        self
+    }
+
+    /// Use a pre-built (possibly shared) [`SemanticCache`].
+    #[cfg(feature = "semantic-cache")]
+    pub fn semantic_cache_instance(mut self, cache: Arc<SemanticCache>) -> Self {
+        self.semantic_cache = Some(cache);
+        self
+    }
+
+    /// Estimate the USD cost of each [`LlmCall`](crate::LlmCall) from the
+    /// token usage its backend reports, recorded in
+    /// [`estimated_cost_usd`](crate::diagnostics::ParseDiagnostics::estimated_cost_usd).
+    /// Calls to models missing from `table` get no estimate.
+    pub fn cost_table(mut self, table: CostTable) -> Self {
+        self.cost_table = Some(Arc::new(table));
+        self
+    }
+
     /// Build the execution context.
+    ///
+    /// Never fails. Anything [`ExecCtx::config_warnings`] flags is emitted
+    /// to the event handler as [`Event::ConfigWarning`].
     pub fn build(self) -> ExecCtx {
-        let timeout = self.timeout.unwrap_or(Duration::from_secs(60));
+        // A custom client's own timeout is respected unless one is asked for.
+        let request_timeout = self
+            .request_timeout
+            .or(self.timeout)
+            .or_else(|| self.client.is_none().then_some(Duration::from_secs(60)));
+        let stream_timeout = self
+            .stream_timeout
+            .or_else(|| self.client.is_none().then_some(Duration::from_secs(600)));
         let client = self.client.unwrap_or_else(|| {
-            Client::builder()
-                .timeout(timeout)
-                .build()
-                .expect("Failed to build HTTP client")
+            let mut builder = Client::builder()
+                .connect_timeout(self.connect_timeout.unwrap_or(Duration::from_secs(10)));
+            if let Some(timeout) = self.timeout {
+                builder = builder.timeout(timeout);
+            }
+            builder.build().expect("Failed to build HTTP client")
         });
-        ExecCtx {
+        let ctx = ExecCtx {
             client,
             base_url: normalize_base_url(&self.base_url),
             backend: self.backend.unwrap_or_else(|| Arc::new(OllamaBackend)),
             backoff: self.backoff.unwrap_or_else(BackoffConfig::none),
             vars: self.vars,
+            dynamic_vars: self.dynamic_vars,
             cancellation: self.cancellation,
             event_handler: self.event_handler,
+            request_timeout,
+            stream_timeout,
+            max_stream_tokens: self.max_stream_tokens,
+            dedup_stream: self.dedup_stream,
+            default_output_strategy: self.default_output_strategy,
+            default_model: self.default_model,
+            normalize_unicode: self.normalize_unicode,
+            normalize_keys: self.normalize_keys,
+            #[cfg(feature = "semantic-cache")]
+            semantic_cache: self.semantic_cache,
+            cost_table: self.cost_table,
+            completion_tokens: Arc::new(AtomicU64::new(0)),
+        };
+        for message in ctx.config_warnings() {
+            emit(&ctx.event_handler, Event::ConfigWarning { message });
         }
+        ctx
     }
 }
 
@@ -210,7 +699,7 @@ impl ExecCtxBuilder {
 fn normalize_base_url(url: &str) -> String {
     let trimmed = url.trim_end_matches('/');
     // Strip known suffixes (order matters — longest first)
-    for suffix in &["/v1/chat/completions", "/v1/chat", "/v1", "/api/generate", "/api/chat", "/api"] {
+    for suffix in &["/v1/chat/completions", "/v1/chat", "/v1beta", "/v1", "/api/generate", "/api/chat", "/api"] {
         if let Some(stripped) = trimmed.strip_suffix(suffix) {
             return stripped.to_string();
         }
@@ -226,6 +715,10 @@ mod tests {
     fn test_normalize_base_url_strips_v1() {
         assert_eq!(normalize_base_url("https://api.openai.com/v1"), "https://api.openai.com");
         assert_eq!(normalize_base_url("https://api.openai.com/v1/"), "https://api.openai.com");
+        assert_eq!(
+            normalize_base_url("https://generativelanguage.googleapis.com/v1beta"),
+            "https://generativelanguage.googleapis.com"
+        );
     }
 
     #[test]
@@ -261,4 +754,227 @@ mod tests {
             .build();
         // Smoke test: builds without panic
     }
+
+    #[test]
+    fn test_split_timeouts() {
+        let ctx = ExecCtx::builder("http://localhost:11434").build();
+        assert_eq!(ctx.call_timeout(false), Some(Duration::from_secs(60)));
+        assert_eq!(ctx.call_timeout(true), Some(Duration::from_secs(600)));
+
+        let ctx = ExecCtx::builder("http://localhost:11434")
+            .connect_timeout(Duration::from_secs(2))
+            .request_timeout(Duration::from_secs(30))
+            .stream_timeout(Duration::from_secs(600))
+            .build();
+        assert_eq!(ctx.call_timeout(false), Some(Duration::from_secs(30)));
+        assert_eq!(ctx.call_timeout(true), Some(Duration::from_secs(600)));
+
+        let ctx = ExecCtx::builder("http://localhost:11434")
+            .timeout(Duration::from_secs(120))
+            .build();
+        assert_eq!(ctx.request_timeout, Some(Duration::from_secs(120)));
+
+        // A custom client keeps its own timeout unless one is set here.
+        let ctx = ExecCtx::builder("http://localhost:11434")
+            .client(Client::new())
+            .build();
+        assert_eq!(ctx.request_timeout, None);
+        assert_eq!(ctx.stream_timeout, None);
+        let ctx = ExecCtx::builder("http://localhost:11434")
+            .client(Client::new())
+            .request_timeout(Duration::from_secs(5))
+            .build();
+        assert_eq!(ctx.request_timeout, Some(Duration::from_secs(5)));
+    }
+
+    #[tokio::test]
+    async fn test_warmup_times_out_on_stalled_server() {
+        let base_url = crate::test_support::serve_silent().await;
+        let ctx = ExecCtx::builder(base_url)
+            .request_timeout(Duration::from_millis(50))
+            .build();
+        let err = tokio::time::timeout(Duration::from_secs(5), ctx.warmup("m"))
+            .await
+            .expect("warmup should give up on its own")
+            .unwrap_err();
+        assert!(matches!(err, crate::PipelineError::Timeout(d) if d == Duration::from_millis(50)));
+    }
+
+    #[test]
+    fn test_max_stream_tokens_builder() {
+        let ctx = ExecCtx::builder("http://localhost:11434").build();
+        assert_eq!(ctx.max_stream_tokens, None);
+
+        let ctx = ExecCtx::builder("http://localhost:11434")
+            .max_stream_tokens(256)
+            .build();
+        assert_eq!(ctx.max_stream_tokens, Some(256));
+        assert_eq!(ctx.with_extra_vars([("k", "v")]).max_stream_tokens, Some(256));
+    }
+
+    #[test]
+    fn test_dynamic_var_recomputed_and_shadows_static() {
+        use std::sync::atomic::AtomicUsize;
+
+        let counter = Arc::new(AtomicUsize::new(0));
+        let c = counter.clone();
+        let ctx = ExecCtx::builder("http://localhost:11434")
+            .var("step", "static")
+            .var("other", "kept")
+            .dynamic_var(
+                "step",
+                Arc::new(move || (c.fetch_add(1, Ordering::SeqCst) + 1).to_string()),
+            )
+            .build();
+
+        assert_eq!(ctx.resolved_vars()["step"], "1");
+        assert_eq!(ctx.resolved_vars()["step"], "2");
+        assert_eq!(ctx.resolved_vars()["other"], "kept");
+        assert_eq!(counter.load(Ordering::SeqCst), 3);
+    }
+
+    #[test]
+    fn test_extra_var_overrides_dynamic_var() {
+        let ctx = ExecCtx::builder("http://localhost:11434")
+            .dynamic_var("acc", Arc::new(|| "dynamic".to_string()))
+            .dynamic_var("step", Arc::new(|| "dynamic".to_string()))
+            .build();
+        let child = ctx.with_extra_vars([("acc", "explicit")]);
+        let vars = child.resolved_vars();
+        assert_eq!(vars["acc"], "explicit");
+        assert_eq!(vars["step"], "dynamic");
+        assert_eq!(ctx.resolved_vars()["acc"], "dynamic");
+    }
+
+    fn lookup(pairs: &[(&str, &str)]) -> impl Fn(&str) -> Option<String> {
+        let map: HashMap<String, String> = pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        move |key| map.get(key).cloned()
+    }
+
+    #[test]
+    fn test_from_env_defaults_to_local_ollama() {
+        let ctx = ExecCtx::builder_from_lookup(lookup(&[])).unwrap().build();
+        assert_eq!(ctx.base_url, "http://localhost:11434");
+        assert_eq!(ctx.backend.name(), "ollama");
+        assert!(ctx.default_model.is_none());
+    }
+
+    #[test]
+    fn test_from_env_reads_model_and_url() {
+        let ctx = ExecCtx::builder_from_lookup(lookup(&[
+            ("LLM_BASE_URL", "http://gpu-box:11434/api"),
+            ("LLM_MODEL", "qwen2.5:7b"),
+            ("LLM_BACKEND", " "),
+        ]))
+        .unwrap()
+        .build();
+        assert_eq!(ctx.base_url, "http://gpu-box:11434");
+        assert_eq!(ctx.default_model.as_deref(), Some("qwen2.5:7b"));
+    }
+
+    #[test]
+    fn test_from_env_rejects_invalid_values() {
+        let err = ExecCtx::builder_from_lookup(lookup(&[("LLM_BACKEND", "bedrock")]))
+            .err()
+            .unwrap();
+        assert!(err.to_string().contains("bedrock"));
+        let err = ExecCtx::builder_from_lookup(lookup(&[("LLM_BASE_URL", "localhost:11434")]))
+            .err()
+            .unwrap();
+        assert!(err.to_string().contains("LLM_BASE_URL"));
+    }
+
+    #[cfg(feature = "openai")]
+    #[test]
+    fn test_from_env_openai_with_key() {
+        let ctx = ExecCtx::builder_from_lookup(lookup(&[
+            ("LLM_BACKEND", "OpenAI"),
+            ("LLM_API_KEY", "sk-test"),
+        ]))
+        .unwrap()
+        .build();
+        assert_eq!(ctx.base_url, "https://api.openai.com");
+        assert_eq!(ctx.backend.name(), "openai");
+    }
+
+    #[test]
+    fn test_config_warnings_flag_mismatched_backend() {
+        use crate::events::FnEventHandler;
+        use std::sync::Mutex;
+
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let sink = seen.clone();
+        let ctx = ExecCtx::builder("https://api.openai.com/v1")
+            .event_handler(Arc::new(FnEventHandler(move |event: Event| {
+                if let Event::ConfigWarning { message } = event {
+                    sink.lock().unwrap().push(message);
+                }
+            })))
+            .build();
+        assert_eq!(seen.lock().unwrap().len(), 1);
+        assert!(seen.lock().unwrap()[0].contains("OpenAiBackend"));
+        assert!(ctx.validate().is_err());
+
+        let ctx = ExecCtx::builder("http://localhost:11434").build();
+        assert!(ctx.config_warnings().is_empty());
+        assert!(ctx.validate().is_ok());
+    }
+
+    #[cfg(feature = "openai")]
+    #[test]
+    fn test_config_warnings_openai_on_ollama_port() {
+        let ctx = ExecCtx::builder("http://localhost:11434").openai().build();
+        let warnings = ctx.config_warnings();
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].contains("11434"));
+    }
+
+    #[cfg(feature = "anthropic")]
+    #[test]
+    fn test_anthropic_with_key() {
+        let ctx = ExecCtx::builder("https://api.anthropic.com/v1")
+            .anthropic_with_key("sk-ant-test")
+            .build();
+        assert_eq!(ctx.base_url, "https://api.anthropic.com");
+        assert_eq!(ctx.backend.name(), "anthropic");
+        assert!(ctx.config_warnings().is_empty());
+    }
+
+    #[cfg(feature = "gemini")]
+    #[test]
+    fn test_gemini_with_key() {
+        let ctx = ExecCtx::builder("https://generativelanguage.googleapis.com/v1beta")
+            .gemini_with_key("AIza-test")
+            .build();
+        assert_eq!(ctx.base_url, "https://generativelanguage.googleapis.com");
+        assert_eq!(ctx.backend.name(), "gemini");
+    }
+
+    #[tokio::test]
+    async fn test_warmup_sends_one_token_request() {
+        use crate::backend::MockBackend;
+
+        let backend = Arc::new(MockBackend::fixed(""));
+        let ctx = ExecCtx::builder("http://test")
+            .backend(backend.clone())
+            .build();
+        ctx.warmup("llama3.2:3b").await.unwrap();
+
+        let requests = backend.requests();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].model, "llama3.2:3b");
+        assert_eq!(requests[0].config.max_tokens, 1);
+        assert!(!requests[0].stream);
+
+        let cancelled = ExecCtx::builder("http://test")
+            .cancellation(Some(Arc::new(AtomicBool::new(true))))
+            .build();
+        assert!(matches!(
+            cancelled.warmup("llama3.2:3b").await,
+            Err(crate::PipelineError::Cancelled)
+        ));
+    }
 }
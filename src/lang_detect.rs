@@ -0,0 +1,154 @@
+//! Output language detection.
+//!
+//! Thin wrapper over [`whatlang`] used by
+//! [`RetryConfig::requiring_language`](crate::RetryConfig::requiring_language).
+//! Languages are identified by ISO 639-1 (`"fr"`) or ISO 639-3 (`"fra"`)
+//! codes. Enable with the `lang-detect` feature.
+
+use serde_json::Value;
+
+/// A language the detector knows, re-exported from [`whatlang`].
+pub use whatlang::Lang;
+
+/// ISO 639-1 codes for every language the detector knows.
+const ISO_639_1: &[(&str, Lang)] = &[
+    ("af", Lang::Afr),
+    ("ak", Lang::Aka),
+    ("am", Lang::Amh),
+    ("ar", Lang::Ara),
+    ("az", Lang::Aze),
+    ("be", Lang::Bel),
+    ("bg", Lang::Bul),
+    ("bn", Lang::Ben),
+    ("ca", Lang::Cat),
+    ("cs", Lang::Ces),
+    ("da", Lang::Dan),
+    ("de", Lang::Deu),
+    ("el", Lang::Ell),
+    ("en", Lang::Eng),
+    ("eo", Lang::Epo),
+    ("es", Lang::Spa),
+    ("et", Lang::Est),
+    ("fa", Lang::Pes),
+    ("fi", Lang::Fin),
+    ("fr", Lang::Fra),
+    ("gu", Lang::Guj),
+    ("he", Lang::Heb),
+    ("hi", Lang::Hin),
+    ("hr", Lang::Hrv),
+    ("hu", Lang::Hun),
+    ("hy", Lang::Hye),
+    ("id", Lang::Ind),
+    ("it", Lang::Ita),
+    ("ja", Lang::Jpn),
+    ("jv", Lang::Jav),
+    ("ka", Lang::Kat),
+    ("km", Lang::Khm),
+    ("kn", Lang::Kan),
+    ("ko", Lang::Kor),
+    ("la", Lang::Lat),
+    ("lt", Lang::Lit),
+    ("lv", Lang::Lav),
+    ("mk", Lang::Mkd),
+    ("ml", Lang::Mal),
+    ("mr", Lang::Mar),
+    ("my", Lang::Mya),
+    ("nb", Lang::Nob),
+    ("ne", Lang::Nep),
+    ("nl", Lang::Nld),
+    ("no", Lang::Nob),
+    ("or", Lang::Ori),
+    ("pa", Lang::Pan),
+    ("pl", Lang::Pol),
+    ("pt", Lang::Por),
+    ("ro", Lang::Ron),
+    ("ru", Lang::Rus),
+    ("si", Lang::Sin),
+    ("sk", Lang::Slk),
+    ("sl", Lang::Slv),
+    ("sn", Lang::Sna),
+    ("sr", Lang::Srp),
+    ("sv", Lang::Swe),
+    ("ta", Lang::Tam),
+    ("te", Lang::Tel),
+    ("th", Lang::Tha),
+    ("tk", Lang::Tuk),
+    ("tl", Lang::Tgl),
+    ("tr", Lang::Tur),
+    ("uk", Lang::Ukr),
+    ("ur", Lang::Urd),
+    ("uz", Lang::Uzb),
+    ("vi", Lang::Vie),
+    ("yi", Lang::Yid),
+    ("zh", Lang::Cmn),
+    ("zu", Lang::Zul),
+];
+
+/// Look up a language by ISO 639-1 or ISO 639-3 code (case-insensitive).
+pub fn language_from_code(code: &str) -> Option<Lang> {
+    let code = code.trim().to_ascii_lowercase();
+    ISO_639_1
+        .iter()
+        .find(|(c, _)| *c == code)
+        .map(|(_, lang)| *lang)
+        .or_else(|| Lang::from_code(code))
+}
+
+/// Detect the language of `text`. Returns `None` when the text is too short
+/// or ambiguous for a reliable answer.
+pub fn detect_language(text: &str) -> Option<Lang> {
+    whatlang::detect(text)
+        .filter(|info| info.is_reliable())
+        .map(|info| info.lang())
+}
+
+/// The natural-language text in a parsed value: the string itself, or every
+/// string inside an array or object joined by newlines.
+pub fn value_text(value: &Value) -> String {
+    fn collect<'v>(value: &'v Value, out: &mut Vec<&'v str>) {
+        match value {
+            Value::String(s) => out.push(s),
+            Value::Array(items) => items.iter().for_each(|v| collect(v, out)),
+            Value::Object(map) => map.values().for_each(|v| collect(v, out)),
+            _ => {}
+        }
+    }
+    let mut parts = Vec::new();
+    collect(value, &mut parts);
+    parts.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_language_from_code() {
+        assert_eq!(language_from_code("fr"), Some(Lang::Fra));
+        assert_eq!(language_from_code("FRA"), Some(Lang::Fra));
+        assert_eq!(language_from_code("zh"), Some(Lang::Cmn));
+        assert_eq!(language_from_code("xx"), None);
+    }
+
+    #[test]
+    fn test_detect_language() {
+        assert_eq!(
+            detect_language("Le chat dort sur le canapé pendant que nous préparons le dîner."),
+            Some(Lang::Fra)
+        );
+        assert_eq!(
+            detect_language("The cat is sleeping on the sofa while we cook dinner tonight."),
+            Some(Lang::Eng)
+        );
+        assert_eq!(detect_language("ok"), None);
+    }
+
+    #[test]
+    fn test_value_text() {
+        let value = json!({"title": "Bonjour", "tags": ["un", "deux"], "n": 3});
+        let text = value_text(&value);
+        assert!(text.contains("Bonjour") && text.contains("deux"));
+        assert!(!text.contains('3'));
+    }
+}
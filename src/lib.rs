@@ -85,16 +85,23 @@
 // --- New payload layer ---
 pub mod backend;
 pub mod chain;
+pub mod cost;
 pub mod diagnostics;
 pub mod events;
 pub mod exec_ctx;
+#[cfg(feature = "lang-detect")]
+pub mod lang_detect;
 pub mod llm_call;
+pub mod metrics;
 pub mod output_parser;
 pub mod output_strategy;
 pub mod parsing;
 pub mod payload;
 pub mod retry;
+#[cfg(feature = "semantic-cache")]
+pub mod semantic_cache;
 pub mod streaming;
+pub mod truncation;
 
 // --- Original modules (still public) ---
 pub mod client;
@@ -104,21 +111,34 @@ pub mod prompt;
 pub mod stage;
 pub mod types;
 
+#[cfg(test)]
+mod test_support;
+
 // --- Primary exports: new payload API ---
-pub use backend::{BackoffConfig, MockBackend, OllamaBackend};
+pub use backend::{
+    BackoffConfig, BackoffConfigBuilder, MockBackend, OllamaBackend, ToolCall, ToolSpec, Usage,
+};
+#[cfg(feature = "anthropic")]
+pub use backend::AnthropicBackend;
+#[cfg(feature = "bedrock")]
+pub use backend::BedrockBackend;
+#[cfg(feature = "gemini")]
+pub use backend::GeminiBackend;
 #[cfg(feature = "openai")]
 pub use backend::OpenAiBackend;
-pub use chain::Chain;
+pub use chain::{Chain, ChainResult, ChainStep, PipeMode};
+pub use cost::{CostTable, ModelPrice};
 pub use diagnostics::ParseDiagnostics;
-pub use exec_ctx::{ExecCtx, ExecCtxBuilder};
+pub use exec_ctx::{DynamicVar, ExecCtx, ExecCtxBuilder};
 pub use llm_call::LlmCall;
 pub use output_strategy::OutputStrategy;
 pub use payload::{BoxFut, Payload, PayloadOutput};
-pub use retry::RetryConfig;
+pub use retry::{RetryConfig, RetryReason};
 pub use streaming::StreamingDecoder;
+pub use truncation::{TruncationMode, TruncationStrategy};
 
 // --- Re-exports: original API (compatibility) ---
-pub use client::LlmConfig;
+pub use client::{LlmConfig, LlmConfigOverride};
 pub use error::{PipelineError, Result};
 pub use pipeline::{Pipeline, PipelineBuilder};
 pub use stage::{Stage, StageBuilder};
@@ -6,20 +6,34 @@
 //! [`RetryConfig`].
 
 use crate::{
-    backend::{self, ChatMessage, LlmRequest, LlmResponse},
+    backend::{self, ChatMessage, LlmRequest, LlmResponse, ToolSpec},
     client::LlmConfig,
     diagnostics::ParseDiagnostics,
     error::Result,
     events::{emit, Event},
     exec_ctx::ExecCtx,
-    output_parser,
+    output_parser::{
+        self, json::JsonRecovery, streaming::StreamingJsonParser, JsonCandidateSelection,
+    },
     output_strategy::OutputStrategy,
     parsing,
     payload::{BoxFut, Payload, PayloadOutput},
-    retry::RetryConfig,
+    retry::{RetryConfig, RetryReason},
+    truncation::TruncationStrategy,
 };
 use serde_json::{json, Value};
 use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+/// Fallback strategy when neither the call nor the context sets one.
+static LOSSY: OutputStrategy = OutputStrategy::Lossy;
+
+/// Fallback model when neither the call nor the context sets one.
+pub(crate) const DEFAULT_MODEL: &str = "llama3.2:3b";
+
+/// Joins [`LlmCall::with_system_parts`] fragments into one system prompt.
+const SYSTEM_PART_SEPARATOR: &str = "\n\n";
 
 /// An LLM call payload that invokes a backend with output strategy and optional retry.
 ///
@@ -38,6 +52,16 @@ use std::collections::HashMap;
 /// let ctx = ExecCtx::builder("http://localhost:11434").build();
 /// let output = call.invoke(&ctx, json!("Some long text...")).await?;
 /// ```
+///
+/// `LlmCall` is `Clone`, so a base call can be configured once and cloned
+/// into variants:
+///
+/// ```ignore
+/// let base = LlmCall::new("classify", "Classify: {input}").expecting_json();
+/// let small = base.clone().with_name("classify-small").with_model("llama3.2:3b");
+/// let large = base.clone().with_name("classify-large").with_model("qwen2.5:32b");
+/// ```
+#[derive(Clone)]
 pub struct LlmCall {
     /// Instance name (for logging/events).
     name: String,
@@ -45,16 +69,34 @@ pub struct LlmCall {
     prompt_template: String,
     /// Optional system prompt template (triggers chat endpoint on Ollama).
     system_template: Option<String>,
-    /// Model identifier (e.g. `"llama3.2:3b"`).
-    model: String,
+    /// Fragments `system_template` was joined from by
+    /// [`with_system_parts`](Self::with_system_parts); empty otherwise.
+    system_parts: Vec<String>,
+    /// Model identifier (e.g. `"llama3.2:3b"`). `None` defers to the
+    /// context default, falling back to [`DEFAULT_MODEL`].
+    model: Option<String>,
     /// LLM configuration (temperature, tokens, json_mode, etc.).
     config: LlmConfig,
     /// Whether to use the streaming endpoint.
     streaming: bool,
-    /// How to parse the raw LLM text into a Value. Default: `Lossy`.
-    output_strategy: OutputStrategy,
+    /// How to parse the raw LLM text into a Value. `None` defers to the
+    /// chain/context default, falling back to `Lossy`.
+    output_strategy: Option<OutputStrategy>,
     /// Optional semantic retry configuration.
     retry: Option<RetryConfig>,
+    /// Which bracketed region the JSON strategies use when the response
+    /// contains several.
+    json_selection: JsonCandidateSelection,
+    /// Per-attempt deadline for backend calls.
+    timeout: Option<Duration>,
+    /// Whether an object input's top-level fields become template vars.
+    input_vars: bool,
+    /// Length limit applied to the `{input}` substitution.
+    input_truncation: Option<TruncationStrategy>,
+    /// Text the assistant's reply is prefilled with.
+    assistant_prefix: Option<String>,
+    /// Functions the model may call instead of answering.
+    tools: Vec<ToolSpec>,
 }
 
 impl LlmCall {
@@ -64,11 +106,18 @@ impl LlmCall {
             name: name.into(),
             prompt_template: prompt_template.into(),
             system_template: None,
-            model: "llama3.2:3b".to_string(),
+            system_parts: Vec::new(),
+            model: None,
             config: LlmConfig::default(),
             streaming: false,
-            output_strategy: OutputStrategy::default(),
+            output_strategy: None,
             retry: None,
+            json_selection: JsonCandidateSelection::default(),
+            timeout: None,
+            input_vars: false,
+            input_truncation: None,
+            assistant_prefix: None,
+            tools: Vec::new(),
         }
     }
 
@@ -82,9 +131,22 @@ impl LlmCall {
         self.system_template.as_deref()
     }
 
-    /// Returns the model identifier.
+    /// Returns the model set on this call, or `"llama3.2:3b"` if none was
+    /// set. A context default may still apply at invocation time; see
+    /// [`resolve_model`](Self::resolve_model).
     pub fn model(&self) -> &str {
-        &self.model
+        self.model.as_deref().unwrap_or(DEFAULT_MODEL)
+    }
+
+    /// The model used when invoked with `ctx`.
+    ///
+    /// Precedence: explicit model on this call > `ctx.default_model` >
+    /// `"llama3.2:3b"`.
+    pub fn resolve_model<'s>(&'s self, ctx: &'s ExecCtx) -> &'s str {
+        self.model
+            .as_deref()
+            .or(ctx.default_model.as_deref())
+            .unwrap_or(DEFAULT_MODEL)
     }
 
     /// Returns the LLM config.
@@ -97,9 +159,28 @@ impl LlmCall {
         self.streaming
     }
 
-    /// Returns the output strategy.
+    /// Returns the output strategy set on this call, or `Lossy` if none was
+    /// set. A chain or context default may still apply at invocation time;
+    /// see [`resolve_output_strategy`](Self::resolve_output_strategy).
     pub fn output_strategy(&self) -> &OutputStrategy {
-        &self.output_strategy
+        self.output_strategy.as_ref().unwrap_or(&LOSSY)
+    }
+
+    /// Whether an output strategy was set explicitly on this call.
+    pub fn has_output_strategy(&self) -> bool {
+        self.output_strategy.is_some()
+    }
+
+    /// The strategy used when invoked with `ctx`.
+    ///
+    /// Precedence: explicit strategy on this call > `ctx.default_output_strategy`
+    /// (which a [`Chain`](crate::Chain) default overrides for its payloads) >
+    /// `Lossy`.
+    pub fn resolve_output_strategy<'s>(&'s self, ctx: &'s ExecCtx) -> &'s OutputStrategy {
+        self.output_strategy
+            .as_ref()
+            .or(ctx.default_output_strategy.as_ref())
+            .unwrap_or(&LOSSY)
     }
 
     /// Returns the retry configuration, if any.
@@ -107,15 +188,71 @@ impl LlmCall {
         self.retry.as_ref()
     }
 
+    /// Set the instance name.
+    pub fn with_name(mut self, name: impl Into<String>) -> Self {
+        self.name = name.into();
+        self
+    }
+
+    /// Replace the prompt template.
+    pub fn with_prompt(mut self, template: impl Into<String>) -> Self {
+        self.prompt_template = template.into();
+        self
+    }
+
     /// Set a system prompt template (enables `/api/chat` mode on Ollama).
     pub fn with_system(mut self, template: impl Into<String>) -> Self {
         self.system_template = Some(template.into());
+        self.system_parts.clear();
+        self
+    }
+
+    /// Build the system prompt from reusable fragments (role, constraints,
+    /// tools, examples), each a template like [`with_system`](Self::with_system).
+    ///
+    /// Backends that take plain-text system prompts get the fragments
+    /// joined by blank lines. With
+    /// [`with_cached_system`](Self::with_cached_system), the
+    /// OpenAI-compatible backend instead sends each one as a separate
+    /// content block and marks the last one cacheable, so keep the stable
+    /// fragments first.
+    ///
+    /// ```ignore
+    /// let call = LlmCall::new("review", "Review: {input}").with_system_parts(vec![
+    ///     ROLE.to_string(),
+    ///     CONSTRAINTS.to_string(),
+    ///     format!("Examples:\n{}", EXAMPLES),
+    /// ]);
+    /// ```
+    pub fn with_system_parts(mut self, parts: Vec<String>) -> Self {
+        self.system_template = Some(parts.join(SYSTEM_PART_SEPARATOR));
+        self.system_parts = parts;
+        self
+    }
+
+    /// Mark the system prompt as cacheable on providers with prompt
+    /// caching. See [`LlmConfig::cache_system`]. Set this after
+    /// [`with_config`](Self::with_config), which replaces the whole config.
+    pub fn with_cached_system(mut self, enabled: bool) -> Self {
+        self.config.cache_system = enabled;
+        self
+    }
+
+    /// Fail a backend call that takes longer than `timeout` with
+    /// [`PipelineError::Timeout`](crate::PipelineError::Timeout), regardless
+    /// of the HTTP client's own timeout. Applies to each transport attempt
+    /// separately, so with [`BackoffConfig`](crate::BackoffConfig) retries a
+    /// timed-out attempt is retried. Overrides the context's
+    /// [`request_timeout`](crate::ExecCtxBuilder::request_timeout) and
+    /// [`stream_timeout`](crate::ExecCtxBuilder::stream_timeout).
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
         self
     }
 
     /// Set the model.
     pub fn with_model(mut self, model: impl Into<String>) -> Self {
-        self.model = model.into();
+        self.model = Some(model.into());
         self
     }
 
@@ -133,7 +270,7 @@ impl LlmCall {
 
     /// Set a custom output strategy.
     pub fn with_output_strategy(mut self, strategy: OutputStrategy) -> Self {
-        self.output_strategy = strategy;
+        self.output_strategy = Some(strategy);
         self
     }
 
@@ -145,37 +282,140 @@ impl LlmCall {
 
     /// Shorthand: expect JSON output (full multi-strategy extraction with repair).
     pub fn expecting_json(mut self) -> Self {
-        self.output_strategy = OutputStrategy::Json;
+        self.output_strategy = Some(OutputStrategy::Json);
+        self
+    }
+
+    /// Shorthand: expect JSON and keep only the value at `pointer`
+    /// (e.g. `"/result/items"`).
+    pub fn expecting_json_pointer(mut self, pointer: impl Into<String>) -> Self {
+        self.output_strategy = Some(OutputStrategy::JsonPointer(pointer.into()));
+        self
+    }
+
+    /// Choose which JSON region the `Json` and `JsonPointer` strategies use
+    /// when the response contains several (e.g. the answer plus an example
+    /// with braces). Default: [`JsonCandidateSelection::Last`].
+    pub fn with_json_candidate_selection(mut self, selection: JsonCandidateSelection) -> Self {
+        self.json_selection = selection;
+        self
+    }
+
+    /// Splice the fields of an object input into the templates as vars, so
+    /// `{"title": "Dune", "year": 1965}` fills `{title}` and `{year}`.
+    ///
+    /// String fields are substituted as-is, other values as JSON, and they
+    /// take precedence over context vars of the same name. `{input}` is
+    /// still the whole object. Saves structured data from upstream payloads
+    /// being stringified into one blob and re-read by the model. Non-object
+    /// inputs are unaffected. Default: off.
+    pub fn with_input_vars(mut self, enabled: bool) -> Self {
+        self.input_vars = enabled;
+        self
+    }
+
+    /// Trim the text substituted for `{input}` to fit `strategy`, instead
+    /// of sending an oversized prompt. Context vars and fields spliced in
+    /// by [`with_input_vars`](Self::with_input_vars) are left whole. A
+    /// clipped input is reported as
+    /// [`ParseDiagnostics::input_truncated`]. Default: off.
+    pub fn with_input_truncation(mut self, strategy: TruncationStrategy) -> Self {
+        self.input_truncation = Some(strategy);
+        self
+    }
+
+    /// Prefill the start of the model's reply, e.g. `"{"` to force a JSON
+    /// object.
+    ///
+    /// The prefix is sent as a trailing assistant message, which the model
+    /// continues, and is prepended to the response before parsing (unless
+    /// the response already starts with it). This sends the call as chat:
+    /// Ollama uses `/api/chat`. Streamed tokens don't include the prefix.
+    /// Not every provider continues a prefilled reply; OpenAI's chat API
+    /// treats it as a finished turn.
+    pub fn with_assistant_prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.assistant_prefix = Some(prefix.into());
+        self
+    }
+
+    /// Offer the model functions it may call instead of (or before)
+    /// answering.
+    ///
+    /// Requested calls are returned in [`PayloadOutput::tool_calls`]; the
+    /// call does not run them. A response with tool calls never triggers a
+    /// semantic retry, whatever its text, and one with no text at all
+    /// skips output parsing: its value is `null` and its diagnostics
+    /// report the `"tool_calls"` strategy. Supported by the OpenAI backend
+    /// and by Ollama, which switches to `/api/chat`; the Anthropic,
+    /// Gemini, and Bedrock backends fail the call with
+    /// [`PipelineError::InvalidConfig`](crate::PipelineError::InvalidConfig).
+    pub fn with_tools(mut self, tools: Vec<ToolSpec>) -> Self {
+        self.tools = tools;
+        self
+    }
+
+    /// Shorthand: expect YAML output, converted to JSON. Requires the
+    /// `yaml` feature.
+    #[cfg(feature = "yaml")]
+    pub fn expecting_yaml(mut self) -> Self {
+        self.output_strategy = Some(OutputStrategy::Yaml);
+        self
+    }
+
+    /// Shorthand: extract the first match of `pattern` (its capture group
+    /// 1, if it has one). See [`OutputStrategy::Regex`]. Requires the
+    /// `regex` feature.
+    #[cfg(feature = "regex")]
+    pub fn extracting_regex(mut self, pattern: impl Into<String>) -> Self {
+        self.output_strategy = Some(OutputStrategy::Regex(pattern.into()));
         self
     }
 
     /// Shorthand: expect a string list.
     pub fn expecting_list(mut self) -> Self {
-        self.output_strategy = OutputStrategy::StringList;
+        self.output_strategy = Some(OutputStrategy::StringList);
         self
     }
 
     /// Shorthand: expect one of the given choices.
     pub fn expecting_choice(mut self, choices: Vec<String>) -> Self {
-        self.output_strategy = OutputStrategy::Choice(choices);
+        self.output_strategy = Some(OutputStrategy::Choice(choices));
+        self
+    }
+
+    /// Shorthand: expect exactly one of the given choices, case-sensitively.
+    pub fn expecting_choice_strict(mut self, choices: Vec<String>) -> Self {
+        self.output_strategy = Some(OutputStrategy::ChoiceStrict(choices));
         self
     }
 
     /// Shorthand: expect a number.
     pub fn expecting_number(mut self) -> Self {
-        self.output_strategy = OutputStrategy::Number;
+        self.output_strategy = Some(OutputStrategy::Number);
         self
     }
 
     /// Shorthand: expect a number in a range.
     pub fn expecting_number_in_range(mut self, min: f64, max: f64) -> Self {
-        self.output_strategy = OutputStrategy::NumberInRange(min, max);
+        self.output_strategy = Some(OutputStrategy::NumberInRange(min, max));
+        self
+    }
+
+    /// Shorthand: expect a yes/no answer, parsed to a boolean.
+    pub fn expecting_bool(mut self) -> Self {
+        self.output_strategy = Some(OutputStrategy::Boolean);
         self
     }
 
     /// Shorthand: expect clean text output.
     pub fn expecting_text(mut self) -> Self {
-        self.output_strategy = OutputStrategy::Text;
+        self.output_strategy = Some(OutputStrategy::Text);
+        self
+    }
+
+    /// Shorthand: expect a fenced code block in `lang` (e.g. `"sql"`).
+    pub fn expecting_code(mut self, lang: impl Into<String>) -> Self {
+        self.output_strategy = Some(OutputStrategy::CodeBlock(lang.into()));
         self
     }
 
@@ -185,12 +425,56 @@ impl LlmCall {
             name: stage.name.clone(),
             prompt_template: stage.prompt_template.clone(),
             system_template: stage.system_prompt.clone(),
-            model: stage.model.clone(),
+            system_parts: Vec::new(),
+            model: Some(stage.model.clone()),
             config: stage.config.clone(),
             streaming,
-            output_strategy: OutputStrategy::default(),
-            retry: None,
+            // Stage output is deserialized from JSON, so a retrying stage
+            // needs the JSON strategy to notice an unparseable response.
+            output_strategy: stage.retry.as_ref().map(|_| OutputStrategy::Json),
+            retry: stage.retry.clone(),
+            json_selection: JsonCandidateSelection::default(),
+            timeout: None,
+            input_vars: false,
+            input_truncation: None,
+            assistant_prefix: None,
+            tools: Vec::new(),
+        }
+    }
+
+    /// Render this call's prompt for `input` against `ctx`'s vars — the
+    /// prompt [`invoke`](Payload::invoke) sends on its first attempt.
+    pub fn rendered_prompt(&self, ctx: &ExecCtx, input: &Value) -> String {
+        Self::render_prompt(
+            &self.prompt_template,
+            &self.input_text(input).0,
+            &self.template_vars(ctx, input),
+        )
+    }
+
+    /// The text substituted for `{input}`, after
+    /// [`with_input_truncation`](Self::with_input_truncation), and whether
+    /// it was clipped.
+    fn input_text(&self, input: &Value) -> (String, bool) {
+        let text = Self::input_to_string(input);
+        match self.input_truncation.and_then(|t| t.apply(&text)) {
+            Some(clipped) => (clipped, true),
+            None => (text, false),
+        }
+    }
+
+    /// The context's vars, plus the input's fields with
+    /// [`with_input_vars`](Self::with_input_vars).
+    fn template_vars(&self, ctx: &ExecCtx, input: &Value) -> HashMap<String, String> {
+        let mut vars = ctx.resolved_vars();
+        if let (true, Value::Object(fields)) = (self.input_vars, input) {
+            vars.extend(
+                fields
+                    .iter()
+                    .map(|(key, value)| (key.clone(), Self::input_to_string(value))),
+            );
         }
+        vars
     }
 
     /// Render the prompt template, substituting `{input}` and context vars.
@@ -213,6 +497,27 @@ impl LlmCall {
         rendered
     }
 
+    /// `messages` plus the [`assistant_prefix`](Self::with_assistant_prefix)
+    /// as a final assistant message, if set.
+    fn with_prefill(&self, mut messages: Vec<ChatMessage>) -> Vec<ChatMessage> {
+        if let Some(ref prefix) = self.assistant_prefix {
+            messages.push(ChatMessage {
+                role: backend::Role::Assistant,
+                content: prefix.clone(),
+            });
+        }
+        messages
+    }
+
+    /// Restore the [`assistant_prefix`](Self::with_assistant_prefix) the
+    /// model continued from.
+    fn prefixed(&self, text: String) -> String {
+        match self.assistant_prefix {
+            Some(ref prefix) if !text.starts_with(prefix.as_str()) => prefix.clone() + &text,
+            _ => text,
+        }
+    }
+
     /// Convert a `Value` input to a string for template substitution.
     fn input_to_string(input: &Value) -> String {
         match input {
@@ -230,12 +535,18 @@ impl LlmCall {
         stream: bool,
     ) -> LlmRequest {
         LlmRequest {
-            model: self.model.clone(),
+            model: self.model().to_string(),
             system_prompt: system.map(|s| s.to_string()),
+            system_parts: Vec::new(),
             prompt: prompt.to_string(),
             messages,
             config: self.config.clone(),
             stream,
+            max_stream_tokens: None,
+            dedup_stream: false,
+            accept_statuses: Vec::new(),
+            timeout: self.timeout,
+            tools: self.tools.clone(),
         }
     }
 
@@ -281,21 +592,31 @@ impl LlmCall {
     }
 
     /// Execute via the backend (streaming), emitting Token events and tracking transport retries.
+    /// With `partial_values`, also emits `PartialValue` events as the JSON
+    /// parsed from the tokens so far changes.
     ///
     /// Returns `(LlmResponse, transport_retries, backoff_total_ms)`.
     async fn call_backend_streaming(
         &self,
         ctx: &ExecCtx,
         request: &LlmRequest,
+        partial_values: bool,
     ) -> Result<(LlmResponse, u32, u64)> {
         let mut transport_retries: u32 = 0;
         let mut backoff_total_ms: u64 = 0;
         let retry_name = self.name.clone();
         let retry_event_handler = ctx.event_handler.clone();
+        // Set when a retry will replay the stream from the start rather than
+        // resume it, so the partial parser starts over too.
+        let restarted = AtomicBool::new(false);
+        let restarted = &restarted;
 
         let mut on_retry = |attempt: u32, delay: std::time::Duration, reason: &str| {
             transport_retries = attempt;
             backoff_total_ms += delay.as_millis() as u64;
+            if !ctx.backoff.resume_streams {
+                restarted.store(true, Ordering::Relaxed);
+            }
             emit(
                 &retry_event_handler,
                 Event::TransportRetry {
@@ -309,7 +630,23 @@ impl LlmCall {
 
         let name = self.name.clone();
         let event_handler = ctx.event_handler.clone();
+        let new_parser = || {
+            let mut parser = StreamingJsonParser::new();
+            if let (true, Some(prefix)) = (partial_values, &self.assistant_prefix) {
+                parser.push(prefix);
+            }
+            parser
+        };
+        let mut partial = new_parser();
+        let mut last_partial: Option<Value> = None;
         let mut on_token = move |token: String| {
+            if restarted.swap(false, Ordering::Relaxed) {
+                partial = new_parser();
+                last_partial = None;
+            }
+            if partial_values {
+                partial.push(&token);
+            }
             emit(
                 &event_handler,
                 Event::Token {
@@ -317,6 +654,42 @@ impl LlmCall {
                     chunk: token,
                 },
             );
+            if let Some(value) = partial.current_value() {
+                if last_partial.as_ref() != Some(value) {
+                    last_partial = Some(value.clone());
+                    emit(
+                        &event_handler,
+                        Event::PartialValue {
+                            name: name.clone(),
+                            value: value.clone(),
+                        },
+                    );
+                }
+            }
+        };
+
+        let thinking_name = self.name.clone();
+        let thinking_event_handler = ctx.event_handler.clone();
+        let mut on_thinking = move |token: String| {
+            emit(
+                &thinking_event_handler,
+                Event::ThinkingToken {
+                    name: thinking_name.clone(),
+                    chunk: token,
+                },
+            );
+        };
+
+        let metadata_name = self.name.clone();
+        let metadata_event_handler = ctx.event_handler.clone();
+        let mut on_metadata = move |metadata: Value| {
+            emit(
+                &metadata_event_handler,
+                Event::StreamMetadata {
+                    name: metadata_name.clone(),
+                    metadata,
+                },
+            );
         };
 
         let response = backend::with_backoff_streaming(
@@ -329,6 +702,8 @@ impl LlmCall {
                 cancel: ctx.cancel_flag(),
                 on_retry: Some(&mut on_retry),
                 on_token: &mut on_token,
+                on_thinking: Some(&mut on_thinking),
+                on_metadata: Some(&mut on_metadata),
             },
         )
         .await?;
@@ -336,47 +711,174 @@ impl LlmCall {
         Ok((response, transport_retries, backoff_total_ms))
     }
 
-    /// Check if a retry is needed. Returns `Some(reason)` if retry needed, `None` if output is ok.
+    /// Check if a retry is needed. Returns `Some(trigger)` if retry needed, `None` if output is ok.
     fn check_retry_needed(
         &self,
         output: &PayloadOutput,
         retry_config: &RetryConfig,
-    ) -> Option<String> {
+    ) -> Option<RetryTrigger> {
+        // Check for a refusal, which leaves the response empty
+        if let Some(refusal) = output.diagnostics.as_ref().and_then(|d| d.refusal.as_ref()) {
+            return Some(RetryTrigger::Refused(refusal.clone()));
+        }
+
+        // Check for a response that ran out of tokens while thinking
+        if output
+            .diagnostics
+            .as_ref()
+            .is_some_and(|d| d.truncated_in_thinking)
+        {
+            return Some(RetryTrigger::TruncatedThinking);
+        }
+
+        // Check for an empty response, which some strategies would accept
+        if retry_config.retry_on_empty && output.raw_response.trim().is_empty() {
+            return Some(RetryTrigger::Empty);
+        }
+
         // Check parse error from OutputStrategy
         if let Some(ref diag) = output.diagnostics {
             if let Some(ref err) = diag.parse_error {
-                return Some(err.clone());
+                return Some(RetryTrigger::Invalid(
+                    parse_failure_reason(diag.strategy, err),
+                    err.clone(),
+                ));
             }
         }
 
-        // Check semantic validator
-        if let Some(ref validator) = retry_config.validator {
-            if let Err(reason) = validator(&output.raw_response, &output.value) {
-                return Some(reason);
+        // Check semantic validators
+        if let Err((category, reason)) = retry_config.validate(&output.raw_response, &output.value)
+        {
+            return Some(RetryTrigger::Invalid(category, reason));
+        }
+
+        // Check retry_if predicate
+        if let Some(ref retry_if) = retry_config.retry_if {
+            if let Some(feedback) = retry_if(output) {
+                return Some(RetryTrigger::Requested(feedback));
             }
         }
 
         None
     }
 
-    /// Build a `PayloadOutput` from raw LLM text using the configured `OutputStrategy`.
+    /// [`check_retry_needed`](Self::check_retry_needed), then the async
+    /// validator if every synchronous check passed.
+    async fn retry_trigger(
+        &self,
+        output: &PayloadOutput,
+        retry_config: &RetryConfig,
+    ) -> Option<RetryTrigger> {
+        if !output.tool_calls.is_empty() {
+            return None;
+        }
+        if let Some(trigger) = self.check_retry_needed(output, retry_config) {
+            return Some(trigger);
+        }
+        let validator = retry_config.async_validator.as_ref()?;
+        validator(&output.raw_response, &output.value)
+            .await
+            .err()
+            .map(|reason| RetryTrigger::Invalid(RetryReason::Custom, reason))
+    }
+
+    /// Emit [`Event::ParseResult`] for `output`'s diagnostics, if any.
+    fn emit_parse_result(&self, ctx: &ExecCtx, output: &PayloadOutput) {
+        if let Some(ref diag) = output.diagnostics {
+            emit(
+                &ctx.event_handler,
+                Event::ParseResult {
+                    name: self.name.clone(),
+                    strategy: diag.strategy.unwrap_or("unknown"),
+                    ok: diag.ok(),
+                    repaired: diag.repaired || diag.auto_completed,
+                    retry_attempts: diag.retry_attempts,
+                },
+            );
+        }
+    }
+
+    /// Build a `PayloadOutput` from raw LLM text using the call's own
+    /// `OutputStrategy` (ignoring any context default).
+    #[cfg(test)]
+    fn build_output(&self, raw_text: String) -> PayloadOutput {
+        self.build_output_with(raw_text, self.output_strategy(), ParseOptions::default())
+    }
+
+    /// Build a `PayloadOutput` from raw LLM text using `strategy`, as
+    /// adjusted by `options`.
     ///
     /// Per CLAUDE.md: `build_output` MUST always return `Ok(PayloadOutput)`.
     /// Parse failures go into `diagnostics.parse_error`, not `Err`.
-    fn build_output(&self, raw_text: String) -> PayloadOutput {
+    fn build_output_with(
+        &self,
+        raw_text: String,
+        strategy: &OutputStrategy,
+        options: ParseOptions,
+    ) -> PayloadOutput {
+        let ParseOptions {
+            normalize_unicode,
+            normalize_keys,
+            truncated,
+            ..
+        } = options;
         let (thinking, cleaned) = parsing::extract_thinking(&raw_text);
+        let cleaned = if normalize_unicode {
+            output_parser::normalize_unicode(&cleaned)
+        } else {
+            cleaned
+        };
 
         let mut diag = ParseDiagnostics::default();
 
-        let value = match &self.output_strategy {
+        let value = match strategy {
             OutputStrategy::Lossy => {
                 diag.strategy = Some("lossy");
-                parsing::parse_value_lossy(&cleaned)
+                let value = parsing::parse_value_lossy(&cleaned);
+                if normalize_keys {
+                    output_parser::normalize_keys(value)
+                } else {
+                    value
+                }
             }
-            OutputStrategy::Json => {
-                diag.strategy = Some("json");
-                match output_parser::parse_json_value(&cleaned) {
-                    Ok(v) => v,
+            OutputStrategy::Json | OutputStrategy::JsonPointer(_) => {
+                diag.strategy = Some(match strategy {
+                    OutputStrategy::JsonPointer(_) => "json_pointer",
+                    _ => "json",
+                });
+                let parsed = if truncated {
+                    output_parser::json::parse_truncated_json_tracked::<Value>(
+                        &cleaned,
+                        self.json_selection,
+                    )
+                } else {
+                    output_parser::json::parse_json_tracked_with::<Value>(
+                        &cleaned,
+                        self.json_selection,
+                    )
+                };
+                match parsed {
+                    Ok((v, recovery)) => {
+                        diag.auto_completed = recovery == JsonRecovery::AutoCompleted;
+                        let v = if normalize_keys {
+                            output_parser::normalize_keys(v)
+                        } else {
+                            v
+                        };
+                        match strategy {
+                            OutputStrategy::JsonPointer(pointer) => match v.pointer(pointer) {
+                                Some(sub) => sub.clone(),
+                                None => {
+                                    let err = output_parser::ParseError::MissingPointer {
+                                        pointer: pointer.clone(),
+                                    };
+                                    diag.parse_error = Some(err.to_string());
+                                    v
+                                }
+                            },
+                            _ => v,
+                        }
+                    }
                     Err(e) => {
                         diag.parse_error = Some(e.to_string());
                         // Fallback: try lossy parse
@@ -384,6 +886,21 @@ impl LlmCall {
                     }
                 }
             }
+            #[cfg(feature = "yaml")]
+            OutputStrategy::Yaml => {
+                diag.strategy = Some("yaml");
+                let parsed = output_parser::parse_yaml::<serde_yaml::Value>(&cleaned)
+                    .map_err(|e| e.to_string())
+                    .and_then(|v| serde_json::to_value(v).map_err(|e| e.to_string()));
+                match parsed {
+                    Ok(v) if normalize_keys => output_parser::normalize_keys(v),
+                    Ok(v) => v,
+                    Err(e) => {
+                        diag.parse_error = Some(e);
+                        parsing::parse_value_lossy(&cleaned)
+                    }
+                }
+            }
             OutputStrategy::StringList => {
                 diag.strategy = Some("string_list");
                 match output_parser::parse_string_list_raw(&cleaned) {
@@ -415,10 +932,21 @@ impl LlmCall {
                     }
                 }
             }
+            OutputStrategy::ChoiceStrict(choices) => {
+                diag.strategy = Some("choice_strict");
+                let choice_refs: Vec<&str> = choices.iter().map(|s| s.as_str()).collect();
+                match output_parser::parse_choice_strict(&cleaned, &choice_refs) {
+                    Ok(matched) => Value::String(matched.to_string()),
+                    Err(e) => {
+                        diag.parse_error = Some(e.to_string());
+                        Value::String(cleaned.clone())
+                    }
+                }
+            }
             OutputStrategy::Number => {
                 diag.strategy = Some("number");
-                match output_parser::parse_number::<f64>(&cleaned) {
-                    Ok(n) => json!(n),
+                match parse_number_value(&cleaned) {
+                    Ok(n) => n,
                     Err(e) => {
                         diag.parse_error = Some(e.to_string());
                         Value::String(cleaned.clone())
@@ -435,6 +963,16 @@ impl LlmCall {
                     }
                 }
             }
+            OutputStrategy::Boolean => {
+                diag.strategy = Some("boolean");
+                match output_parser::parse_bool(&cleaned) {
+                    Ok(b) => Value::Bool(b),
+                    Err(e) => {
+                        diag.parse_error = Some(e.to_string());
+                        Value::String(cleaned.clone())
+                    }
+                }
+            }
             OutputStrategy::Text => {
                 diag.strategy = Some("text");
                 match output_parser::parse_text(&cleaned) {
@@ -445,6 +983,75 @@ impl LlmCall {
                     }
                 }
             }
+            OutputStrategy::CodeBlock(lang) => {
+                diag.strategy = Some("code_block");
+                match output_parser::parse_code_block(&cleaned, lang) {
+                    Ok(block) => {
+                        diag.code_block_fallback = !block.language_matched;
+                        Value::String(block.code)
+                    }
+                    Err(e) => {
+                        diag.parse_error = Some(e.to_string());
+                        Value::String(cleaned.clone())
+                    }
+                }
+            }
+            OutputStrategy::Csv => {
+                diag.strategy = Some("csv");
+                match output_parser::parse_csv(&cleaned) {
+                    Ok(rows) => rows,
+                    Err(e) => {
+                        diag.parse_error = Some(e.to_string());
+                        Value::String(cleaned.clone())
+                    }
+                }
+            }
+            OutputStrategy::MarkdownTable => {
+                diag.strategy = Some("markdown_table");
+                match output_parser::parse_markdown_table(&cleaned) {
+                    Ok(rows) => Value::Array(
+                        rows.into_iter()
+                            .map(|row| {
+                                Value::Object(
+                                    row.into_iter()
+                                        .map(|(k, v)| (k, Value::String(v)))
+                                        .collect(),
+                                )
+                            })
+                            .collect(),
+                    ),
+                    Err(e) => {
+                        diag.parse_error = Some(e.to_string());
+                        Value::String(cleaned.clone())
+                    }
+                }
+            }
+            OutputStrategy::KeyValue => {
+                diag.strategy = Some("key_value");
+                match output_parser::parse_key_value(&cleaned) {
+                    Ok(pairs) if normalize_keys => output_parser::normalize_keys(pairs),
+                    Ok(pairs) => pairs,
+                    Err(e) => {
+                        diag.parse_error = Some(e.to_string());
+                        Value::String(cleaned.clone())
+                    }
+                }
+            }
+            #[cfg(feature = "regex")]
+            OutputStrategy::Regex(pattern) => {
+                diag.strategy = Some("regex");
+                let parsed = match options.regex {
+                    Some(re) => output_parser::pattern::parse_with_regex(&cleaned, re),
+                    None => output_parser::parse_regex(&cleaned, pattern),
+                };
+                match parsed {
+                    Ok(captured) => Value::String(captured),
+                    Err(e) => {
+                        diag.parse_error = Some(e.to_string());
+                        Value::String(cleaned.clone())
+                    }
+                }
+            }
             OutputStrategy::Custom(f) => {
                 diag.strategy = Some("custom");
                 match f(&cleaned) {
@@ -460,7 +1067,7 @@ impl LlmCall {
         // Check if repair was applied (for Json strategy, the output_parser
         // internally tries repair — we can detect this by checking if the
         // parse succeeded on repaired input)
-        if diag.parse_error.is_none() && matches!(self.output_strategy, OutputStrategy::Json) {
+        if diag.parse_error.is_none() && matches!(diag.strategy, Some("json" | "json_pointer")) {
             // If direct parse of cleaned text fails but output_parser succeeded,
             // it means repair was applied
             if serde_json::from_str::<Value>(&cleaned).is_err() {
@@ -468,16 +1075,229 @@ impl LlmCall {
             }
         }
 
+        if is_truncated_in_thinking(&raw_text) {
+            diag.truncated_in_thinking = true;
+            diag.parse_error =
+                Some("response was cut off inside a <think> block, before any answer".to_string());
+        }
+
         PayloadOutput {
             value,
             raw_response: raw_text,
             thinking,
-            model: Some(self.model.clone()),
+            model: Some(self.model().to_string()),
             diagnostics: Some(diag),
+            meta: serde_json::Map::new(),
+            chain: None,
+            provider_metadata: None,
+            tool_calls: Vec::new(),
+        }
+    }
+
+    /// Output for a response that only requests tool calls: there is no
+    /// answer to parse, so the value is `null` and the diagnostics record
+    /// the `"tool_calls"` strategy rather than a parse failure.
+    fn tool_calls_output(&self, raw_text: String) -> PayloadOutput {
+        PayloadOutput {
+            value: Value::Null,
+            raw_response: raw_text,
+            thinking: None,
+            model: Some(self.model().to_string()),
+            diagnostics: Some(ParseDiagnostics {
+                strategy: Some("tool_calls"),
+                ..Default::default()
+            }),
+            meta: serde_json::Map::new(),
+            chain: None,
+            provider_metadata: None,
+            tool_calls: Vec::new(),
+        }
+    }
+}
+
+/// How [`LlmCall::build_output_with`] treats the raw text.
+#[derive(Debug, Clone, Copy, Default)]
+struct ParseOptions<'a> {
+    /// Normalize typographic Unicode before parsing.
+    normalize_unicode: bool,
+    /// Give parsed JSON objects snake_case keys.
+    normalize_keys: bool,
+    /// The backend stopped on a length limit (see [`is_truncated_finish`]).
+    /// JSON output is then auto-completed before parsing instead of only
+    /// after a direct parse fails.
+    truncated: bool,
+    /// The compiled [`OutputStrategy::Regex`] pattern, so it is compiled
+    /// once per invocation rather than once per response.
+    #[cfg(feature = "regex")]
+    regex: Option<&'a regex::Regex>,
+    #[cfg(not(feature = "regex"))]
+    _marker: std::marker::PhantomData<&'a ()>,
+}
+
+impl ParseOptions<'_> {
+    /// The context's normalization settings, for a complete response.
+    fn from_ctx(ctx: &ExecCtx) -> Self {
+        Self {
+            normalize_unicode: ctx.normalize_unicode,
+            normalize_keys: ctx.normalize_keys,
+            ..Self::default()
+        }
+    }
+}
+
+/// Compile the pattern of an [`OutputStrategy::Regex`] strategy, failing
+/// with [`PipelineError::InvalidConfig`](crate::PipelineError::InvalidConfig)
+/// if it is invalid. `None` for
+/// every other strategy.
+#[cfg(feature = "regex")]
+fn compile_strategy_regex(strategy: &OutputStrategy) -> Result<Option<regex::Regex>> {
+    match strategy {
+        OutputStrategy::Regex(pattern) => output_parser::pattern::compile_regex(pattern)
+            .map(Some)
+            .map_err(|e| crate::PipelineError::InvalidConfig(e.to_string())),
+        _ => Ok(None),
+    }
+}
+
+/// Whether `response` asks for tool calls and has no text to parse.
+fn is_tool_calls_only(response: &LlmResponse) -> bool {
+    !response.tool_calls.is_empty() && response.text.trim().is_empty()
+}
+
+/// Outcome of consulting the context's semantic cache before a call.
+#[cfg(feature = "semantic-cache")]
+enum CacheProbe {
+    /// A similar prompt was cached; serve its output.
+    Hit(Box<PayloadOutput>),
+    /// No match; store the result under this scope and embedding.
+    Miss(String, Vec<f32>),
+    /// No cache configured, or the prompt could not be embedded.
+    Off,
+}
+
+#[cfg(feature = "semantic-cache")]
+impl LlmCall {
+    async fn probe_semantic_cache(
+        &self,
+        ctx: &ExecCtx,
+        model: &str,
+        prompt: &str,
+        system: Option<&str>,
+    ) -> CacheProbe {
+        let Some(ref cache) = ctx.semantic_cache else {
+            return CacheProbe::Off;
+        };
+        let text = match system {
+            Some(sys) => format!("{}\n\n{}", sys, prompt),
+            None => prompt.to_string(),
+        };
+        let texts = [text];
+        let call = ctx
+            .backend
+            .embed(&ctx.client, &ctx.base_url, cache.embed_model(), &texts);
+        let embedded = match ctx.call_timeout(false) {
+            Some(limit) => tokio::time::timeout(limit, call)
+                .await
+                .unwrap_or(Err(crate::PipelineError::Timeout(limit))),
+            None => call.await,
+        };
+        let embedding = match embedded {
+            Ok(mut vectors) if vectors.len() == 1 => vectors.remove(0),
+            _ => return CacheProbe::Off,
+        };
+
+        let scope = format!("{}\u{0}{}", self.name, model);
+        match cache.lookup(&scope, &embedding) {
+            Some((mut output, _)) => {
+                if let Some(ref mut diag) = output.diagnostics {
+                    diag.cache_hit = true;
+                }
+                CacheProbe::Hit(Box::new(output))
+            }
+            None => CacheProbe::Miss(scope, embedding),
         }
     }
 }
 
+/// Read `(prompt_tokens, completion_tokens)` from the response's
+/// [`reported_usage`](LlmResponse::reported_usage).
+fn token_usage_of(response: &LlmResponse) -> (Option<u64>, Option<u64>) {
+    response.reported_usage().map_or((None, None), |usage| {
+        (usage.prompt_tokens, usage.completion_tokens)
+    })
+}
+
+/// Sum two optional token counts, staying `None` only if both are.
+fn add_tokens(a: Option<u64>, b: Option<u64>) -> Option<u64> {
+    match (a, b) {
+        (None, None) => None,
+        _ => Some(a.unwrap_or(0) + b.unwrap_or(0)),
+    }
+}
+
+/// Read `finish_reason` from provider metadata, if the backend reported one.
+fn finish_reason_of(response: &LlmResponse) -> Option<String> {
+    metadata_str(response, "finish_reason")
+}
+
+/// Read a string field from provider metadata.
+fn metadata_str(response: &LlmResponse, key: &str) -> Option<String> {
+    response
+        .metadata
+        .as_ref()
+        .and_then(|m| m.get(key))
+        .and_then(|v| v.as_str())
+        .map(str::to_string)
+}
+
+/// The response's HTTP status, treating `0` (a backend that sets none) as
+/// unknown.
+fn http_status_of(response: &LlmResponse) -> Option<u16> {
+    Some(response.status).filter(|&status| status != 0)
+}
+
+/// The response's refusal, or a stand-in when the provider's content filter
+/// stopped generation (`finish_reason = "content_filter"`) without one.
+fn refusal_of(response: &LlmResponse, finish_reason: Option<&str>) -> Option<String> {
+    response.refusal.clone().or_else(|| {
+        (finish_reason == Some("content_filter"))
+            .then(|| "blocked by the provider's content filter".to_string())
+    })
+}
+
+/// Whether `raw` ends inside an unclosed `<think>` or `<thinking>` block
+/// with no answer outside the thinking: the model used up its tokens
+/// reasoning.
+fn is_truncated_in_thinking(raw: &str) -> bool {
+    let last = |tags: [&str; 2]| tags.iter().filter_map(|t| raw.rfind(t)).max();
+    let Some(open) = last(["<think>", "<thinking>"]) else {
+        return false;
+    };
+    last(["</think>", "</thinking>"]).is_none_or(|close| close < open)
+        && output_parser::strip_think_tags(raw).trim().is_empty()
+}
+
+/// Whether `finish_reason` says generation was cut off by a length limit:
+/// the provider's `"length"` or our own `"client_limit"`.
+fn is_truncated_finish(finish_reason: Option<&str>) -> bool {
+    matches!(finish_reason, Some("length" | "client_limit"))
+}
+
+/// Parse a response for [`OutputStrategy::Number`].
+///
+/// With the `arbitrary_precision` feature the number is kept exactly as the
+/// model wrote it (big integers, long decimals); otherwise it goes through
+/// `f64`.
+#[cfg(feature = "arbitrary_precision")]
+fn parse_number_value(text: &str) -> std::result::Result<Value, output_parser::ParseError> {
+    output_parser::parse_number::<serde_json::Number>(text).map(Value::Number)
+}
+
+#[cfg(not(feature = "arbitrary_precision"))]
+fn parse_number_value(text: &str) -> std::result::Result<Value, output_parser::ParseError> {
+    output_parser::parse_number::<f64>(text).map(|n| json!(n))
+}
+
 impl Payload for LlmCall {
     fn kind(&self) -> &'static str {
         "llm-call"
@@ -491,7 +1311,16 @@ impl Payload for LlmCall {
         Box::pin(async move {
             ctx.check_cancelled()?;
 
-            emit(
+            let strategy = self.resolve_output_strategy(ctx);
+            #[cfg(feature = "regex")]
+            let regex = compile_strategy_regex(strategy)?;
+            let parse_options = ParseOptions {
+                #[cfg(feature = "regex")]
+                regex: regex.as_ref(),
+                ..ParseOptions::from_ctx(ctx)
+            };
+
+            emit(
                 &ctx.event_handler,
                 Event::PayloadStart {
                     name: self.name.clone(),
@@ -499,29 +1328,125 @@ impl Payload for LlmCall {
                 },
             );
 
-            let input_str = Self::input_to_string(&input);
-            let prompt = Self::render_prompt(&self.prompt_template, &input_str, &ctx.vars);
+            let (input_str, input_truncated) = self.input_text(&input);
+            let vars = self.template_vars(ctx, &input);
+            let prompt = Self::render_prompt(&self.prompt_template, &input_str, &vars);
             let system = self
                 .system_template
                 .as_ref()
-                .map(|t| Self::render_system(t, &ctx.vars));
+                .map(|t| Self::render_system(t, &vars));
+            let system_parts: Vec<String> = self
+                .system_parts
+                .iter()
+                .map(|t| Self::render_system(t, &vars))
+                .collect();
+
+            let model = self.resolve_model(ctx);
+
+            #[cfg(feature = "semantic-cache")]
+            let cache_miss = match self
+                .probe_semantic_cache(ctx, model, &prompt, system.as_deref())
+                .await
+            {
+                CacheProbe::Hit(output) => {
+                    self.emit_parse_result(ctx, &output);
+                    emit(
+                        &ctx.event_handler,
+                        Event::PayloadEnd {
+                            name: self.name.clone(),
+                            ok: true,
+                        },
+                    );
+                    return Ok(*output);
+                }
+                CacheProbe::Miss(scope, embedding) => Some((scope, embedding)),
+                CacheProbe::Off => None,
+            };
 
             // --- Initial call ---
-            let request =
-                self.build_request(&prompt, system.as_deref(), Vec::new(), self.streaming);
+            let prefill = match self.assistant_prefix {
+                Some(_) => self.with_prefill(vec![ChatMessage {
+                    role: backend::Role::User,
+                    content: prompt.clone(),
+                }]),
+                None => Vec::new(),
+            };
+            let mut request =
+                self.build_request(&prompt, system.as_deref(), prefill, self.streaming);
+            request.model = model.to_string();
+            request.system_parts = system_parts.clone();
+            request.max_stream_tokens = ctx.max_stream_tokens;
+            request.dedup_stream = ctx.dedup_stream;
+            request.timeout = self.timeout.or(ctx.call_timeout(self.streaming));
 
             let result = if self.streaming {
-                self.call_backend_streaming(ctx, &request).await
+                let partial_values = matches!(
+                    strategy,
+                    OutputStrategy::Json | OutputStrategy::JsonPointer(_)
+                );
+                self.call_backend_streaming(ctx, &request, partial_values)
+                    .await
             } else {
                 self.call_backend(ctx, &request).await
             };
 
             let mut output = match result {
                 Ok((response, transport_retries, backoff_total_ms)) => {
-                    let mut out = self.build_output(response.text);
+                    let finish_reason = finish_reason_of(&response);
+                    let truncated = is_truncated_finish(finish_reason.as_deref());
+                    let http_status = http_status_of(&response);
+                    let endpoint_mode = metadata_str(&response, "endpoint_mode");
+                    let (prompt_tokens, completion_tokens) = token_usage_of(&response);
+                    ctx.record_completion_tokens(completion_tokens.unwrap_or(0));
+                    let refusal = refusal_of(&response, finish_reason.as_deref());
+                    let tool_calls_only = is_tool_calls_only(&response);
+                    let candidates: Vec<String> = response
+                        .candidates
+                        .into_iter()
+                        .map(|c| self.prefixed(c))
+                        .collect();
+                    let mut out = if tool_calls_only {
+                        self.tool_calls_output(response.text)
+                    } else {
+                        self.build_output_with(
+                            self.prefixed(response.text),
+                            strategy,
+                            ParseOptions {
+                                truncated,
+                                ..parse_options
+                            },
+                        )
+                    };
+                    out.model = Some(model.to_string());
+                    out.provider_metadata = response.metadata;
+                    out.tool_calls = response.tool_calls;
+                    if !candidates.is_empty() {
+                        // Parse every candidate with the same strategy; `null`
+                        // marks a candidate that failed to parse.
+                        let values: Vec<Value> = candidates
+                            .iter()
+                            .map(|c| {
+                                let parsed =
+                                    self.build_output_with(c.clone(), strategy, parse_options);
+                                match parsed.diagnostics {
+                                    Some(ref d) if !d.ok() => Value::Null,
+                                    _ => parsed.value,
+                                }
+                            })
+                            .collect();
+                        out = out
+                            .with_meta("candidates", json!(candidates))
+                            .with_meta("candidate_values", Value::Array(values));
+                    }
                     if let Some(ref mut diag) = out.diagnostics {
                         diag.transport_retries = transport_retries;
                         diag.backoff_total_ms = backoff_total_ms;
+                        diag.finish_reason = finish_reason;
+                        diag.endpoint_mode = endpoint_mode;
+                        diag.http_status = http_status;
+                        diag.prompt_tokens = prompt_tokens;
+                        diag.completion_tokens = completion_tokens;
+                        diag.mark_refusal(refusal);
                     }
                     out
                 }
@@ -537,43 +1462,49 @@ impl Payload for LlmCall {
                 }
             };
 
+            // Check if initial output needs retry
+            let mut retry_reason = match self.retry {
+                Some(ref retry_config) => self.retry_trigger(&output, retry_config).await,
+                None => None,
+            };
+
             // --- Retry loop ---
             if let Some(ref retry_config) = self.retry {
-                // Check if initial output needs retry
-                let mut retry_reason = self.check_retry_needed(&output, retry_config);
-
                 if retry_reason.is_some() {
                     let mut messages = vec![ChatMessage {
                         role: backend::Role::User,
                         content: prompt.clone(),
                     }];
                     let mut temp_offset = 0.0f64;
+                    let mut max_tokens = self.config.max_tokens;
+                    let mut thinking = self.config.thinking;
 
                     for attempt in 1..=retry_config.max_retries {
                         ctx.check_cancelled()?;
 
-                        let reason = retry_reason.take().unwrap_or_default();
+                        let trigger = retry_reason
+                            .take()
+                            .unwrap_or(RetryTrigger::Invalid(RetryReason::Parse, String::new()));
 
                         emit(
                             &ctx.event_handler,
                             Event::RetryStart {
                                 name: self.name.clone(),
                                 attempt,
-                                reason: reason.clone(),
+                                reason: trigger.reason().to_string(),
+                                category: trigger.category(),
                             },
                         );
 
                         // Build correction messages
+                        let refused = output.diagnostics.as_ref().and_then(|d| d.refusal.clone());
                         messages.push(ChatMessage {
                             role: backend::Role::Assistant,
-                            content: output.raw_response.clone(),
+                            content: refused.unwrap_or_else(|| output.raw_response.clone()),
                         });
                         messages.push(ChatMessage {
                             role: backend::Role::User,
-                            content: format!(
-                                "Your previous response was invalid: {}. Please try again with the correct format.",
-                                reason
-                            ),
+                            content: trigger.feedback(retry_config.example.as_ref()),
                         });
 
                         // Cool down temperature
@@ -581,26 +1512,73 @@ impl Payload for LlmCall {
                             temp_offset += 0.2;
                         }
 
+                        // Make room for an answer after the model ran out
+                        // of tokens while thinking
+                        if matches!(trigger, RetryTrigger::TruncatedThinking) {
+                            max_tokens = max_tokens.saturating_mul(2);
+                            thinking = false;
+                        }
+
                         let mut retry_config_clone = self.config.clone();
                         retry_config_clone.temperature =
                             (retry_config_clone.temperature - temp_offset).max(0.0);
+                        retry_config_clone.max_tokens = max_tokens;
+                        retry_config_clone.thinking = thinking;
 
                         let retry_request = LlmRequest {
-                            model: self.model.clone(),
+                            model: model.to_string(),
                             system_prompt: system.clone(),
+                            system_parts: system_parts.clone(),
                             prompt: prompt.clone(),
-                            messages: messages.clone(),
+                            messages: self.with_prefill(messages.clone()),
                             config: retry_config_clone,
                             stream: false, // retries always non-streaming
+                            max_stream_tokens: None,
+                            dedup_stream: false,
+                            accept_statuses: Vec::new(),
+                            timeout: self.timeout.or(ctx.call_timeout(false)),
+                            tools: self.tools.clone(),
                         };
 
                         match self.call_backend(ctx, &retry_request).await {
                             Ok((response, tr, bt)) => {
-                                output = self.build_output(response.text);
+                                let finish_reason = finish_reason_of(&response);
+                                let truncated = is_truncated_finish(finish_reason.as_deref());
+                                let http_status = http_status_of(&response);
+                                let endpoint_mode = metadata_str(&response, "endpoint_mode");
+                                let (prompt_tokens, completion_tokens) = token_usage_of(&response);
+                                ctx.record_completion_tokens(completion_tokens.unwrap_or(0));
+                                let refusal = refusal_of(&response, finish_reason.as_deref());
+                                let previous = output.diagnostics.take().unwrap_or_default();
+                                output = if is_tool_calls_only(&response) {
+                                    self.tool_calls_output(response.text)
+                                } else {
+                                    self.build_output_with(
+                                        self.prefixed(response.text),
+                                        strategy,
+                                        ParseOptions {
+                                            truncated,
+                                            ..parse_options
+                                        },
+                                    )
+                                };
+                                output.model = Some(model.to_string());
+                                output.provider_metadata = response.metadata;
+                                output.tool_calls = response.tool_calls;
                                 if let Some(ref mut diag) = output.diagnostics {
                                     diag.retry_attempts = attempt;
+                                    diag.retry_reasons = previous.retry_reasons;
+                                    diag.retry_reasons.push(trigger.category());
                                     diag.transport_retries = tr;
                                     diag.backoff_total_ms = bt;
+                                    diag.finish_reason = finish_reason;
+                                    diag.endpoint_mode = endpoint_mode;
+                                    diag.http_status = http_status;
+                                    diag.prompt_tokens =
+                                        add_tokens(previous.prompt_tokens, prompt_tokens);
+                                    diag.completion_tokens =
+                                        add_tokens(previous.completion_tokens, completion_tokens);
+                                    diag.mark_refusal(refusal);
                                 }
                             }
                             Err(e) => {
@@ -624,7 +1602,7 @@ impl Payload for LlmCall {
                         }
 
                         // Check if this retry succeeded
-                        retry_reason = self.check_retry_needed(&output, retry_config);
+                        retry_reason = self.retry_trigger(&output, retry_config).await;
 
                         if retry_reason.is_none() {
                             // Success!
@@ -657,6 +1635,34 @@ impl Payload for LlmCall {
                 }
             }
 
+            if let Some(ref mut diag) = output.diagnostics {
+                diag.input_truncated = input_truncated;
+                diag.estimated_cost_usd = ctx
+                    .cost_table
+                    .as_ref()
+                    .zip(diag.usage())
+                    .and_then(|(table, usage)| table.estimate(model, &usage));
+            }
+
+            #[cfg(feature = "semantic-cache")]
+            if let (Some(cache), Some((scope, embedding))) = (&ctx.semantic_cache, cache_miss) {
+                // Only cache output every retry check accepts (the defaults
+                // when there is no RetryConfig), and never tool calls, which
+                // answer this prompt only.
+                let rejected = match self.retry {
+                    Some(_) => retry_reason.is_some(),
+                    None => self
+                        .retry_trigger(&output, &RetryConfig::new(0))
+                        .await
+                        .is_some(),
+                };
+                if !rejected && output.tool_calls.is_empty() {
+                    cache.insert(scope, embedding, output.clone());
+                }
+            }
+
+            self.emit_parse_result(ctx, &output);
+
             emit(
                 &ctx.event_handler,
                 Event::PayloadEnd {
@@ -670,6 +1676,84 @@ impl Payload for LlmCall {
     }
 }
 
+/// Categorize a parse failure reported by `strategy`.
+fn parse_failure_reason(strategy: Option<&str>, error: &str) -> RetryReason {
+    match strategy {
+        Some("choice" | "choice_strict") => RetryReason::Choice,
+        Some("number_in_range") => RetryReason::Range,
+        // JSON that parsed but lacks the pointer, vs. JSON that didn't parse.
+        Some("json_pointer") if error.starts_with("JSON pointer") => RetryReason::MissingKey,
+        _ => RetryReason::Parse,
+    }
+}
+
+/// Why the retry loop is re-calling the model.
+enum RetryTrigger {
+    /// Parse or validator failure; the reason is wrapped in a correction prompt.
+    Invalid(RetryReason, String),
+    /// A [`RetryConfig::retry_if`] predicate fired; sent to the model verbatim.
+    Requested(String),
+    /// The response was empty or whitespace-only
+    /// ([`RetryConfig::retry_on_empty`]).
+    Empty,
+    /// The model declined to answer; holds its refusal message.
+    Refused(String),
+    /// The response ended inside an unclosed `<think>` block.
+    TruncatedThinking,
+}
+
+impl RetryTrigger {
+    fn reason(&self) -> &str {
+        match self {
+            Self::Invalid(_, reason) | Self::Requested(reason) | Self::Refused(reason) => reason,
+            Self::Empty => "empty response",
+            Self::TruncatedThinking => "ran out of tokens while thinking",
+        }
+    }
+
+    fn category(&self) -> RetryReason {
+        match self {
+            Self::Invalid(category, _) => *category,
+            Self::Requested(_) => RetryReason::Custom,
+            Self::Empty => RetryReason::Empty,
+            Self::Refused(_) => RetryReason::Refusal,
+            Self::TruncatedThinking => RetryReason::TruncatedThinking,
+        }
+    }
+
+    /// The correction message sent to the model. `example` is appended to
+    /// [`Invalid`](Self::Invalid) feedback only.
+    fn feedback(&self, example: Option<&Value>) -> String {
+        match self {
+            Self::Invalid(_, reason) => {
+                let mut message = format!(
+                    "Your previous response was invalid: {}. Please try again with the correct format.",
+                    reason
+                );
+                if let Some(example) = example {
+                    let example = match example {
+                        Value::String(s) => s.clone(),
+                        other => other.to_string(),
+                    };
+                    message.push_str("\n\nHere is an example of valid output: ");
+                    message.push_str(&example);
+                }
+                message
+            }
+            Self::Requested(feedback) => feedback.clone(),
+            Self::Empty => {
+                "Your previous response was empty. Please answer the request.".to_string()
+            }
+            Self::TruncatedThinking => "Your previous response ran out of tokens while still \
+                thinking, before giving an answer. Answer directly, keeping any reasoning brief."
+                .to_string(),
+            Self::Refused(_) => "You declined the previous request. If it can be answered, \
+                please answer it in the requested format."
+                .to_string(),
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -703,6 +1787,72 @@ mod tests {
         assert!(output.diagnostics.as_ref().unwrap().repaired);
     }
 
+    #[test]
+    fn test_build_output_json_strategy_flags_auto_completed() {
+        let call = LlmCall::new("test", "prompt").expecting_json();
+        let output = call.build_output(r#"{"items": [1, 2, 3"#.into());
+        assert_eq!(output.value["items"], json!([1, 2, 3]));
+        assert!(output.was_auto_completed());
+
+        let output = call.build_output(r#"{"items": [1, 2, 3]}"#.into());
+        assert!(!output.was_auto_completed());
+    }
+
+    #[test]
+    fn test_resolve_output_strategy_precedence() {
+        let plain = ExecCtx::builder("http://test").build();
+        let with_default = ExecCtx::builder("http://test")
+            .default_output_strategy(OutputStrategy::Json)
+            .build();
+
+        let implicit = LlmCall::new("test", "prompt");
+        assert!(!implicit.has_output_strategy());
+        assert!(matches!(
+            implicit.resolve_output_strategy(&plain),
+            OutputStrategy::Lossy
+        ));
+        assert!(matches!(
+            implicit.resolve_output_strategy(&with_default),
+            OutputStrategy::Json
+        ));
+
+        let explicit = LlmCall::new("test", "prompt").expecting_text();
+        assert!(matches!(
+            explicit.resolve_output_strategy(&with_default),
+            OutputStrategy::Text
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_invoke_records_candidates() {
+        use crate::MockBackend;
+        use std::sync::Arc;
+
+        let ctx = ExecCtx::builder("http://test")
+            .backend(Arc::new(MockBackend::new(vec!["yes".into(), "no".into()])))
+            .build();
+        let call = LlmCall::new("test", "{input}").with_config(LlmConfig::default().with_n(2));
+        let out = call.invoke(&ctx, json!("q")).await.unwrap();
+        assert_eq!(out.value, json!("yes"));
+        assert_eq!(out.meta["candidates"], json!(["yes", "no"]));
+        assert_eq!(out.meta["candidate_values"], json!(["yes", "no"]));
+    }
+
+    #[tokio::test]
+    async fn test_invoke_records_http_status() {
+        use crate::MockBackend;
+        use std::sync::Arc;
+
+        let ctx = ExecCtx::builder("http://test")
+            .backend(Arc::new(MockBackend::fixed("ok")))
+            .build();
+        let out = LlmCall::new("test", "{input}")
+            .invoke(&ctx, json!("q"))
+            .await
+            .unwrap();
+        assert_eq!(out.diagnostics.unwrap().http_status, Some(200));
+    }
+
     #[test]
     fn test_build_output_json_strategy_fails() {
         let call = LlmCall::new("test", "prompt").expecting_json();
@@ -712,6 +1862,17 @@ mod tests {
         assert!(output.value.is_string());
     }
 
+    #[test]
+    fn test_build_output_json_candidate_selection() {
+        let raw = r#"{"label": "spam", "score": 0.9} Format: {"label": "..."}"#;
+        let call = LlmCall::new("test", "prompt").expecting_json();
+        assert_eq!(call.build_output(raw.into()).value, json!({"label": "..."}));
+
+        let call = call.with_json_candidate_selection(JsonCandidateSelection::MostKeys);
+        let output = call.build_output(raw.into());
+        assert_eq!(output.value, json!({"label": "spam", "score": 0.9}));
+    }
+
     #[test]
     fn test_build_output_string_list_strategy() {
         let call = LlmCall::new("test", "prompt").expecting_list();
@@ -743,6 +1904,48 @@ mod tests {
         assert!(output.diagnostics.as_ref().unwrap().ok());
     }
 
+    #[test]
+    fn test_clone_as_template() {
+        let base = LlmCall::new("base", "Classify: {input}")
+            .with_system("Be terse.")
+            .expecting_choice(vec!["yes".into(), "no".into()])
+            .with_retry(RetryConfig::new(2));
+        let variant = base
+            .clone()
+            .with_name("variant")
+            .with_model("qwen2.5:7b")
+            .with_prompt("Decide: {input}");
+
+        assert_eq!(variant.name(), "variant");
+        assert_eq!(variant.model(), "qwen2.5:7b");
+        assert_eq!(variant.prompt_template(), "Decide: {input}");
+        assert_eq!(variant.system_template(), Some("Be terse."));
+        assert!(matches!(
+            variant.output_strategy(),
+            OutputStrategy::Choice(_)
+        ));
+        assert_eq!(variant.retry().unwrap().max_retries, 2);
+
+        // The base is untouched.
+        assert_eq!(base.name(), "base");
+        assert_eq!(base.model(), DEFAULT_MODEL);
+        assert_eq!(base.prompt_template(), "Classify: {input}");
+    }
+
+    #[test]
+    fn test_build_output_choice_strict_strategy() {
+        let call = LlmCall::new("test", "prompt")
+            .expecting_choice_strict(vec!["PASS".into(), "Pass".into()]);
+        let output = call.build_output("Pass\n".into());
+        assert_eq!(output.value, json!("Pass"));
+        let diag = output.diagnostics.unwrap();
+        assert!(diag.ok());
+        assert_eq!(diag.strategy, Some("choice_strict"));
+
+        let output = call.build_output("pass".into());
+        assert!(output.diagnostics.unwrap().parse_error.is_some());
+    }
+
     #[test]
     fn test_build_output_number_strategy() {
         let call = LlmCall::new("test", "prompt").expecting_number();
@@ -760,6 +1963,100 @@ mod tests {
         assert!(output.diagnostics.as_ref().unwrap().parse_error.is_some());
     }
 
+    #[cfg(feature = "yaml")]
+    #[test]
+    fn test_build_output_yaml_strategy() {
+        let call = LlmCall::new("test", "prompt").expecting_yaml();
+        let output = call.build_output("```yaml\nname: Josh\ntags: [a, b]\n```".into());
+        assert_eq!(output.value, json!({"name": "Josh", "tags": ["a", "b"]}));
+        let diag = output.diagnostics.unwrap();
+        assert_eq!(diag.strategy, Some("yaml"));
+        assert!(diag.ok());
+
+        let output = call.build_output("key: [unclosed".into());
+        assert!(output.diagnostics.unwrap().parse_error.is_some());
+        assert_eq!(output.value, json!("key: [unclosed"));
+    }
+
+    #[test]
+    fn test_build_output_csv_strategy() {
+        let call = LlmCall::new("test", "prompt").with_output_strategy(OutputStrategy::Csv);
+        let output = call.build_output("```\nid\tname\n7\tAda\n```".into());
+        assert_eq!(output.value, json!([{"id": "7", "name": "Ada"}]));
+        assert_eq!(output.diagnostics.as_ref().unwrap().strategy, Some("csv"));
+
+        let output = call.build_output("No table today.".into());
+        assert!(output.diagnostics.unwrap().parse_error.is_some());
+    }
+
+    #[test]
+    fn test_build_output_markdown_table_strategy() {
+        let call =
+            LlmCall::new("test", "prompt").with_output_strategy(OutputStrategy::MarkdownTable);
+        let output = call.build_output("Results:\n| k | v |\n|---|---|\n| a | 1 |".into());
+        assert_eq!(output.value, json!([{"k": "a", "v": "1"}]));
+        assert_eq!(
+            output.diagnostics.as_ref().unwrap().strategy,
+            Some("markdown_table")
+        );
+    }
+
+    #[test]
+    fn test_build_output_key_value_strategy() {
+        let call = LlmCall::new("test", "prompt").with_output_strategy(OutputStrategy::KeyValue);
+        let output = call.build_output("**Full Name:** Alice\nAge: 30".into());
+        assert_eq!(output.value, json!({"Full Name": "Alice", "Age": "30"}));
+        assert!(output.diagnostics.as_ref().unwrap().ok());
+
+        let output = call.build_output("I don't know.".into());
+        assert!(output.diagnostics.unwrap().parse_error.is_some());
+    }
+
+    #[cfg(feature = "regex")]
+    #[test]
+    fn test_build_output_regex_strategy() {
+        let call = LlmCall::new("test", "prompt").extracting_regex(r"(?i)ticket:\s*(\S+)");
+        let output = call.build_output("Opened TICKET: INC-42 for you.".into());
+        assert_eq!(output.value, json!("INC-42"));
+        assert_eq!(output.diagnostics.as_ref().unwrap().strategy, Some("regex"));
+
+        let output = call.build_output("Could not open one.".into());
+        assert!(output.diagnostics.unwrap().parse_error.is_some());
+    }
+
+    #[cfg(feature = "regex")]
+    #[tokio::test]
+    async fn test_invalid_regex_fails_before_backend_call() {
+        use crate::MockBackend;
+        use std::sync::Arc;
+
+        let backend = Arc::new(MockBackend::fixed("Code: 7"));
+        let ctx = ExecCtx::builder("http://test")
+            .backend(backend.clone())
+            .build();
+        let call = LlmCall::new("test", "{input}")
+            .extracting_regex(r"Code: (\d+")
+            .with_retry(RetryConfig::new(3));
+        let err = call.invoke(&ctx, json!("q")).await.unwrap_err();
+        assert!(matches!(err, crate::PipelineError::InvalidConfig(_)));
+        assert!(backend.requests().is_empty());
+    }
+
+    #[test]
+    fn test_build_output_bool_strategy() {
+        let call = LlmCall::new("test", "prompt").expecting_bool();
+        let output = call.build_output("<think>hmm</think>The answer is yes.".into());
+        assert_eq!(output.value, json!(true));
+        assert_eq!(
+            output.diagnostics.as_ref().unwrap().strategy,
+            Some("boolean")
+        );
+
+        let output = call.build_output("Maybe yes, maybe no".into());
+        assert!(output.diagnostics.as_ref().unwrap().parse_error.is_some());
+        assert_eq!(output.value, json!("Maybe yes, maybe no"));
+    }
+
     #[test]
     fn test_build_output_text_strategy() {
         let call = LlmCall::new("test", "prompt").expecting_text();
@@ -803,6 +2100,43 @@ mod tests {
         assert_eq!(output.value["result"], 42);
     }
 
+    #[test]
+    fn test_build_output_json_pointer() {
+        let call = LlmCall::new("test", "prompt").expecting_json_pointer("/result/items");
+        let output = call.build_output(r#"{"result": {"items": [1, 2], "total": 2}}"#.into());
+        assert_eq!(output.value, json!([1, 2]));
+        let diag = output.diagnostics.unwrap();
+        assert!(diag.ok());
+        assert_eq!(diag.strategy, Some("json_pointer"));
+
+        let output = call.build_output(r#"{"result": {"total": 0}}"#.into());
+        let diag = output.diagnostics.unwrap();
+        assert!(diag.parse_error.unwrap().contains("/result/items"));
+        assert_eq!(output.value, json!({"result": {"total": 0}}));
+    }
+
+    #[test]
+    fn test_build_output_truncated_json() {
+        assert!(is_truncated_finish(Some("length")));
+        assert!(is_truncated_finish(Some("client_limit")));
+        assert!(!is_truncated_finish(Some("stop")));
+        assert!(!is_truncated_finish(None));
+
+        let call = LlmCall::new("test", "prompt").expecting_json();
+        let output = call.build_output_with(
+            r#"{"title": "Rust", "tags": ["fast", "sa"#.into(),
+            call.output_strategy(),
+            ParseOptions {
+                truncated: true,
+                ..Default::default()
+            },
+        );
+        let diag = output.diagnostics.unwrap();
+        assert!(diag.ok());
+        assert!(diag.auto_completed);
+        assert_eq!(output.value["tags"], json!(["fast", "sa"]));
+    }
+
     #[test]
     fn test_backend_default_is_ollama() {
         let ctx = ExecCtx::builder("http://localhost:11434").build();
@@ -838,6 +2172,62 @@ mod tests {
         assert!(!request.stream);
     }
 
+    #[test]
+    fn test_build_request_cached_system() {
+        let call = LlmCall::new("test", "{input}")
+            .with_config(LlmConfig::default().with_json_mode(true))
+            .with_cached_system(true);
+        let request = call.build_request("hi", Some("You are helpful"), Vec::new(), false);
+        assert!(request.config.cache_system);
+        assert!(request.config.json_mode);
+        assert!(!LlmCall::new("test", "{input}").config().cache_system);
+    }
+
+    #[tokio::test]
+    async fn test_system_parts_rendered() {
+        use crate::backend::{MockBackend, MockReply};
+        use std::sync::Arc;
+
+        // Answers with the system prompt and its parts.
+        let backend = MockBackend::from_fn(|request| {
+            Ok(MockReply::text(
+                json!({
+                    "system": request.system_prompt,
+                    "parts": request.system_parts,
+                })
+                .to_string(),
+            ))
+        });
+        let ctx = ExecCtx::builder("http://test")
+            .backend(Arc::new(backend))
+            .var("domain", "law")
+            .build();
+        let call = LlmCall::new("test", "{input}")
+            .expecting_json()
+            .with_system_parts(vec![
+                "You review {domain} texts.".into(),
+                "Be brief.".into(),
+            ]);
+        assert_eq!(
+            call.system_template(),
+            Some("You review {domain} texts.\n\nBe brief.")
+        );
+
+        let out = call.invoke(&ctx, json!("x")).await.unwrap();
+        assert_eq!(out.value["system"], "You review law texts.\n\nBe brief.");
+        assert_eq!(
+            out.value["parts"],
+            json!(["You review law texts.", "Be brief."])
+        );
+
+        let out = call
+            .with_system("Plain.")
+            .invoke(&ctx, json!("x"))
+            .await
+            .unwrap();
+        assert_eq!(out.value["parts"], json!([]));
+    }
+
     #[test]
     fn test_build_request_with_messages() {
         let call = LlmCall::new("test", "prompt");
@@ -880,6 +2270,78 @@ mod tests {
         assert!(reason.is_some());
     }
 
+    #[test]
+    fn test_truncated_in_thinking_detected() {
+        assert!(is_truncated_in_thinking("<think>First, consider"));
+        assert!(is_truncated_in_thinking("<think>a</think><thinking>b"));
+        assert!(!is_truncated_in_thinking("<think>done</think>"));
+        assert!(!is_truncated_in_thinking("Answer: 4 <think>aside"));
+        assert!(!is_truncated_in_thinking("{\"a\": 1}"));
+
+        let call = LlmCall::new("test", "prompt").with_retry(RetryConfig::new(1));
+        let output = call.build_output("<think>Let me work through".into());
+        let diag = output.diagnostics.as_ref().unwrap();
+        assert!(diag.truncated_in_thinking);
+        assert!(!diag.ok());
+        let trigger = call
+            .check_retry_needed(&output, call.retry.as_ref().unwrap())
+            .unwrap();
+        assert_eq!(trigger.category(), RetryReason::TruncatedThinking);
+    }
+
+    #[tokio::test]
+    async fn test_truncated_thinking_retry_raises_max_tokens() {
+        use crate::backend::{MockBackend, MockReply};
+        use std::sync::Arc;
+
+        // Thinks until cut off unless thinking is disabled.
+        let backend = Arc::new(MockBackend::from_fn(|request| {
+            Ok(MockReply::text(if request.config.thinking {
+                "<think>Step 1: the user wants"
+            } else {
+                "42"
+            }))
+        }));
+        let ctx = ExecCtx::builder("http://test")
+            .backend(backend.clone())
+            .build();
+        let call = LlmCall::new("test", "{input}")
+            .with_config(
+                LlmConfig::default()
+                    .with_thinking(true)
+                    .with_max_tokens(100),
+            )
+            .expecting_number()
+            .with_retry(RetryConfig::new(1));
+
+        let out = call.invoke(&ctx, json!("x")).await.unwrap();
+        assert_eq!(out.value.as_f64(), Some(42.0));
+        let diag = out.diagnostics.unwrap();
+        assert!(!diag.truncated_in_thinking);
+        assert_eq!(diag.retry_reasons, vec![RetryReason::TruncatedThinking]);
+
+        let requests = backend.requests();
+        assert_eq!(requests[1].config.max_tokens, 200);
+        assert!(!requests[1].config.thinking);
+    }
+
+    #[test]
+    fn test_retry_triggered_on_empty_response() {
+        // Lossy accepts anything, so only the empty check catches this.
+        let call = LlmCall::new("test", "prompt").with_retry(RetryConfig::new(2));
+        let output = call.build_output("  \n ".into());
+        assert!(output.diagnostics.as_ref().unwrap().ok());
+
+        let trigger = call
+            .check_retry_needed(&output, call.retry.as_ref().unwrap())
+            .unwrap();
+        assert_eq!(trigger.reason(), "empty response");
+        assert_eq!(trigger.category(), RetryReason::Empty);
+
+        let lenient = RetryConfig::new(2).allow_empty();
+        assert!(call.check_retry_needed(&output, &lenient).is_none());
+    }
+
     #[test]
     fn test_retry_triggered_on_semantic_failure() {
         let call = LlmCall::new("test", "prompt")
@@ -889,9 +2351,9 @@ mod tests {
         // Valid JSON but missing required keys
         let output = call.build_output(r#"{"title": "Matrix"}"#.into());
         let retry_config = call.retry.as_ref().unwrap();
-        let reason = call.check_retry_needed(&output, retry_config);
-        assert!(reason.is_some());
-        assert!(reason.unwrap().contains("year"));
+        let reason = call.check_retry_needed(&output, retry_config).unwrap();
+        assert!(reason.reason().contains("year"));
+        assert_eq!(reason.category(), RetryReason::MissingKey);
     }
 
     #[test]
@@ -927,6 +2389,26 @@ mod tests {
         assert!(!call.retry.as_ref().unwrap().cool_down);
     }
 
+    #[test]
+    fn test_retry_feedback_includes_example() {
+        let example = json!({"title": "Dune", "year": 1965});
+        let invalid = RetryTrigger::Invalid(
+            RetryReason::MissingKey,
+            "missing required key: 'year'".into(),
+        );
+        let message = invalid.feedback(Some(&example));
+        assert!(message.starts_with("Your previous response was invalid"));
+        assert!(message
+            .ends_with(r#"Here is an example of valid output: {"title":"Dune","year":1965}"#));
+        assert!(!invalid.feedback(None).contains("example"));
+
+        let text = invalid.feedback(Some(&json!("positive")));
+        assert!(text.ends_with("Here is an example of valid output: positive"));
+
+        let requested = RetryTrigger::Requested("Try again.".into());
+        assert_eq!(requested.feedback(Some(&example)), "Try again.");
+    }
+
     #[test]
     fn test_choice_strategy_with_retry_detects_failure() {
         let call = LlmCall::new("test", "prompt")
@@ -952,6 +2434,40 @@ mod tests {
         assert_eq!(output.value, Value::String("approve".into()));
     }
 
+    #[test]
+    fn test_retry_parse_failure_categories() {
+        let category = |call: LlmCall, raw: &str| {
+            let output = call.build_output(raw.into());
+            call.check_retry_needed(&output, &RetryConfig::new(1))
+                .unwrap()
+                .category()
+        };
+        assert_eq!(
+            category(LlmCall::new("t", "p").expecting_json(), "nope"),
+            RetryReason::Parse
+        );
+        assert_eq!(
+            category(
+                LlmCall::new("t", "p").expecting_choice(vec!["yes".into()]),
+                "maybe"
+            ),
+            RetryReason::Choice
+        );
+        assert_eq!(
+            category(
+                LlmCall::new("t", "p").expecting_number_in_range(1.0, 5.0),
+                "9"
+            ),
+            RetryReason::Range
+        );
+        let pointer = || LlmCall::new("t", "p").expecting_json_pointer("/items");
+        assert_eq!(
+            category(pointer(), r#"{"other": 1}"#),
+            RetryReason::MissingKey
+        );
+        assert_eq!(category(pointer(), "nope"), RetryReason::Parse);
+    }
+
     #[test]
     fn test_number_in_range_with_retry_detects_failure() {
         let call = LlmCall::new("test", "prompt")
@@ -982,9 +2498,9 @@ mod tests {
         // Valid JSON with out-of-range score
         let output = call.build_output(r#"{"score": 1.5}"#.into());
         let retry_config = call.retry.as_ref().unwrap();
-        let reason = call.check_retry_needed(&output, retry_config);
-        assert!(reason.is_some());
-        assert!(reason.unwrap().contains("score 1.5 outside"));
+        let reason = call.check_retry_needed(&output, retry_config).unwrap();
+        assert!(reason.reason().contains("score 1.5 outside"));
+        assert_eq!(reason.category(), RetryReason::Custom);
 
         // Valid JSON with valid score
         let output = call.build_output(r#"{"score": 0.8}"#.into());
@@ -1022,4 +2538,454 @@ mod tests {
         assert!(call.system_template().is_none());
         assert!(call.retry().is_none());
     }
+
+    #[cfg(feature = "arbitrary_precision")]
+    #[test]
+    fn test_arbitrary_precision_preserves_big_numbers() {
+        let call = LlmCall::new("test", "{input}").expecting_json();
+        let raw = r#"{"id": 123456789012345678901, "amount": 0.10000000000000000001}"#;
+        let out = call.build_output(raw.to_string());
+        assert_eq!(out.value["id"].to_string(), "123456789012345678901");
+        assert_eq!(out.value["amount"].to_string(), "0.10000000000000000001");
+
+        let call = LlmCall::new("test", "{input}").expecting_number();
+        let out = call.build_output("ID: 123456789012345678".to_string());
+        assert_eq!(out.value.to_string(), "123456789012345678");
+    }
+
+    #[test]
+    fn test_resolve_model_precedence() {
+        let ctx = ExecCtx::builder("http://test")
+            .default_model("qwen2.5:7b")
+            .build();
+        let inherits = LlmCall::new("a", "{input}");
+        assert_eq!(inherits.model(), "llama3.2:3b");
+        assert_eq!(inherits.resolve_model(&ctx), "qwen2.5:7b");
+
+        let explicit = LlmCall::new("b", "{input}").with_model("gpt-4o");
+        assert_eq!(explicit.resolve_model(&ctx), "gpt-4o");
+
+        let bare = ExecCtx::builder("http://test").build();
+        assert_eq!(inherits.resolve_model(&bare), "llama3.2:3b");
+    }
+
+    #[test]
+    fn test_build_output_code_block() {
+        let call = LlmCall::new("test", "{input}").expecting_code("sql");
+        let out = call.build_output("Query:\n```sql\nSELECT * FROM t;\n```".to_string());
+        let diag = out.diagnostics.as_ref().unwrap();
+        assert_eq!(out.value, json!("SELECT * FROM t;"));
+        assert_eq!(diag.strategy, Some("code_block"));
+        assert!(!diag.code_block_fallback);
+
+        let out = call.build_output("```\nSELECT 1;\n```".to_string());
+        assert_eq!(out.value, json!("SELECT 1;"));
+        assert!(out.diagnostics.unwrap().code_block_fallback);
+
+        let out = call.build_output("SELECT 1;".to_string());
+        assert!(!out.diagnostics.unwrap().ok());
+    }
+
+    #[tokio::test]
+    async fn test_normalize_unicode_opt_in() {
+        use crate::MockBackend;
+        use std::sync::Arc;
+
+        let raw = "{\u{201C}name\u{201D}:\u{a0}\u{201C}Ada\u{201D}}\u{200b}";
+        let call = LlmCall::new("test", "{input}").expecting_json();
+
+        let ctx = ExecCtx::builder("http://test")
+            .backend(Arc::new(MockBackend::fixed(raw)))
+            .build();
+        let out = call.invoke(&ctx, json!("x")).await.unwrap();
+        assert!(!out.diagnostics.unwrap().ok());
+
+        let ctx = ExecCtx::builder("http://test")
+            .backend(Arc::new(MockBackend::fixed(raw)))
+            .normalize_unicode(true)
+            .build();
+        let out = call.invoke(&ctx, json!("x")).await.unwrap();
+        assert_eq!(out.value, json!({"name": "Ada"}));
+        assert_eq!(out.raw_response, raw);
+    }
+
+    #[tokio::test]
+    async fn test_normalize_keys_opt_in() {
+        use crate::MockBackend;
+        use std::sync::Arc;
+
+        let raw = r#"{"Title": "Dune", "PageCount": 412}"#;
+        let ctx = |enabled: bool| {
+            ExecCtx::builder("http://test")
+                .backend(Arc::new(MockBackend::fixed(raw)))
+                .normalize_keys(enabled)
+                .build()
+        };
+        let call = LlmCall::new("test", "{input}").expecting_json();
+
+        let out = call.invoke(&ctx(false), json!("x")).await.unwrap();
+        assert_eq!(out.value, json!({"Title": "Dune", "PageCount": 412}));
+
+        let out = call.invoke(&ctx(true), json!("x")).await.unwrap();
+        assert_eq!(out.value, json!({"title": "Dune", "page_count": 412}));
+        assert_eq!(out.raw_response, raw);
+
+        let pointer = LlmCall::new("test", "{input}").expecting_json_pointer("/page_count");
+        let out = pointer.invoke(&ctx(true), json!("x")).await.unwrap();
+        assert_eq!(out.value, json!(412));
+    }
+
+    #[test]
+    fn test_token_usage_of() {
+        let response = |meta: Value| LlmResponse {
+            metadata: Some(meta),
+            ..Default::default()
+        };
+        assert_eq!(
+            token_usage_of(&response(
+                json!({"prompt_eval_count": 12, "eval_count": 34})
+            )),
+            (Some(12), Some(34))
+        );
+        assert_eq!(
+            token_usage_of(&response(
+                json!({"usage": {"prompt_tokens": 5, "completion_tokens": 7}})
+            )),
+            (Some(5), Some(7))
+        );
+        assert_eq!(token_usage_of(&LlmResponse::default()), (None, None));
+        assert_eq!(add_tokens(Some(3), None), Some(3));
+        assert_eq!(add_tokens(None, None), None);
+    }
+
+    #[test]
+    fn test_rendered_prompt() {
+        let ctx = ExecCtx::builder("http://test").var("domain", "science").build();
+        let call = LlmCall::new("test", "Explain {input} in {domain}");
+        assert_eq!(
+            call.rendered_prompt(&ctx, &json!("gravity")),
+            "Explain gravity in science"
+        );
+        assert_eq!(
+            call.rendered_prompt(&ctx, &json!({"a": 1})),
+            r#"Explain {"a":1} in science"#
+        );
+    }
+
+    #[test]
+    fn test_rendered_prompt_input_vars() {
+        let ctx = ExecCtx::builder("http://test")
+            .var("domain", "science")
+            .var("year", "unknown")
+            .build();
+        let call = LlmCall::new("test", "{title} ({year}, {domain}): {tags}").with_input_vars(true);
+        let input = json!({"title": "Dune", "year": 1965, "tags": ["sf"]});
+        assert_eq!(
+            call.rendered_prompt(&ctx, &input),
+            r#"Dune (1965, science): ["sf"]"#
+        );
+        assert_eq!(
+            call.rendered_prompt(&ctx, &json!("x")),
+            "{title} (unknown, science): {tags}"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_input_truncation() {
+        use crate::truncation::{TruncationMode, TruncationStrategy};
+        use crate::MockBackend;
+        use std::sync::Arc;
+
+        let ctx = ExecCtx::builder("http://test")
+            .backend(Arc::new(MockBackend::fixed("ok")))
+            .build();
+        let call = LlmCall::new("test", "Summarize: {input}")
+            .with_input_truncation(TruncationStrategy::chars(4, TruncationMode::End));
+        assert_eq!(
+            call.rendered_prompt(&ctx, &json!("abcdefgh")),
+            "Summarize: abcd\n[...truncated...]"
+        );
+
+        let out = call.invoke(&ctx, json!("abcdefgh")).await.unwrap();
+        assert!(out.diagnostics.unwrap().input_truncated);
+        let out = call.invoke(&ctx, json!("abc")).await.unwrap();
+        assert!(!out.diagnostics.unwrap().input_truncated);
+    }
+
+    #[tokio::test]
+    async fn test_async_validator_triggers_retry() {
+        use crate::MockBackend;
+        use std::sync::Arc;
+
+        let ctx = ExecCtx::builder("http://test")
+            .backend(Arc::new(MockBackend::new(vec![
+                r#"{"sku": "X-9"}"#.into(),
+                r#"{"sku": "A-1"}"#.into(),
+            ])))
+            .build();
+        let known = Arc::new(vec!["A-1".to_string()]);
+        let call = LlmCall::new("test", "{input}").expecting_json().with_retry(
+            RetryConfig::new(2).with_async_validator(move |_raw, value| {
+                let known = known.clone();
+                let sku = value["sku"].as_str().unwrap_or_default().to_string();
+                async move {
+                    tokio::task::yield_now().await;
+                    if known.contains(&sku) {
+                        Ok(())
+                    } else {
+                        Err(format!("unknown sku '{}'", sku))
+                    }
+                }
+            }),
+        );
+
+        let out = call.invoke(&ctx, json!("x")).await.unwrap();
+        assert_eq!(out.value, json!({"sku": "A-1"}));
+        assert_eq!(out.diagnostics.unwrap().retry_attempts, 1);
+    }
+
+    #[tokio::test]
+    async fn test_retry_if_sends_custom_feedback() {
+        use crate::backend::{MockBackend, MockReply};
+        use std::sync::Arc;
+
+        let backend = Arc::new(MockBackend::scripted(vec![
+            MockReply::text(r#"{"status": "error"}"#),
+            MockReply::text(r#"{"status": "ok"}"#),
+        ]));
+        let ctx = ExecCtx::builder("http://test")
+            .backend(backend.clone())
+            .build();
+        let call = LlmCall::new("test", "{input}").expecting_json().with_retry(
+            RetryConfig::new(2).retry_if(|output| {
+                (output.value["status"] == "error").then(|| "Try a different approach.".to_string())
+            }),
+        );
+
+        let out = call.invoke(&ctx, json!("x")).await.unwrap();
+        assert_eq!(out.value["status"], "ok");
+        assert_eq!(out.diagnostics.unwrap().retry_attempts, 1);
+
+        let requests = backend.requests();
+        assert_eq!(requests.len(), 2);
+        assert_eq!(
+            requests[1].messages.last().unwrap().content,
+            "Try a different approach."
+        );
+    }
+
+    #[tokio::test]
+    async fn test_assistant_prefix_prefills_and_is_restored() {
+        use crate::backend::{MockBackend, MockReply, Role};
+        use std::sync::Arc;
+
+        // Continues a prefilled `{`, then restarts the object on retry.
+        let backend = Arc::new(MockBackend::scripted(vec![
+            MockReply::text(r#""name": "Ada""#),
+            MockReply::text(r#"{"name": "Ada", "born": 1815}"#),
+        ]));
+        let ctx = ExecCtx::builder("http://test")
+            .backend(backend.clone())
+            .build();
+        let call = LlmCall::new("test", "Describe {input}")
+            .with_assistant_prefix("{")
+            .expecting_json()
+            .with_retry(RetryConfig::new(1).requiring_keys(&["born"]));
+
+        let out = call.invoke(&ctx, json!("Ada")).await.unwrap();
+        assert_eq!(out.value, json!({"name": "Ada", "born": 1815}));
+
+        let requests = backend.requests();
+        let first = &requests[0].messages;
+        assert_eq!(first.len(), 2);
+        assert_eq!(first[0].role, Role::User);
+        assert_eq!(first[0].content, "Describe Ada");
+        assert_eq!(first[1].role, Role::Assistant);
+        assert_eq!(first[1].content, "{");
+
+        // The retry history carries the restored reply, then prefills again.
+        let retry = &requests[1].messages;
+        assert_eq!(retry[1].content, r#"{"name": "Ada""#);
+        assert_eq!(retry.last().unwrap().content, "{");
+    }
+
+    #[tokio::test]
+    async fn test_refusal_is_reported_and_retried() {
+        use crate::backend::{MockBackend, MockReply};
+        use std::sync::Arc;
+
+        // Refuses the first request, then answers.
+        let ctx = || {
+            let backend = MockBackend::scripted(vec![
+                MockReply::refusal("I can't help with that."),
+                MockReply::text(r#"{"ok": true}"#),
+            ]);
+            ExecCtx::builder("http://test")
+                .backend(Arc::new(backend))
+                .build()
+        };
+        let call = LlmCall::new("test", "{input}").expecting_json();
+        let out = call.invoke(&ctx(), json!("x")).await.unwrap();
+        let diag = out.diagnostics.unwrap();
+        assert!(diag.refused());
+        assert_eq!(
+            diag.parse_error.as_deref(),
+            Some("model refused: I can't help with that.")
+        );
+
+        let call = call.with_retry(RetryConfig::new(1));
+        let out = call.invoke(&ctx(), json!("x")).await.unwrap();
+        assert_eq!(out.value, json!({"ok": true}));
+        let diag = out.diagnostics.unwrap();
+        assert!(diag.ok() && !diag.refused());
+        assert_eq!(diag.retry_reasons, vec![RetryReason::Refusal]);
+    }
+
+    #[tokio::test]
+    async fn test_tool_calls_only_skips_parsing() {
+        use crate::backend::{MockBackend, MockReply, ToolCall};
+        use std::sync::Arc;
+
+        let call = ToolCall {
+            id: Some("call_1".into()),
+            name: "lookup".into(),
+            arguments: json!({"q": "rust"}),
+        };
+        let backend =
+            MockBackend::scripted(vec![MockReply::text("").with_tool_calls(vec![call.clone()])]);
+        let ctx = ExecCtx::builder("http://test")
+            .backend(Arc::new(backend))
+            .build();
+        let out = LlmCall::new("test", "{input}")
+            .expecting_json()
+            .with_retry(RetryConfig::new(2))
+            .invoke(&ctx, json!("x"))
+            .await
+            .unwrap();
+        assert_eq!(out.value, Value::Null);
+        assert_eq!(out.tool_calls, vec![call]);
+        let diag = out.diagnostics.unwrap();
+        assert!(diag.ok());
+        assert_eq!(diag.strategy, Some("tool_calls"));
+        assert_eq!(diag.retry_attempts, 0);
+    }
+
+    #[tokio::test]
+    async fn test_streaming_json_emits_partial_values() {
+        use crate::backend::{BackoffConfig, MockBackend, MockReply};
+        use crate::events::FnEventHandler;
+        use std::sync::{Arc, Mutex};
+
+        // Streams a fixed JSON document in small chunks.
+        let chunked = MockBackend::scripted(vec![MockReply::chunks([
+            r#"{"name": "#,
+            r#""Ad"#,
+            r#"a", "#,
+            r#""age": 3"#,
+            "6}",
+        ])]);
+
+        let partials = Arc::new(Mutex::new(Vec::new()));
+        let sink = partials.clone();
+        let ctx = ExecCtx::builder("http://test")
+            .backend(Arc::new(chunked))
+            .event_handler(Arc::new(FnEventHandler(move |event: Event| {
+                if let Event::PartialValue { value, .. } = event {
+                    sink.lock().unwrap().push(value);
+                }
+            })))
+            .build();
+
+        let call = LlmCall::new("person", "{input}")
+            .with_streaming(true)
+            .expecting_json();
+        let out = call.invoke(&ctx, json!("x")).await.unwrap();
+        assert_eq!(out.value, json!({"name": "Ada", "age": 36}));
+        assert_eq!(
+            *partials.lock().unwrap(),
+            vec![
+                json!({}),
+                json!({"name": "Ad"}),
+                json!({"name": "Ada"}),
+                json!({"name": "Ada", "age": 3}),
+                json!({"name": "Ada", "age": 36}),
+            ]
+        );
+
+        // A retried stream starts the partial value over.
+        let flaky = MockBackend::scripted(vec![
+            MockReply::chunks([r#"{"a": 1, "#, r#""b"#]).then_fail(503),
+            MockReply::chunks([r#"{"c": "#, "2}"]),
+        ]);
+        let retrying = ExecCtx::builder("http://test")
+            .backend(Arc::new(flaky))
+            .backoff(BackoffConfig {
+                max_retries: 1,
+                initial_delay: Duration::from_millis(1),
+                ..BackoffConfig::standard()
+            })
+            .event_handler(ctx.event_handler.clone().unwrap())
+            .build();
+        partials.lock().unwrap().clear();
+        let call = LlmCall::new("person", "{input}")
+            .with_streaming(true)
+            .expecting_json();
+        let out = call.invoke(&retrying, json!("x")).await.unwrap();
+        assert_eq!(out.value, json!({"c": 2}));
+        assert_eq!(partials.lock().unwrap().last(), Some(&json!({"c": 2})));
+
+        // Text strategies stream tokens only.
+        partials.lock().unwrap().clear();
+        let call = LlmCall::new("person", "{input}")
+            .with_streaming(true)
+            .expecting_text();
+        call.invoke(&ctx, json!("x")).await.unwrap();
+        assert!(partials.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_with_timeout() {
+        use crate::backend::{BackoffConfig, MockBackend, MockReply};
+        use std::sync::Arc;
+
+        // Hangs on its first call, answers immediately afterwards.
+        let slow_start = || {
+            Arc::new(MockBackend::scripted(vec![
+                MockReply::text("done").with_delay(Duration::from_secs(5)),
+                MockReply::text("done"),
+            ]))
+        };
+
+        let call = LlmCall::new("test", "{input}").with_timeout(Duration::from_millis(20));
+
+        let ctx = ExecCtx::builder("http://test")
+            .backend(slow_start())
+            .build();
+        let err = call.invoke(&ctx, json!("x")).await.unwrap_err();
+        assert!(matches!(err, crate::PipelineError::Timeout(d) if d == Duration::from_millis(20)));
+
+        // A timed-out attempt is retried by the transport backoff.
+        let ctx = ExecCtx::builder("http://test")
+            .backend(slow_start())
+            .backoff(BackoffConfig {
+                max_retries: 1,
+                initial_delay: Duration::from_millis(1),
+                ..BackoffConfig::standard()
+            })
+            .build();
+        let out = call.invoke(&ctx, json!("x")).await.unwrap();
+        assert_eq!(out.value, "done");
+        assert_eq!(out.diagnostics.unwrap().transport_retries, 1);
+
+        // Without its own timeout, the call uses the context's.
+        let ctx = ExecCtx::builder("http://test")
+            .backend(slow_start())
+            .request_timeout(Duration::from_millis(30))
+            .build();
+        let err = LlmCall::new("test", "{input}")
+            .invoke(&ctx, json!("x"))
+            .await
+            .unwrap_err();
+        assert!(matches!(err, crate::PipelineError::Timeout(d) if d == Duration::from_millis(30)));
+    }
 }
@@ -0,0 +1,190 @@
+//! Aggregated parsing metrics.
+//!
+//! [`MetricsHandler`] is an [`EventHandler`] that tallies
+//! [`Event::ParseResult`]s per output strategy, giving a per-run view of how
+//! reliably each strategy parses — useful when tuning prompts. It also
+//! counts [`Event::RetryStart`]s by [`RetryReason`], showing whether retries
+//! come from the prompt (parse failures) or from validators.
+
+use crate::events::{Event, EventHandler};
+use crate::retry::RetryReason;
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+
+/// Parse outcome counts for one output strategy.
+///
+/// Every parse lands in exactly one bucket.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct StrategyCounts {
+    /// Parsed on the first attempt without repair.
+    pub direct: u64,
+    /// Parsed on the first attempt after JSON repair or auto-completion.
+    pub repaired: u64,
+    /// Parsed after one or more semantic retries.
+    pub retried: u64,
+    /// Never parsed; the value fell back to the raw text.
+    pub failed: u64,
+}
+
+impl StrategyCounts {
+    /// Total parses recorded.
+    pub fn total(&self) -> u64 {
+        self.direct + self.repaired + self.retried + self.failed
+    }
+
+    /// Fraction of parses that eventually succeeded (`0.0` when empty).
+    pub fn success_rate(&self) -> f64 {
+        match self.total() {
+            0 => 0.0,
+            total => (total - self.failed) as f64 / total as f64,
+        }
+    }
+}
+
+/// Event handler that counts parse outcomes per strategy and retries per
+/// reason.
+///
+/// Install it on the [`ExecCtx`](crate::ExecCtx) (keep an `Arc` to read it
+/// back) and call [`snapshot`](Self::snapshot) after the run. Other events
+/// are ignored.
+///
+/// # Example
+///
+/// ```
+/// use llm_pipeline::metrics::MetricsHandler;
+/// use llm_pipeline::ExecCtx;
+/// use std::sync::Arc;
+///
+/// let metrics = Arc::new(MetricsHandler::new());
+/// let ctx = ExecCtx::builder("http://localhost:11434")
+///     .event_handler(metrics.clone())
+///     .build();
+/// // ... run payloads with `ctx` ...
+/// for (strategy, counts) in metrics.snapshot() {
+///     println!("{}: {:.0}% ok", strategy, counts.success_rate() * 100.0);
+/// }
+/// ```
+#[derive(Debug, Default)]
+pub struct MetricsHandler {
+    by_strategy: Mutex<BTreeMap<&'static str, StrategyCounts>>,
+    retries: Mutex<BTreeMap<RetryReason, u64>>,
+}
+
+impl MetricsHandler {
+    /// Create an empty handler.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Copy of the current counts, keyed by strategy name.
+    pub fn snapshot(&self) -> BTreeMap<&'static str, StrategyCounts> {
+        self.by_strategy.lock().unwrap().clone()
+    }
+
+    /// Counts for a single strategy (zeroes if none recorded).
+    pub fn strategy(&self, strategy: &str) -> StrategyCounts {
+        self.by_strategy
+            .lock()
+            .unwrap()
+            .get(strategy)
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Copy of the semantic retry counts, keyed by why each retry ran.
+    pub fn retry_reasons(&self) -> BTreeMap<RetryReason, u64> {
+        self.retries.lock().unwrap().clone()
+    }
+
+    /// Clear all counts.
+    pub fn reset(&self) {
+        self.by_strategy.lock().unwrap().clear();
+        self.retries.lock().unwrap().clear();
+    }
+}
+
+impl EventHandler for MetricsHandler {
+    fn on_event(&self, event: Event) {
+        match event {
+            Event::ParseResult {
+                strategy,
+                ok,
+                repaired,
+                retry_attempts,
+                ..
+            } => {
+                let mut map = self.by_strategy.lock().unwrap();
+                let counts = map.entry(strategy).or_default();
+                match (ok, retry_attempts, repaired) {
+                    (false, _, _) => counts.failed += 1,
+                    (true, 1.., _) => counts.retried += 1,
+                    (true, 0, true) => counts.repaired += 1,
+                    (true, 0, false) => counts.direct += 1,
+                }
+            }
+            Event::RetryStart { category, .. } => {
+                *self.retries.lock().unwrap().entry(category).or_default() += 1;
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ExecCtx, LlmCall, MockBackend, Payload, RetryConfig};
+    use serde_json::json;
+    use std::sync::Arc;
+
+    #[tokio::test]
+    async fn test_metrics_counts_outcomes_per_strategy() {
+        let metrics = Arc::new(MetricsHandler::new());
+        let ctx = ExecCtx::builder("http://test")
+            .backend(Arc::new(MockBackend::new(vec![
+                r#"{"a": 1}"#.to_string(),
+                r#"{'a': 1,}"#.to_string(),
+                "not json".to_string(),
+                r#"{"a": 2}"#.to_string(),
+                "still not json".to_string(),
+                "42".to_string(),
+            ])))
+            .event_handler(metrics.clone())
+            .build();
+
+        let json_call = LlmCall::new("j", "{input}").expecting_json();
+        let retrying = LlmCall::new("r", "{input}")
+            .expecting_json()
+            .with_retry(RetryConfig::new(1));
+        json_call.invoke(&ctx, json!("x")).await.unwrap(); // direct
+        json_call.invoke(&ctx, json!("x")).await.unwrap(); // repaired
+        retrying.invoke(&ctx, json!("x")).await.unwrap(); // retried
+        json_call.invoke(&ctx, json!("x")).await.unwrap(); // failed
+        LlmCall::new("n", "{input}")
+            .expecting_number()
+            .invoke(&ctx, json!("x"))
+            .await
+            .unwrap();
+
+        let json_counts = metrics.strategy("json");
+        assert_eq!(
+            json_counts,
+            StrategyCounts {
+                direct: 1,
+                repaired: 1,
+                retried: 1,
+                failed: 1,
+            }
+        );
+        assert_eq!(json_counts.success_rate(), 0.75);
+        assert_eq!(metrics.strategy("number").direct, 1);
+        assert_eq!(
+            metrics.retry_reasons(),
+            BTreeMap::from([(RetryReason::Parse, 1)])
+        );
+
+        metrics.reset();
+        assert!(metrics.snapshot().is_empty());
+        assert!(metrics.retry_reasons().is_empty());
+    }
+}
@@ -0,0 +1,182 @@
+//! Yes/no extraction from LLM responses.
+//!
+//! Provides [`parse_bool`] for classification prompts that expect a
+//! binary answer, handling bare tokens (`yes`, `false`, `1`) as well as
+//! prose like "The answer is yes".
+
+use crate::output_parser::error::{truncate, ParseError};
+use crate::output_parser::extract::preprocess;
+
+/// Extract a yes/no answer from an LLM response.
+///
+/// Handles common patterns:
+/// - Bare token: `"true"`, `"No"`, `"y"`, `"0"`
+/// - Decorated: `"**Yes**"`, `"\"false\""`, `"No."`
+/// - Leading answer: `"Yes, because the input mentions..."`
+/// - In prose: `"The answer is yes"`
+/// - With think block: `"<think>hmm</think>no"`
+///
+/// `y`/`n` and `1`/`0` are only recognized as the whole response. A
+/// response that opens with an answer word is decided by it. Otherwise
+/// every `true`/`yes`/`false`/`no` word in the prose must agree; a
+/// response with both kinds of signal, or neither, is an error. `no` only
+/// counts where it ends a clause (`"I'd say no."`), not as a determiner
+/// (`"There is no problem"`). A signal word right after `not`, `never`, or
+/// a negative like `isn't` is flipped: `"That is not true."` is `false`.
+///
+/// # Examples
+///
+/// ```
+/// use llm_pipeline::output_parser::parse_bool;
+///
+/// assert!(parse_bool("**Yes**").unwrap());
+/// assert!(!parse_bool("I think the answer is false.").unwrap());
+/// assert!(parse_bool("It could be yes or no.").is_err());
+/// ```
+pub fn parse_bool(response: &str) -> Result<bool, ParseError> {
+    let cleaned = preprocess(response);
+
+    if cleaned.is_empty() {
+        return Err(ParseError::EmptyResponse);
+    }
+
+    let lower = cleaned.to_lowercase();
+    let stripped = lower
+        .trim_matches(|c: char| {
+            matches!(c, '.' | '!' | ',' | '*' | '"' | '\'' | '`' | '(' | ')') || c.is_whitespace()
+        })
+        .trim();
+
+    // Strategy 1: The whole response is a boolean token
+    let exact = match stripped {
+        "true" | "yes" | "y" | "1" => Some(true),
+        "false" | "no" | "n" | "0" => Some(false),
+        _ => None,
+    };
+    if let Some(value) = exact {
+        return Ok(value);
+    }
+
+    // Each word, and whether punctuation or the end of the text follows it
+    let tokens: Vec<&str> = stripped.split_whitespace().collect();
+    let words: Vec<(&str, bool)> = tokens
+        .iter()
+        .enumerate()
+        .map(|(i, token)| {
+            let word = token.trim_matches(|c: char| !c.is_alphanumeric());
+            let ends_clause =
+                i + 1 == tokens.len() || !token.ends_with(|c: char| c.is_alphanumeric());
+            (word, ends_clause)
+        })
+        .filter(|(word, _)| !word.is_empty())
+        .collect();
+
+    // Each word's signal, flipped when a negation precedes it
+    let signals: Vec<Option<bool>> = words
+        .iter()
+        .enumerate()
+        .map(|(i, &(w, end))| {
+            let negated = i > 0 && is_negation(words[i - 1].0);
+            signal(w, end).map(|value| value != negated)
+        })
+        .collect();
+
+    // Strategy 2: The response opens with the answer ("Yes, because ...")
+    if let Some(value) = signals.first().copied().flatten() {
+        return Ok(value);
+    }
+
+    // Strategy 3: Every signal word in the prose agrees
+    let mut signals = signals.into_iter().flatten();
+    if let Some(first) = signals.next() {
+        if signals.all(|value| value == first) {
+            return Ok(first);
+        }
+    }
+
+    Err(ParseError::Unparseable {
+        expected_format: "boolean",
+        text: truncate(&cleaned, 200),
+    })
+}
+
+/// The boolean a prose word stands for, if any. `no` only answers at the
+/// end of a clause; elsewhere it is usually a determiner ("no problem").
+fn signal(word: &str, ends_clause: bool) -> Option<bool> {
+    match word {
+        "true" | "yes" => Some(true),
+        "false" => Some(false),
+        "no" if ends_clause => Some(false),
+        _ => None,
+    }
+}
+
+/// Whether `word` negates the word after it ("not true", "isn't false").
+fn is_negation(word: &str) -> bool {
+    matches!(
+        word,
+        "not" | "never" | "isn't" | "isn’t" | "aren't" | "aren’t" | "wasn't" | "wasn’t"
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bare_tokens() {
+        for text in ["true", "Yes", "y", "1", " TRUE\n"] {
+            assert!(parse_bool(text).unwrap(), "{}", text);
+        }
+        for text in ["false", "No", "N", "0"] {
+            assert!(!parse_bool(text).unwrap(), "{}", text);
+        }
+    }
+
+    #[test]
+    fn decorated() {
+        assert!(parse_bool("**Yes**").unwrap());
+        assert!(!parse_bool("\"false\"").unwrap());
+        assert!(!parse_bool("No.").unwrap());
+    }
+
+    #[test]
+    fn leading_answer_wins() {
+        assert!(parse_bool("Yes, there is no problem here.").unwrap());
+    }
+
+    #[test]
+    fn in_prose() {
+        assert!(parse_bool("The answer is yes").unwrap());
+        assert!(!parse_bool("After checking, I'd say false.").unwrap());
+    }
+
+    #[test]
+    fn determiner_no_is_not_an_answer() {
+        assert!(parse_bool("There is no problem; the claim is accurate").is_err());
+        assert!(parse_bool("No issues found, so yes.").unwrap());
+        assert!(!parse_bool("No, there is no problem.").unwrap());
+        assert!(!parse_bool("I'd say no; it fails the check.").unwrap());
+    }
+
+    #[test]
+    fn negated_signal_is_flipped() {
+        assert!(!parse_bool("That is not true.").unwrap());
+        assert!(parse_bool("The claim is not false").unwrap());
+        assert!(!parse_bool("This statement isn't true, so false.").unwrap());
+        assert!(parse_bool("It is not true, and not false either").is_err());
+    }
+
+    #[test]
+    fn with_think() {
+        assert!(!parse_bool("<think>yes? maybe yes</think>no").unwrap());
+    }
+
+    #[test]
+    fn ambiguous_or_absent() {
+        assert!(parse_bool("It could be yes or no.").is_err());
+        assert!(parse_bool("It depends on the context.").is_err());
+        assert!(parse_bool("Answer: 1 of 2").is_err());
+        assert!(matches!(parse_bool("  "), Err(ParseError::EmptyResponse)));
+    }
+}
@@ -89,6 +89,41 @@ pub fn parse_choice<'a>(response: &str, valid_choices: &[&'a str]) -> Result<&'a
     })
 }
 
+/// Match the whole response against a set of valid options, exactly and
+/// case-sensitively.
+///
+/// Only `<think>` blocks and surrounding whitespace are removed; there is
+/// no unwrapping of bold or quotes and no search inside prose. Use this
+/// when casing is meaningful (`"A"` vs `"a"`, `"PASS"` vs `"Pass"`); for
+/// free-form answers prefer [`parse_choice`].
+///
+/// # Examples
+///
+/// ```
+/// use llm_pipeline::output_parser::parse_choice_strict;
+///
+/// assert_eq!(parse_choice_strict(" PASS\n", &["PASS", "Pass"]).unwrap(), "PASS");
+/// assert!(parse_choice_strict("pass", &["PASS", "Pass"]).is_err());
+/// ```
+pub fn parse_choice_strict<'a>(
+    response: &str,
+    valid_choices: &[&'a str],
+) -> Result<&'a str, ParseError> {
+    let cleaned = preprocess(response);
+
+    if cleaned.is_empty() {
+        return Err(ParseError::EmptyResponse);
+    }
+
+    valid_choices
+        .iter()
+        .find(|&&choice| choice == cleaned)
+        .copied()
+        .ok_or_else(|| ParseError::NoMatchingChoice {
+            valid: valid_choices.iter().map(|s| s.to_string()).collect(),
+        })
+}
+
 /// Find a word-boundary match of `needle` in `haystack`.
 /// Returns the position of the first match, or None.
 fn find_word_boundary_match(haystack: &str, needle: &str) -> Option<usize> {
@@ -181,4 +216,22 @@ mod tests {
         let result = parse_choice("unpositive", &["positive"]);
         assert!(result.is_err());
     }
+
+    #[test]
+    fn strict_requires_exact_case() {
+        let choices = ["A", "a", "PASS"];
+        assert_eq!(parse_choice_strict("a", &choices).unwrap(), "a");
+        assert_eq!(parse_choice_strict("  A\n", &choices).unwrap(), "A");
+        assert_eq!(
+            parse_choice_strict("<think>hmm</think>PASS", &choices).unwrap(),
+            "PASS"
+        );
+        assert!(parse_choice_strict("Pass", &choices).is_err());
+        assert!(parse_choice_strict("**PASS**", &choices).is_err());
+        assert!(parse_choice_strict("The answer is A", &choices).is_err());
+        assert!(matches!(
+            parse_choice_strict("  ", &choices),
+            Err(ParseError::EmptyResponse)
+        ));
+    }
 }
@@ -0,0 +1,109 @@
+//! Fenced code extraction from LLM responses.
+//!
+//! Provides [`parse_code_block`] for pulling just the code out of a response
+//! that wraps it in prose, e.g. generated SQL, Python, or Rust.
+
+use crate::output_parser::error::{truncate, ParseError};
+use crate::output_parser::extract::{extract_code_block_for, preprocess};
+
+/// A code block extracted by [`parse_code_block`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CodeBlock {
+    /// The block's content, trimmed.
+    pub code: String,
+    /// `true` if the block was tagged with the requested language, `false`
+    /// if it came from the bare-fence fallback.
+    pub language_matched: bool,
+}
+
+/// Extract the content of a `` ```{lang} `` fenced block.
+///
+/// The language tag is matched case-insensitively. If no block carries the
+/// tag, falls back to the first bare (untagged) fence. Blocks tagged with a
+/// different language are never returned.
+///
+/// # Examples
+///
+/// ```
+/// use llm_pipeline::output_parser::parse_code_block;
+///
+/// let input = "Here is the query:\n```sql\nSELECT 1;\n```\nHope it helps!";
+/// let block = parse_code_block(input, "sql").unwrap();
+/// assert_eq!(block.code, "SELECT 1;");
+/// assert!(block.language_matched);
+/// ```
+pub fn parse_code_block(response: &str, lang: &str) -> Result<CodeBlock, ParseError> {
+    let cleaned = preprocess(response);
+
+    if cleaned.is_empty() {
+        return Err(ParseError::EmptyResponse);
+    }
+
+    if let Some(code) = extract_code_block_for(&cleaned, lang) {
+        return Ok(CodeBlock {
+            code: code.to_string(),
+            language_matched: true,
+        });
+    }
+
+    if let Some(code) = first_bare_fence(&cleaned) {
+        return Ok(CodeBlock {
+            code: code.to_string(),
+            language_matched: false,
+        });
+    }
+
+    Err(ParseError::Unparseable {
+        expected_format: "fenced code block",
+        text: truncate(&cleaned, 200),
+    })
+}
+
+/// Content of the first fence with no language tag.
+fn first_bare_fence(text: &str) -> Option<&str> {
+    let mut search_from = 0;
+    while let Some(fence_start) = text[search_from..].find("```") {
+        let after_backticks = search_from + fence_start + 3;
+        let line_end = after_backticks + text[after_backticks..].find('\n')?;
+        let content_start = line_end + 1;
+        let close = content_start + text[content_start..].find("```")?;
+
+        if text[after_backticks..line_end].trim().is_empty() {
+            return Some(text[content_start..close].trim());
+        }
+        // Skip past this block's closing fence.
+        search_from = close + 3;
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_code_block_prefers_language() {
+        let input = "```\nplain\n```\n```python\nprint(1)\n```";
+        let block = parse_code_block(input, "Python").unwrap();
+        assert_eq!(block.code, "print(1)");
+        assert!(block.language_matched);
+    }
+
+    #[test]
+    fn test_code_block_falls_back_to_bare_fence() {
+        let input = "```rust\nfn main() {}\n```\nor\n```\nSELECT 1;\n```";
+        let block = parse_code_block(input, "sql").unwrap();
+        assert_eq!(block.code, "SELECT 1;");
+        assert!(!block.language_matched);
+    }
+
+    #[test]
+    fn test_code_block_missing() {
+        assert!(parse_code_block("SELECT 1;", "sql").is_err());
+        assert!(parse_code_block("```rust\nfn main() {}\n```", "sql").is_err());
+        assert!(matches!(
+            parse_code_block("  ", "sql"),
+            Err(ParseError::EmptyResponse)
+        ));
+    }
+}
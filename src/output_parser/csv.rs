@@ -0,0 +1,252 @@
+//! Delimited table extraction from LLM responses.
+//!
+//! Provides [`parse_csv`] for comma-, tab-, semicolon-, or pipe-separated
+//! tables, bare or inside a markdown fence.
+
+use serde_json::{Map, Value};
+
+use crate::output_parser::error::{truncate, ParseError};
+use crate::output_parser::extract::{extract_code_block, preprocess};
+
+/// Delimiters tried by [`parse_csv`], in tie-break order.
+const DELIMITERS: [char; 4] = [',', '\t', ';', '|'];
+
+/// Parse a delimited table into a JSON array of row objects.
+///
+/// The content of the first fenced block is used if there is one. The
+/// delimiter is whichever of `,`, tab, `;`, or `|` splits the most lines.
+/// The header is the first line whose field count every following line
+/// shares, up to the next blank line, which ends the table. Prose before
+/// it is dropped, even if it contains the delimiter ("Sure, here is the
+/// data:"); a line ending in `:` is always taken as prose. If the rows are
+/// ragged, the header is the first line containing the delimiter.
+///
+/// The first row is the header; each later row becomes an object keyed by
+/// it. Fields are trimmed, and double-quoted fields may contain the
+/// delimiter, newlines, and `""` escapes. Short rows are padded with
+/// `null`; fields past the last header are dropped. A table with only one
+/// row has no header to key by and is returned as an array of strings.
+///
+/// # Examples
+///
+/// ```
+/// use llm_pipeline::output_parser::parse_csv;
+/// use serde_json::json;
+///
+/// let rows = parse_csv("name,city\nAda,\"London, UK\"\nAlan").unwrap();
+/// assert_eq!(
+///     rows,
+///     json!([
+///         {"name": "Ada", "city": "London, UK"},
+///         {"name": "Alan", "city": null},
+///     ])
+/// );
+/// assert_eq!(parse_csv("a\tb\tc").unwrap(), json!(["a", "b", "c"]));
+/// ```
+pub fn parse_csv(response: &str) -> Result<Value, ParseError> {
+    let cleaned = preprocess(response);
+
+    if cleaned.is_empty() {
+        return Err(ParseError::EmptyResponse);
+    }
+
+    let body = extract_code_block(&cleaned)
+        .map(|(_, content)| content)
+        .unwrap_or(&cleaned);
+
+    // Pick the delimiter that splits the most records. `max_by_key` keeps
+    // the last maximum, so iterate in reverse to prefer earlier delimiters.
+    let best = DELIMITERS
+        .iter()
+        .rev()
+        .map(|&delim| {
+            let records = split_records(body, delim);
+            (records.iter().filter(|r| r.len() > 1).count(), records)
+        })
+        .max_by_key(|(split, _)| *split);
+    let records = match best {
+        Some((split, records)) if split > 0 => records,
+        _ => {
+            return Err(ParseError::Unparseable {
+                expected_format: "CSV",
+                text: truncate(&cleaned, 200),
+            })
+        }
+    };
+
+    // The header is the first line every following row agrees with.
+    let is_lead_in = |r: &[String]| r.last().is_some_and(|f| f.ends_with(':'));
+    let rows_after = |i: usize| records[i + 1..].iter().take_while(|r| !r.is_empty());
+    let candidates = || (0..records.len()).filter(|&i| records[i].len() > 1);
+    let start = candidates()
+        .filter(|&i| !is_lead_in(&records[i]))
+        .find(|&i| {
+            let mut rows = rows_after(i).peekable();
+            rows.peek().is_some() && rows.all(|r| r.len() == records[i].len())
+        })
+        .or_else(|| candidates().find(|&i| !is_lead_in(&records[i])))
+        .or_else(|| candidates().next())
+        .unwrap_or_default();
+
+    let header = records[start].clone();
+    let rows: Vec<Value> = rows_after(start)
+        .map(|record| {
+            let mut fields = record.iter().cloned();
+            let row: Map<String, Value> = header
+                .iter()
+                .map(|key| {
+                    (
+                        key.clone(),
+                        fields.next().map_or(Value::Null, Value::String),
+                    )
+                })
+                .collect();
+            Value::Object(row)
+        })
+        .collect();
+
+    if rows.is_empty() {
+        return Ok(Value::Array(
+            header.into_iter().map(Value::String).collect(),
+        ));
+    }
+    Ok(Value::Array(rows))
+}
+
+/// Split `text` into records of trimmed fields, honoring double quotes.
+/// Blank lines become empty records.
+fn split_records(text: &str, delim: char) -> Vec<Vec<String>> {
+    let mut records = Vec::new();
+    let mut record = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut in_quotes = false;
+    let mut chars = text.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if in_quotes {
+            match c {
+                '"' if chars.peek() == Some(&'"') => {
+                    chars.next();
+                    field.push('"');
+                }
+                '"' => in_quotes = false,
+                _ => field.push(c),
+            }
+        } else if c == '"' && field.trim().is_empty() {
+            field.clear();
+            quoted = true;
+            in_quotes = true;
+        } else if c == delim {
+            end_field(&mut field, &mut quoted, &mut record);
+        } else if c == '\n' {
+            end_field(&mut field, &mut quoted, &mut record);
+            end_record(&mut record, &mut records);
+        } else if !quoted && c != '\r' {
+            field.push(c);
+        }
+    }
+    end_field(&mut field, &mut quoted, &mut record);
+    end_record(&mut record, &mut records);
+    records
+}
+
+/// Move `field` onto `record`, trimming it unless it was quoted.
+fn end_field(field: &mut String, quoted: &mut bool, record: &mut Vec<String>) {
+    let value = std::mem::take(field);
+    record.push(if *quoted {
+        value
+    } else {
+        value.trim().to_string()
+    });
+    *quoted = false;
+}
+
+/// Move `record` onto `records`, emptied if it came from a blank line.
+fn end_record(record: &mut Vec<String>, records: &mut Vec<Vec<String>>) {
+    let mut record = std::mem::take(record);
+    if record.len() == 1 && record[0].is_empty() {
+        record.clear();
+    }
+    records.push(record);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn header_and_rows() {
+        let result = parse_csv("name, age\nAda, 36\nAlan, 41\n").unwrap();
+        assert_eq!(
+            result,
+            json!([{"name": "Ada", "age": "36"}, {"name": "Alan", "age": "41"}])
+        );
+    }
+
+    #[test]
+    fn detects_tabs_and_semicolons() {
+        let tsv = parse_csv("a\tb\n1\t2").unwrap();
+        assert_eq!(tsv, json!([{"a": "1", "b": "2"}]));
+        let ssv = parse_csv("a;b\n1,5;2").unwrap();
+        assert_eq!(ssv, json!([{"a": "1,5", "b": "2"}]));
+    }
+
+    #[test]
+    fn quoted_fields() {
+        let result = parse_csv("q,a\n\"x, y\",\"say \"\"hi\"\"\nthere\"").unwrap();
+        assert_eq!(result, json!([{"q": "x, y", "a": "say \"hi\"\nthere"}]));
+    }
+
+    #[test]
+    fn ragged_rows() {
+        let result = parse_csv("a,b,c\n1\n1,2,3,4").unwrap();
+        assert_eq!(
+            result,
+            json!([
+                {"a": "1", "b": null, "c": null},
+                {"a": "1", "b": "2", "c": "3"},
+            ])
+        );
+    }
+
+    #[test]
+    fn fenced_with_prose_and_think() {
+        let input = "<think>table time</think>Here you go:\n```csv\nx,y\n1,2\n```\nDone.";
+        assert_eq!(parse_csv(input).unwrap(), json!([{"x": "1", "y": "2"}]));
+        let bare = "Here is the data:\n\nx,y\n1,2\n\nLet me know!";
+        assert_eq!(parse_csv(bare).unwrap(), json!([{"x": "1", "y": "2"}]));
+    }
+
+    #[test]
+    fn prose_with_delimiter_before_table() {
+        let input = "Sure, here is the data.\nname,age,city\nAda,36,London\nAlan,41,Wilmslow";
+        assert_eq!(
+            parse_csv(input).unwrap(),
+            json!([
+                {"name": "Ada", "age": "36", "city": "London"},
+                {"name": "Alan", "age": "41", "city": "Wilmslow"},
+            ])
+        );
+        let two_columns = "Sure, here you go:\n\nname,age\nAda,36";
+        assert_eq!(
+            parse_csv(two_columns).unwrap(),
+            json!([{"name": "Ada", "age": "36"}])
+        );
+    }
+
+    #[test]
+    fn single_row_is_string_array() {
+        assert_eq!(
+            parse_csv("red, green, blue").unwrap(),
+            json!(["red", "green", "blue"])
+        );
+    }
+
+    #[test]
+    fn no_table() {
+        assert!(parse_csv("Just a sentence").is_err());
+        assert!(matches!(parse_csv(""), Err(ParseError::EmptyResponse)));
+    }
+}
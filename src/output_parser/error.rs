@@ -35,6 +35,29 @@ pub enum ParseError {
     /// No number found, or number was outside the expected range.
     #[error("no valid number found in response")]
     NoNumber,
+
+    /// JSON parsed, but nothing exists at the requested JSON Pointer.
+    #[error("JSON pointer {pointer:?} not found in response")]
+    MissingPointer {
+        /// The pointer that was looked up (e.g. `/result/items`).
+        pointer: String,
+    },
+
+    /// The regex did not match the response.
+    #[error("no match for pattern {pattern:?} in response")]
+    NoMatch {
+        /// The pattern that was searched for.
+        pattern: String,
+    },
+
+    /// The regex failed to compile.
+    #[error("invalid pattern {pattern:?}: {reason}")]
+    InvalidPattern {
+        /// The pattern as given.
+        pattern: String,
+        /// The compile error.
+        reason: String,
+    },
 }
 
 /// Truncate a string to at most `max_len` characters, appending "..." if truncated.
@@ -5,11 +5,61 @@
 
 /// Full preprocessing pipeline applied to every LLM response.
 ///
-/// Strips `<think>` and `<thinking>` blocks, then trims whitespace.
+/// Strips leading garbage (see [`strip_leading_garbage`]), `<think>` and
+/// `<thinking>` blocks, then trims whitespace.
 /// Every parser module calls this as step 1.
 pub fn preprocess(text: &str) -> String {
-    let stripped = strip_think_tags(text);
-    stripped.trim().to_string()
+    let stripped = strip_think_tags(strip_leading_garbage(text.trim_start()));
+    strip_leading_garbage(stripped.trim_start())
+        .trim()
+        .to_string()
+}
+
+/// Strip a UTF-8 byte order mark and non-whitespace control characters from
+/// the start of `text`.
+///
+/// Some providers and proxies prepend these, and `serde_json` rejects them.
+/// Only the leading run is removed; anything after the first printable
+/// character (or whitespace) is left alone.
+///
+/// # Examples
+///
+/// ```
+/// use llm_pipeline::output_parser::strip_leading_garbage;
+///
+/// assert_eq!(strip_leading_garbage("\u{feff}\u{0}{\"a\": 1}"), "{\"a\": 1}");
+/// assert_eq!(strip_leading_garbage("\n{}"), "\n{}");
+/// ```
+pub fn strip_leading_garbage(text: &str) -> &str {
+    text.trim_start_matches(|c: char| c == '\u{FEFF}' || (c.is_control() && !c.is_whitespace()))
+}
+
+/// Normalize typographic Unicode that breaks parsing.
+///
+/// - Curly double quotes (`“ ” „ ‟`) and double primes become `"`
+/// - Curly single quotes (`‘ ’ ‚ ‛`) and primes become `'`
+/// - Non-breaking and other fixed-width spaces become a plain space
+/// - Zero-width characters and the BOM are removed
+///
+/// Ordinary whitespace, including newlines, is left alone.
+///
+/// # Examples
+///
+/// ```
+/// use llm_pipeline::output_parser::normalize_unicode;
+///
+/// assert_eq!(normalize_unicode("{“a”:\u{a0}‘b’}\u{200b}"), "{\"a\": 'b'}");
+/// ```
+pub fn normalize_unicode(text: &str) -> String {
+    text.chars()
+        .filter_map(|c| match c {
+            '\u{201C}' | '\u{201D}' | '\u{201E}' | '\u{201F}' | '\u{2033}' => Some('"'),
+            '\u{2018}' | '\u{2019}' | '\u{201A}' | '\u{201B}' | '\u{2032}' => Some('\''),
+            '\u{00A0}' | '\u{2007}' | '\u{202F}' | '\u{2000}'..='\u{200A}' => Some(' '),
+            '\u{200B}' | '\u{200C}' | '\u{200D}' | '\u{2060}' | '\u{FEFF}' => None,
+            other => Some(other),
+        })
+        .collect()
 }
 
 /// Strip all `<think>...</think>` and `<thinking>...</thinking>` blocks from text.
@@ -91,10 +141,21 @@ pub fn extract_code_block(text: &str) -> Option<(Option<&str>, &str)> {
     None
 }
 
+/// Fence tags treated as the same language as `json`.
+const JSON_ALIASES: &[&str] = &["json", "jsonc", "json5"];
+
+/// Whether a fence tagged `tag` counts as `lang` (case-insensitive, with
+/// `json`, `jsonc` and `json5` all interchangeable).
+fn fence_matches(tag: &str, lang: &str) -> bool {
+    let is_json = |s: &str| JSON_ALIASES.iter().any(|a| a.eq_ignore_ascii_case(s));
+    tag.eq_ignore_ascii_case(lang) || (is_json(tag) && is_json(lang))
+}
+
 /// Extract content from a code block matching a specific language.
 ///
 /// e.g., `extract_code_block_for(text, "json")` looks for `` ```json `` blocks.
-/// Falls back to any code block if no language-specific block is found.
+/// Tags match case-insensitively, and `json`, `jsonc` and `json5` are
+/// treated as one language. Returns `None` if no matching block is found.
 ///
 /// # Examples
 ///
@@ -103,6 +164,9 @@ pub fn extract_code_block(text: &str) -> Option<(Option<&str>, &str)> {
 ///
 /// let input = "```json\n[1, 2, 3]\n```";
 /// assert_eq!(extract_code_block_for(input, "json"), Some("[1, 2, 3]"));
+///
+/// let input = "```JSON5\n{a: 1}\n```";
+/// assert_eq!(extract_code_block_for(input, "json"), Some("{a: 1}"));
 /// ```
 pub fn extract_code_block_for<'a>(text: &'a str, lang: &str) -> Option<&'a str> {
     // First pass: look for a block with the matching language
@@ -115,7 +179,7 @@ pub fn extract_code_block_for<'a>(text: &'a str, lang: &str) -> Option<&'a str>
             let lang_str = text[after_backticks..after_backticks + line_end].trim();
             let content_start = after_backticks + line_end + 1;
 
-            if lang_str.eq_ignore_ascii_case(lang) {
+            if fence_matches(lang_str, lang) {
                 if let Some(close_offset) = text[content_start..].find("```") {
                     let content = text[content_start..content_start + close_offset].trim();
                     return Some(content);
@@ -133,7 +197,8 @@ pub fn extract_code_block_for<'a>(text: &'a str, lang: &str) -> Option<&'a str>
 /// Find a bracketed substring by matching open/close delimiters.
 ///
 /// Handles nesting. Prefers later (more likely to be the actual output)
-/// over earlier occurrences.
+/// over earlier occurrences; use [`find_bracketed_with`] to pick a
+/// different region.
 ///
 /// - `find_bracketed(text, '[', ']')` — finds JSON arrays
 /// - `find_bracketed(text, '{', '}')` — finds JSON objects
@@ -147,9 +212,66 @@ pub fn extract_code_block_for<'a>(text: &'a str, lang: &str) -> Option<&'a str>
 /// assert_eq!(find_bracketed(input, '{', '}'), Some(r#"{"a": [1, 2]}"#));
 /// ```
 pub fn find_bracketed(text: &str, open: char, close: char) -> Option<&str> {
-    // Collect all top-level bracketed regions using nesting-aware scanning.
-    // Prefer the last (later) match, which is more likely to be the LLM's answer.
-    let mut best: Option<&str> = None;
+    find_bracketed_with(text, open, close, JsonCandidateSelection::Last)
+}
+
+/// Which bracketed region wins when a response contains several, e.g. the
+/// answer followed by a prose example with braces in it.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum JsonCandidateSelection {
+    /// The first region.
+    First,
+    /// The last region. The answer usually follows any preamble.
+    #[default]
+    Last,
+    /// The longest region.
+    Largest,
+    /// The region with the most top-level keys (objects) or elements
+    /// (arrays). Regions that aren't valid JSON count as empty.
+    MostKeys,
+}
+
+/// Like [`find_bracketed`], choosing among several top-level regions by
+/// `selection`. Ties go to the later region.
+///
+/// # Examples
+///
+/// ```
+/// use llm_pipeline::output_parser::extract::{find_bracketed_with, JsonCandidateSelection};
+///
+/// let input = r#"{"name": "Ada", "born": 1815} For example: {"name": "..."}"#;
+/// assert_eq!(
+///     find_bracketed_with(input, '{', '}', JsonCandidateSelection::MostKeys),
+///     Some(r#"{"name": "Ada", "born": 1815}"#)
+/// );
+/// ```
+pub fn find_bracketed_with(
+    text: &str,
+    open: char,
+    close: char,
+    selection: JsonCandidateSelection,
+) -> Option<&str> {
+    let regions = bracketed_regions(text, open, close);
+    match selection {
+        JsonCandidateSelection::First => regions.first().copied(),
+        JsonCandidateSelection::Last => regions.last().copied(),
+        JsonCandidateSelection::Largest => regions.into_iter().max_by_key(|r| r.len()),
+        JsonCandidateSelection::MostKeys => {
+            regions
+                .into_iter()
+                .max_by_key(|r| match serde_json::from_str::<serde_json::Value>(r) {
+                    Ok(serde_json::Value::Object(map)) => map.len(),
+                    Ok(serde_json::Value::Array(items)) => items.len(),
+                    _ => 0,
+                })
+        }
+    }
+}
+
+/// All top-level bracketed regions of `text`, in order, using
+/// nesting-aware scanning. Stops at the first unclosed region.
+fn bracketed_regions(text: &str, open: char, close: char) -> Vec<&str> {
+    let mut regions = Vec::new();
     let mut scan_from = 0;
 
     while scan_from < text.len() {
@@ -188,7 +310,7 @@ pub fn find_bracketed(text: &str, open: char, close: char) -> Option<&str> {
             }
 
             if let Some(end) = found_end {
-                best = Some(&text[start..=end]);
+                regions.push(&text[start..=end]);
                 scan_from = end + 1;
             } else {
                 break;
@@ -198,7 +320,7 @@ pub fn find_bracketed(text: &str, open: char, close: char) -> Option<&str> {
         }
     }
 
-    best
+    regions
 }
 
 #[cfg(test)]
@@ -257,6 +379,15 @@ mod tests {
         assert_eq!(preprocess(input), "hello world");
     }
 
+    #[test]
+    fn preprocess_strips_leading_bom_and_control_chars() {
+        assert_eq!(preprocess("\u{feff}{\"a\": 1}"), "{\"a\": 1}");
+        assert_eq!(preprocess("\u{feff}\u{1}\u{7f} \n[1]"), "[1]");
+        assert_eq!(preprocess(" \u{feff}<think>x</think> \u{feff}ok"), "ok");
+        // Only leading garbage is touched.
+        assert_eq!(preprocess("a\u{feff}b\u{1}"), "a\u{feff}b\u{1}");
+    }
+
     // ── extract_code_block ──
 
     #[test]
@@ -289,6 +420,17 @@ mod tests {
         assert_eq!(extract_code_block_for(input, "json"), Some("[1, 2, 3]"));
     }
 
+    #[test]
+    fn extract_code_block_for_json_aliases() {
+        let input = "```sh\ncurl example.com\n```\n```jsonc\n{\"a\": 1}\n```";
+        assert_eq!(extract_code_block_for(input, "json"), Some("{\"a\": 1}"));
+
+        let input = "```JSON5\n{a: 1}\n```";
+        assert_eq!(extract_code_block_for(input, "json"), Some("{a: 1}"));
+        assert_eq!(extract_code_block_for(input, "jsonc"), Some("{a: 1}"));
+        assert_eq!(extract_code_block_for(input, "yaml"), None);
+    }
+
     #[test]
     fn extract_code_block_for_wrong_lang() {
         let input = "```yaml\nname: test\n```";
@@ -326,6 +468,28 @@ mod tests {
         assert_eq!(result, Some(r#"["a", "b"]"#));
     }
 
+    #[test]
+    fn find_bracketed_with_selection() {
+        let input = r#"{"a": 1, "b": 2} e.g. {"example": "a much longer value"}"#;
+        let pick = |s| find_bracketed_with(input, '{', '}', s);
+        assert_eq!(
+            pick(JsonCandidateSelection::First),
+            Some(r#"{"a": 1, "b": 2}"#)
+        );
+        assert_eq!(
+            pick(JsonCandidateSelection::Last),
+            Some(r#"{"example": "a much longer value"}"#)
+        );
+        assert_eq!(
+            pick(JsonCandidateSelection::Largest),
+            Some(r#"{"example": "a much longer value"}"#)
+        );
+        assert_eq!(
+            pick(JsonCandidateSelection::MostKeys),
+            Some(r#"{"a": 1, "b": 2}"#)
+        );
+    }
+
     #[test]
     fn find_bracketed_no_match() {
         let input = "no brackets here";
@@ -8,7 +8,8 @@ use serde::de::DeserializeOwned;
 
 use crate::output_parser::error::{truncate, ParseError};
 use crate::output_parser::extract::{
-    extract_code_block, extract_code_block_for, find_bracketed, preprocess,
+    extract_code_block, extract_code_block_for, find_bracketed_with, preprocess,
+    JsonCandidateSelection,
 };
 use crate::output_parser::repair::try_repair_json;
 use crate::output_parser::streaming::auto_complete_json;
@@ -40,18 +41,74 @@ use crate::output_parser::streaming::auto_complete_json;
 /// assert_eq!(result.sentiment, "positive");
 /// ```
 pub fn parse_json<T: DeserializeOwned>(response: &str) -> Result<T, ParseError> {
-    let (candidate, cleaned) = extract_json_candidate(response)?;
+    parse_json_tracked(response).map(|(val, _)| val)
+}
+
+/// Same as [`parse_json`], choosing among several bracketed JSON regions
+/// (strategies 4 and 5) by `selection` instead of taking the last one.
+///
+/// # Examples
+///
+/// ```
+/// use llm_pipeline::output_parser::{parse_json_with, JsonCandidateSelection};
+///
+/// let response = r#"{"label": "spam"} Format: {"label": "<spam|ham>", ...}"#;
+/// let value: serde_json::Value =
+///     parse_json_with(response, JsonCandidateSelection::First).unwrap();
+/// assert_eq!(value["label"], "spam");
+/// ```
+pub fn parse_json_with<T: DeserializeOwned>(
+    response: &str,
+    selection: JsonCandidateSelection,
+) -> Result<T, ParseError> {
+    parse_json_tracked_with(response, selection).map(|(val, _)| val)
+}
+
+/// Which fallback, if any, [`parse_json_tracked`] needed to produce a value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum JsonRecovery {
+    /// The extracted candidate deserialized as-is.
+    None,
+    /// JSON repair (trailing commas, quotes, ...) was applied.
+    Repaired,
+    /// The candidate was truncated (unclosed brackets or string) and had
+    /// to be closed, either by repair or by [`auto_complete_json`].
+    AutoCompleted,
+}
+
+/// Same as [`parse_json`], but also reports which fallback succeeded so
+/// callers can surface it in diagnostics.
+pub(crate) fn parse_json_tracked<T: DeserializeOwned>(
+    response: &str,
+) -> Result<(T, JsonRecovery), ParseError> {
+    parse_json_tracked_with(response, JsonCandidateSelection::default())
+}
+
+/// [`parse_json_tracked`] with an explicit [`JsonCandidateSelection`].
+pub(crate) fn parse_json_tracked_with<T: DeserializeOwned>(
+    response: &str,
+    selection: JsonCandidateSelection,
+) -> Result<(T, JsonRecovery), ParseError> {
+    let (candidate, cleaned) = extract_json_candidate(response, selection)?;
 
     // Try deserializing the candidate
     let deser_err = match serde_json::from_str::<T>(&candidate) {
-        Ok(val) => return Ok(val),
+        Ok(val) => return Ok((val, JsonRecovery::None)),
         Err(e) => e.to_string(),
     };
 
+    // Repair also closes missing brackets, so a truncated candidate usually
+    // succeeds here rather than in the auto-complete step below.
+    let repair_kind = if is_truncated(&candidate) {
+        JsonRecovery::AutoCompleted
+    } else {
+        JsonRecovery::Repaired
+    };
+
     // Try repair on the candidate
     if let Some(repaired) = try_repair_json(&candidate) {
         if let Ok(val) = serde_json::from_str::<T>(&repaired) {
-            return Ok(val);
+            return Ok((val, repair_kind));
         }
     }
 
@@ -59,7 +116,7 @@ pub fn parse_json<T: DeserializeOwned>(response: &str) -> Result<T, ParseError>
     if candidate != cleaned {
         if let Some(repaired) = try_repair_json(&cleaned) {
             if let Ok(val) = serde_json::from_str::<T>(&repaired) {
-                return Ok(val);
+                return Ok((val, repair_kind));
             }
         }
     }
@@ -67,7 +124,7 @@ pub fn parse_json<T: DeserializeOwned>(response: &str) -> Result<T, ParseError>
     // Try auto-completing truncated JSON as final strategy
     if let Some(completed) = auto_complete_json(&candidate) {
         if let Ok(val) = serde_json::from_str::<T>(&completed) {
-            return Ok(val);
+            return Ok((val, JsonRecovery::AutoCompleted));
         }
     }
 
@@ -78,6 +135,32 @@ pub fn parse_json<T: DeserializeOwned>(response: &str) -> Result<T, ParseError>
     })
 }
 
+/// Same as [`parse_json_tracked`], for output known to be cut off (the
+/// backend reported `finish_reason == "length"`). The candidate is closed
+/// with [`auto_complete_json`] before anything else is tried; if that does
+/// not produce a value, falls back to the usual strategy pipeline.
+pub(crate) fn parse_truncated_json_tracked<T: DeserializeOwned>(
+    response: &str,
+    selection: JsonCandidateSelection,
+) -> Result<(T, JsonRecovery), ParseError> {
+    let (candidate, _) = extract_json_candidate(response, selection)?;
+
+    if let Some(completed) = auto_complete_json(&candidate) {
+        if let Ok(val) = serde_json::from_str::<T>(&completed) {
+            // auto_complete_json returns valid input unchanged, e.g. when
+            // the limit hit right after the closing bracket.
+            let recovery = if completed == candidate.trim() {
+                JsonRecovery::None
+            } else {
+                JsonRecovery::AutoCompleted
+            };
+            return Ok((val, recovery));
+        }
+    }
+
+    parse_json_tracked_with(response, selection)
+}
+
 /// Parse into a `serde_json::Value` when you don't know the schema.
 ///
 /// Uses the same strategy pipeline as [`parse_json`].
@@ -85,9 +168,111 @@ pub fn parse_json_value(response: &str) -> Result<serde_json::Value, ParseError>
     parse_json(response)
 }
 
+/// Rewrite top-level object keys in snake_case, so `"Title"`, `"TITLE"`,
+/// and `"title"` all deserialize into a `title` field.
+///
+/// Applies to the keys of `value` itself if it is an object, or of each
+/// object element if it is an array; nested objects are left alone. When
+/// two keys normalize to the same name, one that was already snake_case
+/// wins, otherwise the first.
+///
+/// ```
+/// use llm_pipeline::output_parser::json::normalize_keys;
+/// use serde_json::json;
+///
+/// let value = normalize_keys(json!({"Title": "Dune", "firstName": "Frank", "Page-Count": 412}));
+/// assert_eq!(value, json!({"title": "Dune", "first_name": "Frank", "page_count": 412}));
+/// ```
+pub fn normalize_keys(value: serde_json::Value) -> serde_json::Value {
+    use serde_json::Value;
+
+    fn normalize_object(map: serde_json::Map<String, Value>) -> Value {
+        let mut out = serde_json::Map::with_capacity(map.len());
+        for (key, v) in map {
+            let normalized = snake_case(&key);
+            if normalized == key {
+                out.insert(normalized, v);
+            } else {
+                out.entry(normalized).or_insert(v);
+            }
+        }
+        Value::Object(out)
+    }
+
+    match value {
+        Value::Object(map) => normalize_object(map),
+        Value::Array(items) => Value::Array(
+            items
+                .into_iter()
+                .map(|item| match item {
+                    Value::Object(map) => normalize_object(map),
+                    other => other,
+                })
+                .collect(),
+        ),
+        other => other,
+    }
+}
+
+/// `firstName`, `FirstName`, `FIRST_NAME`, `first-name`, and `First Name`
+/// all become `first_name`; acronyms stay whole (`userID` → `user_id`).
+fn snake_case(key: &str) -> String {
+    let chars: Vec<char> = key.trim().chars().collect();
+    let mut out = String::with_capacity(key.len() + 4);
+    for (i, &c) in chars.iter().enumerate() {
+        if c == ' ' || c == '-' || c == '_' {
+            if !out.is_empty() && !out.ends_with('_') {
+                out.push('_');
+            }
+            continue;
+        }
+        if c.is_uppercase() && i > 0 {
+            let prev = chars[i - 1];
+            let next_lower = chars.get(i + 1).is_some_and(|n| n.is_lowercase());
+            let boundary =
+                prev.is_lowercase() || prev.is_ascii_digit() || (prev.is_uppercase() && next_lower);
+            if boundary && !out.ends_with('_') {
+                out.push('_');
+            }
+        }
+        out.extend(c.to_lowercase());
+    }
+    while out.ends_with('_') {
+        out.pop();
+    }
+    out
+}
+
+/// Whether `s` ends inside a string or with unclosed `{`/`[`.
+fn is_truncated(s: &str) -> bool {
+    let mut depth: i32 = 0;
+    let mut in_string = false;
+    let mut escape_next = false;
+
+    for ch in s.chars() {
+        if escape_next {
+            escape_next = false;
+            continue;
+        }
+        match ch {
+            '\\' if in_string => escape_next = true,
+            '"' => in_string = !in_string,
+            '{' | '[' if !in_string => depth += 1,
+            '}' | ']' if !in_string => depth -= 1,
+            _ => {}
+        }
+    }
+
+    in_string || depth > 0
+}
+
 /// Try all extraction strategies and return the best JSON candidate string.
-/// Returns `(best_candidate, cleaned_text)`.
-fn extract_json_candidate(response: &str) -> Result<(String, String), ParseError> {
+/// Returns `(best_candidate, cleaned_text)`. `selection` picks among
+/// several bracketed regions.
+fn extract_json_candidate(
+    response: &str,
+    selection: JsonCandidateSelection,
+) -> Result<(String, String), ParseError> {
     let trimmed = response.trim();
 
     if trimmed.is_empty() {
@@ -127,7 +312,7 @@ fn extract_json_candidate(response: &str) -> Result<(String, String), ParseError
     }
 
     // Strategy 4: Bracket-match a JSON object
-    if let Some(bracket_str) = find_bracketed(&cleaned, '{', '}') {
+    if let Some(bracket_str) = find_bracketed_with(&cleaned, '{', '}', selection) {
         if serde_json::from_str::<serde_json::Value>(bracket_str).is_ok() {
             return Ok((bracket_str.to_string(), cleaned));
         }
@@ -135,7 +320,7 @@ fn extract_json_candidate(response: &str) -> Result<(String, String), ParseError
     }
 
     // Strategy 5: Bracket-match a JSON array
-    if let Some(bracket_str) = find_bracketed(&cleaned, '[', ']') {
+    if let Some(bracket_str) = find_bracketed_with(&cleaned, '[', ']', selection) {
         if serde_json::from_str::<serde_json::Value>(bracket_str).is_ok() {
             return Ok((bracket_str.to_string(), cleaned));
         }
@@ -150,6 +335,7 @@ fn extract_json_candidate(response: &str) -> Result<(String, String), ParseError
 mod tests {
     use super::*;
     use serde::Deserialize;
+    use serde_json::json;
 
     #[derive(Debug, Deserialize, PartialEq)]
     struct Kv {
@@ -185,6 +371,16 @@ mod tests {
         assert_eq!(result, vec![1, 2, 3]);
     }
 
+    #[test]
+    fn bom_prefixed_json_object() {
+        let input = "\u{feff}{\"key\": \"value\"}";
+        let (result, recovery) = parse_json_tracked::<Kv>(input).unwrap();
+        assert_eq!(result.key, "value");
+        assert_eq!(recovery, JsonRecovery::None);
+        let value = parse_json_value("\u{feff}\u{0}[1, 2]").unwrap();
+        assert_eq!(value, serde_json::json!([1, 2]));
+    }
+
     #[test]
     fn think_then_json() {
         let input = r#"<think>analyzing</think>{"key": "value"}"#;
@@ -241,6 +437,20 @@ mod tests {
         assert_eq!(result.key, "value");
     }
 
+    #[test]
+    fn jsonc_fence_with_comments() {
+        let input = "Run this:\n```bash\necho hi\n```\nConfig:\n```jsonc\n// settings\n{\"key\": \"value\"}\n```";
+        let result: Kv = parse_json(input).unwrap();
+        assert_eq!(result.key, "value");
+    }
+
+    #[test]
+    fn json5_fence_uppercase() {
+        let input = "```JSON5\n{'key': 'value',}\n```";
+        let result: Kv = parse_json(input).unwrap();
+        assert_eq!(result.key, "value");
+    }
+
     #[test]
     fn json_with_surrounding_text() {
         let input = "Sure! Here's your result: {\"key\": \"value\"}\nHope that helps!";
@@ -261,4 +471,87 @@ mod tests {
         let result: Result<Kv, _> = parse_json("");
         assert!(result.is_err());
     }
+
+    #[test]
+    fn tracked_reports_recovery() {
+        let (_, rec) = parse_json_tracked::<serde_json::Value>(r#"{"a": 1}"#).unwrap();
+        assert_eq!(rec, JsonRecovery::None);
+
+        let (_, rec) = parse_json_tracked::<serde_json::Value>("{'a': 1,}").unwrap();
+        assert_eq!(rec, JsonRecovery::Repaired);
+
+        let (val, rec) =
+            parse_json_tracked::<serde_json::Value>(r#"{"a": 1, "b": [1, 2"#).unwrap();
+        assert_eq!(rec, JsonRecovery::AutoCompleted);
+        assert_eq!(val["b"], serde_json::json!([1, 2]));
+    }
+
+    #[test]
+    fn truncated_completes_before_parsing() {
+        let (val, rec) = parse_truncated_json_tracked::<serde_json::Value>(
+            r#"{"title": "Rust", "tags": ["a", "b"#,
+            JsonCandidateSelection::Last,
+        )
+        .unwrap();
+        assert_eq!(rec, JsonRecovery::AutoCompleted);
+        assert_eq!(val["tags"], serde_json::json!(["a", "b"]));
+
+        let (_, rec) = parse_truncated_json_tracked::<serde_json::Value>(
+            r#"{"a": 1}"#,
+            JsonCandidateSelection::Last,
+        )
+        .unwrap();
+        assert_eq!(rec, JsonRecovery::None);
+
+        // Not completable: falls back to the regular pipeline.
+        let (_, rec) = parse_truncated_json_tracked::<serde_json::Value>(
+            "{'a': 1,}",
+            JsonCandidateSelection::Last,
+        )
+        .unwrap();
+        assert_eq!(rec, JsonRecovery::Repaired);
+    }
+
+    #[test]
+    fn test_snake_case_variants() {
+        for key in [
+            "firstName",
+            "FirstName",
+            "FIRST_NAME",
+            "first-name",
+            "First Name",
+        ] {
+            assert_eq!(snake_case(key), "first_name", "{}", key);
+        }
+        assert_eq!(snake_case("Title"), "title");
+        assert_eq!(snake_case("userID"), "user_id");
+        assert_eq!(snake_case("HTTPStatus"), "http_status");
+        assert_eq!(snake_case("already_snake"), "already_snake");
+        assert_eq!(snake_case("item2Count"), "item2_count");
+    }
+
+    #[test]
+    fn test_normalize_keys_top_level_only() {
+        let value = normalize_keys(json!({"Title": "A", "title": "B", "Meta": {"InnerKey": 1}}));
+        assert_eq!(value, json!({"title": "B", "meta": {"InnerKey": 1}}));
+
+        let list = normalize_keys(json!([{"Name": "x"}, "plain"]));
+        assert_eq!(list, json!([{"name": "x"}, "plain"]));
+
+        #[derive(Debug, Deserialize, PartialEq)]
+        struct Book {
+            title: String,
+            page_count: u32,
+        }
+        let book: Book =
+            serde_json::from_value(normalize_keys(json!({"TITLE": "Dune", "PageCount": 412})))
+                .unwrap();
+        assert_eq!(
+            book,
+            Book {
+                title: "Dune".into(),
+                page_count: 412
+            }
+        );
+    }
 }
@@ -0,0 +1,106 @@
+//! `Key: value` block extraction from LLM responses.
+//!
+//! Provides [`parse_key_value`] for responses like `Name: Alice\nAge: 30`,
+//! a common shape when a model is asked for a few labeled fields.
+
+use serde_json::{Map, Value};
+
+use crate::output_parser::error::{truncate, ParseError};
+use crate::output_parser::extract::preprocess;
+
+/// Extract `key: value` (or `key = value`) lines into a JSON object.
+///
+/// Each line is split on its first `:` or `=`, whichever comes first, and
+/// both sides are trimmed. Markdown bold/italic markers around keys and
+/// values (`**Name:** Alice`) and list bullets (`- Name: Alice`) are
+/// removed. Lines without a separator, and lines with nothing after it
+/// (usually headings like `Details:`), are skipped. Values are strings;
+/// a repeated key keeps its last value.
+///
+/// Fails if no pair is found.
+///
+/// # Examples
+///
+/// ```
+/// use llm_pipeline::output_parser::parse_key_value;
+/// use serde_json::json;
+///
+/// let input = "Here are the details:\n**Name:** Alice\n- Age = 30\n\nThanks!";
+/// assert_eq!(
+///     parse_key_value(input).unwrap(),
+///     json!({"Name": "Alice", "Age": "30"})
+/// );
+/// ```
+pub fn parse_key_value(response: &str) -> Result<Value, ParseError> {
+    let cleaned = preprocess(response);
+
+    if cleaned.is_empty() {
+        return Err(ParseError::EmptyResponse);
+    }
+
+    let is_markup = |c: char| c == '*' || c == '_' || c.is_whitespace();
+    let mut pairs = Map::new();
+    for line in cleaned.lines() {
+        let line = line.trim();
+        let line = ["- ", "* ", "+ "]
+            .iter()
+            .find_map(|bullet| line.strip_prefix(bullet))
+            .unwrap_or(line);
+        let Some(sep) = line.find([':', '=']) else {
+            continue;
+        };
+        let key = line[..sep].trim_matches(is_markup);
+        let value = line[sep + 1..].trim_matches(is_markup);
+        if key.is_empty() || value.is_empty() {
+            continue;
+        }
+        pairs.insert(key.to_string(), Value::String(value.to_string()));
+    }
+
+    if pairs.is_empty() {
+        return Err(ParseError::Unparseable {
+            expected_format: "key-value pairs",
+            text: truncate(&cleaned, 200),
+        });
+    }
+    Ok(Value::Object(pairs))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn colon_and_equals() {
+        let result = parse_key_value("Name: Alice\nAge = 30\nurl: https://a.b/c?x=1").unwrap();
+        assert_eq!(
+            result,
+            json!({"Name": "Alice", "Age": "30", "url": "https://a.b/c?x=1"})
+        );
+    }
+
+    #[test]
+    fn bold_keys_and_bullets() {
+        let input = "**Name:** Alice\n* __Role__: *admin*\n- **Team**: Core";
+        assert_eq!(
+            parse_key_value(input).unwrap(),
+            json!({"Name": "Alice", "Role": "admin", "Team": "Core"})
+        );
+    }
+
+    #[test]
+    fn skips_noise_and_think() {
+        let input = "<think>Name: Bob</think>\nSummary:\n\nName: Alice\njust prose\n";
+        assert_eq!(parse_key_value(input).unwrap(), json!({"Name": "Alice"}));
+    }
+
+    #[test]
+    fn no_pairs() {
+        assert!(parse_key_value("Nothing to see here.\nHeading:").is_err());
+        assert!(matches!(
+            parse_key_value(" "),
+            Err(ParseError::EmptyResponse)
+        ));
+    }
+}
@@ -0,0 +1,152 @@
+//! Markdown table extraction from LLM responses.
+//!
+//! Provides [`parse_markdown_table`] for the `| col | col |` tables models
+//! reach for whenever they are asked to compare or list things.
+
+use std::collections::HashMap;
+
+use crate::output_parser::error::{truncate, ParseError};
+use crate::output_parser::extract::preprocess;
+
+/// Extract the first markdown table as one map per row, keyed by header.
+///
+/// A table is a header line followed by an alignment row such as
+/// `| --- | :---: |`. Prose before and after it is ignored, outer pipes
+/// are optional, cells are trimmed, and `\|` is an escaped pipe. The table
+/// ends at the first line without a `|`. Short rows are padded with empty
+/// strings; cells past the last header are dropped.
+///
+/// # Examples
+///
+/// ```
+/// use llm_pipeline::output_parser::parse_markdown_table;
+///
+/// let input = "Here you go:\n\n| Name | Age |\n|------|----:|\n| Ada  | 36  |\n";
+/// let rows = parse_markdown_table(input).unwrap();
+/// assert_eq!(rows.len(), 1);
+/// assert_eq!(rows[0]["Name"], "Ada");
+/// assert_eq!(rows[0]["Age"], "36");
+/// ```
+pub fn parse_markdown_table(response: &str) -> Result<Vec<HashMap<String, String>>, ParseError> {
+    let cleaned = preprocess(response);
+
+    if cleaned.is_empty() {
+        return Err(ParseError::EmptyResponse);
+    }
+
+    let lines: Vec<&str> = cleaned.lines().map(str::trim).collect();
+    let Some(start) = lines
+        .windows(2)
+        .position(|pair| pair[0].contains('|') && is_alignment_row(pair[1]))
+    else {
+        return Err(ParseError::Unparseable {
+            expected_format: "markdown table",
+            text: truncate(&cleaned, 200),
+        });
+    };
+
+    let header = split_row(lines[start]);
+    let rows = lines[start + 2..]
+        .iter()
+        .take_while(|line| line.contains('|'))
+        .map(|line| {
+            let mut cells = split_row(line).into_iter();
+            header
+                .iter()
+                .map(|key| (key.clone(), cells.next().unwrap_or_default()))
+                .collect()
+        })
+        .collect();
+    Ok(rows)
+}
+
+/// Whether `line` is an alignment row like `|---|:--:|` or `--- | ---`.
+fn is_alignment_row(line: &str) -> bool {
+    line.contains('|')
+        && split_row(line).iter().all(|cell| {
+            let dashes = cell.trim_start_matches(':').trim_end_matches(':');
+            !dashes.is_empty() && dashes.chars().all(|c| c == '-')
+        })
+}
+
+/// Split a table row into trimmed cells, dropping optional outer pipes.
+fn split_row(line: &str) -> Vec<String> {
+    let line = line.trim();
+    let line = line.strip_prefix('|').unwrap_or(line);
+    let line = match line.strip_suffix('|') {
+        Some(rest) if !rest.ends_with('\\') => rest,
+        _ => line,
+    };
+
+    let mut cells = Vec::new();
+    let mut cell = String::new();
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' if chars.peek() == Some(&'|') => {
+                chars.next();
+                cell.push('|');
+            }
+            '|' => cells.push(std::mem::take(&mut cell).trim().to_string()),
+            _ => cell.push(c),
+        }
+    }
+    cells.push(cell.trim().to_string());
+    cells
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn row(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn basic_table() {
+        let input = "| a | b |\n| --- | --- |\n| 1 | 2 |\n| 3 | 4 |";
+        assert_eq!(
+            parse_markdown_table(input).unwrap(),
+            vec![
+                row(&[("a", "1"), ("b", "2")]),
+                row(&[("a", "3"), ("b", "4")])
+            ]
+        );
+    }
+
+    #[test]
+    fn prose_around_and_no_outer_pipes() {
+        let input = "<think>format it</think>Sure! Results:\n\nname   |  score\n:--- | ---:\nAda|  9 \n\nHope that helps.";
+        assert_eq!(
+            parse_markdown_table(input).unwrap(),
+            vec![row(&[("name", "Ada"), ("score", "9")])]
+        );
+    }
+
+    #[test]
+    fn ragged_and_escaped_cells() {
+        let input =
+            "| op | meaning | note |\n|---|---|---|\n| a \\| b | or |\n| x | y | z | extra |";
+        assert_eq!(
+            parse_markdown_table(input).unwrap(),
+            vec![
+                row(&[("op", "a | b"), ("meaning", "or"), ("note", "")]),
+                row(&[("op", "x"), ("meaning", "y"), ("note", "z")]),
+            ]
+        );
+    }
+
+    #[test]
+    fn no_table() {
+        assert!(parse_markdown_table("a | b\nno separator here").is_err());
+        assert!(parse_markdown_table("Title\n---\ntext").is_err());
+        assert!(matches!(
+            parse_markdown_table(""),
+            Err(ParseError::EmptyResponse)
+        ));
+    }
+}
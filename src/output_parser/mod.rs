@@ -15,42 +15,67 @@
 //! | [`parse_xml_tag`] | Extract content from an XML tag |
 //! | [`parse_xml_tags`] | Extract content from multiple XML tags |
 //! | [`parse_choice`] | Extract a choice from valid options |
+//! | [`parse_bool`] | Extract a yes/no answer |
 //! | [`parse_number`] | Extract a numeric value |
 //! | [`parse_number_in_range`] | Extract a bounded numeric value |
 //! | [`parse_text`] | Clean text extraction |
+//! | [`parse_csv`] | Extract a delimited table as row objects |
+//! | [`parse_markdown_table`] | Extract a markdown table as row maps |
+//! | [`parse_key_value`] | Extract `Key: value` lines as an object |
+//! | [`parse_code_block`] | Extract a fenced code block by language |
 //! | `parse_yaml` | Extract typed YAML (feature: `yaml`) |
+//! | `parse_regex` | Extract a regex capture (feature: `regex`) |
 //!
 //! ## Shared Utilities
 //!
 //! | Function | Purpose |
 //! |----------|---------|
 //! | [`strip_think_tags`] | Remove `<think>` blocks from text |
+//! | [`strip_leading_garbage`] | Drop a leading BOM and control characters |
 //! | [`try_repair_json`] | Fix common LLM JSON errors |
+//! | [`normalize_unicode`] | Map smart quotes/NBSP to ASCII, drop zero-width chars |
+//! | [`normalize_keys`] | Rewrite top-level object keys in snake_case |
 
+pub mod boolean;
 pub mod choice;
+pub mod code;
+pub mod csv;
 pub mod error;
 pub mod extract;
 pub mod json;
+pub mod key_value;
 pub mod list;
+pub mod markdown;
 pub mod number;
 pub mod repair;
 pub mod streaming;
 pub mod text;
 pub mod xml;
 
+#[cfg(feature = "regex")]
+pub mod pattern;
 #[cfg(feature = "yaml")]
 pub mod yaml;
 
 // Re-export all public functions at module level
-pub use choice::parse_choice;
+pub use boolean::parse_bool;
+pub use choice::{parse_choice, parse_choice_strict};
+pub use code::{parse_code_block, CodeBlock};
+pub use csv::parse_csv;
 pub use error::ParseError;
-pub use extract::{preprocess, strip_think_tags};
-pub use json::{parse_json, parse_json_value};
+pub use extract::{
+    normalize_unicode, preprocess, strip_leading_garbage, strip_think_tags, JsonCandidateSelection,
+};
+pub use json::{normalize_keys, parse_json, parse_json_value, parse_json_with};
+pub use key_value::parse_key_value;
 pub use list::{parse_string_list, parse_string_list_raw};
+pub use markdown::parse_markdown_table;
 pub use number::{parse_number, parse_number_in_range};
 pub use repair::try_repair_json;
 pub use text::parse_text;
 pub use xml::{parse_xml_tag, parse_xml_tags};
 
+#[cfg(feature = "regex")]
+pub use pattern::parse_regex;
 #[cfg(feature = "yaml")]
 pub use yaml::parse_yaml;
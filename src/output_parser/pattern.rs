@@ -0,0 +1,94 @@
+//! Regex extraction from LLM responses (feature-gated behind `regex`).
+//!
+//! Provides [`parse_regex`] for pulling a single code, ID, or field out of
+//! prose. Requires the `regex` feature flag.
+
+use regex::Regex;
+
+use crate::output_parser::error::ParseError;
+use crate::output_parser::extract::preprocess;
+
+/// Extract the first match of `pattern` from an LLM response.
+///
+/// Returns capture group 1 if the pattern has one and it took part in the
+/// match, otherwise the whole match. `<think>` blocks are stripped first.
+///
+/// Patterns use [`regex`](https://docs.rs/regex) syntax, including inline
+/// flags: `(?i)` for case-insensitive, `(?m)` for `^`/`$` at line breaks,
+/// and `(?s)` for `.` matching newlines. The pattern is compiled on every
+/// call; [`LlmCall`](crate::LlmCall) compiles an
+/// [`OutputStrategy::Regex`](crate::output_strategy::OutputStrategy::Regex)
+/// pattern once per invocation.
+///
+/// Requires the `regex` feature flag.
+///
+/// # Examples
+///
+/// ```
+/// use llm_pipeline::output_parser::parse_regex;
+///
+/// let ticket = parse_regex("Filed as ticket OPS-1423, see above.", r"\b([A-Z]+-\d+)\b").unwrap();
+/// assert_eq!(ticket, "OPS-1423");
+/// assert!(parse_regex("no ticket here", r"[A-Z]+-\d+").is_err());
+/// ```
+pub fn parse_regex(response: &str, pattern: &str) -> Result<String, ParseError> {
+    let re = compile_regex(pattern)?;
+    parse_with_regex(response, &re)
+}
+
+/// Compile `pattern`, mapping a syntax error to [`ParseError::InvalidPattern`].
+pub(crate) fn compile_regex(pattern: &str) -> Result<Regex, ParseError> {
+    Regex::new(pattern).map_err(|e| ParseError::InvalidPattern {
+        pattern: pattern.to_string(),
+        reason: e.to_string(),
+    })
+}
+
+/// [`parse_regex`] with an already compiled pattern.
+pub(crate) fn parse_with_regex(response: &str, re: &Regex) -> Result<String, ParseError> {
+    let cleaned = preprocess(response);
+
+    if cleaned.is_empty() {
+        return Err(ParseError::EmptyResponse);
+    }
+
+    let captures = re.captures(&cleaned).ok_or_else(|| ParseError::NoMatch {
+        pattern: re.as_str().to_string(),
+    })?;
+    let found = captures.get(1).or_else(|| captures.get(0));
+    Ok(found.map_or("", |m| m.as_str()).to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn group_or_whole_match() {
+        assert_eq!(parse_regex("Code: 4821", r"Code: (\d+)").unwrap(), "4821");
+        assert_eq!(parse_regex("Code: 4821", r"\d+").unwrap(), "4821");
+        assert_eq!(parse_regex("id=7", r"id=(x)?\d").unwrap(), "id=7");
+    }
+
+    #[test]
+    fn inline_flags() {
+        let input = "<think>ANSWER: wrong</think>The answer:\nfirst\nsecond";
+        assert_eq!(
+            parse_regex(input, r"(?is)answer:\s*(.*)").unwrap(),
+            "first\nsecond"
+        );
+        assert_eq!(parse_regex(input, r"(?m)^(\w+)$").unwrap(), "first");
+    }
+
+    #[test]
+    fn errors() {
+        assert!(matches!(
+            parse_regex("abc", r"\d"),
+            Err(ParseError::NoMatch { .. })
+        ));
+        assert!(matches!(
+            parse_regex("abc", r"("),
+            Err(ParseError::InvalidPattern { .. })
+        ));
+    }
+}
@@ -1,10 +1,11 @@
 //! Clean text extraction from LLM responses.
 //!
 //! Provides [`parse_text`] for extracting clean prose from LLM output,
-//! stripping think blocks and common boilerplate prefixes.
+//! stripping think blocks, stray code fences, and common boilerplate
+//! prefixes.
 
 use crate::output_parser::error::ParseError;
-use crate::output_parser::extract::preprocess;
+use crate::output_parser::extract::{extract_code_block, preprocess};
 
 /// Common boilerplate prefixes that LLMs add to responses.
 const SIMPLE_PREFIXES: &[&str] = &[
@@ -29,7 +30,9 @@ const LINE_PREFIXES: &[&str] = &["Here's ", "Here is "];
 /// Processing:
 /// 1. Strip `<think>` blocks
 /// 2. Trim whitespace
-/// 3. Strip common LLM boilerplate prefixes:
+/// 3. Unwrap the response if it is entirely one `` ``` `` fence (fences
+///    mid-text are left alone)
+/// 4. Strip common LLM boilerplate prefixes:
 ///    "Sure!", "Here's...", "Of course!", "Certainly!", etc.
 ///
 /// Returns the cleaned text or `EmptyResponse` if nothing remains.
@@ -44,6 +47,7 @@ const LINE_PREFIXES: &[&str] = &["Here's ", "Here is "];
 /// ```
 pub fn parse_text(response: &str) -> Result<String, ParseError> {
     let cleaned = preprocess(response);
+    let cleaned = unwrap_fence(&cleaned).unwrap_or(cleaned);
 
     if cleaned.is_empty() {
         return Err(ParseError::EmptyResponse);
@@ -85,6 +89,14 @@ pub fn parse_text(response: &str) -> Result<String, ParseError> {
     Ok(result)
 }
 
+/// The body of `text` if the whole of it is a single fenced code block.
+fn unwrap_fence(text: &str) -> Option<String> {
+    if !text.starts_with("```") || !text.ends_with("```") || text.matches("```").count() != 2 {
+        return None;
+    }
+    extract_code_block(text).map(|(_lang, content)| content.to_string())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -113,6 +125,24 @@ mod tests {
         assert_eq!(result, "Paris.");
     }
 
+    #[test]
+    fn fully_fenced_is_unwrapped() {
+        let result = parse_text("```\nParis is the capital.\n```").unwrap();
+        assert_eq!(result, "Paris is the capital.");
+
+        let result = parse_text("```text\nSure! Paris.\n```").unwrap();
+        assert_eq!(result, "Paris.");
+    }
+
+    #[test]
+    fn partial_fence_is_kept() {
+        let input = "Run this:\n```\nls -la\n```";
+        assert_eq!(parse_text(input).unwrap(), input);
+
+        let input = "```\na\n```\nand\n```\nb\n```";
+        assert_eq!(parse_text(input).unwrap(), input);
+    }
+
     #[test]
     fn empty_after_strip() {
         let result = parse_text("<think>just thinking</think>");
@@ -38,6 +38,17 @@ pub enum OutputStrategy {
     /// with repair. Can fail, producing a parse error in diagnostics.
     Json,
 
+    /// Parses like [`Json`](Self::Json), then keeps only the sub-value at
+    /// the given JSON Pointer (RFC 6901, e.g. `/result/items`). Fails if
+    /// nothing exists at the pointer.
+    JsonPointer(String),
+
+    /// Uses `output_parser::parse_yaml` — YAML (bare or fenced), converted
+    /// to JSON. Falls back to a lossy parse on failure, like
+    /// [`Json`](Self::Json). Requires the `yaml` feature.
+    #[cfg(feature = "yaml")]
+    Yaml,
+
     /// Uses `output_parser::parse_string_list_raw` — extracts a list of strings.
     /// The returned Value is a `Value::Array` of `Value::String`.
     StringList,
@@ -51,6 +62,12 @@ pub enum OutputStrategy {
     /// Critical for agent-graph routing nodes.
     Choice(Vec<String>),
 
+    /// Uses `output_parser::parse_choice_strict` — the whole response
+    /// (trimmed) must equal one of the options, case-sensitively.
+    /// Returns `Value::String` containing the matched choice. For
+    /// enum-like codes where casing matters.
+    ChoiceStrict(Vec<String>),
+
     /// Uses `output_parser::parse_number` — extracts a numeric value.
     /// Returns `Value::Number`. Handles "Score: 8.5", "8/10", prose.
     Number,
@@ -59,10 +76,44 @@ pub enum OutputStrategy {
     /// Returns `Value::Number`. Fails if outside `[min, max]`.
     NumberInRange(f64, f64),
 
+    /// Uses `output_parser::parse_bool` — extracts a yes/no answer.
+    /// Returns `Value::Bool`. Handles "yes", "false", "y", "1", and prose
+    /// like "The answer is yes". Fails on ambiguous or missing answers.
+    Boolean,
+
     /// Uses `output_parser::parse_text` — clean text with boilerplate stripping.
     /// Returns `Value::String` with "Sure!", "Here's..." prefixes removed.
     Text,
 
+    /// Uses `output_parser::parse_code_block` — extracts the content of a
+    /// `` ```{lang} `` fence, falling back to the first bare fence.
+    /// Returns `Value::String` with just the code.
+    CodeBlock(String),
+
+    /// Uses `output_parser::parse_csv` — a comma-, tab-, semicolon-, or
+    /// pipe-separated table. Returns a `Value::Array` of objects keyed by
+    /// the header row (or of strings for a single row).
+    Csv,
+
+    /// Uses `output_parser::parse_markdown_table` — the first `| a | b |`
+    /// table. Returns a `Value::Array` of objects with string values,
+    /// keyed by the header row.
+    MarkdownTable,
+
+    /// Uses `output_parser::parse_key_value` — `Key: value` or
+    /// `key = value` lines. Returns a `Value::Object` of strings. Fails
+    /// if no pair is found.
+    KeyValue,
+
+    /// Uses `output_parser::parse_regex` — the first match of the pattern,
+    /// as capture group 1 or the whole match if there is no group.
+    /// Returns `Value::String`. Inline flags such as `(?s)`, `(?m)`, and
+    /// `(?i)` are supported. Fails on no match. An invalid pattern fails
+    /// the call with `PipelineError::InvalidConfig` before any request is
+    /// sent. Requires the `regex` feature.
+    #[cfg(feature = "regex")]
+    Regex(String),
+
     /// Caller-provided parse function. Maximum flexibility.
     Custom(CustomParseFn),
 }
@@ -79,14 +130,25 @@ impl std::fmt::Debug for OutputStrategy {
         match self {
             OutputStrategy::Lossy => write!(f, "Lossy"),
             OutputStrategy::Json => write!(f, "Json"),
+            OutputStrategy::JsonPointer(pointer) => write!(f, "JsonPointer({:?})", pointer),
+            #[cfg(feature = "yaml")]
+            OutputStrategy::Yaml => write!(f, "Yaml"),
             OutputStrategy::StringList => write!(f, "StringList"),
             OutputStrategy::XmlTag(tag) => write!(f, "XmlTag({:?})", tag),
             OutputStrategy::Choice(choices) => write!(f, "Choice({:?})", choices),
+            OutputStrategy::ChoiceStrict(choices) => write!(f, "ChoiceStrict({:?})", choices),
             OutputStrategy::Number => write!(f, "Number"),
             OutputStrategy::NumberInRange(min, max) => {
                 write!(f, "NumberInRange({}, {})", min, max)
             }
+            OutputStrategy::Boolean => write!(f, "Boolean"),
             OutputStrategy::Text => write!(f, "Text"),
+            OutputStrategy::CodeBlock(lang) => write!(f, "CodeBlock({:?})", lang),
+            OutputStrategy::Csv => write!(f, "Csv"),
+            OutputStrategy::MarkdownTable => write!(f, "MarkdownTable"),
+            OutputStrategy::KeyValue => write!(f, "KeyValue"),
+            #[cfg(feature = "regex")]
+            OutputStrategy::Regex(pattern) => write!(f, "Regex({:?})", pattern),
             OutputStrategy::Custom(_) => write!(f, "Custom(...)"),
         }
     }
@@ -109,5 +171,13 @@ mod tests {
             format!("{:?}", OutputStrategy::Choice(vec!["a".into(), "b".into()])),
             "Choice([\"a\", \"b\"])"
         );
+        assert_eq!(
+            format!("{:?}", OutputStrategy::ChoiceStrict(vec!["A".into()])),
+            "ChoiceStrict([\"A\"])"
+        );
+        assert_eq!(
+            format!("{:?}", OutputStrategy::JsonPointer("/result".into())),
+            "JsonPointer(\"/result\")"
+        );
     }
 }
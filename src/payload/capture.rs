@@ -0,0 +1,118 @@
+//! Record input/output pairs from an inner payload.
+//!
+//! [`CapturePayload`] is a transparent decorator: it invokes the wrapped
+//! payload and hands each successful `(input, output)` pair to a sink, e.g.
+//! to append a line to a JSONL fine-tuning dataset. The output is returned
+//! unchanged.
+
+use crate::{
+    error::Result,
+    exec_ctx::ExecCtx,
+    payload::{BoxFut, Payload, PayloadOutput},
+};
+use serde_json::Value;
+use std::sync::Arc;
+
+/// Callback receiving each captured `(input, output)` pair.
+pub type CaptureSink = Arc<dyn Fn(&Value, &PayloadOutput) + Send + Sync>;
+
+/// Passes every invocation through to an inner payload, reporting successful
+/// ones to a [`CaptureSink`].
+///
+/// The sink is called only when the inner payload returns `Ok` and its
+/// output parsed cleanly (no `parse_error` in its diagnostics), so failed
+/// generations never end up in the dataset. It runs inline before the output
+/// is returned; keep it cheap or hand the pair off to a channel.
+///
+/// `kind` and `name` are the inner payload's, so events and metrics look the
+/// same with or without the wrapper.
+///
+/// # Example
+///
+/// ```ignore
+/// use llm_pipeline::payload::CapturePayload;
+/// use llm_pipeline::LlmCall;
+/// use std::io::Write;
+/// use std::sync::{Arc, Mutex};
+///
+/// let file = Arc::new(Mutex::new(std::fs::File::create("dataset.jsonl")?));
+/// let capture = CapturePayload::new(
+///     Box::new(LlmCall::new("summarize", "Summarize: {input}")),
+///     Arc::new(move |input, output| {
+///         let line = serde_json::json!({ "input": input, "output": output.value });
+///         writeln!(file.lock().unwrap(), "{}", line).ok();
+///     }),
+/// );
+/// ```
+pub struct CapturePayload {
+    inner: Box<dyn Payload>,
+    sink: CaptureSink,
+}
+
+impl CapturePayload {
+    /// Wrap `inner`, sending successful pairs to `sink`.
+    pub fn new(inner: Box<dyn Payload>, sink: CaptureSink) -> Self {
+        Self { inner, sink }
+    }
+}
+
+impl Payload for CapturePayload {
+    fn kind(&self) -> &'static str {
+        self.inner.kind()
+    }
+
+    fn name(&self) -> &str {
+        self.inner.name()
+    }
+
+    fn invoke<'a>(&'a self, ctx: &'a ExecCtx, input: Value) -> BoxFut<'a, Result<PayloadOutput>> {
+        Box::pin(async move {
+            let output = self.inner.invoke(ctx, input.clone()).await?;
+            if output.diagnostics.as_ref().is_none_or(|d| d.ok()) {
+                (self.sink)(&input, &output);
+            }
+            Ok(output)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{LlmCall, MockBackend};
+    use serde_json::json;
+    use std::sync::Mutex;
+
+    #[tokio::test]
+    async fn test_capture_records_successful_pairs() {
+        let ctx = ExecCtx::builder("http://test")
+            .backend(Arc::new(MockBackend::new(vec![
+                r#"{"label": "spam"}"#.to_string(),
+                "not json".to_string(),
+            ])))
+            .build();
+
+        let captured = Arc::new(Mutex::new(Vec::new()));
+        let sink = captured.clone();
+        let capture = CapturePayload::new(
+            Box::new(LlmCall::new("classify", "{input}").expecting_json()),
+            Arc::new(move |input: &Value, output: &PayloadOutput| {
+                sink.lock()
+                    .unwrap()
+                    .push((input.clone(), output.value.clone()));
+            }),
+        );
+        assert_eq!(capture.name(), "classify");
+        assert_eq!(capture.kind(), "llm-call");
+
+        let out = capture.invoke(&ctx, json!("buy now")).await.unwrap();
+        assert_eq!(out.value, json!({"label": "spam"}));
+        capture.invoke(&ctx, json!("hello")).await.unwrap();
+
+        let captured = captured.lock().unwrap();
+        assert_eq!(
+            *captured,
+            vec![(json!("buy now"), json!({"label": "spam"}))]
+        );
+    }
+}
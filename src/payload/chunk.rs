@@ -0,0 +1,303 @@
+//! Split oversized text into overlapping chunks.
+//!
+//! [`ChunkPayload`] turns one long string into a `Value::Array` of smaller
+//! strings, ready to feed into a [`MapPayload`](super::MapPayload). Cuts
+//! prefer paragraph breaks, then sentence ends, then whitespace, and only
+//! fall back to a hard cut when a single word is longer than the chunk size.
+
+use crate::{
+    error::Result,
+    exec_ctx::ExecCtx,
+    payload::{BoxFut, Payload, PayloadOutput},
+};
+use serde_json::{json, Value};
+
+/// Rough characters-per-token ratio used by [`ChunkPayload::with_max_tokens`].
+pub const CHARS_PER_TOKEN: usize = 4;
+
+/// Splits a string input into overlapping chunks.
+///
+/// Non-string inputs are serialized to JSON text first, matching how
+/// [`LlmCall`](crate::LlmCall) renders `{input}`.
+///
+/// # Example
+///
+/// ```
+/// use llm_pipeline::payload::ChunkPayload;
+///
+/// let chunker = ChunkPayload::new("chunk", 2000).with_overlap(200);
+/// assert_eq!(chunker.max_chars(), 2000);
+/// ```
+#[derive(Debug, Clone)]
+pub struct ChunkPayload {
+    name: String,
+    max_chars: usize,
+    overlap: usize,
+}
+
+impl ChunkPayload {
+    /// Create a chunker producing chunks of at most `max_chars` characters.
+    pub fn new(name: impl Into<String>, max_chars: usize) -> Self {
+        Self {
+            name: name.into(),
+            max_chars: max_chars.max(1),
+            overlap: 0,
+        }
+    }
+
+    /// Size chunks by an estimated token budget instead of characters
+    /// (using [`CHARS_PER_TOKEN`]).
+    pub fn with_max_tokens(mut self, tokens: usize) -> Self {
+        self.max_chars = tokens.saturating_mul(CHARS_PER_TOKEN).max(1);
+        self
+    }
+
+    /// Number of characters repeated at the start of each chunk from the
+    /// end of the previous one. Default: 0.
+    pub fn with_overlap(mut self, chars: usize) -> Self {
+        self.overlap = chars;
+        self
+    }
+
+    /// Returns the maximum chunk size in characters.
+    pub fn max_chars(&self) -> usize {
+        self.max_chars
+    }
+
+    /// Returns the overlap in characters.
+    pub fn overlap(&self) -> usize {
+        self.overlap
+    }
+}
+
+impl Payload for ChunkPayload {
+    fn kind(&self) -> &'static str {
+        "chunk"
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn invoke<'a>(&'a self, ctx: &'a ExecCtx, input: Value) -> BoxFut<'a, Result<PayloadOutput>> {
+        Box::pin(async move {
+            ctx.check_cancelled()?;
+            let text = match input {
+                Value::String(s) => s,
+                other => other.to_string(),
+            };
+            let chunks = chunk_text(&text, self.max_chars, self.overlap);
+            let count = chunks.len();
+            let value = Value::Array(chunks.into_iter().map(Value::String).collect());
+            Ok(PayloadOutput::from_value(value).with_meta("chunks", json!(count)))
+        })
+    }
+}
+
+/// Split `text` into chunks of at most `max_chars` characters, each
+/// overlapping the previous one by up to `overlap` characters.
+///
+/// Break preference: paragraph (`\n\n`), sentence end (`.`/`!`/`?` followed
+/// by whitespace, or a newline), whitespace, then a hard cut. A preferred
+/// break is only used if it falls in the second half of the window, so
+/// chunks never shrink to slivers. Overlap starts on a word boundary.
+///
+/// # Example
+///
+/// ```
+/// use llm_pipeline::payload::chunk::chunk_text;
+///
+/// let chunks = chunk_text("One. Two. Three.", 10, 0);
+/// assert_eq!(chunks, vec!["One. Two.", "Three."]);
+/// ```
+pub fn chunk_text(text: &str, max_chars: usize, overlap: usize) -> Vec<String> {
+    let text = text.trim();
+    let max_chars = max_chars.max(1);
+    let mut chunks = Vec::new();
+    if text.is_empty() {
+        return chunks;
+    }
+
+    let mut start = 0;
+    loop {
+        let rest = &text[start..];
+        let window_end = match rest.char_indices().nth(max_chars) {
+            Some((offset, _)) => start + offset,
+            None => {
+                chunks.push(rest.trim().to_string());
+                break;
+            }
+        };
+
+        let end = start + find_break(&text[start..window_end]);
+        let chunk = text[start..end].trim();
+        if !chunk.is_empty() {
+            chunks.push(chunk.to_string());
+        }
+
+        let next = overlap_start(text, start, end, overlap);
+        start = skip_whitespace(text, next);
+        if start >= text.len() {
+            break;
+        }
+    }
+
+    chunks
+}
+
+/// Byte offset (within `window`) at which to end the chunk.
+fn find_break(window: &str) -> usize {
+    let min = window.len() / 2;
+
+    if let Some(pos) = window.rfind("\n\n") {
+        if pos >= min {
+            return pos + 2;
+        }
+    }
+
+    let mut sentence_end = None;
+    let mut prev: Option<char> = None;
+    for (i, ch) in window.char_indices() {
+        if ch == '\n' || (ch.is_whitespace() && matches!(prev, Some('.' | '!' | '?'))) {
+            sentence_end = Some(i);
+        }
+        prev = Some(ch);
+    }
+    if let Some(pos) = sentence_end.filter(|&p| p >= min) {
+        return pos;
+    }
+
+    if let Some(pos) = window.rfind(char::is_whitespace) {
+        if pos > 0 {
+            return pos;
+        }
+    }
+
+    window.len()
+}
+
+/// Where the next chunk should begin, given the current chunk `[start, end)`.
+fn overlap_start(text: &str, start: usize, end: usize, overlap: usize) -> usize {
+    if overlap == 0 {
+        return end;
+    }
+    let back = text[start..end]
+        .char_indices()
+        .rev()
+        .nth(overlap.saturating_sub(1))
+        .map(|(i, _)| start + i)
+        .unwrap_or(start);
+
+    // Move forward to the next word boundary so the overlap never starts mid-word.
+    let candidate = if back == 0 || text[..back].ends_with(char::is_whitespace) {
+        back
+    } else {
+        match text[back..end].find(char::is_whitespace) {
+            Some(offset) => back + offset,
+            None => end,
+        }
+    };
+
+    if candidate <= start {
+        end
+    } else {
+        candidate
+    }
+}
+
+fn skip_whitespace(text: &str, from: usize) -> usize {
+    text[from..]
+        .find(|c: char| !c.is_whitespace())
+        .map(|offset| from + offset)
+        .unwrap_or(text.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_short_text_single_chunk() {
+        assert_eq!(chunk_text("hello world", 100, 0), vec!["hello world"]);
+    }
+
+    #[test]
+    fn test_empty_text_no_chunks() {
+        assert!(chunk_text("   ", 100, 0).is_empty());
+    }
+
+    #[test]
+    fn test_prefers_paragraph_breaks() {
+        let text = "First paragraph here.\n\nSecond paragraph here.";
+        let chunks = chunk_text(text, 30, 0);
+        assert_eq!(
+            chunks,
+            vec!["First paragraph here.", "Second paragraph here."]
+        );
+    }
+
+    #[test]
+    fn test_prefers_sentence_ends() {
+        let chunks = chunk_text("Alpha beta gamma. Delta epsilon zeta.", 25, 0);
+        assert_eq!(chunks, vec!["Alpha beta gamma.", "Delta epsilon zeta."]);
+    }
+
+    #[test]
+    fn test_never_splits_mid_word() {
+        let text = "lorem ipsum dolor sit amet consectetur adipiscing elit";
+        for chunk in chunk_text(text, 12, 0) {
+            assert!(chunk.chars().count() <= 12);
+            for word in chunk.split_whitespace() {
+                assert!(text.split_whitespace().any(|w| w == word), "{}", word);
+            }
+        }
+    }
+
+    #[test]
+    fn test_hard_cut_for_long_word() {
+        let chunks = chunk_text("abcdefghij", 4, 0);
+        assert_eq!(chunks, vec!["abcd", "efgh", "ij"]);
+    }
+
+    #[test]
+    fn test_overlap_repeats_tail_words() {
+        let text = "one two three four five six seven eight";
+        let chunks = chunk_text(text, 15, 6);
+        assert!(chunks.len() > 1);
+        for pair in chunks.windows(2) {
+            let last_word = pair[0].split_whitespace().last().unwrap();
+            assert!(pair[1].starts_with(last_word), "{:?}", pair);
+        }
+    }
+
+    #[test]
+    fn test_multibyte_safe() {
+        let text = "日本語のテキスト。これは二番目の文です。";
+        let chunks = chunk_text(text, 5, 1);
+        assert!(!chunks.is_empty());
+        assert!(chunks.iter().all(|c| c.chars().count() <= 5));
+    }
+
+    #[tokio::test]
+    async fn test_chunk_payload_emits_array() {
+        let ctx = ExecCtx::builder("http://test").build();
+        let chunker = ChunkPayload::new("chunk", 25);
+        let out = chunker
+            .invoke(&ctx, json!("Alpha beta gamma. Delta epsilon zeta."))
+            .await
+            .unwrap();
+        assert_eq!(
+            out.value,
+            json!(["Alpha beta gamma.", "Delta epsilon zeta."])
+        );
+        assert_eq!(out.meta["chunks"], 2);
+    }
+
+    #[test]
+    fn test_with_max_tokens() {
+        let chunker = ChunkPayload::new("chunk", 10).with_max_tokens(100);
+        assert_eq!(chunker.max_chars(), 100 * CHARS_PER_TOKEN);
+        let unbounded = ChunkPayload::new("chunk", 10).with_max_tokens(usize::MAX);
+        assert_eq!(unbounded.max_chars(), usize::MAX);
+    }
+}
@@ -0,0 +1,275 @@
+//! Run one prompt against several models side by side.
+//!
+//! [`CompareModels`] fans the same prompt out to a list of models, one
+//! [`LlmCall`] per model, and collects the parsed outputs into an object
+//! keyed by model name. It is meant for evaluation: picking which model to
+//! use for a given node.
+
+use crate::{
+    diagnostics::ParseDiagnostics,
+    error::Result,
+    exec_ctx::ExecCtx,
+    llm_call::LlmCall,
+    payload::{BoxFut, Payload, PayloadOutput},
+    PipelineError,
+};
+use futures::StreamExt;
+use serde_json::{json, Map, Value};
+
+/// Invokes the same prompt once per model and returns the results keyed by
+/// model name.
+///
+/// Every model goes through the context's backend, base URL, and auth, so
+/// the models must all be served by the same provider. The calls run
+/// concurrently, up to [`with_concurrency`](Self::with_concurrency) at once.
+///
+/// A model whose call fails does not abort the comparison: its value becomes
+/// `{"error": "..."}`. Only if every model fails is the first error
+/// returned. Cancellation is always propagated.
+///
+/// The result's `meta` records:
+/// - `models` — the model names, in the order given
+/// - `diagnostics` — per model: `ok`, `strategy`, `parse_error`,
+///   `retry_attempts`, `prompt_tokens`, `completion_tokens`, or `error` if
+///   the call failed
+///
+/// Use [`execute_all`](Self::execute_all) for the full per-model
+/// [`PayloadOutput`]s.
+///
+/// # Example
+///
+/// ```ignore
+/// use llm_pipeline::payload::CompareModels;
+///
+/// let compare = CompareModels::new(
+///     "Extract the people mentioned in: {input}",
+///     vec!["llama3.2:3b".into(), "qwen2.5:7b".into(), "mistral:7b".into()],
+/// )
+/// .with_call(|call| call.expecting_json())
+/// .with_concurrency(2);
+///
+/// let output = compare.invoke(&ctx, json!(article)).await?;
+/// for (model, value) in output.value.as_object().unwrap() {
+///     println!("{model}: {value}");
+/// }
+/// ```
+pub struct CompareModels {
+    name: String,
+    models: Vec<String>,
+    calls: Vec<LlmCall>,
+    concurrency: usize,
+}
+
+impl CompareModels {
+    /// Compare `models` on `prompt`, a template with the same placeholders
+    /// as [`LlmCall::new`]. Queries every model at once by default.
+    pub fn new(prompt: impl Into<String>, models: Vec<String>) -> Self {
+        let prompt = prompt.into();
+        let calls = models
+            .iter()
+            .map(|model| LlmCall::new(model.clone(), prompt.clone()).with_model(model.clone()))
+            .collect();
+        Self {
+            name: "compare-models".to_string(),
+            concurrency: models.len().max(1),
+            models,
+            calls,
+        }
+    }
+
+    /// Set the instance name. Default: `"compare-models"`.
+    pub fn with_name(mut self, name: impl Into<String>) -> Self {
+        self.name = name.into();
+        self
+    }
+
+    /// Configure every per-model call, e.g. to set an output strategy,
+    /// system prompt, or retry policy. The model itself is already set.
+    pub fn with_call(mut self, configure: impl Fn(LlmCall) -> LlmCall) -> Self {
+        self.calls = self.calls.into_iter().map(configure).collect();
+        self
+    }
+
+    /// Maximum number of models queried at once, e.g. to stay under a
+    /// provider's rate limit. Default: the number of models. Clamped to at
+    /// least 1.
+    pub fn with_concurrency(mut self, limit: usize) -> Self {
+        self.concurrency = limit.max(1);
+        self
+    }
+
+    /// Returns the models being compared.
+    pub fn models(&self) -> &[String] {
+        &self.models
+    }
+
+    /// Returns the concurrency limit.
+    pub fn concurrency(&self) -> usize {
+        self.concurrency
+    }
+
+    /// Run the prompt against every model, returning `(model, result)`
+    /// pairs in the order the models were given.
+    pub async fn execute_all(
+        &self,
+        ctx: &ExecCtx,
+        input: Value,
+    ) -> Result<Vec<(String, Result<PayloadOutput>)>> {
+        let mut slots: Vec<Option<Result<PayloadOutput>>> =
+            (0..self.calls.len()).map(|_| None).collect();
+
+        let mut results = futures::stream::iter(0..self.calls.len())
+            .map(|index| {
+                let input = input.clone();
+                async move {
+                    let result = match ctx.check_cancelled() {
+                        Ok(()) => self.calls[index].invoke(ctx, input).await,
+                        Err(e) => Err(e),
+                    };
+                    (index, result)
+                }
+            })
+            .buffer_unordered(self.concurrency);
+
+        while let Some((index, result)) = results.next().await {
+            if let Err(PipelineError::Cancelled) = result {
+                return Err(PipelineError::Cancelled);
+            }
+            slots[index] = Some(result);
+        }
+
+        Ok(self
+            .models
+            .iter()
+            .cloned()
+            .zip(slots.into_iter().flatten())
+            .collect())
+    }
+}
+
+/// Summarize one model's diagnostics for `meta["diagnostics"]`.
+fn diagnostics_json(diag: Option<&ParseDiagnostics>) -> Value {
+    let diag = diag.cloned().unwrap_or_default();
+    json!({
+        "ok": diag.ok(),
+        "strategy": diag.strategy,
+        "parse_error": diag.parse_error,
+        "retry_attempts": diag.retry_attempts,
+        "prompt_tokens": diag.prompt_tokens,
+        "completion_tokens": diag.completion_tokens,
+    })
+}
+
+impl Payload for CompareModels {
+    fn kind(&self) -> &'static str {
+        "compare-models"
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn invoke<'a>(&'a self, ctx: &'a ExecCtx, input: Value) -> BoxFut<'a, Result<PayloadOutput>> {
+        Box::pin(async move {
+            let results = self.execute_all(ctx, input).await?;
+
+            let mut values = Map::new();
+            let mut diagnostics = Map::new();
+            let mut first_error = None;
+            let mut succeeded = 0;
+            for (model, result) in results {
+                match result {
+                    Ok(output) => {
+                        succeeded += 1;
+                        diagnostics
+                            .insert(model.clone(), diagnostics_json(output.diagnostics.as_ref()));
+                        values.insert(model, output.value);
+                    }
+                    Err(e) => {
+                        let message = e.to_string();
+                        diagnostics.insert(model.clone(), json!({ "ok": false, "error": message }));
+                        values.insert(model, json!({ "error": message }));
+                        first_error.get_or_insert(e);
+                    }
+                }
+            }
+
+            match first_error {
+                Some(e) if succeeded == 0 => Err(e),
+                _ => Ok(PayloadOutput::from_value(Value::Object(values))
+                    .with_meta("models", json!(self.models))
+                    .with_meta("diagnostics", Value::Object(diagnostics))),
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::{MockBackend, MockReply};
+    use std::sync::Arc;
+    use std::time::{Duration, Instant};
+
+    /// Answers with the requested model's name; fails for `"missing"`.
+    fn test_ctx() -> ExecCtx {
+        let backend = MockBackend::from_fn(|request| {
+            if request.model == "missing" {
+                return Err(PipelineError::Other(format!(
+                    "model not found: {}",
+                    request.model
+                )));
+            }
+            Ok(MockReply::text(
+                json!({ "answer": request.model }).to_string(),
+            ))
+        });
+        ExecCtx::builder("http://test")
+            .backend(Arc::new(backend))
+            .build()
+    }
+
+    #[tokio::test]
+    async fn test_compare_models_keyed_by_model() {
+        let compare = CompareModels::new("{input}", vec!["a".into(), "b".into(), "missing".into()])
+            .with_call(|call| call.expecting_json())
+            .with_concurrency(3);
+
+        let out = compare.invoke(&test_ctx(), json!("q")).await.unwrap();
+        assert_eq!(out.value["a"], json!({"answer": "a"}));
+        assert_eq!(out.value["b"], json!({"answer": "b"}));
+        assert!(out.value["missing"]["error"].is_string());
+        assert_eq!(out.meta["models"], json!(["a", "b", "missing"]));
+        assert_eq!(out.meta["diagnostics"]["a"]["ok"], true);
+        assert_eq!(out.meta["diagnostics"]["a"]["strategy"], "json");
+        assert_eq!(out.meta["diagnostics"]["missing"]["ok"], false);
+
+        let all = compare.execute_all(&test_ctx(), json!("q")).await.unwrap();
+        let models: Vec<&str> = all.iter().map(|(m, _)| m.as_str()).collect();
+        assert_eq!(models, vec!["a", "b", "missing"]);
+        assert_eq!(all[1].1.as_ref().unwrap().model.as_deref(), Some("b"));
+    }
+
+    #[tokio::test]
+    async fn test_compare_models_all_failed() {
+        let compare = CompareModels::new("{input}", vec!["missing".into()]);
+        let result = compare.invoke(&test_ctx(), json!("q")).await;
+        assert!(matches!(result, Err(PipelineError::Other(_))));
+    }
+
+    #[tokio::test]
+    async fn test_compare_models_concurrent_by_default() {
+        let compare = CompareModels::new("{input}", vec!["a".into(), "b".into(), "c".into()]);
+        assert_eq!(compare.concurrency(), 3);
+
+        let backend = MockBackend::from_fn(|request| {
+            Ok(MockReply::text(request.model.clone()).with_delay(Duration::from_millis(100)))
+        });
+        let ctx = ExecCtx::builder("http://test")
+            .backend(Arc::new(backend))
+            .build();
+        let started = Instant::now();
+        compare.invoke(&ctx, json!("q")).await.unwrap();
+        assert!(started.elapsed() < Duration::from_millis(250));
+    }
+}
@@ -0,0 +1,110 @@
+//! Payloads defined by a closure.
+//!
+//! [`from_fn`] turns an async closure into a payload, like
+//! `tower::service_fn`, so a small custom step can go into a
+//! [`Chain`](crate::Chain) without a struct and a [`Payload`] impl.
+
+use crate::{
+    error::Result,
+    exec_ctx::ExecCtx,
+    payload::{BoxFut, Payload, PayloadOutput},
+};
+use serde_json::Value;
+
+/// A payload that runs a closure. Create with [`from_fn`].
+pub struct FnPayload<F> {
+    name: String,
+    kind: &'static str,
+    f: F,
+}
+
+impl<F> FnPayload<F>
+where
+    F: for<'a> Fn(&'a ExecCtx, Value) -> BoxFut<'a, Result<PayloadOutput>> + Send + Sync,
+{
+    /// Wrap `f` as a payload with the given `name` and `kind`.
+    pub fn new(name: impl Into<String>, kind: &'static str, f: F) -> Self {
+        Self {
+            name: name.into(),
+            kind,
+            f,
+        }
+    }
+}
+
+impl<F> Payload for FnPayload<F>
+where
+    F: for<'a> Fn(&'a ExecCtx, Value) -> BoxFut<'a, Result<PayloadOutput>> + Send + Sync,
+{
+    fn kind(&self) -> &'static str {
+        self.kind
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn invoke<'a>(&'a self, ctx: &'a ExecCtx, input: Value) -> BoxFut<'a, Result<PayloadOutput>> {
+        (self.f)(ctx, input)
+    }
+}
+
+/// Box an async closure as a payload.
+///
+/// The closure receives the context and input and returns a [`BoxFut`],
+/// usually `Box::pin(async move { ... })`.
+///
+/// ```
+/// use llm_pipeline::payload::{from_fn, PayloadOutput};
+/// use llm_pipeline::{Chain, ExecCtx};
+/// use serde_json::json;
+///
+/// # tokio_test::block_on(async {
+/// let upper = from_fn("upper", "transform", |_ctx, input| {
+///     Box::pin(async move {
+///         let text = input.as_str().unwrap_or_default().to_uppercase();
+///         Ok(PayloadOutput::from_value(json!(text)))
+///     })
+/// });
+/// let chain = Chain::new("shout").push(upper);
+/// let ctx = ExecCtx::builder("http://localhost:11434").build();
+/// let output = chain.execute(&ctx, json!("hi")).await.unwrap();
+/// assert_eq!(output.value, "HI");
+/// # });
+/// ```
+pub fn from_fn<F>(name: impl Into<String>, kind: &'static str, f: F) -> Box<dyn Payload>
+where
+    F: for<'a> Fn(&'a ExecCtx, Value) -> BoxFut<'a, Result<PayloadOutput>> + Send + Sync + 'static,
+{
+    Box::new(FnPayload::new(name, kind, f))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::PipelineError;
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn test_from_fn_borrows_ctx() {
+        let greet = from_fn("greet", "custom", |ctx, input| {
+            Box::pin(async move {
+                ctx.check_cancelled()?;
+                let name = input
+                    .as_str()
+                    .ok_or_else(|| PipelineError::Other("expected a name".to_string()))?;
+                let greeting = format!("{}, {}", ctx.vars["salutation"], name);
+                Ok(PayloadOutput::from_value(json!(greeting)))
+            })
+        });
+        assert_eq!(greet.name(), "greet");
+        assert_eq!(greet.kind(), "custom");
+
+        let ctx = ExecCtx::builder("http://test")
+            .var("salutation", "Hello")
+            .build();
+        let out = greet.invoke(&ctx, json!("Ada")).await.unwrap();
+        assert_eq!(out.value, "Hello, Ada");
+        assert!(greet.invoke(&ctx, json!(1)).await.is_err());
+    }
+}
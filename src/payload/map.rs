@@ -0,0 +1,291 @@
+//! Apply an inner payload to every element of an array input.
+//!
+//! [`MapPayload`] is the fan-out primitive: it takes a `Value::Array`,
+//! invokes the wrapped payload once per element, and collects the results
+//! back into an array. Elements may run concurrently (see
+//! [`MapPayload::with_concurrency`]), but the output order always matches
+//! the input order.
+
+use crate::{
+    error::Result,
+    exec_ctx::ExecCtx,
+    payload::{BoxFut, Payload, PayloadOutput},
+    PipelineError,
+};
+use futures::StreamExt;
+use serde_json::{json, Value};
+
+/// How [`MapPayload`] reacts when the inner payload fails for an element.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MapErrorMode {
+    /// Abort the whole map and return the first error. Default.
+    #[default]
+    FailFast,
+
+    /// Keep going. A failed element is replaced, at its own index, by
+    /// `{"error": {"index": i, "message": "..."}}`.
+    Collect,
+}
+
+/// Applies an inner payload to each element of an array input.
+///
+/// Ordering guarantee: the collected array (and the `Vec` returned by
+/// [`execute_all`](Self::execute_all)) is always in input order, regardless
+/// of the order in which elements complete. Every per-element
+/// [`PayloadOutput`] carries its source position in `meta["index"]`.
+///
+/// Cancellation is always propagated, even in [`MapErrorMode::Collect`].
+///
+/// # Example
+///
+/// ```ignore
+/// use llm_pipeline::payload::MapPayload;
+/// use llm_pipeline::LlmCall;
+/// use serde_json::json;
+///
+/// let map = MapPayload::new("summarize-each", Box::new(
+///     LlmCall::new("summarize", "Summarize: {input}"),
+/// ))
+/// .with_concurrency(4)
+/// .collect_errors();
+///
+/// let output = map.invoke(&ctx, json!(["chunk one", "chunk two"])).await?;
+/// assert_eq!(output.value.as_array().unwrap().len(), 2);
+/// ```
+pub struct MapPayload {
+    name: String,
+    inner: Box<dyn Payload>,
+    concurrency: usize,
+    error_mode: MapErrorMode,
+}
+
+impl MapPayload {
+    /// Create a map over `inner`. Runs sequentially and fails fast by default.
+    pub fn new(name: impl Into<String>, inner: Box<dyn Payload>) -> Self {
+        Self {
+            name: name.into(),
+            inner,
+            concurrency: 1,
+            error_mode: MapErrorMode::default(),
+        }
+    }
+
+    /// Maximum number of elements in flight at once. Clamped to at least 1.
+    pub fn with_concurrency(mut self, limit: usize) -> Self {
+        self.concurrency = limit.max(1);
+        self
+    }
+
+    /// Set the error mode.
+    pub fn with_error_mode(mut self, mode: MapErrorMode) -> Self {
+        self.error_mode = mode;
+        self
+    }
+
+    /// Shorthand for `with_error_mode(MapErrorMode::Collect)`.
+    pub fn collect_errors(self) -> Self {
+        self.with_error_mode(MapErrorMode::Collect)
+    }
+
+    /// Returns the concurrency limit.
+    pub fn concurrency(&self) -> usize {
+        self.concurrency
+    }
+
+    /// Returns the error mode.
+    pub fn error_mode(&self) -> MapErrorMode {
+        self.error_mode
+    }
+
+    /// Run the inner payload over every element, returning one output per
+    /// element in input order.
+    ///
+    /// Each output has `meta["index"]` set to its source position. In
+    /// [`MapErrorMode::Collect`], failed elements additionally have
+    /// `meta["error"] = true` and a structured error object as their value.
+    pub async fn execute_all(&self, ctx: &ExecCtx, input: Value) -> Result<Vec<PayloadOutput>> {
+        let items = match input {
+            Value::Array(items) => items,
+            other => {
+                return Err(PipelineError::StageFailed {
+                    stage: self.name.clone(),
+                    message: format!("MapPayload expects an array input, got: {}", other),
+                })
+            }
+        };
+
+        let mut slots: Vec<Option<PayloadOutput>> = vec![None; items.len()];
+
+        let mut results = futures::stream::iter(items.into_iter().enumerate())
+            .map(|(index, item)| async move {
+                let result = match ctx.check_cancelled() {
+                    Ok(()) => self.inner.invoke(ctx, item).await,
+                    Err(e) => Err(e),
+                };
+                (index, result)
+            })
+            .buffer_unordered(self.concurrency);
+
+        while let Some((index, result)) = results.next().await {
+            let output = match result {
+                Ok(output) => output,
+                Err(PipelineError::Cancelled) => return Err(PipelineError::Cancelled),
+                Err(e) => match self.error_mode {
+                    MapErrorMode::FailFast => return Err(e),
+                    MapErrorMode::Collect => PayloadOutput::from_value(json!({
+                        "error": { "index": index, "message": e.to_string() }
+                    }))
+                    .with_meta("error", Value::Bool(true)),
+                },
+            };
+            slots[index] = Some(output.with_meta("index", json!(index)));
+        }
+
+        Ok(slots.into_iter().flatten().collect())
+    }
+}
+
+impl Payload for MapPayload {
+    fn kind(&self) -> &'static str {
+        "map"
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn invoke<'a>(&'a self, ctx: &'a ExecCtx, input: Value) -> BoxFut<'a, Result<PayloadOutput>> {
+        Box::pin(async move {
+            let outputs = self.execute_all(ctx, input).await?;
+            let failed = outputs
+                .iter()
+                .filter(|o| o.meta.get("error") == Some(&Value::Bool(true)))
+                .count();
+            let count = outputs.len();
+            let values = outputs.into_iter().map(|o| o.value).collect();
+            Ok(PayloadOutput::from_value(Value::Array(values))
+                .with_meta("count", json!(count))
+                .with_meta("failed", json!(failed)))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicBool;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    /// Sleeps for `input` milliseconds, then echoes it. Fails on negative input.
+    struct SleepEcho;
+
+    impl Payload for SleepEcho {
+        fn kind(&self) -> &'static str {
+            "sleep-echo"
+        }
+        fn name(&self) -> &str {
+            "sleep-echo"
+        }
+        fn invoke<'a>(
+            &'a self,
+            _ctx: &'a ExecCtx,
+            input: Value,
+        ) -> BoxFut<'a, Result<PayloadOutput>> {
+            Box::pin(async move {
+                let ms = input.as_i64().unwrap_or(0);
+                if ms < 0 {
+                    return Err(PipelineError::Other(format!("negative: {}", ms)));
+                }
+                tokio::time::sleep(Duration::from_millis(ms as u64)).await;
+                Ok(PayloadOutput::from_value(input))
+            })
+        }
+    }
+
+    fn test_ctx() -> ExecCtx {
+        ExecCtx::builder("http://test").build()
+    }
+
+    #[tokio::test]
+    async fn test_map_sequential_preserves_order() {
+        let map = MapPayload::new("map", Box::new(SleepEcho));
+        let out = map.invoke(&test_ctx(), json!([3, 1, 2])).await.unwrap();
+        assert_eq!(out.value, json!([3, 1, 2]));
+        assert_eq!(out.meta["count"], 3);
+        assert_eq!(out.meta["failed"], 0);
+    }
+
+    #[tokio::test]
+    async fn test_map_concurrent_preserves_order_despite_completion_order() {
+        // Earlier elements sleep longer, so they complete last.
+        let map = MapPayload::new("map", Box::new(SleepEcho)).with_concurrency(4);
+        let outputs = map
+            .execute_all(&test_ctx(), json!([40, 30, 20, 10]))
+            .await
+            .unwrap();
+
+        let values: Vec<i64> = outputs.iter().map(|o| o.value.as_i64().unwrap()).collect();
+        assert_eq!(values, vec![40, 30, 20, 10]);
+        for (i, out) in outputs.iter().enumerate() {
+            assert_eq!(out.meta["index"], i);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_map_fail_fast() {
+        let map = MapPayload::new("map", Box::new(SleepEcho));
+        let result = map.invoke(&test_ctx(), json!([1, -1, 2])).await;
+        assert!(matches!(result, Err(PipelineError::Other(_))));
+    }
+
+    #[tokio::test]
+    async fn test_map_collect_errors_at_index() {
+        let map = MapPayload::new("map", Box::new(SleepEcho))
+            .with_concurrency(3)
+            .collect_errors();
+        let outputs = map
+            .execute_all(&test_ctx(), json!([20, -1, 5]))
+            .await
+            .unwrap();
+
+        assert_eq!(outputs.len(), 3);
+        assert_eq!(outputs[0].value, json!(20));
+        assert_eq!(outputs[1].value["error"]["index"], 1);
+        assert!(outputs[1].value["error"]["message"]
+            .as_str()
+            .unwrap()
+            .contains("negative"));
+        assert_eq!(outputs[1].meta["error"], true);
+        assert_eq!(outputs[1].meta["index"], 1);
+        assert_eq!(outputs[2].value, json!(5));
+
+        let out = map.invoke(&test_ctx(), json!([20, -1, 5])).await.unwrap();
+        assert_eq!(out.meta["failed"], 1);
+        assert_eq!(out.value[1]["error"]["index"], 1);
+    }
+
+    #[tokio::test]
+    async fn test_map_rejects_non_array() {
+        let map = MapPayload::new("map", Box::new(SleepEcho));
+        let result = map.invoke(&test_ctx(), json!("not an array")).await;
+        assert!(matches!(result, Err(PipelineError::StageFailed { .. })));
+    }
+
+    #[tokio::test]
+    async fn test_map_empty_array() {
+        let map = MapPayload::new("map", Box::new(SleepEcho));
+        let out = map.invoke(&test_ctx(), json!([])).await.unwrap();
+        assert_eq!(out.value, json!([]));
+    }
+
+    #[tokio::test]
+    async fn test_map_cancellation_not_collected() {
+        let ctx = ExecCtx::builder("http://test")
+            .cancellation(Some(Arc::new(AtomicBool::new(true))))
+            .build();
+        let map = MapPayload::new("map", Box::new(SleepEcho)).collect_errors();
+        let result = map.invoke(&ctx, json!([1, 2])).await;
+        assert!(matches!(result, Err(PipelineError::Cancelled)));
+    }
+}
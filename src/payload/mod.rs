@@ -0,0 +1,649 @@
+//! Core payload trait and output types.
+//!
+//! Payloads are the fundamental execution unit in this crate. Each payload
+//! takes a `serde_json::Value` input, does some work (typically an LLM call),
+//! and returns a [`PayloadOutput`]. The `Value`-based wire type allows
+//! heterogeneous workflows where each node produces a different shape.
+//!
+//! Besides the core trait, this module hosts composable payloads built on
+//! top of it:
+//!
+//! - [`MapPayload`] — apply an inner payload to every element of an array
+//! - [`ChunkPayload`] — split long text into overlapping chunks for a map
+//! - [`ReducePayload`] — fold an array into one result, pairwise or all at once
+//! - [`VotingPayload`] — sample an inner payload and return the consensus
+//! - [`CapturePayload`] — pass through to an inner payload, recording
+//!   successful input/output pairs
+//! - [`CompareModels`] — run one prompt against several models side by side
+//! - [`ReformatPayload`] — ask for malformed JSON output to be reformatted
+//!   instead of regenerated
+//! - [`from_fn()`] — wrap an async closure as a payload
+
+pub mod capture;
+pub mod chunk;
+pub mod compare;
+pub mod from_fn;
+pub mod map;
+pub mod reduce;
+pub mod reformat;
+pub mod voting;
+
+pub use capture::{CapturePayload, CaptureSink};
+pub use chunk::ChunkPayload;
+pub use compare::CompareModels;
+pub use from_fn::{from_fn, FnPayload};
+pub use map::{MapErrorMode, MapPayload};
+pub use reduce::{ReduceMode, ReducePayload};
+pub use reformat::ReformatPayload;
+pub use voting::{VoteSource, VotingPayload};
+
+use crate::backend::ToolCall;
+use crate::chain::ChainResult;
+use crate::diagnostics::ParseDiagnostics;
+use crate::error::Result;
+use crate::exec_ctx::ExecCtx;
+use crate::PipelineError;
+use futures::stream::BoxStream;
+use futures::StreamExt;
+use serde::de::DeserializeOwned;
+use serde_json::{json, Map, Value};
+use std::future::Future;
+use std::pin::Pin;
+use std::str::FromStr;
+
+/// A boxed, pinned, Send future -- the return type of [`Payload::invoke`].
+pub type BoxFut<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
+
+/// Object-safe trait for executable payloads.
+///
+/// Payloads are the building blocks for LLM workflows. They accept a
+/// `Value` input, execute against an [`ExecCtx`], and return a
+/// [`PayloadOutput`].
+///
+/// The trait is object-safe so payloads can be stored as `Box<dyn Payload>`
+/// and composed in chains or graphs without generic constraints.
+pub trait Payload: Send + Sync {
+    /// Stable identifier for the payload type (e.g. `"llm-call"`, `"chain"`).
+    fn kind(&self) -> &'static str;
+
+    /// Instance name (for logging/debugging/events).
+    fn name(&self) -> &str;
+
+    /// Execute the payload.
+    fn invoke<'a>(&'a self, ctx: &'a ExecCtx, input: Value) -> BoxFut<'a, Result<PayloadOutput>>;
+
+    /// Invoke the payload once per input, yielding `(index, result)` pairs
+    /// as each item completes.
+    ///
+    /// Up to `concurrency` items (clamped to at least 1) run at once, so
+    /// results arrive in completion order; `index` is the position in
+    /// `inputs`. Nothing is buffered beyond the in-flight items, which makes
+    /// this suitable for large batches. Pair with [`ndjson_line`] to write
+    /// results out as they land. Once `ctx` is cancelled, remaining items
+    /// yield [`PipelineError::Cancelled`] without running.
+    ///
+    /// ```ignore
+    /// let mut results = call.invoke_batch_stream(&ctx, inputs, 4);
+    /// while let Some((index, result)) = results.next().await {
+    ///     println!("{}", ndjson_line(index, &result));
+    /// }
+    /// ```
+    fn invoke_batch_stream<'a>(
+        &'a self,
+        ctx: &'a ExecCtx,
+        inputs: Vec<Value>,
+        concurrency: usize,
+    ) -> BoxStream<'a, (usize, Result<PayloadOutput>)> {
+        futures::stream::iter(inputs.into_iter().enumerate())
+            .map(move |(index, input)| async move {
+                let result = match ctx.check_cancelled() {
+                    Ok(()) => self.invoke(ctx, input).await,
+                    Err(e) => Err(e),
+                };
+                (index, result)
+            })
+            .buffer_unordered(concurrency.max(1))
+            .boxed()
+    }
+}
+
+/// Render one batch result as a single NDJSON line (no trailing newline).
+///
+/// Successes become `{"index": i, "value": ...}` and failures
+/// `{"index": i, "error": "..."}`.
+pub fn ndjson_line(index: usize, result: &Result<PayloadOutput>) -> String {
+    match result {
+        Ok(output) => json!({ "index": index, "value": output.value }),
+        Err(e) => json!({ "index": index, "error": e.to_string() }),
+    }
+    .to_string()
+}
+
+/// Output from a payload invocation.
+///
+/// Uses `serde_json::Value` as the wire type so heterogeneous workflows
+/// can pass data between nodes without sharing a single generic `T`.
+#[derive(Debug, Clone)]
+pub struct PayloadOutput {
+    /// Structured output (JSON value).
+    pub value: Value,
+    /// Raw response text from the LLM (before parsing).
+    pub raw_response: String,
+    /// Extracted thinking/reasoning (if extended thinking was enabled).
+    pub thinking: Option<String>,
+    /// Model that produced this output (if applicable).
+    pub model: Option<String>,
+    /// Parse diagnostics (strategy used, errors, retry info). Outputs built
+    /// with [`from_value`](Self::from_value) report the `"passthrough"`
+    /// strategy, meaning no LLM call or parsing was involved.
+    pub diagnostics: Option<ParseDiagnostics>,
+    /// Free-form metadata attached by composite payloads (e.g. the source
+    /// `index` of an element produced by [`MapPayload`]), or by
+    /// [`LlmCall`](crate::LlmCall) (raw `candidates` and parsed
+    /// `candidate_values` when [`LlmConfig::n`](crate::LlmConfig::n) is
+    /// above 1). Empty by default.
+    pub meta: Map<String, Value>,
+    /// Per-step outputs of the [`Chain`](crate::Chain) that produced this
+    /// output, including those of nested chains. `None` for other payloads.
+    pub chain: Option<Box<ChainResult>>,
+    /// Provider metadata of the backend response that produced this output
+    /// (token counts, timings), as in
+    /// [`LlmResponse::metadata`](crate::backend::LlmResponse::metadata).
+    /// Set by [`LlmCall`](crate::LlmCall); `None` for other payloads.
+    pub provider_metadata: Option<Value>,
+    /// Functions the model asked to call, from
+    /// [`LlmResponse::tool_calls`](crate::backend::LlmResponse::tool_calls).
+    /// Set by an [`LlmCall`](crate::LlmCall) with
+    /// [`with_tools`](crate::LlmCall::with_tools); empty otherwise.
+    pub tool_calls: Vec<ToolCall>,
+}
+
+impl PayloadOutput {
+    /// Create an output wrapping a pre-existing `Value`, for payloads that
+    /// produce structured data without calling a model.
+    pub fn from_value(value: Value) -> Self {
+        let raw = value.to_string();
+        Self {
+            value,
+            raw_response: raw,
+            thinking: None,
+            model: None,
+            diagnostics: Some(ParseDiagnostics {
+                strategy: Some("passthrough"),
+                ..Default::default()
+            }),
+            meta: Map::new(),
+            chain: None,
+            provider_metadata: None,
+            tool_calls: Vec::new(),
+        }
+    }
+
+    /// Attach a metadata entry (builder style).
+    pub fn with_meta(mut self, key: impl Into<String>, value: Value) -> Self {
+        self.meta.insert(key.into(), value);
+        self
+    }
+
+    /// Whether the value was recovered by auto-completing truncated JSON.
+    ///
+    /// A `true` here means the value is structurally valid but may be
+    /// missing trailing fields or elements; consider re-requesting.
+    pub fn was_auto_completed(&self) -> bool {
+        self.diagnostics.as_ref().is_some_and(|d| d.auto_completed)
+    }
+
+    /// The call's estimated USD cost. See
+    /// [`ParseDiagnostics::estimated_cost_usd`](crate::ParseDiagnostics::estimated_cost_usd).
+    pub fn estimated_cost_usd(&self) -> Option<f64> {
+        self.diagnostics.as_ref()?.estimated_cost_usd
+    }
+
+    /// Generation speed in tokens per second, from the provider metadata.
+    /// See [`LlmResponse::tokens_per_second`](crate::backend::LlmResponse::tokens_per_second).
+    pub fn tokens_per_second(&self) -> Option<f64> {
+        crate::backend::tokens_per_second(self.provider_metadata.as_ref()?)
+    }
+
+    /// Prompt evaluation time in milliseconds, from the provider metadata.
+    /// See [`LlmResponse::prompt_eval_duration_ms`](crate::backend::LlmResponse::prompt_eval_duration_ms).
+    pub fn prompt_eval_duration_ms(&self) -> Option<f64> {
+        crate::backend::duration_ms(self.provider_metadata.as_ref()?, "prompt_eval_duration")
+    }
+
+    /// Generation time in milliseconds, from the provider metadata.
+    pub fn eval_duration_ms(&self) -> Option<f64> {
+        crate::backend::duration_ms(self.provider_metadata.as_ref()?, "eval_duration")
+    }
+
+    /// Total request time in milliseconds, from the provider metadata.
+    pub fn total_duration_ms(&self) -> Option<f64> {
+        crate::backend::duration_ms(self.provider_metadata.as_ref()?, "total_duration")
+    }
+
+    /// The provider's fingerprint of the backend configuration that served
+    /// this output (OpenAI's `system_fingerprint`), from the provider
+    /// metadata.
+    ///
+    /// Compare it across runs: when it changes, outputs may drift even with
+    /// a fixed seed.
+    pub fn model_fingerprint(&self) -> Option<&str> {
+        self.provider_metadata
+            .as_ref()?
+            .get("system_fingerprint")?
+            .as_str()
+    }
+
+    /// The exact model version the provider served (e.g.
+    /// `"gpt-4o-2024-08-06"` for a request for `"gpt-4o"`), from the
+    /// provider metadata. [`model`](Self::model) is the model requested.
+    pub fn served_model(&self) -> Option<&str> {
+        self.provider_metadata.as_ref()?.get("model")?.as_str()
+    }
+
+    /// Whether two outputs carry the same parsed `value`.
+    ///
+    /// Everything else (raw response, thinking, model, diagnostics, meta) is
+    /// ignored, so a fresh output can be compared against a stored golden.
+    pub fn value_eq(&self, other: &PayloadOutput) -> bool {
+        self.value == other.value
+    }
+
+    /// List the differences between this output's `value` and `other`'s.
+    ///
+    /// Each entry is `"<path>: <detail>"`, where `<path>` is a JSON Pointer
+    /// into the value (`/` for the root). An empty list means
+    /// [`value_eq`](Self::value_eq) holds.
+    ///
+    /// ```
+    /// use llm_pipeline::PayloadOutput;
+    /// use serde_json::json;
+    ///
+    /// let golden = PayloadOutput::from_value(json!({"tags": ["a", "b"], "score": 3}));
+    /// let fresh = PayloadOutput::from_value(json!({"tags": ["a", "c"], "score": 3}));
+    /// assert_eq!(golden.diff(&fresh), vec![r#"/tags/1: "b" != "c""#]);
+    /// ```
+    pub fn diff(&self, other: &PayloadOutput) -> Vec<String> {
+        let mut out = Vec::new();
+        diff_values("", &self.value, &other.value, &mut out);
+        out
+    }
+
+    /// Read the field at JSON Pointer `pointer` (e.g. `"/approved"`, or `""`
+    /// for the whole value) as a boolean, accepting the spellings models use
+    /// in place of one.
+    ///
+    /// `true`/`false`, the numbers `1`/`0`, and the strings `"true"`,
+    /// `"yes"`, `"y"`, `"on"`, `"1"` (and their negatives) are recognized,
+    /// ignoring case and surrounding whitespace. Returns `None` if the field
+    /// is missing or anything else.
+    ///
+    /// ```
+    /// use llm_pipeline::PayloadOutput;
+    /// use serde_json::json;
+    ///
+    /// let output = PayloadOutput::from_value(json!({"approved": "Yes", "flagged": 0}));
+    /// assert_eq!(output.get_bool("/approved"), Some(true));
+    /// assert_eq!(output.get_bool("/flagged"), Some(false));
+    /// assert_eq!(output.get_bool("/missing"), None);
+    /// ```
+    pub fn get_bool(&self, pointer: &str) -> Option<bool> {
+        match self.value.pointer(pointer)? {
+            Value::Bool(b) => Some(*b),
+            Value::Number(n) => {
+                let n = n.as_f64()?;
+                (n == 1.0 || n == 0.0).then_some(n == 1.0)
+            }
+            Value::String(s) => match s.trim().to_ascii_lowercase().as_str() {
+                "true" | "yes" | "y" | "on" | "1" => Some(true),
+                "false" | "no" | "n" | "off" | "0" => Some(false),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    /// Parse the output value into a typed `T`.
+    ///
+    /// This is the primary way to extract typed data at workflow edges.
+    ///
+    /// ```ignore
+    /// let output: PayloadOutput = payload.invoke(&ctx, input).await?;
+    /// let analysis: MyStruct = output.parse_as()?;
+    /// ```
+    pub fn parse_as<T: DeserializeOwned>(&self) -> Result<T> {
+        serde_json::from_value(self.value.clone()).map_err(|e| {
+            let snippet = self.value.to_string();
+            let snippet = &snippet[..snippet.len().min(200)];
+            PipelineError::Other(format!(
+                "Failed to parse PayloadOutput into target type: {}. Value (truncated): {}",
+                e, snippet
+            ))
+        })
+    }
+
+    /// Like [`parse_as`](Self::parse_as), but if `value` doesn't fit `T`,
+    /// re-extracts `T` from `raw_response` with the full JSON pipeline
+    /// (think-block stripping, fence and bracket extraction, repair,
+    /// auto-completion) as a last resort.
+    ///
+    /// Recovers outputs whose strategy stored a lossy string although the
+    /// raw text was salvageable. Returns the original `parse_as` error if
+    /// the fallback fails too.
+    pub fn parse_as_lenient<T: DeserializeOwned>(&self) -> Result<T> {
+        self.parse_as().or_else(|e| {
+            if self.raw_response.is_empty() {
+                return Err(e);
+            }
+            crate::output_parser::parse_json(&self.raw_response).map_err(|_| e)
+        })
+    }
+
+    /// [`parse_as_lenient`](Self::parse_as_lenient), or `default` if the
+    /// output can't be parsed into `T` at all.
+    pub fn parse_as_or<T: DeserializeOwned>(&self, default: T) -> T {
+        self.parse_as_lenient().unwrap_or(default)
+    }
+
+    /// Parse a single-choice output (see
+    /// [`OutputStrategy::Choice`](crate::output_strategy::OutputStrategy::Choice))
+    /// into a typed `E`, typically an enum implementing [`FromStr`].
+    ///
+    /// Fails if the value is not a string or `E` rejects it.
+    ///
+    /// ```ignore
+    /// #[derive(Debug)]
+    /// enum Route { Billing, Support }
+    ///
+    /// impl FromStr for Route {
+    ///     type Err = String;
+    ///     fn from_str(s: &str) -> Result<Self, String> {
+    ///         match s {
+    ///             "billing" => Ok(Route::Billing),
+    ///             "support" => Ok(Route::Support),
+    ///             other => Err(format!("unknown route {other:?}")),
+    ///         }
+    ///     }
+    /// }
+    ///
+    /// let route: Route = output.parse_choice_as()?;
+    /// ```
+    pub fn parse_choice_as<E>(&self) -> Result<E>
+    where
+        E: FromStr,
+        E::Err: std::fmt::Display,
+    {
+        let choice = self.value.as_str().ok_or_else(|| {
+            PipelineError::Other(format!("Expected a choice string, got: {}", self.value))
+        })?;
+        choice.parse().map_err(|e| {
+            PipelineError::Other(format!(
+                "Choice {:?} does not map to the target type: {}",
+                choice, e
+            ))
+        })
+    }
+}
+
+/// Recursively collect differences between `left` and `right` under `path`.
+fn diff_values(path: &str, left: &Value, right: &Value, out: &mut Vec<String>) {
+    let display = if path.is_empty() { "/" } else { path };
+    match (left, right) {
+        (Value::Object(l), Value::Object(r)) => {
+            for (key, lv) in l {
+                let child = format!("{}/{}", path, escape_pointer(key));
+                match r.get(key) {
+                    Some(rv) => diff_values(&child, lv, rv, out),
+                    None => out.push(format!("{}: missing on right", child)),
+                }
+            }
+            for key in r.keys().filter(|k| !l.contains_key(*k)) {
+                out.push(format!("{}/{}: missing on left", path, escape_pointer(key)));
+            }
+        }
+        (Value::Array(l), Value::Array(r)) => {
+            for (i, (lv, rv)) in l.iter().zip(r).enumerate() {
+                diff_values(&format!("{}/{}", path, i), lv, rv, out);
+            }
+            if l.len() != r.len() {
+                out.push(format!(
+                    "{}: array length {} != {}",
+                    display,
+                    l.len(),
+                    r.len()
+                ));
+            }
+        }
+        (l, r) if l != r => out.push(format!("{}: {} != {}", display, l, r)),
+        _ => {}
+    }
+}
+
+/// Escape a key for use as a JSON Pointer segment (RFC 6901).
+fn escape_pointer(key: &str) -> String {
+    key.replace('~', "~0").replace('/', "~1")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Doubles numeric input; fails on anything else.
+    struct Double;
+
+    impl Payload for Double {
+        fn kind(&self) -> &'static str {
+            "double"
+        }
+        fn name(&self) -> &str {
+            "double"
+        }
+        fn invoke<'a>(
+            &'a self,
+            _ctx: &'a ExecCtx,
+            input: Value,
+        ) -> BoxFut<'a, Result<PayloadOutput>> {
+            Box::pin(async move {
+                match input.as_i64() {
+                    Some(n) => Ok(PayloadOutput::from_value(json!(n * 2))),
+                    None => Err(PipelineError::Other("not a number".to_string())),
+                }
+            })
+        }
+    }
+
+    #[test]
+    fn test_from_value_is_passthrough() {
+        let output = PayloadOutput::from_value(json!({"a": 1}));
+        let diag = output.diagnostics.unwrap();
+        assert_eq!(diag.strategy, Some("passthrough"));
+        assert!(diag.ok());
+        assert_eq!(output.raw_response, r#"{"a":1}"#);
+    }
+
+    #[test]
+    fn test_model_fingerprint_from_provider_metadata() {
+        let mut output = PayloadOutput::from_value(json!("hi"));
+        assert_eq!(output.model_fingerprint(), None);
+        output.provider_metadata = Some(json!({
+            "model": "gpt-4o-2024-08-06",
+            "system_fingerprint": "fp_44709d6fcb"
+        }));
+        assert_eq!(output.model_fingerprint(), Some("fp_44709d6fcb"));
+        assert_eq!(output.served_model(), Some("gpt-4o-2024-08-06"));
+    }
+
+    #[test]
+    fn test_get_bool_coerces_model_spellings() {
+        let output = PayloadOutput::from_value(json!({
+            "a": true, "b": "TRUE", "c": " no ", "d": 1, "e": "0", "f": 0.0,
+            "g": 2, "h": "maybe", "i": null, "nested": {"ok": "on"}
+        }));
+        assert_eq!(output.get_bool("/a"), Some(true));
+        assert_eq!(output.get_bool("/b"), Some(true));
+        assert_eq!(output.get_bool("/c"), Some(false));
+        assert_eq!(output.get_bool("/d"), Some(true));
+        assert_eq!(output.get_bool("/e"), Some(false));
+        assert_eq!(output.get_bool("/f"), Some(false));
+        assert_eq!(output.get_bool("/nested/ok"), Some(true));
+        for pointer in ["/g", "/h", "/i", "/missing", ""] {
+            assert_eq!(output.get_bool(pointer), None, "{}", pointer);
+        }
+        assert_eq!(PayloadOutput::from_value(json!("y")).get_bool(""), Some(true));
+    }
+
+    #[test]
+    fn test_value_eq_ignores_volatile_fields() {
+        let golden = PayloadOutput::from_value(json!({"a": 1}));
+        let mut fresh = PayloadOutput::from_value(json!({"a": 1}));
+        fresh.raw_response = "```json\n{\"a\": 1}\n```".to_string();
+        fresh.model = Some("llama3".to_string());
+        fresh.diagnostics = Some(ParseDiagnostics::default());
+        assert!(golden.value_eq(&fresh));
+        assert!(golden.diff(&fresh).is_empty());
+    }
+
+    #[test]
+    fn test_diff_reports_paths() {
+        let left = PayloadOutput::from_value(json!({
+            "name": "x",
+            "items": [1, 2, 3],
+            "nested": {"a/b": true, "gone": 1}
+        }));
+        let right = PayloadOutput::from_value(json!({
+            "name": "y",
+            "items": [1, 5],
+            "nested": {"a/b": false, "new": 2}
+        }));
+        assert!(!left.value_eq(&right));
+        assert_eq!(
+            left.diff(&right),
+            vec![
+                r#"/items/1: 2 != 5"#,
+                "/items: array length 3 != 2",
+                r#"/name: "x" != "y""#,
+                "/nested/a~1b: true != false",
+                "/nested/gone: missing on right",
+                "/nested/new: missing on left",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_provider_timing_accessors() {
+        let mut output = PayloadOutput::from_value(json!("hi"));
+        assert_eq!(output.tokens_per_second(), None);
+
+        output.provider_metadata = Some(json!({
+            "eval_count": 30,
+            "eval_duration": 1_500_000_000u64,
+            "prompt_eval_duration": 40_000_000u64,
+        }));
+        assert_eq!(output.tokens_per_second(), Some(20.0));
+        assert_eq!(output.prompt_eval_duration_ms(), Some(40.0));
+        assert_eq!(output.eval_duration_ms(), Some(1500.0));
+        assert_eq!(output.total_duration_ms(), None);
+    }
+
+    #[test]
+    fn test_parse_as_lenient_falls_back_to_raw_response() {
+        #[derive(Debug, PartialEq, serde::Deserialize)]
+        struct Person {
+            name: String,
+        }
+
+        // A lossy strategy kept the unparseable text as a string.
+        let raw = "Sure! {\"name\": \"Ada\",}";
+        let mut out = PayloadOutput::from_value(json!(raw));
+        out.raw_response = raw.to_string();
+        assert!(out.parse_as::<Person>().is_err());
+        let person: Person = out.parse_as_lenient().unwrap();
+        assert_eq!(person.name, "Ada");
+
+        let mut hopeless = PayloadOutput::from_value(json!("no json here"));
+        hopeless.raw_response = "no json here".to_string();
+        assert!(hopeless.parse_as_lenient::<Person>().is_err());
+        let fallback = hopeless.parse_as_or(Person {
+            name: "unknown".to_string(),
+        });
+        assert_eq!(fallback.name, "unknown");
+    }
+
+    #[test]
+    fn test_parse_choice_as() {
+        #[derive(Debug, PartialEq)]
+        enum Route {
+            Billing,
+            Support,
+        }
+
+        impl FromStr for Route {
+            type Err = String;
+
+            fn from_str(s: &str) -> std::result::Result<Self, String> {
+                match s {
+                    "billing" => Ok(Route::Billing),
+                    "support" => Ok(Route::Support),
+                    other => Err(format!("unknown route {:?}", other)),
+                }
+            }
+        }
+
+        let out = PayloadOutput::from_value(json!("support"));
+        assert_eq!(out.parse_choice_as::<Route>().unwrap(), Route::Support);
+
+        let err = PayloadOutput::from_value(json!("sales"))
+            .parse_choice_as::<Route>()
+            .unwrap_err();
+        assert!(err.to_string().contains("unknown route"));
+
+        let err = PayloadOutput::from_value(json!(3))
+            .parse_choice_as::<Route>()
+            .unwrap_err();
+        assert!(err.to_string().contains("Expected a choice string"));
+    }
+
+    #[test]
+    fn test_diff_root_type_mismatch() {
+        let left = PayloadOutput::from_value(json!("text"));
+        let right = PayloadOutput::from_value(json!(["text"]));
+        assert_eq!(left.diff(&right), vec![r#"/: "text" != ["text"]"#]);
+    }
+
+    #[tokio::test]
+    async fn test_invoke_batch_stream_yields_every_index() {
+        let ctx = ExecCtx::builder("http://test").build();
+        let payload: Box<dyn Payload> = Box::new(Double);
+        let mut lines: Vec<(usize, String)> = payload
+            .invoke_batch_stream(&ctx, vec![json!(1), json!("x"), json!(3)], 2)
+            .map(|(i, result)| (i, ndjson_line(i, &result)))
+            .collect()
+            .await;
+        lines.sort();
+        assert_eq!(
+            lines,
+            vec![
+                (0, r#"{"index":0,"value":2}"#.to_string()),
+                (1, r#"{"error":"not a number","index":1}"#.to_string()),
+                (2, r#"{"index":2,"value":6}"#.to_string()),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_invoke_batch_stream_cancelled() {
+        let ctx = ExecCtx::builder("http://test")
+            .cancellation(Some(std::sync::Arc::new(
+                std::sync::atomic::AtomicBool::new(true),
+            )))
+            .build();
+        let results: Vec<_> = Double
+            .invoke_batch_stream(&ctx, vec![json!(1), json!(2)], 1)
+            .collect()
+            .await;
+        assert_eq!(results.len(), 2);
+        assert!(results
+            .iter()
+            .all(|(_, r)| matches!(r, Err(PipelineError::Cancelled))));
+    }
+}
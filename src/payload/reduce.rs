@@ -0,0 +1,275 @@
+//! Fold an array input into a single result.
+//!
+//! [`ReducePayload`] is the combine step of map-reduce: after a
+//! [`MapPayload`](super::MapPayload) has produced one result per chunk, a
+//! reduce merges them — either pairwise, threading an accumulator through
+//! `{acc}` / `{item}` template vars, or all at once by handing the whole
+//! array to the inner payload as `{input}`.
+
+use crate::{
+    error::Result,
+    exec_ctx::ExecCtx,
+    payload::{BoxFut, Payload, PayloadOutput},
+    PipelineError,
+};
+use serde_json::{json, Value};
+
+/// How [`ReducePayload`] combines the elements of its input.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ReduceMode {
+    /// Fold left, one inner call per element after the first. Each call sees
+    /// the running result as `{acc}` and the next element as `{item}` (also
+    /// passed as `{input}`). Default.
+    #[default]
+    Pairwise,
+
+    /// A single inner call receiving the whole array as `{input}`.
+    AllAtOnce,
+}
+
+/// Folds a `Value::Array` input into a single output via an inner payload.
+///
+/// In [`ReduceMode::Pairwise`] the accumulator starts as the first element
+/// (or the value given to [`with_initial`](Self::with_initial)), and a
+/// single-element array is returned as-is without calling the inner
+/// payload. The result's `meta["steps"]` records how many inner calls ran.
+///
+/// # Example
+///
+/// ```ignore
+/// use llm_pipeline::payload::{ChunkPayload, MapPayload, ReducePayload};
+/// use llm_pipeline::{Chain, LlmCall};
+///
+/// let chain = Chain::new("summarize-doc")
+///     .push(Box::new(ChunkPayload::new("chunk", 4000)))
+///     .push(Box::new(MapPayload::new("each", Box::new(
+///         LlmCall::new("summarize", "Summarize:\n{input}"),
+///     ))))
+///     .push(Box::new(ReducePayload::new("combine", Box::new(
+///         LlmCall::new("merge", "Merge these summaries:\n{acc}\n---\n{item}"),
+///     ))));
+/// ```
+pub struct ReducePayload {
+    name: String,
+    inner: Box<dyn Payload>,
+    mode: ReduceMode,
+    initial: Option<Value>,
+}
+
+impl ReducePayload {
+    /// Create a pairwise reduce over `inner`.
+    pub fn new(name: impl Into<String>, inner: Box<dyn Payload>) -> Self {
+        Self {
+            name: name.into(),
+            inner,
+            mode: ReduceMode::default(),
+            initial: None,
+        }
+    }
+
+    /// Set the reduce mode.
+    pub fn with_mode(mut self, mode: ReduceMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    /// Shorthand for `with_mode(ReduceMode::AllAtOnce)`.
+    pub fn all_at_once(self) -> Self {
+        self.with_mode(ReduceMode::AllAtOnce)
+    }
+
+    /// Seed the pairwise accumulator. With a seed, every element gets its
+    /// own inner call and an empty array reduces to the seed.
+    pub fn with_initial(mut self, initial: Value) -> Self {
+        self.initial = Some(initial);
+        self
+    }
+
+    /// Returns the reduce mode.
+    pub fn mode(&self) -> ReduceMode {
+        self.mode
+    }
+
+    async fn reduce_pairwise(&self, ctx: &ExecCtx, items: Vec<Value>) -> Result<PayloadOutput> {
+        let mut items = items.into_iter();
+        let mut acc = match self.initial.clone().or_else(|| items.next()) {
+            Some(first) => PayloadOutput::from_value(first),
+            None => {
+                return Err(PipelineError::StageFailed {
+                    stage: self.name.clone(),
+                    message: "ReducePayload received an empty array and has no initial value"
+                        .to_string(),
+                })
+            }
+        };
+
+        let mut steps = 0usize;
+        for item in items {
+            ctx.check_cancelled()?;
+            let step_ctx = ctx.with_extra_vars([
+                ("acc", value_to_var(&acc.value)),
+                ("item", value_to_var(&item)),
+            ]);
+            acc = self.inner.invoke(&step_ctx, item).await?;
+            steps += 1;
+        }
+
+        Ok(acc.with_meta("steps", json!(steps)))
+    }
+}
+
+impl Payload for ReducePayload {
+    fn kind(&self) -> &'static str {
+        "reduce"
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn invoke<'a>(&'a self, ctx: &'a ExecCtx, input: Value) -> BoxFut<'a, Result<PayloadOutput>> {
+        Box::pin(async move {
+            ctx.check_cancelled()?;
+            let items = match input {
+                Value::Array(items) => items,
+                other => {
+                    return Err(PipelineError::StageFailed {
+                        stage: self.name.clone(),
+                        message: format!("ReducePayload expects an array input, got: {}", other),
+                    })
+                }
+            };
+
+            match self.mode {
+                ReduceMode::Pairwise => self.reduce_pairwise(ctx, items).await,
+                ReduceMode::AllAtOnce => {
+                    let output = self.inner.invoke(ctx, Value::Array(items)).await?;
+                    Ok(output.with_meta("steps", json!(1)))
+                }
+            }
+        })
+    }
+}
+
+/// Render a value for template substitution: strings verbatim, everything
+/// else as JSON.
+fn value_to_var(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    /// Returns `"{acc}+{item}"` from ctx vars, or the input length when
+    /// no `acc` var is set. Counts invocations.
+    struct Join {
+        calls: Arc<AtomicUsize>,
+    }
+
+    impl Payload for Join {
+        fn kind(&self) -> &'static str {
+            "join"
+        }
+        fn name(&self) -> &str {
+            "join"
+        }
+        fn invoke<'a>(
+            &'a self,
+            ctx: &'a ExecCtx,
+            input: Value,
+        ) -> BoxFut<'a, Result<PayloadOutput>> {
+            Box::pin(async move {
+                self.calls.fetch_add(1, Ordering::SeqCst);
+                let value = match ctx.vars.get("acc") {
+                    Some(acc) => json!(format!("{}+{}", acc, ctx.vars["item"])),
+                    None => json!(input.as_array().map(|a| a.len()).unwrap_or(0)),
+                };
+                Ok(PayloadOutput::from_value(value))
+            })
+        }
+    }
+
+    fn join() -> (Box<dyn Payload>, Arc<AtomicUsize>) {
+        let calls = Arc::new(AtomicUsize::new(0));
+        (
+            Box::new(Join {
+                calls: calls.clone(),
+            }),
+            calls,
+        )
+    }
+
+    fn test_ctx() -> ExecCtx {
+        ExecCtx::builder("http://test").build()
+    }
+
+    #[tokio::test]
+    async fn test_reduce_pairwise_folds_left() {
+        let (inner, calls) = join();
+        let reduce = ReducePayload::new("reduce", inner);
+        let out = reduce
+            .invoke(&test_ctx(), json!(["a", "b", "c"]))
+            .await
+            .unwrap();
+        assert_eq!(out.value, json!("a+b+c"));
+        assert_eq!(out.meta["steps"], 2);
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_reduce_pairwise_with_initial() {
+        let (inner, calls) = join();
+        let reduce = ReducePayload::new("reduce", inner).with_initial(json!("seed"));
+        let out = reduce.invoke(&test_ctx(), json!(["a", "b"])).await.unwrap();
+        assert_eq!(out.value, json!("seed+a+b"));
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+
+        let (inner, _) = join();
+        let reduce = ReducePayload::new("reduce", inner).with_initial(json!("seed"));
+        let out = reduce.invoke(&test_ctx(), json!([])).await.unwrap();
+        assert_eq!(out.value, json!("seed"));
+    }
+
+    #[tokio::test]
+    async fn test_reduce_single_element_skips_inner() {
+        let (inner, calls) = join();
+        let reduce = ReducePayload::new("reduce", inner);
+        let out = reduce.invoke(&test_ctx(), json!(["only"])).await.unwrap();
+        assert_eq!(out.value, json!("only"));
+        assert_eq!(calls.load(Ordering::SeqCst), 0);
+    }
+
+    #[tokio::test]
+    async fn test_reduce_empty_without_initial_fails() {
+        let (inner, _) = join();
+        let reduce = ReducePayload::new("reduce", inner);
+        let result = reduce.invoke(&test_ctx(), json!([])).await;
+        assert!(matches!(result, Err(PipelineError::StageFailed { .. })));
+    }
+
+    #[tokio::test]
+    async fn test_reduce_all_at_once() {
+        let (inner, calls) = join();
+        let reduce = ReducePayload::new("reduce", inner).all_at_once();
+        let out = reduce
+            .invoke(&test_ctx(), json!(["a", "b", "c"]))
+            .await
+            .unwrap();
+        assert_eq!(out.value, json!(3));
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_reduce_rejects_non_array() {
+        let (inner, _) = join();
+        let reduce = ReducePayload::new("reduce", inner);
+        let result = reduce.invoke(&test_ctx(), json!({"a": 1})).await;
+        assert!(matches!(result, Err(PipelineError::StageFailed { .. })));
+    }
+}
@@ -0,0 +1,393 @@
+//! Repair malformed JSON output by asking for a reformat.
+//!
+//! [`ReformatPayload`] wraps a payload (typically an
+//! [`LlmCall`]) whose output should be JSON. When the inner
+//! output fails to parse, it replays the raw response as the model's own
+//! reply and follows up asking only for the same content as valid JSON,
+//! rather than re-running the original prompt. The follow-up is usually far cheaper than
+//! a semantic retry and can go to a smaller model.
+
+use crate::{
+    backend::{self, ChatMessage, LlmRequest, Role},
+    client::LlmConfig,
+    error::{PipelineError, Result},
+    exec_ctx::ExecCtx,
+    llm_call::{LlmCall, DEFAULT_MODEL},
+    output_parser::{
+        self,
+        json::{parse_json_tracked, JsonRecovery},
+    },
+    output_strategy::OutputStrategy,
+    parsing,
+    payload::{BoxFut, Payload, PayloadOutput},
+};
+use serde_json::{json, Value};
+
+const REFORMAT_INSTRUCTION: &str = "Rewrite your previous reply as valid JSON. \
+Keep its content and structure exactly; do not add, remove, or change any \
+information. Respond with only the JSON.";
+
+/// Wraps a payload and reformats its output into valid JSON on parse failure.
+///
+/// Outputs that parse (or that carry no diagnostics) pass through unchanged,
+/// as do outputs parsed with a strategy other than JSON: reformatting them
+/// into JSON would change their shape. Otherwise a follow-up conversation
+/// is sent: the input as the user turn, the inner output's `raw_response`
+/// (with any thinking block removed) as the assistant's reply, and a
+/// request to rewrite that reply as JSON. It is sent up to
+/// [`with_max_attempts`](Self::with_max_attempts) times, until the reply
+/// parses as JSON.
+///
+/// Wrap an [`LlmCall`] with [`for_call`](Self::for_call) to replay its
+/// rendered prompt as the user turn, to also handle
+/// [`OutputStrategy::JsonPointer`] by applying the pointer to the reply, and
+/// to check the reply with the call's
+/// [`RetryConfig::validate`](crate::retry::RetryConfig::validate); a reply
+/// that fails validation counts as a failed attempt.
+///
+/// On success the returned output carries the parsed value, the reformatted
+/// text as `raw_response`, and the inner diagnostics with the parse error
+/// cleared. Either way, `meta["reformat_attempts"]` records how many
+/// follow-ups were sent. If every attempt fails, the inner output is returned
+/// as is.
+///
+/// # Example
+///
+/// ```ignore
+/// use llm_pipeline::payload::ReformatPayload;
+/// use llm_pipeline::LlmCall;
+///
+/// let extract = ReformatPayload::new("extract", Box::new(
+///     LlmCall::new("extract", "List the people in: {input}").expecting_json(),
+/// ))
+/// .with_model("llama3.2:1b");
+///
+/// let output = extract.invoke(&ctx, json!(article)).await?;
+/// ```
+pub struct ReformatPayload {
+    name: String,
+    inner: Box<dyn Payload>,
+    model: Option<String>,
+    config: LlmConfig,
+    max_attempts: u32,
+    pointer: Option<String>,
+    /// The wrapped call, when built with [`for_call`](Self::for_call).
+    call: Option<LlmCall>,
+}
+
+impl ReformatPayload {
+    /// Reformat the output of `inner` when it fails to parse.
+    pub fn new(name: impl Into<String>, inner: Box<dyn Payload>) -> Self {
+        Self {
+            name: name.into(),
+            inner,
+            model: None,
+            config: LlmConfig::default()
+                .with_temperature(0.0)
+                .with_json_mode(true),
+            max_attempts: 1,
+            pointer: None,
+            call: None,
+        }
+    }
+
+    /// Reformat the output of `call` when it fails to parse.
+    ///
+    /// Fails with [`PipelineError::InvalidConfig`] unless `call` expects
+    /// [`OutputStrategy::Json`] or [`OutputStrategy::JsonPointer`]; for the
+    /// latter, the pointer is applied to the reformatted reply. The call's
+    /// prompt and retry validators are used for the follow-up.
+    pub fn for_call(name: impl Into<String>, call: LlmCall) -> Result<Self> {
+        let pointer = match call.output_strategy() {
+            OutputStrategy::Json => None,
+            OutputStrategy::JsonPointer(pointer) => Some(pointer.clone()),
+            other => {
+                return Err(PipelineError::InvalidConfig(format!(
+                    "ReformatPayload needs a JSON output strategy, got {:?}",
+                    other
+                )))
+            }
+        };
+        Ok(Self {
+            pointer,
+            call: Some(call.clone()),
+            ..Self::new(name, Box::new(call))
+        })
+    }
+
+    /// Model for the reformat request.
+    ///
+    /// Default: the model that produced the inner output, then
+    /// `ctx.default_model`, then `"llama3.2:3b"`.
+    pub fn with_model(mut self, model: impl Into<String>) -> Self {
+        self.model = Some(model.into());
+        self
+    }
+
+    /// Generation settings for the reformat request.
+    ///
+    /// Default: temperature `0.0` with [`json_mode`](LlmConfig::json_mode) on.
+    pub fn with_config(mut self, config: LlmConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Maximum reformat requests per invocation. Clamped to at least 1.
+    /// Default: 1.
+    pub fn with_max_attempts(mut self, attempts: u32) -> Self {
+        self.max_attempts = attempts.max(1);
+        self
+    }
+
+    /// The follow-up conversation: `prompt`, the unparseable reply `text`,
+    /// and the reformat instruction.
+    fn request(&self, ctx: &ExecCtx, model: &str, prompt: String, text: String) -> LlmRequest {
+        let mut request = LlmRequest::new(model, REFORMAT_INSTRUCTION);
+        request.messages = vec![
+            ChatMessage {
+                role: Role::User,
+                content: prompt,
+            },
+            ChatMessage {
+                role: Role::Assistant,
+                content: text,
+            },
+            ChatMessage {
+                role: Role::User,
+                content: REFORMAT_INSTRUCTION.to_string(),
+            },
+        ];
+        request.config = self.config.clone();
+        request.timeout = ctx.call_timeout(false);
+        request
+    }
+}
+
+impl Payload for ReformatPayload {
+    fn kind(&self) -> &'static str {
+        "reformat"
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn invoke<'a>(&'a self, ctx: &'a ExecCtx, input: Value) -> BoxFut<'a, Result<PayloadOutput>> {
+        Box::pin(async move {
+            let prompt = match &self.call {
+                Some(call) => call.rendered_prompt(ctx, &input),
+                None => match &input {
+                    Value::String(s) => s.clone(),
+                    other => other.to_string(),
+                },
+            };
+            let output = self.inner.invoke(ctx, input).await?;
+            let failed = output.diagnostics.as_ref().is_some_and(|diag| {
+                let json = match diag.strategy {
+                    Some("json") => true,
+                    Some("json_pointer") => self.pointer.is_some(),
+                    _ => false,
+                };
+                json && !diag.ok()
+            });
+            if !failed {
+                return Ok(output);
+            }
+
+            let model = self
+                .model
+                .as_deref()
+                .or(output.model.as_deref())
+                .or(ctx.default_model.as_deref())
+                .unwrap_or(DEFAULT_MODEL)
+                .to_string();
+            let (_, text) = parsing::extract_thinking(&output.raw_response);
+            let request = self.request(ctx, &model, prompt, text);
+
+            for attempt in 1..=self.max_attempts {
+                ctx.check_cancelled()?;
+                let response = backend::with_backoff(
+                    &ctx.backend,
+                    &ctx.client,
+                    &ctx.base_url,
+                    &request,
+                    &ctx.backoff,
+                    ctx.cancel_flag(),
+                    None,
+                )
+                .await?;
+                let completion_tokens = response
+                    .reported_usage()
+                    .and_then(|usage| usage.completion_tokens);
+                ctx.record_completion_tokens(completion_tokens.unwrap_or(0));
+
+                let Ok((value, recovery)) = parse_json_tracked::<Value>(&response.text) else {
+                    continue;
+                };
+                let value = if ctx.normalize_keys {
+                    output_parser::normalize_keys(value)
+                } else {
+                    value
+                };
+                let value = match &self.pointer {
+                    Some(pointer) => match value.pointer(pointer) {
+                        Some(sub) => sub.clone(),
+                        None => continue,
+                    },
+                    None => value,
+                };
+                let retry = self.call.as_ref().and_then(|call| call.retry());
+                if retry.is_some_and(|retry| retry.validate(&response.text, &value).is_err()) {
+                    continue;
+                }
+
+                let mut diagnostics = output.diagnostics.clone().unwrap_or_default();
+                diagnostics.parse_error = None;
+                diagnostics.strategy = Some(match self.pointer {
+                    Some(_) => "json_pointer",
+                    None => "json",
+                });
+                diagnostics.repaired = recovery != JsonRecovery::None;
+                diagnostics.auto_completed = recovery == JsonRecovery::AutoCompleted;
+                if let Some(tokens) = completion_tokens {
+                    diagnostics.completion_tokens =
+                        Some(diagnostics.completion_tokens.unwrap_or(0) + tokens);
+                }
+
+                return Ok(PayloadOutput {
+                    value,
+                    raw_response: response.text,
+                    diagnostics: Some(diagnostics),
+                    provider_metadata: response.metadata,
+                    ..output
+                }
+                .with_meta("reformat_attempts", json!(attempt)));
+            }
+
+            Ok(output.with_meta("reformat_attempts", json!(self.max_attempts)))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MockBackend;
+    use std::sync::Arc;
+
+    fn mock_ctx(responses: &[&str]) -> ExecCtx {
+        let responses = responses.iter().map(|r| r.to_string()).collect();
+        ExecCtx::builder("http://test")
+            .backend(Arc::new(MockBackend::new(responses)))
+            .build()
+    }
+
+    #[tokio::test]
+    async fn test_reformat_repairs_unparseable_output() {
+        let ctx = mock_ctx(&[
+            "name: Ada, born: 1815",
+            "<think>easy</think>{\"name\": \"Ada\", \"born\": 1815}",
+        ]);
+        let payload = ReformatPayload::new(
+            "person",
+            Box::new(LlmCall::new("person", "Who is {input}?").expecting_json()),
+        );
+        assert_eq!(payload.kind(), "reformat");
+
+        let out = payload.invoke(&ctx, json!("Ada")).await.unwrap();
+        assert_eq!(out.value, json!({"name": "Ada", "born": 1815}));
+        assert!(out.diagnostics.as_ref().unwrap().ok());
+        assert_eq!(out.meta["reformat_attempts"], 1);
+    }
+
+    #[tokio::test]
+    async fn test_reformat_skips_parsed_and_gives_up_after_attempts() {
+        let ctx = mock_ctx(&["{\"ok\": true}"]);
+        let payload = ReformatPayload::new(
+            "ok",
+            Box::new(LlmCall::new("ok", "Check {input}").expecting_json()),
+        );
+        let out = payload.invoke(&ctx, json!("x")).await.unwrap();
+        assert_eq!(out.value, json!({"ok": true}));
+        assert!(!out.meta.contains_key("reformat_attempts"));
+
+        let ctx = mock_ctx(&["not json"]);
+        let payload = payload.with_max_attempts(2);
+        let out = payload.invoke(&ctx, json!("x")).await.unwrap();
+        assert!(!out.diagnostics.as_ref().unwrap().ok());
+        assert_eq!(out.raw_response, "not json");
+        assert_eq!(out.meta["reformat_attempts"], 2);
+    }
+
+    #[tokio::test]
+    async fn test_reformat_applies_json_pointer() {
+        let ctx = mock_ctx(&["result: {name: Ada}", "{\"result\": {\"name\": \"Ada\"}}"]);
+        let call = LlmCall::new("person", "Who is {input}?").expecting_json_pointer("/result");
+        let payload = ReformatPayload::for_call("person", call).unwrap();
+        let out = payload.invoke(&ctx, json!("Ada")).await.unwrap();
+        assert_eq!(out.value, json!({"name": "Ada"}));
+        assert_eq!(out.meta["reformat_attempts"], 1);
+        let diag = out.diagnostics.unwrap();
+        assert!(diag.ok());
+        assert_eq!(diag.strategy, Some("json_pointer"));
+    }
+
+    #[tokio::test]
+    async fn test_reformat_leaves_non_json_strategies_alone() {
+        let list = || LlmCall::new("names", "List {input}").expecting_list();
+        assert!(matches!(
+            ReformatPayload::for_call("names", list()),
+            Err(PipelineError::InvalidConfig(_))
+        ));
+
+        // Through `new`, a failed non-JSON parse is passed through as is.
+        let ctx = mock_ctx(&["", "[\"Ada\"]"]);
+        let payload = ReformatPayload::new("names", Box::new(list()));
+        let out = payload.invoke(&ctx, json!("people")).await.unwrap();
+        assert!(!out.diagnostics.unwrap().ok());
+        assert!(!out.meta.contains_key("reformat_attempts"));
+    }
+
+    #[tokio::test]
+    async fn test_reformat_replays_reply_as_assistant_turn() {
+        let backend = Arc::new(MockBackend::new(vec![
+            "<think>hm</think>name: Ada".into(),
+            "{\"name\": \"Ada\"}".into(),
+        ]));
+        let ctx = ExecCtx::builder("http://test")
+            .backend(backend.clone())
+            .build();
+        let call = LlmCall::new("person", "Who is {input}?").expecting_json();
+        let payload = ReformatPayload::for_call("person", call).unwrap();
+        payload.invoke(&ctx, json!("Ada")).await.unwrap();
+
+        let requests = backend.requests();
+        let messages = &requests[1].messages;
+        assert_eq!(messages.len(), 3);
+        assert_eq!(messages[0].role, Role::User);
+        assert_eq!(messages[0].content, "Who is Ada?");
+        assert_eq!(messages[1].role, Role::Assistant);
+        assert_eq!(messages[1].content, "name: Ada");
+        assert_eq!(messages[2].content, REFORMAT_INSTRUCTION);
+    }
+
+    #[tokio::test]
+    async fn test_reformat_runs_call_validators() {
+        use crate::retry::RetryConfig;
+
+        let ctx = mock_ctx(&["score: high", "{\"score\": \"high\"}"]);
+        let call = LlmCall::new("score", "Score {input}")
+            .expecting_json()
+            .with_retry(RetryConfig::new(0).add_validator(
+                |_, value| match value["score"].as_f64() {
+                    Some(_) => Ok(()),
+                    None => Err("score must be a number".to_string()),
+                },
+            ));
+        let payload = ReformatPayload::for_call("score", call).unwrap();
+        let out = payload.invoke(&ctx, json!("x")).await.unwrap();
+        assert!(!out.diagnostics.as_ref().unwrap().ok());
+        assert_eq!(out.raw_response, "score: high");
+        assert_eq!(out.meta["reformat_attempts"], 1);
+    }
+}
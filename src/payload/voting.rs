@@ -0,0 +1,386 @@
+//! Self-consistency voting over repeated samples.
+//!
+//! [`VotingPayload`] samples an inner payload several times and aggregates
+//! the parsed results: the modal value for strings, choices, and other
+//! values, or the median when every vote is a number. Agreement between the
+//! votes is reported as a `confidence` in `[0, 1]`.
+
+use crate::{
+    error::Result,
+    exec_ctx::ExecCtx,
+    payload::{BoxFut, Payload, PayloadOutput},
+    PipelineError,
+};
+use futures::StreamExt;
+use serde_json::{json, Value};
+
+/// Where [`VotingPayload`] gets its votes from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum VoteSource {
+    /// Invoke the inner payload `n` times. Default.
+    #[default]
+    Repeat,
+
+    /// Invoke the inner payload once and vote over its
+    /// `meta["candidate_values"]`, as produced by an
+    /// [`LlmCall`](crate::LlmCall) configured with
+    /// [`LlmConfig::with_n`](crate::LlmConfig::with_n).
+    Candidates,
+}
+
+/// Samples an inner payload and returns the consensus result.
+///
+/// Votes whose output carries a parse error are discarded, as are failed
+/// invocations (unless every invocation fails, in which case the first
+/// error is returned). Cancellation is always propagated.
+///
+/// The result's `meta` records:
+/// - `votes` — `[{"value": v, "count": c}, ...]`, most common first
+/// - `confidence` — for modal votes, the winner's share of valid votes; for
+///   numeric votes, the share within [`with_tolerance`](Self::with_tolerance)
+///   of the median
+/// - `samples` / `valid` — how many samples were taken / usable
+///
+/// # Example
+///
+/// ```ignore
+/// use llm_pipeline::payload::VotingPayload;
+/// use llm_pipeline::LlmCall;
+///
+/// let vote = VotingPayload::new("classify", Box::new(
+///     LlmCall::new("classify", "Sentiment of: {input}")
+///         .expecting_choice(vec!["positive".into(), "negative".into()]),
+/// ), 5);
+///
+/// let output = vote.invoke(&ctx, json!("I loved it")).await?;
+/// println!("{} ({})", output.value, output.meta["confidence"]);
+/// ```
+pub struct VotingPayload {
+    name: String,
+    inner: Box<dyn Payload>,
+    samples: usize,
+    source: VoteSource,
+    concurrency: usize,
+    tolerance: f64,
+}
+
+impl VotingPayload {
+    /// Vote over `n` invocations of `inner`. `n` is clamped to at least 1.
+    pub fn new(name: impl Into<String>, inner: Box<dyn Payload>, n: usize) -> Self {
+        Self {
+            name: name.into(),
+            inner,
+            samples: n.max(1),
+            source: VoteSource::default(),
+            concurrency: 1,
+            tolerance: 0.0,
+        }
+    }
+
+    /// Set the vote source.
+    pub fn with_source(mut self, source: VoteSource) -> Self {
+        self.source = source;
+        self
+    }
+
+    /// Shorthand for `with_source(VoteSource::Candidates)`.
+    pub fn using_candidates(self) -> Self {
+        self.with_source(VoteSource::Candidates)
+    }
+
+    /// Maximum number of samples in flight at once ([`VoteSource::Repeat`]
+    /// only). Clamped to at least 1. Default: 1.
+    pub fn with_concurrency(mut self, limit: usize) -> Self {
+        self.concurrency = limit.max(1);
+        self
+    }
+
+    /// Numeric votes within this distance of the median count as agreeing
+    /// when computing `confidence`. Default: 0 (exact).
+    pub fn with_tolerance(mut self, tolerance: f64) -> Self {
+        self.tolerance = tolerance.abs();
+        self
+    }
+
+    /// Returns the number of samples.
+    pub fn samples(&self) -> usize {
+        self.samples
+    }
+
+    /// Returns the vote source.
+    pub fn source(&self) -> VoteSource {
+        self.source
+    }
+
+    /// Collect `(vote, output that produced it)` pairs, and the number of
+    /// samples taken, usable or not.
+    async fn collect_votes(
+        &self,
+        ctx: &ExecCtx,
+        input: Value,
+    ) -> Result<(Vec<(Value, PayloadOutput)>, usize)> {
+        match self.source {
+            VoteSource::Candidates => {
+                let output = self.inner.invoke(ctx, input).await?;
+                let values = match output.meta.get("candidate_values") {
+                    Some(Value::Array(values)) => values.clone(),
+                    _ => {
+                        return Err(PipelineError::StageFailed {
+                            stage: self.name.clone(),
+                            message: "inner payload produced no candidate_values; \
+                                      configure it with LlmConfig::with_n"
+                                .to_string(),
+                        })
+                    }
+                };
+                // Each candidate keeps the call's raw response, diagnostics,
+                // and metadata, with its own parsed value.
+                let samples = values.len();
+                let votes = values
+                    .into_iter()
+                    .filter(|v| !v.is_null())
+                    .map(|v| {
+                        let mut vote_output = output.clone();
+                        vote_output.value = v.clone();
+                        (v, vote_output)
+                    })
+                    .collect();
+                Ok((votes, samples))
+            }
+            VoteSource::Repeat => {
+                let mut results = futures::stream::iter(0..self.samples)
+                    .map(|_| {
+                        let input = input.clone();
+                        async move {
+                            ctx.check_cancelled()?;
+                            self.inner.invoke(ctx, input).await
+                        }
+                    })
+                    .buffer_unordered(self.concurrency);
+
+                let mut votes = Vec::new();
+                let mut first_error = None;
+                while let Some(result) = results.next().await {
+                    match result {
+                        Ok(output) => {
+                            let parse_failed = output.diagnostics.as_ref().is_some_and(|d| !d.ok());
+                            if !parse_failed {
+                                votes.push((output.value.clone(), output));
+                            }
+                        }
+                        Err(PipelineError::Cancelled) => return Err(PipelineError::Cancelled),
+                        Err(e) => {
+                            first_error.get_or_insert(e);
+                        }
+                    }
+                }
+
+                match first_error {
+                    Some(e) if votes.is_empty() => Err(e),
+                    _ => Ok((votes, self.samples)),
+                }
+            }
+        }
+    }
+}
+
+impl Payload for VotingPayload {
+    fn kind(&self) -> &'static str {
+        "voting"
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn invoke<'a>(&'a self, ctx: &'a ExecCtx, input: Value) -> BoxFut<'a, Result<PayloadOutput>> {
+        Box::pin(async move {
+            ctx.check_cancelled()?;
+            let (mut votes, samples) = self.collect_votes(ctx, input).await?;
+            if votes.is_empty() {
+                return Err(PipelineError::StageFailed {
+                    stage: self.name.clone(),
+                    message: "no valid votes".to_string(),
+                });
+            }
+
+            let valid = votes.len();
+            let distribution = tally(votes.iter().map(|(v, _)| v));
+            let numbers: Option<Vec<f64>> = votes.iter().map(|(v, _)| v.as_f64()).collect();
+
+            let (output, confidence) = match numbers {
+                Some(mut numbers) => {
+                    let median = median(&mut numbers);
+                    let agreeing = numbers
+                        .iter()
+                        .filter(|n| (*n - median).abs() <= self.tolerance)
+                        .count();
+                    let value = if median.fract() == 0.0 && votes.iter().all(|(v, _)| v.is_i64()) {
+                        json!(median as i64)
+                    } else {
+                        json!(median)
+                    };
+                    // The vote closest to the median supplies the output;
+                    // an even count's median replaces its value.
+                    let distance = |(v, _): &(Value, PayloadOutput)| {
+                        v.as_f64().map_or(f64::INFINITY, |n| (n - median).abs())
+                    };
+                    let closest = votes
+                        .iter()
+                        .enumerate()
+                        .min_by(|a, b| distance(a.1).total_cmp(&distance(b.1)))
+                        .map_or(0, |(i, _)| i);
+                    let mut output = votes.swap_remove(closest).1;
+                    output.value = value;
+                    (output, agreeing as f64 / valid as f64)
+                }
+                None => {
+                    let (winner, count) = distribution
+                        .first()
+                        .map(|(v, c)| (v.clone(), *c))
+                        .unwrap_or((Value::Null, 0));
+                    let i = votes.iter().position(|(v, _)| *v == winner).unwrap_or(0);
+                    (votes.swap_remove(i).1, count as f64 / valid as f64)
+                }
+            };
+
+            let votes_meta: Vec<Value> = distribution
+                .into_iter()
+                .map(|(value, count)| json!({ "value": value, "count": count }))
+                .collect();
+
+            Ok(output
+                .with_meta("votes", Value::Array(votes_meta))
+                .with_meta("confidence", json!(confidence))
+                .with_meta("samples", json!(samples))
+                .with_meta("valid", json!(valid)))
+        })
+    }
+}
+
+/// Count equal values, most common first (ties keep first-seen order).
+fn tally<'v>(values: impl Iterator<Item = &'v Value>) -> Vec<(Value, usize)> {
+    let mut counts: Vec<(Value, usize)> = Vec::new();
+    for value in values {
+        match counts.iter_mut().find(|(v, _)| v == value) {
+            Some((_, count)) => *count += 1,
+            None => counts.push((value.clone(), 1)),
+        }
+    }
+    // Stable sort preserves first-seen order among ties.
+    counts.sort_by_key(|(_, count)| std::cmp::Reverse(*count));
+    counts
+}
+
+/// Median of a non-empty slice (mean of the two middle values when even).
+fn median(numbers: &mut [f64]) -> f64 {
+    numbers.sort_by(|a, b| a.total_cmp(b));
+    let mid = numbers.len() / 2;
+    if numbers.len().is_multiple_of(2) {
+        (numbers[mid - 1] + numbers[mid]) / 2.0
+    } else {
+        numbers[mid]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{LlmCall, LlmConfig, MockBackend};
+    use std::sync::Arc;
+
+    fn mock_ctx(responses: &[&str]) -> ExecCtx {
+        ExecCtx::builder("http://test")
+            .backend(Arc::new(MockBackend::new(
+                responses.iter().map(|s| s.to_string()).collect(),
+            )))
+            .build()
+    }
+
+    fn choice_call() -> Box<dyn Payload> {
+        Box::new(
+            LlmCall::new("classify", "{input}")
+                .expecting_choice(vec!["positive".into(), "negative".into()]),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_voting_modal_choice() {
+        let ctx = mock_ctx(&["positive", "negative", "Positive.", "gibberish", "positive"]);
+        let vote = VotingPayload::new("vote", choice_call(), 5);
+        let out = vote.invoke(&ctx, json!("x")).await.unwrap();
+
+        assert_eq!(out.value, json!("positive"));
+        assert_eq!(out.meta["valid"], 4);
+        assert_eq!(out.meta["samples"], 5);
+        assert_eq!(out.meta["confidence"], json!(0.75));
+        assert_eq!(
+            out.meta["votes"][0],
+            json!({"value": "positive", "count": 3})
+        );
+        assert_eq!(
+            out.meta["votes"][1],
+            json!({"value": "negative", "count": 1})
+        );
+    }
+
+    #[tokio::test]
+    async fn test_voting_median_number() {
+        let ctx = mock_ctx(&["7", "9", "8", "100", "8"]);
+        let vote = VotingPayload::new(
+            "score",
+            Box::new(LlmCall::new("score", "{input}").expecting_number()),
+            5,
+        )
+        .with_concurrency(5)
+        .with_tolerance(1.0);
+        let out = vote.invoke(&ctx, json!("x")).await.unwrap();
+
+        assert_eq!(out.value.as_f64(), Some(8.0));
+        // 7, 8, 8, 9 are within 1.0 of the median; 100 is not.
+        assert_eq!(out.meta["confidence"], json!(0.8));
+    }
+
+    #[tokio::test]
+    async fn test_voting_from_backend_candidates() {
+        let ctx = mock_ctx(&["negative", "positive", "negative"]);
+        let call = LlmCall::new("classify", "{input}")
+            .with_config(LlmConfig::default().with_n(3))
+            .expecting_choice(vec!["positive".into(), "negative".into()]);
+        let vote = VotingPayload::new("vote", Box::new(call), 1).using_candidates();
+        let out = vote.invoke(&ctx, json!("x")).await.unwrap();
+
+        assert_eq!(out.value, json!("negative"));
+        assert_eq!(out.meta["samples"], 3);
+    }
+
+    #[tokio::test]
+    async fn test_voting_candidates_keep_call_output() {
+        let ctx = mock_ctx(&["negative", "gibberish", "negative"]);
+        let call = LlmCall::new("classify", "{input}")
+            .with_config(LlmConfig::default().with_n(3))
+            .expecting_choice(vec!["positive".into(), "negative".into()]);
+        let vote = VotingPayload::new("vote", Box::new(call), 1).using_candidates();
+        let out = vote.invoke(&ctx, json!("x")).await.unwrap();
+
+        assert_eq!(out.value, json!("negative"));
+        assert_eq!(out.meta["samples"], 3);
+        assert_eq!(out.meta["valid"], 2);
+        assert!(!out.raw_response.is_empty());
+        assert!(out.model.is_some());
+        assert_eq!(out.diagnostics.unwrap().strategy, Some("choice"));
+    }
+
+    #[tokio::test]
+    async fn test_voting_candidates_missing() {
+        let ctx = mock_ctx(&["positive"]);
+        let vote = VotingPayload::new("vote", choice_call(), 1).using_candidates();
+        let result = vote.invoke(&ctx, json!("x")).await;
+        assert!(matches!(result, Err(PipelineError::StageFailed { .. })));
+    }
+
+    #[test]
+    fn test_median_even() {
+        assert_eq!(median(&mut [4.0, 1.0, 3.0, 2.0]), 2.5);
+    }
+}
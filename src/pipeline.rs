@@ -1,17 +1,17 @@
 use crate::{
     error::Result,
+    events::{emit, Event, FnEventHandler},
     exec_ctx::ExecCtx,
     llm_call::LlmCall,
-    parsing,
-    payload::Payload,
+    payload::{Payload, PayloadOutput},
     stage::Stage,
-    streaming::StreamingDecoder,
     types::{PipelineContext, PipelineInput, PipelineProgress, PipelineResult, StageOutput},
     PipelineError,
 };
+use futures::future::Either;
 use futures::StreamExt;
 use reqwest::Client;
-use serde_json::{json, Value};
+use serde_json::Value;
 use std::sync::{
     atomic::{AtomicBool, Ordering},
     Arc,
@@ -115,8 +115,11 @@ where
 
     /// Execute the pipeline with a progress callback (non-streaming LLM calls).
     ///
-    /// The callback is invoked at the start of each stage. Stages are executed
-    /// as [`LlmCall`] payloads internally.
+    /// The callback is invoked at the start of each stage. For a stage with
+    /// a [retry policy](Stage::with_retry) it is invoked again for every
+    /// retry, with `current_step` as the attempt number (1 for the first
+    /// call) out of `total_steps` (`max_retries + 1`); other stages leave
+    /// both `None`. Stages are executed as [`LlmCall`] payloads internally.
     pub async fn execute_with_progress<F>(
         &self,
         client: &Client,
@@ -138,19 +141,29 @@ where
         for (idx, payload) in &payloads {
             self.check_cancelled()?;
 
-            on_progress(PipelineProgress {
+            let total_steps = payload.retry().map(|r| r.max_retries + 1);
+            let progress = PipelineProgress {
                 stage_index: *idx,
                 total_stages,
                 stage_name: payload.name().to_string(),
-                current_step: None,
-                total_steps: None,
-            });
-
-            let output = payload.invoke(&ctx, current_input).await.map_err(|e| {
-                PipelineError::StageFailed {
-                    stage: payload.name().to_string(),
-                    message: e.to_string(),
+                current_step: total_steps.map(|_| 1),
+                total_steps,
+            };
+            on_progress(progress.clone());
+
+            let prompt = payload.rendered_prompt(&ctx, &current_input);
+            let output = invoke_reporting(&ctx, payload, current_input, |event| {
+                if let StageEvent::Retry(attempt) = event {
+                    on_progress(PipelineProgress {
+                        current_step: Some(attempt + 1),
+                        ..progress.clone()
+                    })
                 }
+            })
+            .await
+            .map_err(|e| PipelineError::StageFailed {
+                stage: payload.name().to_string(),
+                message: e.to_string(),
             })?;
 
             // Parse into T from the structured output value
@@ -161,9 +174,11 @@ where
 
             current_input = output.value;
             stage_results.push(StageOutput {
+                stage: payload.name().to_string(),
                 output: parsed,
                 thinking: output.thinking,
                 raw_response: output.raw_response,
+                prompt,
             });
         }
 
@@ -182,11 +197,11 @@ where
 
     /// Execute the pipeline with streaming LLM calls and per-token callbacks.
     ///
-    /// Uses buffered line-framing to correctly handle JSON lines split across
-    /// chunk boundaries.
-    ///
-    /// `on_progress` is called at the start of each stage.
-    /// `on_token` is called for each token received from the LLM.
+    /// `on_progress` is called at the start of each stage and, for a stage
+    /// with a [retry policy](Stage::with_retry), for every retry, exactly
+    /// as in [`execute_with_progress`](Self::execute_with_progress).
+    /// `on_token` is called for each token received from the LLM. Only the
+    /// first attempt of a stage is streamed; retries are not.
     pub async fn execute_streaming<F, G>(
         &self,
         client: &Client,
@@ -200,7 +215,7 @@ where
         G: FnMut(usize, &str),
     {
         let ctx = self.build_ctx(client, endpoint);
-        let payloads = self.build_payloads(false); // don't use payload streaming path
+        let payloads = self.build_payloads(true);
         let stages_enabled: Vec<bool> = self.stages.iter().map(|s| s.enabled).collect();
         let total_stages = self.stages.len();
 
@@ -210,50 +225,42 @@ where
         for (idx, payload) in &payloads {
             self.check_cancelled()?;
 
-            on_progress(PipelineProgress {
+            let total_steps = payload.retry().map(|r| r.max_retries + 1);
+            let progress = PipelineProgress {
                 stage_index: *idx,
                 total_stages,
                 stage_name: payload.name().to_string(),
-                current_step: None,
-                total_steps: None,
-            });
-
-            // For streaming, we call the Ollama API directly with the callback
-            let input_str = match &current_input {
-                Value::String(s) => s.clone(),
-                other => other.to_string(),
-            };
-
-            let prompt = {
-                let mut rendered = payload.prompt_template().replace("{input}", &input_str);
-                for (key, value) in &ctx.vars {
-                    let placeholder = format!("{{{}}}", key);
-                    rendered = rendered.replace(&placeholder, value);
-                }
-                rendered
+                current_step: total_steps.map(|_| 1),
+                total_steps,
             };
+            on_progress(progress.clone());
+
+            let prompt = payload.rendered_prompt(&ctx, &current_input);
+            let output = invoke_reporting(&ctx, payload, current_input, |event| match event {
+                StageEvent::Retry(attempt) => on_progress(PipelineProgress {
+                    current_step: Some(attempt + 1),
+                    ..progress.clone()
+                }),
+                StageEvent::Token(chunk) => on_token(*idx, &chunk),
+            })
+            .await
+            .map_err(|e| PipelineError::StageFailed {
+                stage: payload.name().to_string(),
+                message: e.to_string(),
+            })?;
 
-            let raw_response = self
-                .stream_call(client, endpoint, payload, &prompt, *idx, &mut on_token)
-                .await
-                .map_err(|e| PipelineError::StageFailed {
-                    stage: payload.name().to_string(),
-                    message: e.to_string(),
-                })?;
-
-            let (thinking, cleaned) = parsing::extract_thinking(&raw_response);
-            let parsed: T = crate::output_parser::parse_json(&cleaned).map_err(|e| {
-                PipelineError::StageFailed {
-                    stage: payload.name().to_string(),
-                    message: e.to_string(),
-                }
+            let parsed: T = output.parse_as().map_err(|e| PipelineError::StageFailed {
+                stage: payload.name().to_string(),
+                message: e.to_string(),
             })?;
 
-            current_input = parsing::parse_value_lossy(&cleaned);
+            current_input = output.value;
             stage_results.push(StageOutput {
+                stage: payload.name().to_string(),
                 output: parsed,
-                thinking,
-                raw_response,
+                thinking: output.thinking,
+                raw_response: output.raw_response,
+                prompt,
             });
         }
 
@@ -269,87 +276,54 @@ where
             stages_enabled,
         })
     }
+}
 
-    /// Perform a single streaming call to Ollama, using buffered line framing.
-    async fn stream_call<G>(
-        &self,
-        client: &Client,
-        endpoint: &str,
-        payload: &LlmCall,
-        prompt: &str,
-        stage_idx: usize,
-        on_token: &mut G,
-    ) -> Result<String>
-    where
-        G: FnMut(usize, &str),
-    {
-        let config = payload.config();
-        let mut body = json!({
-            "model": payload.model(),
-            "prompt": prompt,
-            "stream": true,
-            "options": {
-                "temperature": config.temperature,
-                "num_predict": config.max_tokens,
-            },
-        });
-
-        if config.thinking {
-            body["options"]["extended_thinking"] = json!(true);
-        }
-
-        if config.json_mode {
-            body["format"] = json!("json");
-        }
+/// What a stage's invocation reports while it is still running.
+enum StageEvent {
+    /// A retry with this attempt number started.
+    Retry(u32),
+    /// A streamed token arrived.
+    Token(String),
+}
 
-        // Merge custom options
-        if let Some(ref opts) = config.options {
-            if let Some(options) = body["options"].as_object_mut() {
-                if let Some(custom) = opts.as_object() {
-                    for (k, v) in custom {
-                        options.insert(k.clone(), v.clone());
-                    }
-                }
+/// Invoke `payload`, calling `on_event` for every retry it starts and every
+/// token it streams while the invocation is still running. Events still
+/// reach `ctx`'s own event handler.
+async fn invoke_reporting(
+    ctx: &ExecCtx,
+    payload: &LlmCall,
+    input: Value,
+    mut on_event: impl FnMut(StageEvent),
+) -> Result<PayloadOutput> {
+    let (tx, mut rx) = futures::channel::mpsc::unbounded();
+    let mut stage_ctx = ctx.clone();
+    let handler = ctx.event_handler.clone();
+    stage_ctx.event_handler = Some(Arc::new(FnEventHandler(move |event: Event| {
+        match &event {
+            Event::RetryStart { attempt, .. } => {
+                let _ = tx.unbounded_send(StageEvent::Retry(*attempt));
             }
-        }
-
-        let url = format!("{}/api/generate", endpoint.trim_end_matches('/'));
-        let resp = client.post(&url).json(&body).send().await.map_err(|e| {
-            PipelineError::Other(format!("Failed to connect to LLM at {}: {}", url, e))
-        })?;
-
-        if !resp.status().is_success() {
-            let status = resp.status();
-            let text = resp.text().await.unwrap_or_default();
-            return Err(PipelineError::Other(format!(
-                "LLM returned error {}: {}",
-                status, text
-            )));
-        }
-
-        let mut stream = resp.bytes_stream();
-        let mut decoder = StreamingDecoder::new();
-        let mut accumulated = String::new();
-
-        while let Some(chunk) = stream.next().await {
-            let chunk = chunk.map_err(PipelineError::Request)?;
-            for json_val in decoder.decode(&chunk) {
-                if let Some(response) = json_val.get("response").and_then(|v| v.as_str()) {
-                    accumulated.push_str(response);
-                    on_token(stage_idx, response);
-                }
+            Event::Token { chunk, .. } => {
+                let _ = tx.unbounded_send(StageEvent::Token(chunk.clone()));
             }
+            _ => {}
         }
-
-        // Flush remaining buffer
-        if let Some(json_val) = decoder.flush() {
-            if let Some(response) = json_val.get("response").and_then(|v| v.as_str()) {
-                accumulated.push_str(response);
-                on_token(stage_idx, response);
+        emit(&handler, event);
+    })));
+
+    let mut invocation = payload.invoke(&stage_ctx, input);
+    loop {
+        match futures::future::select(&mut invocation, rx.next()).await {
+            Either::Left((result, _)) => {
+                // Events sent in the same poll that finished the call.
+                while let Ok(event) = rx.try_recv() {
+                    on_event(event);
+                }
+                return result;
             }
+            Either::Right((Some(event), _)) => on_event(event),
+            Either::Right((None, _)) => return invocation.await,
         }
-
-        Ok(accumulated)
     }
 }
 
@@ -535,4 +509,114 @@ mod tests {
         assert_eq!(payloads[1].0, 2); // stage index 2 (b was skipped)
         assert_eq!(payloads[1].1.name(), "c");
     }
+
+    #[tokio::test]
+    async fn test_execute_with_progress_reports_retry_steps() {
+        use crate::retry::RetryConfig;
+        use crate::test_support::serve_replies;
+
+        // First reply (generate) isn't JSON; the retry (chat) is.
+        let endpoint = serve_replies(vec![
+            (200, r#"{"response": "not json", "done": true}"#),
+            (
+                200,
+                r#"{"message": {"role": "assistant", "content": "{\"value\": \"ok\"}"}, "done": true}"#,
+            ),
+        ])
+        .await;
+
+        let pipeline = Pipeline::<TestOutput>::builder()
+            .add_stage(Stage::new("extract", "{input}").with_retry(RetryConfig::new(2)))
+            .build()
+            .unwrap();
+        let mut steps = Vec::new();
+        let result = pipeline
+            .execute_with_progress(&Client::new(), &endpoint, PipelineInput::new("x"), |p| {
+                steps.push((p.current_step, p.total_steps))
+            })
+            .await
+            .unwrap();
+
+        assert_eq!(result.final_output.value, "ok");
+        assert_eq!(result.stage_results[0].stage, "extract");
+        assert_eq!(steps, vec![(Some(1), Some(3)), (Some(2), Some(3))]);
+    }
+
+    #[tokio::test]
+    async fn test_execute_streaming_retries_and_streams_tokens() {
+        use crate::retry::RetryConfig;
+        use crate::test_support::serve_replies;
+
+        // The streamed first attempt isn't JSON; the (non-streaming) retry is.
+        let endpoint = serve_replies(vec![
+            (
+                200,
+                "{\"response\": \"not \", \"done\": false}\n{\"response\": \"json\", \"done\": true}\n",
+            ),
+            (
+                200,
+                r#"{"message": {"role": "assistant", "content": "{\"value\": \"ok\"}"}, "done": true}"#,
+            ),
+        ])
+        .await;
+
+        let pipeline = Pipeline::<TestOutput>::builder()
+            .add_stage(Stage::new("extract", "{input}").with_retry(RetryConfig::new(2)))
+            .build()
+            .unwrap();
+        let mut steps = Vec::new();
+        let mut tokens = Vec::new();
+        let result = pipeline
+            .execute_streaming(
+                &Client::new(),
+                &endpoint,
+                PipelineInput::new("x"),
+                |p| steps.push((p.current_step, p.total_steps)),
+                |stage, token| tokens.push((stage, token.to_string())),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(result.final_output.value, "ok");
+        assert_eq!(steps, vec![(Some(1), Some(3)), (Some(2), Some(3))]);
+        assert_eq!(
+            tokens,
+            vec![(0, "not ".to_string()), (0, "json".to_string())]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_invoke_reporting_keeps_ctx_event_handler() {
+        use crate::retry::RetryConfig;
+        use crate::MockBackend;
+        use std::sync::Mutex;
+
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let sink = seen.clone();
+        let ctx = ExecCtx::builder("http://test")
+            .backend(Arc::new(MockBackend::new(vec![
+                "nope".into(),
+                r#"{"value": "ok"}"#.into(),
+            ])))
+            .event_handler(Arc::new(FnEventHandler(move |event: Event| {
+                if let Event::RetryStart { attempt, .. } = event {
+                    sink.lock().unwrap().push(attempt);
+                }
+            })))
+            .build();
+        let stage = Stage::new("extract", "{input}").with_retry(RetryConfig::new(2));
+        let payload = LlmCall::from_stage(&stage, false);
+
+        let mut retries = Vec::new();
+        invoke_reporting(&ctx, &payload, Value::from("x"), |event| {
+            if let StageEvent::Retry(attempt) = event {
+                retries.push(attempt);
+            }
+        })
+        .await
+        .unwrap();
+
+        assert_eq!(retries, vec![1]);
+        assert_eq!(*seen.lock().unwrap(), vec![1]);
+    }
 }
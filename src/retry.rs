@@ -5,12 +5,75 @@
 //! the retry system constructs a correction prompt containing the original
 //! request, the bad output, and the error description, then re-calls the model.
 
+use crate::payload::PayloadOutput;
+use futures::future::BoxFuture;
 use serde_json::Value;
+use std::future::Future;
 use std::sync::Arc;
 
 /// Type alias for the semantic validator function used in [`RetryConfig`].
 pub type ValidatorFn = Arc<dyn Fn(&str, &Value) -> Result<(), String> + Send + Sync>;
 
+/// Type alias for the async validator function used in [`RetryConfig`].
+pub type AsyncValidatorFn =
+    Arc<dyn Fn(&str, &Value) -> BoxFuture<'static, Result<(), String>> + Send + Sync>;
+
+/// Type alias for the retry trigger predicate used in [`RetryConfig`].
+pub type RetryIfFn = Arc<dyn Fn(&PayloadOutput) -> Option<String> + Send + Sync>;
+
+/// Why a semantic retry was triggered, for bucketing retries in metrics.
+///
+/// Reported in [`Event::RetryStart`](crate::events::Event::RetryStart) and
+/// [`ParseDiagnostics::retry_reasons`](crate::diagnostics::ParseDiagnostics::retry_reasons).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum RetryReason {
+    /// The output strategy could not parse the response.
+    Parse,
+    /// A required key was missing or null: [`RetryConfig::requiring_keys`],
+    /// or nothing at a `JsonPointer` strategy's pointer.
+    MissingKey,
+    /// A `NumberInRange` response had no number in range, or a list's
+    /// length was outside [`RetryConfig::requiring_list_len`].
+    Range,
+    /// A `Choice` or `ChoiceStrict` response matched none of the choices.
+    Choice,
+    /// A user validator, async validator, or
+    /// [`retry_if`](RetryConfig::retry_if) predicate rejected the output.
+    Custom,
+    /// The response was empty or whitespace-only.
+    Empty,
+    /// The model declined to answer (see
+    /// [`ParseDiagnostics::refusal`](crate::diagnostics::ParseDiagnostics::refusal)).
+    Refusal,
+    /// The response was cut off inside a `<think>` block before any answer
+    /// (see
+    /// [`ParseDiagnostics::truncated_in_thinking`](crate::diagnostics::ParseDiagnostics::truncated_in_thinking)).
+    /// The retry doubles `max_tokens` and turns thinking off.
+    TruncatedThinking,
+}
+
+impl RetryReason {
+    /// Stable lowercase name, e.g. `"missing_key"`.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Parse => "parse",
+            Self::MissingKey => "missing_key",
+            Self::Range => "range",
+            Self::Choice => "choice",
+            Self::Custom => "custom",
+            Self::Empty => "empty",
+            Self::Refusal => "refusal",
+            Self::TruncatedThinking => "truncated_thinking",
+        }
+    }
+}
+
+impl std::fmt::Display for RetryReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
 /// Configuration for LLM-in-the-loop retry on parse failure.
 ///
 /// When the output strategy on [`LlmCall`](crate::llm_call::LlmCall) produces
@@ -30,6 +93,12 @@ pub type ValidatorFn = Arc<dyn Fn(&str, &Value) -> Result<(), String> + Send + S
 /// let config = RetryConfig::new(2)
 ///     .requiring_keys(&["title", "year"]);
 ///
+/// // Retry on a valid-but-unwanted output, with custom feedback
+/// let config = RetryConfig::new(2).retry_if(|output| {
+///     (output.value["status"] == "error")
+///         .then(|| "The status was \"error\"; try the task again.".to_string())
+/// });
+///
 /// // Disable temperature cool-down
 /// let config = RetryConfig::new(3).no_cool_down();
 /// ```
@@ -44,9 +113,39 @@ pub struct RetryConfig {
     /// that goes beyond structural parsing.
     ///
     /// The function receives `(raw_text, parsed_value)` and returns
-    /// `Ok(())` on success or `Err(reason)` on failure.
+    /// `Ok(())` on success or `Err(reason)` on failure. Set by
+    /// [`with_validator`](Self::with_validator) and the `requiring_*`
+    /// shorthands, each replacing the last; validators added with
+    /// [`add_validator`](Self::add_validator) run after it.
     pub validator: Option<ValidatorFn>,
 
+    /// How failures of `validator` are categorized. Default:
+    /// [`RetryReason::Custom`]; [`requiring_keys`](Self::requiring_keys)
+    /// sets [`RetryReason::MissingKey`].
+    pub validator_reason: RetryReason,
+
+    /// Validators added with [`add_validator`](Self::add_validator), in
+    /// order. See [`validators`](Self::validators).
+    added_validators: Vec<ValidatorFn>,
+
+    /// Optional validator that can await, e.g. to ask an external service.
+    /// Runs last, only for output every other check accepted, so the
+    /// external call is never made for output that would be retried anyway.
+    pub async_validator: Option<AsyncValidatorFn>,
+
+    /// Optional retry trigger. Runs after the OutputStrategy and validator
+    /// both pass; returning `Some(feedback)` retries with `feedback` sent to
+    /// the model verbatim as the correction message.
+    pub retry_if: Option<RetryIfFn>,
+
+    /// Optional example of valid output, shown to the model in the
+    /// correction message after a parse or validator failure.
+    pub example: Option<Value>,
+
+    /// Retry when the raw response is empty or whitespace-only, even if the
+    /// output strategy accepts it. Default: `true`.
+    pub retry_on_empty: bool,
+
     /// Lower temperature on each retry. Default: `true`.
     /// Drops by 0.2 per retry (floored at 0.0).
     pub cool_down: bool,
@@ -58,6 +157,12 @@ impl RetryConfig {
         Self {
             max_retries: max_retries.min(5),
             validator: None,
+            validator_reason: RetryReason::Custom,
+            added_validators: Vec::new(),
+            async_validator: None,
+            retry_if: None,
+            example: None,
+            retry_on_empty: true,
             cool_down: true,
         }
     }
@@ -65,19 +170,137 @@ impl RetryConfig {
     /// Retry with an additional semantic validator.
     ///
     /// The validator receives `(raw_text, parsed_value)` and returns
-    /// `Ok(())` on success or `Err(reason_string)` on failure.
+    /// `Ok(())` on success or `Err(reason_string)` on failure. Replaces the
+    /// [`validator`](Self::validator) set by an earlier call or `requiring_*`
+    /// shorthand, but not those added with
+    /// [`add_validator`](Self::add_validator).
     pub fn with_validator(
+        self,
+        f: impl Fn(&str, &Value) -> Result<(), String> + Send + Sync + 'static,
+    ) -> Self {
+        self.with_validator_as(RetryReason::Custom, f)
+    }
+
+    /// Set [`validator`](Self::validator), reporting its failures as `reason`.
+    fn with_validator_as(
         mut self,
+        reason: RetryReason,
         f: impl Fn(&str, &Value) -> Result<(), String> + Send + Sync + 'static,
     ) -> Self {
         self.validator = Some(Arc::new(f));
+        self.validator_reason = reason;
+        self
+    }
+
+    /// Add a semantic validator that runs after
+    /// [`validator`](Self::validator) and any added before it. Every
+    /// validator must pass; the first failure triggers the retry, reported
+    /// as [`RetryReason::Custom`]. Nothing replaces an added validator.
+    /// Combine alternatives with [`any_of`].
+    ///
+    /// ```
+    /// use llm_pipeline::retry::RetryConfig;
+    ///
+    /// let config = RetryConfig::new(2)
+    ///     .requiring_keys(&["score"])
+    ///     .add_validator(|_raw, value| match value["score"].as_f64() {
+    ///         Some(score) if (0.0..=1.0).contains(&score) => Ok(()),
+    ///         _ => Err("score must be a number between 0 and 1".to_string()),
+    ///     });
+    /// assert_eq!(config.validators().count(), 2);
+    /// ```
+    pub fn add_validator(
+        mut self,
+        f: impl Fn(&str, &Value) -> Result<(), String> + Send + Sync + 'static,
+    ) -> Self {
+        self.added_validators.push(Arc::new(f));
+        self
+    }
+
+    /// Every synchronous validator in the order they run, with the category
+    /// its failures are reported as: [`validator`](Self::validator), then
+    /// those added with [`add_validator`](Self::add_validator).
+    pub fn validators(&self) -> impl Iterator<Item = (RetryReason, &ValidatorFn)> {
+        let primary = self
+            .validator
+            .as_ref()
+            .map(|validator| (self.validator_reason, validator));
+        primary.into_iter().chain(
+            self.added_validators
+                .iter()
+                .map(|validator| (RetryReason::Custom, validator)),
+        )
+    }
+
+    /// Run the [`validators`](Self::validators) in order, returning the
+    /// first failure and its category.
+    pub fn validate(&self, raw: &str, value: &Value) -> Result<(), (RetryReason, String)> {
+        self.validators()
+            .try_for_each(|(reason, validator)| validator(raw, value).map_err(|e| (reason, e)))
+    }
+
+    /// Retry with a validator that can await, such as a schema registry,
+    /// moderation API, or database lookup.
+    ///
+    /// Like [`with_validator`](Self::with_validator), `f` receives
+    /// `(raw_text, parsed_value)` and its future resolves to `Ok(())` or
+    /// `Err(reason)`. The future must be `'static`, so clone what it needs
+    /// from the arguments before awaiting. It runs after the output strategy,
+    /// the [`validators`](Self::validators), and [`retry_if`](Self::retry_if)
+    /// have all passed.
+    ///
+    /// ```ignore
+    /// let registry = registry.clone();
+    /// let config = RetryConfig::new(2).with_async_validator(move |_raw, value| {
+    ///     let registry = registry.clone();
+    ///     let value = value.clone();
+    ///     async move { registry.validate("order-v2", &value).await }
+    /// });
+    /// ```
+    pub fn with_async_validator<F, Fut>(mut self, f: F) -> Self
+    where
+        F: Fn(&str, &Value) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<(), String>> + Send + 'static,
+    {
+        self.async_validator = Some(Arc::new(move |raw: &str, value: &Value| {
+            Box::pin(f(raw, value)) as BoxFuture<'static, Result<(), String>>
+        }));
+        self
+    }
+
+    /// Also retry when `f` returns `Some(feedback)` for an otherwise valid
+    /// output.
+    ///
+    /// Unlike [`with_validator`](Self::with_validator), which reports the
+    /// output as invalid, the returned string is sent to the model as-is, so
+    /// it can ask for a different answer without calling the last one wrong.
+    pub fn retry_if(
+        mut self,
+        f: impl Fn(&PayloadOutput) -> Option<String> + Send + Sync + 'static,
+    ) -> Self {
+        self.retry_if = Some(Arc::new(f));
+        self
+    }
+
+    /// Include `valid_output` in the correction message, as
+    /// "Here is an example of valid output: ...".
+    ///
+    /// A concrete example helps small models that struggle with abstract
+    /// format descriptions. Strings are shown as-is, other values as JSON.
+    /// Not added to [`retry_if`](Self::retry_if) feedback, which is sent
+    /// verbatim.
+    pub fn with_example(mut self, valid_output: Value) -> Self {
+        self.example = Some(valid_output);
         self
     }
 
     /// Shorthand: validate that specific JSON keys exist and are non-null.
+    /// Sets [`validator`](Self::validator) like
+    /// [`with_validator`](Self::with_validator); failures are reported as
+    /// [`RetryReason::MissingKey`].
     pub fn requiring_keys(self, keys: &[&str]) -> Self {
         let keys: Vec<String> = keys.iter().map(|k| k.to_string()).collect();
-        self.with_validator(move |_raw, value| {
+        self.with_validator_as(RetryReason::MissingKey, move |_raw, value| {
             for key in &keys {
                 match value.get(key.as_str()) {
                     None => return Err(format!("missing required key: '{}'", key)),
@@ -91,6 +314,66 @@ impl RetryConfig {
         })
     }
 
+    /// Shorthand: validate that the parsed value is an array of between
+    /// `min` and `max` items (inclusive), e.g. from a `StringList` or
+    /// `JsonArray` strategy. Sets [`validator`](Self::validator) like
+    /// [`with_validator`](Self::with_validator); failures are reported as
+    /// [`RetryReason::Range`].
+    ///
+    /// The correction message reads like "expected 3-10 items, got 1".
+    pub fn requiring_list_len(self, min: usize, max: usize) -> Self {
+        let expected = if min == max {
+            min.to_string()
+        } else {
+            format!("{}-{}", min, max)
+        };
+        self.with_validator_as(RetryReason::Range, move |_raw, value| {
+            match value.as_array() {
+                Some(items) if (min..=max).contains(&items.len()) => Ok(()),
+                Some(items) => Err(format!("expected {} items, got {}", expected, items.len())),
+                None => Err(format!("expected a list of {} items", expected)),
+            }
+        })
+    }
+
+    /// Retry when the output isn't in `language` (`lang-detect` feature).
+    ///
+    /// Checks the parsed value's text: the string itself, or every string
+    /// inside an array or object. Text too short or ambiguous to detect
+    /// reliably passes. Sets [`validator`](Self::validator) like
+    /// [`with_validator`](Self::with_validator).
+    ///
+    /// Look up an ISO 639-1 (`"fr"`) or ISO 639-3 (`"fra"`) code with
+    /// [`language_from_code`](crate::lang_detect::language_from_code).
+    ///
+    /// ```
+    /// use llm_pipeline::lang_detect::Lang;
+    /// use llm_pipeline::retry::RetryConfig;
+    ///
+    /// let config = RetryConfig::new(2).requiring_language(Lang::Fra);
+    /// ```
+    #[cfg(feature = "lang-detect")]
+    pub fn requiring_language(self, language: crate::lang_detect::Lang) -> Self {
+        use crate::lang_detect::{detect_language, value_text};
+
+        self.with_validator(
+            move |_raw, value| match detect_language(&value_text(value)) {
+                Some(detected) if detected != language => Err(format!(
+                    "response is in {}, but it must be written in {}",
+                    detected.eng_name(),
+                    language.eng_name()
+                )),
+                _ => Ok(()),
+            },
+        )
+    }
+
+    /// Accept an empty or whitespace-only response instead of retrying it.
+    pub fn allow_empty(mut self) -> Self {
+        self.retry_on_empty = false;
+        self
+    }
+
     /// Disable temperature cool-down.
     pub fn no_cool_down(mut self) -> Self {
         self.cool_down = false;
@@ -103,11 +386,53 @@ impl std::fmt::Debug for RetryConfig {
         f.debug_struct("RetryConfig")
             .field("max_retries", &self.max_retries)
             .field("has_validator", &self.validator.is_some())
+            .field("validator_reason", &self.validator_reason)
+            .field("added_validators", &self.added_validators.len())
+            .field("has_async_validator", &self.async_validator.is_some())
+            .field("has_retry_if", &self.retry_if.is_some())
+            .field("example", &self.example)
+            .field("retry_on_empty", &self.retry_on_empty)
             .field("cool_down", &self.cool_down)
             .finish()
     }
 }
 
+/// Combine validators so that the output passes if ANY of them accepts it.
+///
+/// Runs them in order and stops at the first success. If all fail, the
+/// reasons are joined with `"; or "`. Pass the result to
+/// [`RetryConfig::add_validator`].
+///
+/// ```
+/// use llm_pipeline::retry::{any_of, RetryConfig, ValidatorFn};
+/// use std::sync::Arc;
+///
+/// let is_string: ValidatorFn = Arc::new(|_raw, v| {
+///     v.is_string().then_some(()).ok_or_else(|| "expected a string".to_string())
+/// });
+/// let is_list: ValidatorFn = Arc::new(|_raw, v| {
+///     v.is_array().then_some(()).ok_or_else(|| "expected a list".to_string())
+/// });
+/// let config = RetryConfig::new(2).add_validator(any_of(vec![is_string, is_list]));
+/// assert!(config.validate("", &serde_json::json!(["a"])).is_ok());
+/// let (_, reason) = config.validate("", &serde_json::json!(1)).unwrap_err();
+/// assert_eq!(reason, "expected a string; or expected a list");
+/// ```
+pub fn any_of(
+    validators: Vec<ValidatorFn>,
+) -> impl Fn(&str, &Value) -> Result<(), String> + Send + Sync + 'static {
+    move |raw, value| {
+        let mut reasons = Vec::with_capacity(validators.len());
+        for validator in &validators {
+            match validator(raw, value) {
+                Ok(()) => return Ok(()),
+                Err(reason) => reasons.push(reason),
+            }
+        }
+        Err(reasons.join("; or "))
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -117,6 +442,11 @@ mod tests {
         let config = RetryConfig::new(3);
         assert_eq!(config.max_retries, 3);
         assert!(config.validator.is_none());
+        assert!(config.async_validator.is_none());
+        assert_eq!(config.validator_reason, RetryReason::Custom);
+        assert!(config.retry_if.is_none());
+        assert!(config.example.is_none());
+        assert!(config.retry_on_empty);
         assert!(config.cool_down);
     }
 
@@ -126,6 +456,12 @@ mod tests {
         assert_eq!(config.max_retries, 5);
     }
 
+    #[test]
+    fn test_retry_allow_empty() {
+        let config = RetryConfig::new(2).allow_empty();
+        assert!(!config.retry_on_empty);
+    }
+
     #[test]
     fn test_retry_no_cool_down() {
         let config = RetryConfig::new(2).no_cool_down();
@@ -159,6 +495,46 @@ mod tests {
         assert!(result.unwrap().is_err());
     }
 
+    #[test]
+    fn test_requiring_list_len() {
+        let config = RetryConfig::new(2).requiring_list_len(3, 10);
+        assert_eq!(config.validator_reason, RetryReason::Range);
+        let validate = |v: Value| config.validator.as_ref().unwrap()("", &v);
+
+        assert!(validate(serde_json::json!(["a", "b", "c"])).is_ok());
+        assert_eq!(
+            validate(serde_json::json!(["a"])).unwrap_err(),
+            "expected 3-10 items, got 1"
+        );
+        assert_eq!(
+            validate(serde_json::json!({"a": 1})).unwrap_err(),
+            "expected a list of 3-10 items"
+        );
+
+        let exact = RetryConfig::new(2).requiring_list_len(2, 2);
+        assert_eq!(
+            exact.validator.as_ref().unwrap()("", &serde_json::json!([])).unwrap_err(),
+            "expected 2 items, got 0"
+        );
+    }
+
+    #[cfg(feature = "lang-detect")]
+    #[test]
+    fn test_requiring_language() {
+        let config = RetryConfig::new(2).requiring_language(crate::lang_detect::Lang::Fra);
+        let validate = |v: Value| config.validator.as_ref().unwrap()("", &v);
+
+        let french = serde_json::json!({"summary": "Le gouvernement a annoncé une nouvelle réforme des retraites ce matin."});
+        assert!(validate(french).is_ok());
+
+        let english =
+            serde_json::json!("The government announced a new pension reform this morning.");
+        let err = validate(english).unwrap_err();
+        assert!(err.contains("English") && err.contains("French"));
+
+        assert!(validate(serde_json::json!("ok")).is_ok());
+    }
+
     #[test]
     fn test_custom_validator() {
         let config = RetryConfig::new(2).with_validator(|_raw, value| {
@@ -188,4 +564,27 @@ mod tests {
             .unwrap()
             .is_err());
     }
+
+    #[test]
+    fn test_validators_compose() {
+        let config = RetryConfig::new(2)
+            .add_validator(|_raw, value| match value.get("tags") {
+                Some(_) => Ok(()),
+                None => Err("no tags".to_string()),
+            })
+            .with_validator(|_raw, _value| Err("replaced".to_string()))
+            .requiring_keys(&["title"]);
+        assert_eq!(config.validators().count(), 2);
+
+        // `validator` runs first, then the added ones in order.
+        let (reason, _) = config.validate("", &serde_json::json!({})).unwrap_err();
+        assert_eq!(reason, RetryReason::MissingKey);
+        let (reason, message) = config
+            .validate("", &serde_json::json!({"title": "x"}))
+            .unwrap_err();
+        assert_eq!((reason, message.as_str()), (RetryReason::Custom, "no tags"));
+        assert!(config
+            .validate("", &serde_json::json!({"tags": [], "title": "x"}))
+            .is_ok());
+    }
 }
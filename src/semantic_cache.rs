@@ -0,0 +1,277 @@
+//! Similarity-based response cache.
+//!
+//! [`SemanticCache`] stores the outputs of [`LlmCall`](crate::LlmCall)s
+//! together with an embedding of their rendered prompt. A later call whose
+//! prompt embeds within the cosine threshold of a stored one gets the stored
+//! output back instead of hitting the model — useful for FAQ-style nodes that
+//! see many paraphrases of the same question.
+//!
+//! Enable with the `semantic-cache` feature and install on a context with
+//! [`ExecCtxBuilder::semantic_cache`](crate::ExecCtxBuilder::semantic_cache).
+
+use crate::payload::PayloadOutput;
+use std::collections::VecDeque;
+use std::sync::Mutex;
+
+/// Default maximum number of stored entries.
+const DEFAULT_MAX_ENTRIES: usize = 1024;
+
+struct Entry {
+    scope: String,
+    embedding: Vec<f32>,
+    output: PayloadOutput,
+}
+
+/// In-memory vector store of prompt embeddings and their outputs.
+///
+/// Entries are scoped per call (payload name and model), so two different
+/// calls never answer for each other. Lookups are a linear scan, which is
+/// fine for the few thousand entries this is meant for; once
+/// [`max_entries`](Self::with_max_entries) is reached the oldest entry is
+/// evicted.
+pub struct SemanticCache {
+    threshold: f32,
+    embed_model: String,
+    max_entries: usize,
+    entries: Mutex<VecDeque<Entry>>,
+}
+
+impl SemanticCache {
+    /// Create a cache that treats prompts with cosine similarity above
+    /// `threshold` as equivalent, embedding them with `embed_model`.
+    pub fn new(threshold: f32, embed_model: impl Into<String>) -> Self {
+        Self {
+            threshold,
+            embed_model: embed_model.into(),
+            max_entries: DEFAULT_MAX_ENTRIES,
+            entries: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    /// Cap the number of stored entries. Clamped to at least 1. Default: 1024.
+    pub fn with_max_entries(mut self, max: usize) -> Self {
+        self.max_entries = max.max(1);
+        self
+    }
+
+    /// Returns the similarity threshold.
+    pub fn threshold(&self) -> f32 {
+        self.threshold
+    }
+
+    /// Returns the embedding model.
+    pub fn embed_model(&self) -> &str {
+        &self.embed_model
+    }
+
+    /// Number of stored entries.
+    pub fn len(&self) -> usize {
+        self.entries.lock().unwrap().len()
+    }
+
+    /// Whether the cache is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Remove every entry.
+    pub fn clear(&self) {
+        self.entries.lock().unwrap().clear();
+    }
+
+    /// Find the most similar entry in `scope` above the threshold, returning
+    /// its output and similarity.
+    pub fn lookup(&self, scope: &str, embedding: &[f32]) -> Option<(PayloadOutput, f32)> {
+        let entries = self.entries.lock().unwrap();
+        entries
+            .iter()
+            .filter(|e| e.scope == scope)
+            .map(|e| (e, cosine_similarity(&e.embedding, embedding)))
+            .filter(|(_, sim)| *sim > self.threshold)
+            .max_by(|a, b| a.1.total_cmp(&b.1))
+            .map(|(e, sim)| (e.output.clone(), sim))
+    }
+
+    /// Store `output` under `scope` and `embedding`.
+    pub fn insert(&self, scope: impl Into<String>, embedding: Vec<f32>, output: PayloadOutput) {
+        let mut entries = self.entries.lock().unwrap();
+        if entries.len() >= self.max_entries {
+            entries.pop_front();
+        }
+        entries.push_back(Entry {
+            scope: scope.into(),
+            embedding,
+            output,
+        });
+    }
+}
+
+impl std::fmt::Debug for SemanticCache {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SemanticCache")
+            .field("threshold", &self.threshold)
+            .field("embed_model", &self.embed_model)
+            .field("max_entries", &self.max_entries)
+            .field("len", &self.len())
+            .finish()
+    }
+}
+
+/// Cosine similarity of two vectors; `0.0` if either is all zeros or the
+/// lengths differ.
+pub fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
+    if a.len() != b.len() {
+        return 0.0;
+    }
+    let dot: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+    let norm_a = a.iter().map(|x| x * x).sum::<f32>().sqrt();
+    let norm_b = b.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if norm_a == 0.0 || norm_b == 0.0 {
+        0.0
+    } else {
+        dot / (norm_a * norm_b)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ExecCtx, LlmCall, MockBackend, Payload};
+    use serde_json::json;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_cosine_similarity() {
+        assert!((cosine_similarity(&[1.0, 0.0], &[2.0, 0.0]) - 1.0).abs() < 1e-6);
+        assert_eq!(cosine_similarity(&[1.0, 0.0], &[0.0, 1.0]), 0.0);
+        assert_eq!(cosine_similarity(&[0.0, 0.0], &[1.0, 0.0]), 0.0);
+        assert_eq!(cosine_similarity(&[1.0], &[1.0, 0.0]), 0.0);
+    }
+
+    #[test]
+    fn test_lookup_respects_scope_and_eviction() {
+        let cache = SemanticCache::new(0.9, "embed").with_max_entries(2);
+        cache.insert("a", vec![1.0, 0.0], PayloadOutput::from_value(json!(1)));
+        assert!(cache.lookup("b", &[1.0, 0.0]).is_none());
+        assert_eq!(cache.lookup("a", &[1.0, 0.1]).unwrap().0.value, json!(1));
+        assert!(cache.lookup("a", &[0.0, 1.0]).is_none());
+
+        cache.insert("a", vec![0.0, 1.0], PayloadOutput::from_value(json!(2)));
+        cache.insert("a", vec![1.0, 1.0], PayloadOutput::from_value(json!(3)));
+        assert_eq!(cache.len(), 2);
+        assert!(cache.lookup("a", &[1.0, 0.0]).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_llm_call_hits_semantic_cache() {
+        let ctx = ExecCtx::builder("http://test")
+            .backend(Arc::new(MockBackend::new(vec![
+                "Paris".to_string(),
+                "Berlin".to_string(),
+            ])))
+            .semantic_cache(0.8, "nomic-embed-text")
+            .build();
+        let call = LlmCall::new("faq", "Question: {input}").expecting_text();
+
+        let first = call
+            .invoke(&ctx, json!("What is the capital of France?"))
+            .await
+            .unwrap();
+        assert_eq!(first.value, json!("Paris"));
+        assert!(!first.diagnostics.unwrap().cache_hit);
+
+        let second = call
+            .invoke(&ctx, json!("what is the capital of france"))
+            .await
+            .unwrap();
+        assert_eq!(second.value, json!("Paris"));
+        assert!(second.diagnostics.unwrap().cache_hit);
+
+        let third = call
+            .invoke(&ctx, json!("Best pizza topping in Naples?"))
+            .await
+            .unwrap();
+        assert_eq!(third.value, json!("Berlin"));
+        assert_eq!(ctx.semantic_cache.as_ref().unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_cache_hit_emits_parse_result() {
+        use crate::events::{Event, FnEventHandler};
+
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let sink = seen.clone();
+        let ctx = ExecCtx::builder("http://test")
+            .backend(Arc::new(MockBackend::new(vec!["Paris".to_string()])))
+            .event_handler(Arc::new(FnEventHandler(move |event: Event| {
+                if let Event::ParseResult { ok, .. } = event {
+                    sink.lock().unwrap().push(ok);
+                }
+            })))
+            .semantic_cache(0.8, "nomic-embed-text")
+            .build();
+        let call = LlmCall::new("faq", "Question: {input}").expecting_text();
+
+        call.invoke(&ctx, json!("What is the capital of France?"))
+            .await
+            .unwrap();
+        let hit = call
+            .invoke(&ctx, json!("what is the capital of france"))
+            .await
+            .unwrap();
+        assert!(hit.diagnostics.unwrap().cache_hit);
+        assert_eq!(*seen.lock().unwrap(), vec![true, true]);
+    }
+
+    #[tokio::test]
+    async fn test_rejected_output_not_cached() {
+        use crate::RetryConfig;
+
+        let ctx = ExecCtx::builder("http://test")
+            .backend(Arc::new(MockBackend::new(vec![
+                "Paris".to_string(),
+                "Paris".to_string(),
+                "Lyon".to_string(),
+            ])))
+            .semantic_cache(0.8, "nomic-embed-text")
+            .build();
+        let call = LlmCall::new("faq", "Question: {input}")
+            .expecting_text()
+            .with_retry(RetryConfig::new(1).with_validator(|raw, _| {
+                (raw != "Paris")
+                    .then_some(())
+                    .ok_or_else(|| "not Paris".to_string())
+            }));
+
+        let first = call
+            .invoke(&ctx, json!("What is the capital of France?"))
+            .await
+            .unwrap();
+        assert_eq!(first.value, json!("Paris"));
+        assert!(ctx.semantic_cache.as_ref().unwrap().is_empty());
+
+        let second = call
+            .invoke(&ctx, json!("what is the capital of france"))
+            .await
+            .unwrap();
+        assert_eq!(second.value, json!("Lyon"));
+        assert!(!second.diagnostics.unwrap().cache_hit);
+    }
+
+    #[tokio::test]
+    async fn test_cache_embed_times_out_on_stalled_server() {
+        use std::time::Duration;
+
+        let base_url = crate::test_support::serve_silent().await;
+        let ctx = ExecCtx::builder(base_url)
+            .request_timeout(Duration::from_millis(50))
+            .semantic_cache(0.8, "nomic-embed-text")
+            .build();
+        let call = LlmCall::new("faq", "Question: {input}").expecting_text();
+        let err = tokio::time::timeout(Duration::from_secs(5), call.invoke(&ctx, json!("q")))
+            .await
+            .expect("the cache lookup should give up on its own")
+            .unwrap_err();
+        assert!(matches!(err, crate::PipelineError::Timeout(_)));
+    }
+}
@@ -1,4 +1,6 @@
-use crate::{client::LlmConfig, error::Result, types::PipelineContext, PipelineError};
+use crate::{
+    client::LlmConfig, error::Result, retry::RetryConfig, types::PipelineContext, PipelineError,
+};
 
 /// A single stage in the pipeline.
 #[derive(Clone)]
@@ -20,6 +22,12 @@ pub struct Stage {
 
     /// Whether this stage is enabled.
     pub enabled: bool,
+
+    /// Retry policy. A retrying stage also retries responses that aren't
+    /// valid JSON, since its output must deserialize into the pipeline's
+    /// type. Each attempt is reported as a step through the progress
+    /// callback of [`Pipeline::execute_with_progress`](crate::Pipeline::execute_with_progress).
+    pub retry: Option<RetryConfig>,
 }
 
 impl Stage {
@@ -35,6 +43,7 @@ impl Stage {
             model: "llama3.2:3b".to_string(),
             config: LlmConfig::default(),
             enabled: true,
+            retry: None,
         }
     }
 
@@ -56,6 +65,12 @@ impl Stage {
         self
     }
 
+    /// Set the retry policy.
+    pub fn with_retry(mut self, retry: RetryConfig) -> Self {
+        self.retry = Some(retry);
+        self
+    }
+
     /// Set temperature.
     pub fn with_temperature(mut self, temp: f64) -> Self {
         self.config.temperature = temp;
@@ -125,6 +140,7 @@ impl StageBuilder {
                 model: "llama3.2:3b".to_string(),
                 config: LlmConfig::default(),
                 enabled: true,
+                retry: None,
             },
         }
     }
@@ -169,6 +185,11 @@ impl StageBuilder {
         self
     }
 
+    pub fn retry(mut self, retry: RetryConfig) -> Self {
+        self.stage.retry = Some(retry);
+        self
+    }
+
     pub fn build(self) -> Result<Stage> {
         if self.stage.prompt_template.is_empty() {
             return Err(PipelineError::InvalidConfig(
@@ -1,12 +1,17 @@
 //! Buffered streaming decoder for newline-delimited JSON streams.
 //!
 //! Handles the case where JSON objects are split across TCP chunk boundaries,
-//! which is a common issue with Ollama's streaming API.
+//! which is a common issue with Ollama's streaming API. Multi-byte UTF-8
+//! characters split across chunks are reassembled rather than replaced with
+//! `U+FFFD`.
 
 use serde_json::Value;
 
 use crate::output_parser::streaming::auto_complete_json;
 
+/// Default cap on a single buffered line, in bytes (8 MiB).
+pub const DEFAULT_MAX_LINE: usize = 8 * 1024 * 1024;
+
 /// Buffered decoder for newline-delimited JSON streams (NDJSON).
 ///
 /// Accumulates raw bytes, splits on newline boundaries, and yields
@@ -31,23 +36,64 @@ use crate::output_parser::streaming::auto_complete_json;
 /// ```
 pub struct StreamingDecoder {
     buffer: String,
+    pending: Vec<u8>,
+    max_line: usize,
+    dropped_lines: usize,
 }
 
 impl StreamingDecoder {
-    /// Create a new empty decoder.
+    /// Create a new empty decoder with a line cap of [`DEFAULT_MAX_LINE`].
     pub fn new() -> Self {
         Self {
             buffer: String::new(),
+            pending: Vec::new(),
+            max_line: DEFAULT_MAX_LINE,
+            dropped_lines: 0,
+        }
+    }
+
+    /// Cap how many bytes of a single unterminated line are buffered.
+    ///
+    /// Guards against a broken server that never sends a newline: once the
+    /// buffered line exceeds `bytes`, it is force-flushed — parsed as a line
+    /// if it happens to be valid JSON, dropped and counted in
+    /// [`dropped_lines`](Self::dropped_lines) otherwise — and buffering
+    /// starts over. Clamped to at least 1. Default: [`DEFAULT_MAX_LINE`].
+    pub fn with_max_line(mut self, bytes: usize) -> Self {
+        self.max_line = bytes.max(1);
+        self
+    }
+
+    /// Returns the line cap in bytes.
+    pub fn max_line(&self) -> usize {
+        self.max_line
+    }
+
+    /// How many lines over the [`max_line`](Self::max_line) cap were
+    /// dropped because they weren't valid JSON.
+    pub fn dropped_lines(&self) -> usize {
+        self.dropped_lines
+    }
+
+    /// Fail if any oversized line has been dropped, so a stream that lost
+    /// data is reported rather than silently returned short.
+    pub(crate) fn check_dropped(&self) -> crate::Result<()> {
+        match self.dropped_lines {
+            0 => Ok(()),
+            n => Err(crate::PipelineError::Other(format!(
+                "dropped {} stream line(s) over {} bytes that were not valid JSON",
+                n, self.max_line
+            ))),
         }
     }
 
     /// Feed a raw chunk into the decoder and return any complete JSON lines.
     ///
     /// Each returned value is a parsed JSON `Value` from one complete line.
-    /// Incomplete lines are buffered until the next chunk arrives.
+    /// Incomplete lines are buffered until the next chunk arrives, as are
+    /// trailing bytes of an incomplete UTF-8 sequence.
     pub fn decode(&mut self, chunk: &[u8]) -> Vec<Value> {
-        let text = String::from_utf8_lossy(chunk);
-        self.buffer.push_str(&text);
+        decode_utf8_into(&mut self.pending, chunk, &mut self.buffer);
 
         let mut values = Vec::new();
 
@@ -62,6 +108,14 @@ impl StreamingDecoder {
             }
         }
 
+        if self.buffer.len() > self.max_line {
+            let line = std::mem::take(&mut self.buffer);
+            match serde_json::from_str::<Value>(line.trim()) {
+                Ok(val) => values.push(val),
+                Err(_) => self.dropped_lines += 1,
+            }
+        }
+
         values
     }
 
@@ -72,6 +126,7 @@ impl StreamingDecoder {
     /// auto-completion of truncated JSON (closing unclosed strings,
     /// brackets, and braces).
     pub fn flush(&mut self) -> Option<Value> {
+        flush_utf8_into(&mut self.pending, &mut self.buffer);
         let remaining = self.buffer.trim().to_string();
         self.buffer.clear();
         if remaining.is_empty() {
@@ -89,12 +144,244 @@ impl StreamingDecoder {
     }
 }
 
+/// Append the valid UTF-8 in `pending ++ chunk` to `out`.
+///
+/// An incomplete multi-byte sequence at the very end is kept in `pending`
+/// for the next call. Bytes that can never form valid UTF-8 are replaced
+/// with `U+FFFD`, as `String::from_utf8_lossy` would.
+pub(crate) fn decode_utf8_into(pending: &mut Vec<u8>, chunk: &[u8], out: &mut String) {
+    pending.extend_from_slice(chunk);
+    let mut consumed = 0;
+    loop {
+        match std::str::from_utf8(&pending[consumed..]) {
+            Ok(valid) => {
+                out.push_str(valid);
+                consumed = pending.len();
+                break;
+            }
+            Err(e) => {
+                let valid_end = consumed + e.valid_up_to();
+                // `valid_up_to` guarantees this range is valid, so this borrows.
+                out.push_str(&String::from_utf8_lossy(&pending[consumed..valid_end]));
+                match e.error_len() {
+                    Some(len) => {
+                        out.push(char::REPLACEMENT_CHARACTER);
+                        consumed = valid_end + len;
+                    }
+                    None => {
+                        // Incomplete sequence at the end; wait for more bytes.
+                        consumed = valid_end;
+                        break;
+                    }
+                }
+            }
+        }
+    }
+    pending.drain(..consumed);
+}
+
+/// Flush any bytes still held by [`decode_utf8_into`], lossily.
+pub(crate) fn flush_utf8_into(pending: &mut Vec<u8>, out: &mut String) {
+    if !pending.is_empty() {
+        out.push_str(&String::from_utf8_lossy(pending));
+        pending.clear();
+    }
+}
+
 impl Default for StreamingDecoder {
     fn default() -> Self {
         Self::new()
     }
 }
 
+/// How streamed tokens inside a `<think>...</think>` block are delivered.
+///
+/// Only affects the token callback; the accumulated response text always
+/// contains the full think block, so
+/// [`PayloadOutput::thinking`](crate::PayloadOutput::thinking) is unaffected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ThinkStreamMode {
+    /// Emit think-block tokens through `on_token` like any other. Default.
+    #[default]
+    Inline,
+    /// Route think-block tokens to a separate thinking callback
+    /// (surfaced as [`Event::ThinkingToken`](crate::events::Event::ThinkingToken)
+    /// by [`LlmCall`](crate::LlmCall)).
+    Separate,
+    /// Drop think-block tokens from the stream entirely.
+    Suppress,
+}
+
+/// One piece of a token stream, classified by [`ThinkFilter`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ThinkChunk {
+    /// Text outside any think block.
+    Answer(String),
+    /// Text inside a `<think>...</think>` block (tags excluded).
+    Thinking(String),
+}
+
+/// Stateful splitter that separates `<think>` block content from answer
+/// text in a token stream.
+///
+/// Tags may be split across tokens (`"<th"` + `"ink>"`); a trailing partial
+/// tag is held back until the next token disambiguates it.
+///
+/// # Example
+///
+/// ```
+/// use llm_pipeline::streaming::{ThinkChunk, ThinkFilter};
+///
+/// let mut filter = ThinkFilter::new();
+/// let mut chunks = filter.push("<th");
+/// chunks.extend(filter.push("ink>hmm</think>Yes"));
+/// assert_eq!(
+///     chunks,
+///     vec![ThinkChunk::Thinking("hmm".into()), ThinkChunk::Answer("Yes".into())]
+/// );
+/// ```
+#[derive(Debug, Default)]
+pub struct ThinkFilter {
+    in_think: bool,
+    pending: String,
+}
+
+impl ThinkFilter {
+    /// Create a filter positioned outside any think block.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Whether the filter is currently inside a think block.
+    pub fn in_thinking(&self) -> bool {
+        self.in_think
+    }
+
+    /// Feed one token and return the classified chunks it completes.
+    pub fn push(&mut self, token: &str) -> Vec<ThinkChunk> {
+        self.pending.push_str(token);
+        let mut out = Vec::new();
+
+        loop {
+            let tag = if self.in_think { "</think>" } else { "<think>" };
+            if let Some(pos) = self.pending.find(tag) {
+                let before: String = self.pending.drain(..pos).collect();
+                self.pending.drain(..tag.len());
+                self.classify(before, &mut out);
+                self.in_think = !self.in_think;
+                continue;
+            }
+
+            let keep = partial_tag_suffix(&self.pending, tag);
+            let split = self.pending.len() - keep;
+            let text: String = self.pending.drain(..split).collect();
+            self.classify(text, &mut out);
+            break;
+        }
+
+        out
+    }
+
+    /// Release any held-back partial tag at end of stream.
+    pub fn flush(&mut self) -> Option<ThinkChunk> {
+        let text = std::mem::take(&mut self.pending);
+        let mut out = Vec::new();
+        self.classify(text, &mut out);
+        out.pop()
+    }
+
+    fn classify(&self, text: String, out: &mut Vec<ThinkChunk>) {
+        if text.is_empty() {
+            return;
+        }
+        out.push(if self.in_think {
+            ThinkChunk::Thinking(text)
+        } else {
+            ThinkChunk::Answer(text)
+        });
+    }
+}
+
+/// Skips the text a resumed stream re-sends before continuing.
+///
+/// When a stream is resumed after a mid-stream failure, some proxies and
+/// gateways replay the output from the start instead of continuing it.
+/// The guard is given the text already received and matches incoming
+/// chunks against it byte by byte: chunk bytes that fall inside that
+/// replayed prefix are skipped, everything after it is kept. A chunk that
+/// doesn't match means the server is not replaying, and from then on every
+/// chunk is kept as-is. Repeated tokens within the new output (`"ha"`,
+/// `"ha"`) are never dropped.
+///
+/// # Example
+///
+/// ```
+/// use llm_pipeline::streaming::StreamDedup;
+///
+/// let mut dedup = StreamDedup::resuming("Hello, ");
+/// assert_eq!(dedup.push("Hel"), "");
+/// assert_eq!(dedup.push("lo, wor"), "wor");
+/// assert_eq!(dedup.push("ld"), "ld");
+/// assert_eq!(dedup.dropped(), 2);
+///
+/// // A server that continues instead of replaying loses nothing.
+/// let mut dedup = StreamDedup::resuming("10");
+/// assert_eq!(dedup.push("0"), "0");
+/// assert_eq!(dedup.push("0"), "0");
+/// ```
+#[derive(Debug, Default)]
+pub struct StreamDedup {
+    replay: String,
+    matched: usize,
+    dropped: usize,
+}
+
+impl StreamDedup {
+    /// Create a guard for a stream resumed after receiving `received`.
+    pub fn resuming(received: impl Into<String>) -> Self {
+        Self {
+            replay: received.into(),
+            matched: 0,
+            dropped: 0,
+        }
+    }
+
+    /// Feed one chunk and return the part of it that is new: empty if the
+    /// whole chunk replays received text, the chunk itself once past it.
+    pub fn push<'a>(&mut self, chunk: &'a str) -> &'a str {
+        let rest = &self.replay[self.matched..];
+        if rest.is_empty() || chunk.is_empty() {
+            return chunk;
+        }
+        if rest.starts_with(chunk) {
+            self.matched += chunk.len();
+            self.dropped += 1;
+            return "";
+        }
+        if let Some(new) = chunk.strip_prefix(rest) {
+            self.matched = self.replay.len();
+            self.dropped += 1;
+            return new;
+        }
+        // Not a replay: stop matching.
+        self.matched = self.replay.len();
+        chunk
+    }
+
+    /// Number of chunks that were wholly or partly skipped.
+    pub fn dropped(&self) -> usize {
+        self.dropped
+    }
+}
+
+/// Length of the longest suffix of `s` that is a proper prefix of `tag`.
+fn partial_tag_suffix(s: &str, tag: &str) -> usize {
+    (1..tag.len())
+        .rev()
+        .find(|&k| s.ends_with(&tag[..k]))
+        .unwrap_or(0)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -219,6 +506,99 @@ mod tests {
         assert_eq!(val["age"], 30);
     }
 
+    #[test]
+    fn test_multibyte_char_split_across_chunks() {
+        let mut decoder = StreamingDecoder::new();
+        let line = "{\"response\":\"日本🎉\"}\n".as_bytes();
+        // Split inside the emoji and inside the first CJK character.
+        let emoji_start = line.len() - 7;
+        let mut values = decoder.decode(&line[..14]);
+        values.extend(decoder.decode(&line[14..emoji_start + 2]));
+        values.extend(decoder.decode(&line[emoji_start + 2..]));
+        assert_eq!(values.len(), 1);
+        assert_eq!(values[0]["response"], "日本🎉");
+    }
+
+    #[test]
+    fn test_invalid_bytes_replaced() {
+        let mut decoder = StreamingDecoder::new();
+        let values = decoder.decode(b"{\"response\":\"a\xffb\"}\n");
+        assert_eq!(values.len(), 1);
+        assert_eq!(values[0]["response"], "a\u{FFFD}b");
+    }
+
+    #[test]
+    fn test_max_line_force_flushes() {
+        let mut decoder = StreamingDecoder::new().with_max_line(16);
+        assert_eq!(decoder.max_line(), 16);
+
+        // An unterminated blob past the cap is dropped, not buffered forever.
+        assert!(decoder.decode(&[b'x'; 32]).is_empty());
+        assert!(decoder.flush().is_none());
+        assert_eq!(decoder.dropped_lines(), 1);
+        assert!(decoder.check_dropped().is_err());
+
+        // An oversized line that is valid JSON is still delivered.
+        let values = decoder.decode(br#"{"response": "a long token"}"#);
+        assert_eq!(values, vec![json!({"response": "a long token"})]);
+        assert_eq!(decoder.dropped_lines(), 1);
+
+        // Terminated lines are unaffected by the cap.
+        let values = decoder.decode(b"{\"response\": \"another long one\"}\n");
+        assert_eq!(values.len(), 1);
+    }
+
+    #[test]
+    fn test_flush_incomplete_utf8_lossy() {
+        let mut pending = Vec::new();
+        let mut out = String::new();
+        decode_utf8_into(&mut pending, &[b'x', 0xE6, 0x97], &mut out);
+        assert_eq!(out, "x");
+        assert_eq!(pending.len(), 2);
+        flush_utf8_into(&mut pending, &mut out);
+        assert_eq!(out, "x\u{FFFD}");
+        assert!(pending.is_empty());
+    }
+
+    #[test]
+    fn test_think_filter_single_token() {
+        let mut filter = ThinkFilter::new();
+        let chunks = filter.push("<think>reasoning</think>answer");
+        assert_eq!(
+            chunks,
+            vec![
+                ThinkChunk::Thinking("reasoning".into()),
+                ThinkChunk::Answer("answer".into()),
+            ]
+        );
+        assert!(!filter.in_thinking());
+    }
+
+    #[test]
+    fn test_think_filter_tags_split_across_tokens() {
+        let mut filter = ThinkFilter::new();
+        let mut chunks = Vec::new();
+        for token in ["<", "think", ">a", "b</", "thi", "nk>c"] {
+            chunks.extend(filter.push(token));
+        }
+        let thinking: String = chunks
+            .iter()
+            .filter_map(|c| match c {
+                ThinkChunk::Thinking(t) => Some(t.as_str()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(thinking, "ab");
+        assert_eq!(chunks.last(), Some(&ThinkChunk::Answer("c".into())));
+    }
+
+    #[test]
+    fn test_think_filter_flushes_false_partial_tag() {
+        let mut filter = ThinkFilter::new();
+        assert_eq!(filter.push("a <"), vec![ThinkChunk::Answer("a ".into())]);
+        assert_eq!(filter.flush(), Some(ThinkChunk::Answer("<".into())));
+    }
+
     #[test]
     fn test_non_json_lines_skipped() {
         let mut decoder = StreamingDecoder::new();
@@ -227,4 +607,31 @@ mod tests {
         assert_eq!(values.len(), 1);
         assert_eq!(values[0]["ok"], json!(true));
     }
+
+    #[test]
+    fn test_stream_dedup_skips_only_replayed_prefix() {
+        let mut dedup = StreamDedup::resuming("The answer");
+        let kept: String = ["The ", "answer is", " 10", "0"]
+            .iter()
+            .map(|c| dedup.push(c))
+            .collect();
+        assert_eq!(kept, " is 100");
+        assert_eq!(dedup.dropped(), 2);
+    }
+
+    #[test]
+    fn test_stream_dedup_keeps_repeated_tokens() {
+        for chunks in [["ha", "ha"], ["\n", "\n"], ["  ", "  "], ["10", "0"]] {
+            let mut dedup = StreamDedup::resuming("");
+            let kept: String = chunks.iter().map(|c| dedup.push(c)).collect();
+            assert_eq!(kept, chunks.concat());
+        }
+
+        // A continuation that doesn't replay is kept whole, even when it
+        // repeats the end of the received text.
+        let mut dedup = StreamDedup::resuming("10");
+        let kept: String = ["0", "0"].iter().map(|c| dedup.push(c)).collect();
+        assert_eq!(kept, "00");
+        assert_eq!(dedup.dropped(), 0);
+    }
 }
@@ -0,0 +1,47 @@
+//! Local HTTP stubs shared by the unit tests.
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+/// Serve `connections` requests on a local port, each answered with
+/// `status` and `body`. Returns the server's base URL.
+pub(crate) async fn serve(status: u16, body: &'static str, connections: usize) -> String {
+    serve_replies(vec![(status, body); connections]).await
+}
+
+/// Serve one request per entry of `replies`, in order, answering each with
+/// its `(status, body)`. Returns the server's base URL.
+pub(crate) async fn serve_replies(replies: Vec<(u16, &'static str)>) -> String {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        for (status, body) in replies {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut buf = vec![0u8; 8192];
+            let _ = socket.read(&mut buf).await;
+            let reply = format!(
+                "HTTP/1.1 {} Custom\r\nContent-Type: application/json\r\n\
+                 Content-Length: {}\r\nConnection: close\r\n\r\n{}",
+                status,
+                body.len(),
+                body
+            );
+            socket.write_all(reply.as_bytes()).await.unwrap();
+            socket.shutdown().await.ok();
+        }
+    });
+    format!("http://{}", addr)
+}
+
+/// Accept connections on a local port and never answer them, like a stalled
+/// server. Returns the server's base URL.
+pub(crate) async fn serve_silent() -> String {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        let mut open = Vec::new();
+        while let Ok((socket, _)) = listener.accept().await {
+            open.push(socket);
+        }
+    });
+    format!("http://{}", addr)
+}
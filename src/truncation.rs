@@ -0,0 +1,110 @@
+//! Input truncation for prompts that would overflow the context window.
+//!
+//! [`TruncationStrategy`] trims the text substituted for `{input}` by
+//! [`LlmCall::with_input_truncation`](crate::LlmCall::with_input_truncation),
+//! so an oversized document is clipped instead of failing the call.
+
+use crate::payload::chunk::CHARS_PER_TOKEN;
+
+/// Inserted where text was removed.
+pub const TRUNCATION_MARKER: &str = "[...truncated...]";
+
+/// Which part of an oversized input is cut.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TruncationMode {
+    /// Cut the start, keeping the tail.
+    Start,
+    /// Cut the end, keeping the head. Default.
+    #[default]
+    End,
+    /// Cut the middle, keeping the head and the tail in equal parts.
+    Middle,
+}
+
+/// Limits the length of a call's `{input}`.
+///
+/// Inputs longer than `max_chars` characters are cut down to `max_chars`
+/// characters as directed by `mode`, with [`TRUNCATION_MARKER`] marking the
+/// cut.
+///
+/// ```
+/// use llm_pipeline::truncation::{TruncationMode, TruncationStrategy};
+///
+/// let head = TruncationStrategy::chars(5, TruncationMode::End);
+/// assert_eq!(head.apply("abcdefghij").unwrap(), "abcde\n[...truncated...]");
+///
+/// let both = TruncationStrategy::chars(4, TruncationMode::Middle);
+/// assert_eq!(both.apply("abcdefghij").unwrap(), "ab\n[...truncated...]\nij");
+/// assert!(both.apply("abc").is_none());
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TruncationStrategy {
+    /// Maximum characters of the input to keep.
+    pub max_chars: usize,
+    /// Which part of the input to cut.
+    pub mode: TruncationMode,
+}
+
+impl TruncationStrategy {
+    /// Keep at most `max_chars` characters.
+    pub fn chars(max_chars: usize, mode: TruncationMode) -> Self {
+        Self { max_chars, mode }
+    }
+
+    /// Keep roughly `max_tokens` tokens, estimated at
+    /// [`CHARS_PER_TOKEN`] characters each.
+    pub fn tokens(max_tokens: usize, mode: TruncationMode) -> Self {
+        Self::chars(max_tokens.saturating_mul(CHARS_PER_TOKEN), mode)
+    }
+
+    /// Truncate `text`, or `None` if it already fits.
+    pub fn apply(&self, text: &str) -> Option<String> {
+        let len = text.chars().count();
+        if len <= self.max_chars {
+            return None;
+        }
+        let head = |n: usize| text.chars().take(n).collect::<String>();
+        let tail = |n: usize| text.chars().skip(len - n).collect::<String>();
+        Some(match self.mode {
+            TruncationMode::Start => format!("{}\n{}", TRUNCATION_MARKER, tail(self.max_chars)),
+            TruncationMode::End => format!("{}\n{}", head(self.max_chars), TRUNCATION_MARKER),
+            TruncationMode::Middle => {
+                let kept_head = self.max_chars.div_ceil(2);
+                format!(
+                    "{}\n{}\n{}",
+                    head(kept_head),
+                    TRUNCATION_MARKER,
+                    tail(self.max_chars - kept_head)
+                )
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_truncation_modes() {
+        let text = "héllo wörld";
+        let cut = |max, mode| TruncationStrategy::chars(max, mode).apply(text);
+        assert_eq!(cut(11, TruncationMode::End), None);
+        assert_eq!(
+            cut(5, TruncationMode::End).unwrap(),
+            "héllo\n[...truncated...]"
+        );
+        assert_eq!(
+            cut(5, TruncationMode::Start).unwrap(),
+            "[...truncated...]\nwörld"
+        );
+        assert_eq!(
+            cut(5, TruncationMode::Middle).unwrap(),
+            "hél\n[...truncated...]\nld"
+        );
+        assert_eq!(
+            TruncationStrategy::tokens(3, TruncationMode::End).max_chars,
+            12
+        );
+    }
+}
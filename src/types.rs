@@ -1,4 +1,5 @@
 use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
 use std::collections::HashMap;
 
 /// Input to a pipeline execution.
@@ -28,6 +29,12 @@ impl PipelineInput {
 /// Output from a single stage.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct StageOutput<T> {
+    /// Name of the stage that produced this output. Empty for the
+    /// standalone `call_llm*` helpers and in results serialized before this
+    /// field existed.
+    #[serde(default)]
+    pub stage: String,
+
     /// The structured output parsed from the LLM response.
     pub output: T,
 
@@ -36,6 +43,12 @@ pub struct StageOutput<T> {
 
     /// Raw response text from the LLM.
     pub raw_response: String,
+
+    /// The rendered prompt sent for this stage (template with `{input}` and
+    /// context vars substituted). Empty in results serialized before this
+    /// field existed.
+    #[serde(default)]
+    pub prompt: String,
 }
 
 /// Complete pipeline execution result.
@@ -51,6 +64,42 @@ pub struct PipelineResult<T> {
     pub stages_enabled: Vec<bool>,
 }
 
+impl<T: Serialize> PipelineResult<T> {
+    /// The whole run as one JSON value, for audit logs or replaying it in a
+    /// UI:
+    ///
+    /// ```json
+    /// {
+    ///   "stages": [
+    ///     {"stage": "...", "prompt": "...", "raw_response": "...",
+    ///      "thinking": null, "output": {...}}
+    ///   ],
+    ///   "final_output": {...}
+    /// }
+    /// ```
+    ///
+    /// An output that fails to serialize is recorded as `null`.
+    pub fn transcript(&self) -> Value {
+        let stages: Vec<Value> = self
+            .stage_results
+            .iter()
+            .map(|stage| {
+                json!({
+                    "stage": stage.stage,
+                    "prompt": stage.prompt,
+                    "raw_response": stage.raw_response,
+                    "thinking": stage.thinking,
+                    "output": serde_json::to_value(&stage.output).unwrap_or(Value::Null),
+                })
+            })
+            .collect();
+        json!({
+            "stages": stages,
+            "final_output": serde_json::to_value(&self.final_output).unwrap_or(Value::Null),
+        })
+    }
+}
+
 /// Progress update emitted during pipeline execution.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PipelineProgress {
@@ -90,3 +139,38 @@ impl PipelineContext {
         self.data.get(key).map(|s| s.as_str())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pipeline_result_transcript() {
+        let stage = |name: &str, output: &str| StageOutput {
+            stage: name.to_string(),
+            output: output.to_string(),
+            thinking: None,
+            raw_response: format!("\"{}\"", output),
+            prompt: format!("prompt for {}", name),
+        };
+        let result = PipelineResult {
+            final_output: "b".to_string(),
+            stage_results: vec![stage("draft", "a"), stage("refine", "b")],
+            stages_enabled: vec![true, true],
+        };
+
+        let transcript = result.transcript();
+        assert_eq!(transcript["final_output"], "b");
+        assert_eq!(
+            transcript["stages"][0],
+            json!({
+                "stage": "draft",
+                "prompt": "prompt for draft",
+                "raw_response": "\"a\"",
+                "thinking": null,
+                "output": "a",
+            })
+        );
+        assert_eq!(transcript["stages"][1]["stage"], "refine");
+    }
+}